@@ -1,4 +1,4 @@
-use intruducer::{intruduce, Error};
+use intruducer::{diagnose_with, intruduce_with, Error, Options, SeccompFilter, ThreadSelector};
 use std::path::PathBuf;
 use structopt::StructOpt;
 
@@ -12,14 +12,77 @@ struct Opt {
     /// Library path
     #[structopt(short, long, parse(from_os_str))]
     lib_path: PathBuf,
+
+    /// Only check whether the intruduction would succeed
+    #[structopt(long)]
+    diagnose: bool,
+
+    /// Seccomp filter the target process installed, as dumped by PTRACE_SECCOMP_GET_FILTER
+    #[structopt(long, parse(from_os_str))]
+    seccomp_filter: Vec<PathBuf>,
+
+    /// SELinux context the second payload file and the library are labelled with
+    #[structopt(long)]
+    selinux_context: Option<String>,
+
+    /// Identifier of the thread to target
+    #[structopt(long)]
+    tid: Option<u32>,
+
+    /// Name of the thread to target
+    #[structopt(long, conflicts_with = "tid")]
+    thread_name: Option<String>,
+
+    /// Signal the thread is nudged with, so that it leaves its syscall right away
+    #[structopt(long)]
+    nudge: Option<u8>,
+
+    /// Declares that the nudge signal handler of the target process was installed with SA_RESTART
+    #[structopt(long, requires = "nudge")]
+    nudge_sa_restart: bool,
 }
 
 fn main() -> Result<(), Error> {
     let opt = Opt::from_args();
 
-    intruduce(opt.id, opt.lib_path)?;
+    let mut options = Options::new();
+    for path in opt.seccomp_filter {
+        options = options.seccomp_filter(SeccompFilter::from_bytes(&std::fs::read(path)?)?);
+    }
+
+    if let Some(context) = opt.selinux_context {
+        options = options.selinux_context(context);
+    }
+
+    if let Some(tid) = opt.tid {
+        options = options.thread(ThreadSelector::Tid(tid));
+    } else if let Some(name) = opt.thread_name {
+        options = options.thread(ThreadSelector::Name(name));
+    }
+
+    if let Some(signal) = opt.nudge {
+        options = options.nudge(signal);
+    }
+
+    if opt.nudge_sa_restart {
+        options = options.nudge_sa_restart();
+    }
+
+    if opt.diagnose {
+        print!("{}", diagnose_with(opt.id, &options));
+        return Ok(());
+    }
+
+    let intruduction = intruduce_with(opt.id, opt.lib_path, &options)?;
 
     println!("Successful intruduction!");
 
+    if intruduction.nudged == Some(false) {
+        println!(
+            "The thread {} didn't leave its syscall once nudged: the library will be loaded once it does.",
+            intruduction.thread.tid
+        );
+    }
+
     Ok(())
 }
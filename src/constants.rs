@@ -9,3 +9,7 @@ pub(crate) const TMP_DIR: &str = "/tmp";
 
 #[cfg(target_os = "android")]
 pub(crate) const TMP_DIR: &str = "/data/local/tmp";
+
+/// The granularity the second payload mapping size is rounded to. The kernel rounds it up again
+/// to the actual page size of the target, so the smallest one is fine.
+pub(crate) const PAGE_SIZE: usize = 0x1000;
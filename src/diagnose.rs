@@ -0,0 +1,578 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    fs::{self, OpenOptions},
+    io::{BufRead, BufReader},
+    path::Path,
+    process,
+};
+
+use crate::{
+    constants::TMP_DIR,
+    ext::{ProcExt, ProcIntruducerExt},
+    mem::Memory,
+    nudge,
+    os::{PtraceScope, VirtAddr},
+    payloads::{self, Syscalls},
+    proc::{Proc, ProcClass, ProcFs, ProcId, CAP_SYS_PTRACE},
+    seccomp::{self, SeccompFilter},
+    target, Error, Options,
+};
+
+/// The library path the payloads are generated for, since it only affects their length.
+const PLACEHOLDER_LIB_PATH: &str = "/data/local/tmp/placeholder/libplaceholder.so";
+
+/// A enum that represents the outcome of a [`Check`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// The check passed.
+    Pass,
+    /// The check passed, but the intruduction may fail anyway.
+    Warn,
+    /// The check failed, so the intruduction would fail.
+    Fail,
+    /// The check couldn't be performed, since a previous one failed.
+    Skip,
+}
+
+/// A struct that represents one of the checks of a [`Report`].
+#[derive(Clone, Debug)]
+pub struct Check {
+    /// The name of the check, e.g. `dlopen`.
+    pub name: &'static str,
+    /// The outcome of the check.
+    pub outcome: Outcome,
+    /// What was found, or why the check failed.
+    pub explanation: String,
+}
+
+/// A struct that represents the outcome of [`diagnose`] and [`diagnose_with`].
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    /// The performed checks, in the order [`crate::intruduce`] performs them.
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    /// Determines whether the intruduction is expected to succeed, e.g. no check failed.
+    pub fn passed(&self) -> bool {
+        self.checks
+            .iter()
+            .all(|check| check.outcome != Outcome::Fail && check.outcome != Outcome::Skip)
+    }
+
+    /// Gets the check named `name`.
+    ///
+    /// Returns [`None`] if it wasn't performed.
+    pub fn get(&self, name: &str) -> Option<&Check> {
+        self.checks.iter().find(|check| check.name == name)
+    }
+
+    fn push(&mut self, name: &'static str, outcome: Outcome, explanation: impl Into<String>) {
+        self.checks.push(Check {
+            name,
+            outcome,
+            explanation: explanation.into(),
+        });
+    }
+
+    /// Records the outcome of the check `name`, which passes if `result` is [`Ok`].
+    ///
+    /// Returns the value of `result`, if any.
+    fn record<T>(&mut self, name: &'static str, result: Result<(T, String), String>) -> Option<T> {
+        match result {
+            Ok((value, explanation)) => {
+                self.push(name, Outcome::Pass, explanation);
+                Some(value)
+            }
+            Err(explanation) => {
+                self.push(name, Outcome::Fail, explanation);
+                None
+            }
+        }
+    }
+
+    fn skip(&mut self, names: &[&'static str]) {
+        for name in names {
+            self.push(name, Outcome::Skip, "a previous check failed");
+        }
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for check in &self.checks {
+            let outcome = match check.outcome {
+                Outcome::Pass => "pass",
+                Outcome::Warn => "warn",
+                Outcome::Fail => "FAIL",
+                Outcome::Skip => "skip",
+            };
+            writeln!(f, "[{outcome}] {}: {}", check.name, check.explanation)?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks whether loading a shared library into the target process would succeed, without altering it.
+///
+/// Every step of [`crate::intruduce`] is performed up to writing the first payload, which is generated for a
+/// placeholder library path. `id` is either a process or thread (process task) identifier.
+///
+/// Examples:
+///
+/// ```no_run
+/// use intruducer::diagnose;
+///
+/// let report = diagnose(1234);
+/// if !report.passed() {
+///     eprint!("{}", report);
+/// }
+/// ```
+pub fn diagnose(id: ProcId) -> Report {
+    diagnose_with(id, &Options::default())
+}
+
+/// Checks whether loading a shared library into the target process would succeed, as [`diagnose`] does, but as
+/// `options` say, e.g. the targeted thread and the seccomp filters the payloads syscalls are judged by are the ones
+/// [`crate::intruduce_with`] would pick.
+pub fn diagnose_with(id: ProcId, options: &Options) -> Report {
+    diagnose_in(&ProcFs::host(), id, options)
+}
+
+fn diagnose_in(fs: &ProcFs, id: ProcId, options: &Options) -> Report {
+    let mut report = Report::default();
+
+    let Some(proc) = report.record(
+        "process",
+        fs.open(id)
+            .map(|proc| (proc, format!("{id} is running")))
+            .ok_or_else(|| format!("{id} is not running")),
+    ) else {
+        report.skip(&[
+            "ptrace scope",
+            "capabilities",
+            "access",
+            "memory",
+            "architecture",
+            "seccomp",
+            "selinux",
+            "dlopen",
+            "staging directory",
+            "blocked thread",
+            "payloads",
+        ]);
+        return report;
+    };
+
+    let scope = match fs.ptrace_scope() {
+        PtraceScope::All => "0: any process with the same credentials may be accessed",
+        PtraceScope::Restricted => "1: only descendants may be accessed without CAP_SYS_PTRACE",
+        PtraceScope::Admin => "2: only CAP_SYS_PTRACE allows accessing a process",
+        PtraceScope::None => "3: no process may be accessed",
+    };
+    report.push("ptrace scope", Outcome::Pass, scope);
+
+    match fs.current().status() {
+        Ok(status) if status.has_cap(CAP_SYS_PTRACE) => {
+            report.push("capabilities", Outcome::Pass, "CAP_SYS_PTRACE is held")
+        }
+        Ok(_) => report.push("capabilities", Outcome::Pass, "CAP_SYS_PTRACE is not held"),
+        Err(err) => report.push("capabilities", Outcome::Warn, format!("unknown: {err}")),
+    }
+
+    report.record(
+        "access",
+        proc.check_access()
+            .map(|()| ((), "the target process may be accessed".into()))
+            .map_err(explain),
+    );
+
+    let mem = report.record(
+        "memory",
+        Memory::open(&proc)
+            .map(|memory| {
+                let explanation = format!("accessed through {}", memory.names().join(", then "));
+                (memory, explanation)
+            })
+            .map_err(explain),
+    );
+
+    let arch = report.record(
+        "architecture",
+        proc.arch()
+            .ok_or_else(|| explain(Error::UnknownArch))
+            .and_then(|arch| {
+                let explanation = format!("{:?}, {} bit", arch.machine, arch.bits);
+                arch.class()
+                    .map(|class| ((arch, class), explanation))
+                    .map_err(explain)
+            }),
+    );
+
+    let syscalls = match (&arch, proc.status()) {
+        (_, Err(err)) => {
+            report.push("seccomp", Outcome::Warn, format!("unknown: {err}"));
+            Some(Syscalls::default())
+        }
+        (None, Ok(_)) => {
+            report.skip(&["seccomp"]);
+            None
+        }
+        (Some((_, class)), Ok(status)) => check_seccomp(
+            &mut report,
+            class,
+            status.seccomp,
+            status.seccomp_filters,
+            &options.seccomp_filters,
+        ),
+    };
+
+    // Other security modules expose their context through the same file.
+    match fs.selinux() {
+        Some(selinux) => {
+            let context = proc
+                .security_context()
+                .unwrap_or_else(|_| "an unknown context".into());
+            let mode = match selinux.enforcing() {
+                false => "permissive",
+                true => "enforcing",
+            };
+
+            report.push(
+                "selinux",
+                Outcome::Pass,
+                format!(
+                    "{mode}, the second payload file and the library must be labelled (see `Options::selinux_context`) so that they can be mapped by {context}"
+                ),
+            )
+        }
+        None => report.push("selinux", Outcome::Pass, "not enabled"),
+    }
+
+    let dlopen = report.record(
+        "dlopen",
+        proc.find_dlopen()
+            .map(|dlopen| {
+                let explanation = format!("found at {:#x}", dlopen.addr);
+                (dlopen, explanation)
+            })
+            .map_err(explain),
+    );
+
+    check_staging_dir(&mut report, fs, &proc);
+
+    let second_payload_path = fs.resolve(TMP_DIR).join("payload-0-0.bin");
+    let second_payload_path = second_payload_path.to_str().unwrap();
+
+    let (Some(mem), Some((arch, class)), Some(syscalls)) = (mem, arch, syscalls) else {
+        report.skip(&["blocked thread", "payloads"]);
+        return report;
+    };
+
+    let target = report.record(
+        "blocked thread",
+        payloads::gen_first(&class, syscalls, second_payload_path, 0)
+            .and_then(|first_payload| {
+                target::find(
+                    &proc,
+                    &mem,
+                    &options.thread,
+                    &arch,
+                    first_payload.code().len(),
+                )
+            })
+            .and_then(|target| {
+                let task = proc.task(proc.path.join("task").join(target.thread.tid.to_string()));
+                if let Some(signal) = options.nudge {
+                    nudge::check(&task, signal, options.nudge_sa_restart)?;
+                }
+
+                match target.blocked.fits(arch.bits) {
+                    true => Ok(target),
+                    false => Err(Error::AddressOutOfRange(target.blocked.ip)),
+                }
+            })
+            .map(|target| {
+                let mapping =
+                    |addr| find_mapping(&proc, addr).unwrap_or_else(|| "an unknown mapping".into());
+                let mut explanation = format!(
+                    "{} ({}) blocked at {:#x}, in {}",
+                    target.thread.tid,
+                    target.thread.name,
+                    target.blocked.ip,
+                    mapping(target.blocked.ip)
+                );
+                if target.addr != target.blocked.ip {
+                    explanation += &format!(
+                        ", the first payload is written at the return address {:#x}, in {}",
+                        target.addr,
+                        mapping(target.addr)
+                    );
+                }
+
+                (target.addr, explanation)
+            })
+            .map_err(explain),
+    );
+
+    match (dlopen, target) {
+        (Some(dlopen), Some(addr)) => {
+            let result = (|| {
+                let first_payload_len =
+                    payloads::gen_first(&class, syscalls, second_payload_path, 0)?
+                        .code()
+                        .len();
+                let mut original_code = vec![0; first_payload_len];
+                mem.read_exact_at(&mut original_code, addr)?;
+
+                if payloads::is_first(&class, &original_code)? {
+                    return Err(Error::PendingIntruduction(addr));
+                }
+
+                let second_payload = payloads::gen_second(
+                    &class,
+                    syscalls,
+                    &original_code,
+                    addr,
+                    PLACEHOLDER_LIB_PATH,
+                    &dlopen,
+                )?;
+                let first_payload = payloads::gen_first(
+                    &class,
+                    syscalls,
+                    second_payload_path,
+                    payloads::second_payload_size(second_payload.code())?,
+                )?;
+
+                Ok::<_, Error>((
+                    (),
+                    format!(
+                        "the first payload is {} bytes, the second one {} bytes",
+                        first_payload.code().len(),
+                        second_payload.code().len()
+                    ),
+                ))
+            })();
+            report.record("payloads", result.map_err(explain));
+        }
+        _ => report.skip(&["payloads"]),
+    }
+
+    report
+}
+
+/// Checks whether the seccomp `mode` of a `class` process, which has installed `installed` filters, allows the
+/// payloads syscalls, judging them by the `supplied` filters as [`seccomp::pick`] does.
+///
+/// Returns the syscalls the payloads are generated with, unless none is allowed.
+fn check_seccomp(
+    report: &mut Report,
+    class: &ProcClass,
+    mode: Option<u8>,
+    installed: Option<u32>,
+    supplied: &[SeccompFilter],
+) -> Option<Syscalls> {
+    let syscalls = match seccomp::pick(class, mode, supplied) {
+        Ok(syscalls) => syscalls,
+        Err(_) if mode == Some(1) => {
+            report.push(
+                "seccomp",
+                Outcome::Fail,
+                "strict: the payloads can't open or map files",
+            );
+            return None;
+        }
+        Err(err) => {
+            report.push("seccomp", Outcome::Fail, explain(err));
+            return None;
+        }
+    };
+
+    let installed = installed.map_or("unknown".into(), |installed| installed.to_string());
+    match mode {
+        Some(0) => report.push("seccomp", Outcome::Pass, "disabled"),
+        Some(_) if supplied.is_empty() => report.push(
+            "seccomp",
+            Outcome::Warn,
+            format!(
+                "filtered by {installed} filters: the payloads syscalls may be denied, unless the filters are supplied"
+            ),
+        ),
+        Some(_) => report.push(
+            "seccomp",
+            Outcome::Pass,
+            format!("filtered by {installed} filters, which allow the payloads syscalls with {syscalls:?}"),
+        ),
+        None => report.push("seccomp", Outcome::Pass, "not supported by the kernel"),
+    }
+
+    Some(syscalls)
+}
+
+/// Checks whether the second payload file can be written into the staging directory, and mapped as executable by
+/// the target process.
+fn check_staging_dir(report: &mut Report, fs: &ProcFs, proc: &Proc) {
+    let path = fs
+        .resolve(TMP_DIR)
+        .join(format!("payload-{}-diagnose.bin", process::id()));
+
+    if let Err(err) = OpenOptions::new().write(true).create_new(true).open(&path) {
+        report.push(
+            "staging directory",
+            Outcome::Fail,
+            format!("{TMP_DIR} is not writable: {err}"),
+        );
+        return;
+    }
+    let _ = fs::remove_file(&path);
+
+    match proc.mountinfo().ok().and_then(|mountinfo| {
+        let mountinfo = BufReader::new(mountinfo).lines().map_while(Result::ok);
+        find_mount(mountinfo, Path::new(TMP_DIR))
+    }) {
+        Some((mount_point, true)) => report.push(
+            "staging directory",
+            Outcome::Fail,
+            format!("{TMP_DIR} is on {mount_point}, which is mounted noexec"),
+        ),
+        Some((mount_point, false)) => report.push(
+            "staging directory",
+            Outcome::Pass,
+            format!("{TMP_DIR} is writable, and on {mount_point}, which is mounted exec"),
+        ),
+        None => report.push(
+            "staging directory",
+            Outcome::Warn,
+            format!("{TMP_DIR} is writable, but its mount point is unknown"),
+        ),
+    }
+}
+
+/// Finds the mount point `path` is located on, parsing the lines of `/proc/<id>/mountinfo`.
+///
+/// Returns [`None`] if no mount point contains `path`, otherwise the mount point and whether it's `noexec`.
+fn find_mount(mountinfo: impl Iterator<Item = String>, path: &Path) -> Option<(String, bool)> {
+    mountinfo
+        .filter_map(|line| {
+            // <id> <parent id> <major:minor> <root> <mount point> <options> ...
+            let mut fields = line.split_whitespace().skip(4);
+            let (mount_point, options) = (fields.next()?.to_owned(), fields.next()?);
+            let noexec = options.split(',').any(|option| option == "noexec");
+
+            path.starts_with(&mount_point)
+                .then_some((mount_point, noexec))
+        })
+        // The last of the longest ones, since mounts may be stacked.
+        .max_by_key(|(mount_point, _)| Path::new(mount_point).components().count())
+}
+
+/// Finds the path of the mapping - or its name, e.g. `[vdso]` - which contains `addr`.
+fn find_mapping(proc: &Proc, addr: VirtAddr) -> Option<String> {
+    proc.find_map(addr).map(|map| map.name().to_owned())
+}
+
+fn explain(err: Error) -> String {
+    format!("{err:?}")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::{
+        proc::fixture::{process, Fixture},
+        Options, ThreadSelector,
+    };
+
+    use super::{diagnose_in, find_mount, Outcome};
+
+    #[test]
+    fn finds_the_mount_of_a_path() {
+        let mountinfo = "\
+22 1 259:2 / / rw,relatime shared:1 - ext4 /dev/nvme0n1p2 rw
+27 22 0:24 / /tmp rw,nosuid,nodev,noexec shared:5 - tmpfs tmpfs rw
+28 22 0:25 / /tmpfs rw shared:6 - tmpfs tmpfs rw
+";
+        let lines = || mountinfo.lines().map(str::to_owned);
+
+        assert_eq!(
+            find_mount(lines(), Path::new("/tmp")),
+            Some(("/tmp".into(), true))
+        );
+        assert_eq!(
+            find_mount(lines(), Path::new("/tmp/payload.bin")),
+            Some(("/tmp".into(), true))
+        );
+        assert_eq!(
+            find_mount(lines(), Path::new("/data/local/tmp")),
+            Some(("/".into(), false))
+        );
+        assert_eq!(find_mount(std::iter::empty(), Path::new("/tmp")), None);
+    }
+
+    #[test]
+    fn diagnoses_a_missing_process() {
+        let report = diagnose_in(&Fixture::new().fs(), 1234, &Options::default());
+
+        assert_eq!(report.get("process").unwrap().outcome, Outcome::Fail);
+        assert_eq!(report.get("payloads").unwrap().outcome, Outcome::Skip);
+        assert!(!report.passed());
+    }
+
+    #[test]
+    fn diagnoses_a_process() {
+        let fixture = process();
+        fixture
+            .write(
+                "/proc/self/maps",
+                std::fs::read(fixture.0.join("proc/1234/maps")).unwrap(),
+            )
+            .write(
+                "/proc/1234/mountinfo",
+                "27 22 0:24 / /tmp rw,noexec shared:5 - tmpfs tmpfs rw\n",
+            )
+            .write(
+                "/proc/1234/attr/current",
+                "u:r:untrusted_app:s0:c512,c768\0",
+            )
+            .write("/sys/fs/selinux/enforce", "1")
+            .write("/proc/1234/mem", [0_u8; 8])
+            .write("/tmp/.keep", []);
+        let report = diagnose_in(&fixture.fs(), 1234, &Options::default());
+
+        let outcome = |name| report.get(name).unwrap().outcome;
+        assert_eq!(outcome("process"), Outcome::Pass);
+        assert_eq!(outcome("architecture"), Outcome::Pass);
+        assert_eq!(outcome("dlopen"), Outcome::Pass);
+        assert_eq!(outcome("staging directory"), Outcome::Fail);
+        assert!(report
+            .get("selinux")
+            .unwrap()
+            .explanation
+            .ends_with("by u:r:untrusted_app:s0:c512,c768"));
+
+        let blocked = report.get("blocked thread").unwrap();
+        assert_eq!(blocked.outcome, Outcome::Pass);
+        assert_eq!(
+            blocked.explanation,
+            "1235 (worker) blocked at 0x7f3a1c0e57fa, in /usr/lib/libc.so.6"
+        );
+
+        // The original code can't be read from the fixture.
+        assert_eq!(outcome("memory"), Outcome::Pass);
+        assert_eq!(outcome("payloads"), Outcome::Fail);
+        assert!(!report.passed());
+
+        // The thread the options select is the one diagnosed.
+        let report = diagnose_in(
+            &fixture.fs(),
+            1234,
+            &Options::new().thread(ThreadSelector::Name("victim".into())),
+        );
+        assert_eq!(
+            report.get("blocked thread").unwrap().explanation,
+            "InstructionPointerNotFound"
+        );
+        assert_eq!(report.get("payloads").unwrap().outcome, Outcome::Skip);
+    }
+}
@@ -1,4 +1,8 @@
-use std::io::Error as IoError;
+use std::{io::Error as IoError, path::PathBuf};
+
+use tiny_asm::AsmError;
+
+use crate::{os::VirtAddr, proc::Arch};
 
 /// The errors may occurr.
 #[derive(Debug)]
@@ -14,21 +18,115 @@ pub enum Error {
     /// `/proc/<id>/syscall` is missing or was improperly parsed, or none of the process thread was blocked when the intruduction
     /// was attempted.
     InstructionPointerNotFound,
-    /// It occurs when the target process architecture is not supported.
-    UnsupportedArch,
+    /// It occurs when no thread of the target process matches the [`crate::ThreadSelector`], e.g. there's no thread with
+    /// the given identifier.
+    ThreadNotFound,
+    /// It occurs when the signal the targeted thread is nudged with is neither caught nor ignored by the target process,
+    /// e.g. it would be terminated.
+    UnsafeSignal(u8),
+    /// It occurs when the signal the targeted thread is nudged with is ignored by the target process, either explicitly
+    /// or by default: the kernel discards it, so it can't interrupt the syscall.
+    IgnoredSignal(u8),
+    /// It occurs when the signal the targeted thread is nudged with is caught by the target process, but its handler is
+    /// not declared to be installed with `SA_RESTART` (see [`crate::Options::nudge_sa_restart`]): the interrupted syscall
+    /// could fail with an `EINTR` the target process never asked for.
+    UnrestartedSignal(u8),
+    /// It occurs when another intruduction into the target process is in progress, e.g. it holds the lock file.
+    IntruductionInProgress,
+    /// It occurs when the code at `addr`, where the first payload would be written, is already a first payload, e.g. a
+    /// previous intruduction is waiting for the targeted thread to leave its syscall.
+    PendingIntruduction(VirtAddr),
+    /// It occurs when the first payload can't be written where a selected thread would execute it: its instruction pointer
+    /// `addr` is in `region`, which none of the ways the memory is accessed through can write (e.g. the kernel was booted
+    /// with `proc_mem.force_override=never` and `ptrace` is not enabled), and no return address was found on its stack.
+    UnwritableCode { addr: VirtAddr, region: String },
+    /// It occurs when the selected thread is blocked in the vDSO at the given address, whose code is never written, and
+    /// no return address was found on its stack - it's only looked for on `x86` and `x86-64`.
+    BlockedInVdso(VirtAddr),
+    /// It occurs when the memory of the target process at `addr` couldn't be accessed through any way it was `tried`
+    /// with, e.g. `/proc/<id>/mem` or `process_vm_writev`, which failed as reported.
+    MemoryInaccessible {
+        addr: VirtAddr,
+        tried: Vec<(&'static str, IoError)>,
+    },
+    /// It occurs when what was written to `path` at `offset` reads back differently, e.g. the second payload file or the
+    /// target process memory was altered concurrently. The original code of the target process is restored.
+    WriteMismatch { path: PathBuf, offset: u64 },
+    /// It occurs when the target process architecture couldn't be determined, e.g. `/proc/<id>/auxv` is not readable.
+    UnknownArch,
+    /// It occurs when the target process architecture is not supported. `found` is the detected architecture.
+    UnsupportedArch { found: Arch },
+    /// It occurs when an address doesn't fit the target process word size, e.g. a symbol
+    /// located above 4 GiB while targeting a 32 bit process.
+    AddressOutOfRange(VirtAddr),
+    /// It occurs when the second payload is too large to be mapped by the first payload, e.g. a library path of several megabytes.
+    PayloadTooLarge(usize),
+    /// It occurs when a payload couldn't be assembled, e.g. a label offset doesn't fit its instruction.
+    Asm(AsmError),
+    /// It occurs when the seccomp filters of the target process deny `syscall`, which the payloads need whichever
+    /// alternative they are generated with, e.g. the process would be killed. The filters are only known if they have been
+    /// supplied through [`crate::Options::seccomp_filter`], but strict mode denies every syscall.
+    SyscallDenied { syscall: &'static str },
+    /// It occurs when a seccomp filter couldn't be parsed, e.g. the kernel wouldn't install it.
+    InvalidSeccompFilter,
     /// It occurs when the target process is not running - e.g. `/proc/<id>` doesn't exist.
     ProcessNotRunning,
-    /// It occurs when the intruducer process lacks of sufficient priviliges. This typically depends on `/proc/sys/kernel/yama/ptrace_scope`
-    /// value on Linux.
-    InsufficientPriviliges,
+    /// It occurs when the intruducer process lacks of sufficient privileges to access the target process,
+    /// as `reason` details.
+    InsufficientPrivileges { reason: Denial },
+    /// It occurs when SELinux denies the `context` domain the `perms` on `path`, e.g. the target process couldn't map
+    /// the second payload file, or the intruducer process couldn't label it with [`crate::Options::selinux_context`].
+    SelinuxDenied {
+        context: String,
+        path: PathBuf,
+        perms: Vec<&'static str>,
+    },
     #[cfg(target_os = "android")]
     LibraryPathNeeded,
     /// It occurs when a I/O error occurred.
     Io(IoError),
 }
 
+/// The reasons the intruducer process may be denied access to the target process.
+///
+/// They mirror the checks the kernel performs when `/proc/<id>/mem` is opened - see
+/// [`ptrace(2)`](https://man7.org/linux/man-pages/man2/ptrace.2.html) - where `CAP_SYS_PTRACE` only counts if it's held
+/// in the user namespace of the target process, or in the initial one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Denial {
+    /// It occurs when `/proc/sys/kernel/yama/ptrace_scope` is `3`, so no process can be accessed.
+    AttachDisabled,
+    /// It occurs when `/proc/sys/kernel/yama/ptrace_scope` is `2` and the intruducer process lacks `CAP_SYS_PTRACE`.
+    AdminOnly,
+    /// It occurs when `/proc/sys/kernel/yama/ptrace_scope` is `1`, the target process is not a descendant of the intruducer process,
+    /// hasn't declared it as its tracer (`PR_SET_PTRACER`), and the intruducer process lacks `CAP_SYS_PTRACE`.
+    NotDescendant,
+    /// It occurs when the real, effective and saved set user (or group) identifiers of the target process don't all match the
+    /// filesystem one of the intruducer process, which lacks `CAP_SYS_PTRACE`.
+    CredentialsMismatch,
+    /// It occurs when the target process is not dumpable, e.g. it changed its credentials, and the intruducer process
+    /// lacks `CAP_SYS_PTRACE`.
+    NotDumpable,
+    /// It occurs when the target process is an Android application, whose native library directory requires the superuser.
+    NotSuperuser,
+    /// It occurs when the second payload file couldn't be given to the owner of the target process, which requires `CAP_CHOWN`.
+    Chown,
+}
+
 impl From<IoError> for Error {
     fn from(err: IoError) -> Self {
         Error::Io(err)
     }
 }
+
+impl From<AsmError> for Error {
+    fn from(err: AsmError) -> Self {
+        Error::Asm(err)
+    }
+}
+
+// The errors can be sent across threads, e.g. boxed into `Box<dyn std::error::Error + Send + Sync>`.
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Error>();
+};
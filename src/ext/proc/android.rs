@@ -3,7 +3,7 @@ use std::{
     path::PathBuf,
 };
 
-use crate::proc::{Proc, ProcClass};
+use crate::proc::{Machine, Proc};
 
 use super::ProcExt;
 
@@ -25,18 +25,15 @@ impl ProcAndroidExt for Proc {
     fn get_app_lib_dir(&self) -> Option<PathBuf> {
         let package_name = self.get_app_name()?;
 
-        let arch_name = match self.class()? {
-            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
-            ProcClass::ThirtyTwo => "i386",
-            #[cfg(target_arch = "x86_64")]
-            ProcClass::SixtyFour => "x86_64",
-            #[cfg(any(target_arch = "arm", target_arch = "aarch64"))]
-            ProcClass::ThirtyTwo => "arm",
-            #[cfg(target_arch = "aarch64")]
-            ProcClass::SixtyFour => "arm64",
+        let arch_name = match self.arch()?.machine {
+            Machine::X86 => "i386",
+            Machine::X86_64 => "x86_64",
+            Machine::Arm => "arm",
+            Machine::Aarch64 => "arm64",
+            Machine::Other(_) => return None,
         };
 
-        std::fs::read_dir("/data/app")
+        std::fs::read_dir(self.fs.resolve("/data/app"))
             .ok()?
             .filter_map(|entry| entry.ok())
             .find_map(|entry| {
@@ -49,12 +46,13 @@ impl ProcAndroidExt for Proc {
             })
     }
 
+    #[allow(clippy::lines_filter_map_ok)]
     fn get_app_name(&self) -> Option<String> {
         use crate::os::Uid;
 
         let (uid, _) = self.owner().ok()?;
 
-        BufReader::new(std::fs::File::open("/data/system/packages.list").ok()?)
+        BufReader::new(std::fs::File::open(self.fs.resolve("/data/system/packages.list")).ok()?)
             .lines()
             .filter_map(|line| line.ok())
             .find_map(|line| {
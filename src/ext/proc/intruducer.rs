@@ -1,8 +1,11 @@
 use crate::{
     constants::DLOPEN_SYM_NAMES,
-    os::VirtAddr,
-    proc::{Proc, ProcSym},
-    Error,
+    os::PtraceScope,
+    proc::{
+        Proc, ProcFs, ProcId, ProcStatus, ProcSym, ProcSyscall, Thread, ThreadSelector,
+        CAP_SYS_PTRACE,
+    },
+    Denial, Error,
 };
 
 use super::ProcExt;
@@ -14,15 +17,24 @@ pub(crate) trait ProcIntruducerExt {
     /// Returns [`Error`] if it was not found.
     fn find_dlopen(&self) -> Result<ProcSym, Error>;
 
-    /// Retrieves the instruction pointer of this process, looking into other threads until is found.
+    /// Finds the blocked threads of this process `selector` selects, ordered by preference, along with their stack and
+    /// instruction pointers.
     ///
-    /// Returns [`Error`] if it was not found.
-    fn find_ip(&self) -> Result<VirtAddr, Error>;
+    /// Returns [`Error`] if no thread matches or none of the matching ones is blocked.
+    fn find_candidates(
+        &self,
+        selector: &ThreadSelector,
+    ) -> Result<Vec<(Thread, ProcSyscall)>, Error>;
+
+    /// Determines whether the host process may access the memory of this process, the way the kernel does.
+    ///
+    /// Returns [`Error::InsufficientPrivileges`] if it may not.
+    fn check_access(&self) -> Result<(), Error>;
 }
 
 impl ProcIntruducerExt for Proc {
     fn find_dlopen(&self) -> Result<ProcSym, Error> {
-        let dlopen_lib_name = get_dlopen_lib_name();
+        let dlopen_lib_name = get_dlopen_lib_name(&self.fs);
 
         let dlopen_lib = self
             .find_lib_by_name(&dlopen_lib_name)
@@ -33,26 +45,92 @@ impl ProcIntruducerExt for Proc {
             .ok_or_else(|| Error::SymbolNotFound(DLOPEN_SYM_NAMES.to_vec()))
     }
 
-    fn find_ip(&self) -> Result<VirtAddr, Error> {
-        self.ip()
-            .or_else(|| {
-                self.task()
-                    .unwrap()
-                    .filter_map(|dir| dir.ok())
-                    .find_map(|dir| Proc(dir.path()).ip())
-            })
-            .ok_or(Error::InstructionPointerNotFound)
+    fn find_candidates(
+        &self,
+        selector: &ThreadSelector,
+    ) -> Result<Vec<(Thread, ProcSyscall)>, Error> {
+        selector.candidates(self.threads()?)
+    }
+
+    fn check_access(&self) -> Result<(), Error> {
+        let current = self.fs.current();
+        let (tracer, tracee) = (current.status()?, self.status()?);
+        let denied = |reason| Err(Error::InsufficientPrivileges { reason });
+
+        if tracer.tgid == tracee.tgid {
+            return Ok(());
+        }
+
+        // The namespaces are assumed to be the same if they can't be told.
+        let shares_user_ns = |proc: &Proc| match (current.user_ns(), proc.user_ns()) {
+            (Ok(ns), Ok(other_ns)) => ns == other_ns,
+            _ => true,
+        };
+        // The initial user namespace is an ancestor of any other.
+        let capable = tracer.has_cap(CAP_SYS_PTRACE)
+            && (shares_user_ns(self) || self.fs.proc(1).is_some_and(|init| shares_user_ns(&init)));
+
+        let scope = self.fs.ptrace_scope();
+        match scope {
+            PtraceScope::None => return denied(Denial::AttachDisabled),
+            PtraceScope::Admin if !capable => return denied(Denial::AdminOnly),
+            _ => (),
+        }
+
+        let (fsuid, fsgid) = (tracer.uids[3], tracer.gids[3]);
+        let same_credentials = tracee.uids[..3].iter().all(|uid| *uid == fsuid)
+            && tracee.gids[..3].iter().all(|gid| *gid == fsgid);
+        if !capable && !same_credentials {
+            return denied(Denial::CredentialsMismatch);
+        }
+
+        // The directory of a process which is not dumpable is owned by the superuser, rather than by its effective user.
+        if !capable && self.owner()?.0 != tracee.uids[1] {
+            return denied(Denial::NotDumpable);
+        }
+
+        match scope {
+            PtraceScope::Restricted
+                if !capable && !descends_from(&self.fs, &tracee, tracer.tgid) =>
+            {
+                // The target process may have declared the host process as its tracer, which can only be told by trying.
+                self.mem()
+                    .map(drop)
+                    .or_else(|_| denied(Denial::NotDescendant))
+            }
+            _ => Ok(()),
+        }
     }
 }
 
+/// Determines whether the process `status` belongs to descends from the one identified by `ancestor`.
+fn descends_from(fs: &ProcFs, status: &ProcStatus, ancestor: ProcId) -> bool {
+    let mut ppid = status.ppid;
+
+    // Bounded, in case the parents form a cycle while being looked up.
+    for _ in 0..ProcId::from(u16::MAX) {
+        if ppid == ancestor {
+            return true;
+        }
+
+        match fs.proc(ppid).map(|parent| parent.status()) {
+            Some(Ok(parent)) if ppid > 1 => ppid = parent.ppid,
+            _ => return false,
+        }
+    }
+
+    false
+}
+
 use std::{
     io::{BufRead, BufReader},
     path::PathBuf,
 };
 
 #[cfg(target_os = "linux")]
-fn get_dlopen_lib_name() -> String {
-    BufReader::new(Proc::current().maps().unwrap())
+#[allow(clippy::lines_filter_map_ok)]
+fn get_dlopen_lib_name(fs: &ProcFs) -> String {
+    BufReader::new(fs.current().maps().unwrap())
         .lines()
         .filter_map(|line| line.ok())
         .find_map(|line| {
@@ -69,6 +147,180 @@ fn get_dlopen_lib_name() -> String {
 }
 
 #[cfg(target_os = "android")]
-fn get_dlopen_lib_name() -> String {
+fn get_dlopen_lib_name(_: &ProcFs) -> String {
     "libdl.so".to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::{symlink, MetadataExt};
+
+    use crate::{
+        proc::{
+            fixture::{process, Fixture},
+            ProcId, ThreadSelector, CAP_SYS_PTRACE,
+        },
+        Denial, Error,
+    };
+
+    use super::ProcIntruducerExt;
+
+    /// Builds the status of a process whose identifiers are all `uid`.
+    fn status(pid: ProcId, ppid: ProcId, uid: u32, cap_eff: u64) -> String {
+        format!(
+            "Name:\tproc\nTgid:\t{pid}\nPid:\t{pid}\nPPid:\t{ppid}\nUid:\t{uid}\t{uid}\t{uid}\t{uid}\n\
+             Gid:\t0\t0\t0\t0\nSigBlk:\t0\nSigIgn:\t0\nSigCgt:\t0\nCapEff:\t{cap_eff:016x}\n"
+        )
+    }
+
+    /// Mimics the intruducer process `100` which has `cap_eff` and whose identifiers are `uid`, and the target process
+    /// `1234` - a grandchild of the process `ancestor` - whose identifiers are the ones its directory is owned by.
+    fn processes(uid: Option<u32>, cap_eff: u64, ancestor: ProcId) -> (Fixture, u32) {
+        let fixture = process();
+        let owner = fixture.0.join("proc/1234").metadata().unwrap().uid();
+
+        fixture
+            .write(
+                "/proc/self/status",
+                status(100, 1, uid.unwrap_or(owner), cap_eff),
+            )
+            .write("/proc/1234/status", status(1234, 987, owner, 0))
+            .write("/proc/987/status", status(987, ancestor, owner, 0));
+        (fixture, owner)
+    }
+
+    fn check_access(fixture: &Fixture) -> Result<(), Denial> {
+        match fixture.fs().proc(1234).unwrap().check_access() {
+            Ok(()) => Ok(()),
+            Err(Error::InsufficientPrivileges { reason }) => Err(reason),
+            Err(err) => panic!("{err:?}"),
+        }
+    }
+
+    fn scope(fixture: &Fixture, scope: u8) {
+        fixture.write("/proc/sys/kernel/yama/ptrace_scope", format!("{scope}\n"));
+    }
+
+    const CAPABLE: u64 = 1 << CAP_SYS_PTRACE;
+
+    #[test]
+    fn checks_credentials() {
+        let (fixture, _) = processes(None, 0, 1);
+        assert_eq!(check_access(&fixture), Ok(()));
+
+        let (fixture, owner) = processes(Some(u32::MAX), 0, 1);
+        assert_eq!(check_access(&fixture), Err(Denial::CredentialsMismatch));
+
+        let (fixture, _) = processes(Some(u32::MAX), CAPABLE, 1);
+        assert_eq!(check_access(&fixture), Ok(()));
+
+        // The target process changed its identifiers, e.g. it's not dumpable anymore.
+        fixture
+            .write("/proc/self/status", status(100, 1, owner + 1, 0))
+            .write("/proc/1234/status", status(1234, 987, owner + 1, 0));
+        assert_eq!(check_access(&fixture), Err(Denial::NotDumpable));
+    }
+
+    #[test]
+    fn checks_ancestry_if_the_scope_is_restricted() {
+        let (fixture, owner) = processes(None, 0, 100);
+        scope(&fixture, 1);
+        assert_eq!(check_access(&fixture), Ok(()));
+
+        // The intruducer process is identified by its thread group, whichever of its threads checks.
+        let other_thread = status(100, 1, owner, 0).replace("\nPid:\t100", "\nPid:\t101");
+        fixture.write("/proc/self/status", other_thread);
+        assert_eq!(check_access(&fixture), Ok(()));
+
+        let (fixture, _) = processes(None, 0, 1);
+        scope(&fixture, 1);
+        assert_eq!(check_access(&fixture), Err(Denial::NotDescendant));
+
+        // The target process declared the intruducer process as its tracer.
+        fixture.write("/proc/1234/mem", []);
+        assert_eq!(check_access(&fixture), Ok(()));
+
+        let (fixture, _) = processes(None, CAPABLE, 1);
+        scope(&fixture, 1);
+        assert_eq!(check_access(&fixture), Ok(()));
+    }
+
+    #[test]
+    fn checks_capabilities_if_the_scope_is_admin_only() {
+        let (fixture, _) = processes(None, 0, 100);
+        scope(&fixture, 2);
+        assert_eq!(check_access(&fixture), Err(Denial::AdminOnly));
+
+        let (fixture, _) = processes(None, CAPABLE, 100);
+        scope(&fixture, 2);
+        assert_eq!(check_access(&fixture), Ok(()));
+
+        scope(&fixture, 3);
+        assert_eq!(check_access(&fixture), Err(Denial::AttachDisabled));
+    }
+
+    #[test]
+    fn ignores_capabilities_of_other_user_namespaces() {
+        let (fixture, _) = processes(Some(u32::MAX), CAPABLE, 1);
+        for (path, ns) in [
+            ("proc/self", "user:[4026532500]"),
+            ("proc/1234", "user:[4026532601]"),
+            ("proc/1", "user:[4026531837]"),
+        ] {
+            std::fs::create_dir_all(fixture.0.join(path).join("ns")).unwrap();
+            symlink(ns, fixture.0.join(path).join("ns/user")).unwrap();
+        }
+        assert_eq!(check_access(&fixture), Err(Denial::CredentialsMismatch));
+
+        // The intruducer process is in the initial user namespace.
+        std::fs::remove_file(fixture.0.join("proc/self/ns/user")).unwrap();
+        symlink("user:[4026531837]", fixture.0.join("proc/self/ns/user")).unwrap();
+        assert_eq!(check_access(&fixture), Ok(()));
+    }
+
+    #[test]
+    fn finds_a_blocked_thread() {
+        let fixture = process();
+        let find = |selector| {
+            let proc = fixture.fs().proc(1234).unwrap();
+            proc.find_candidates(&selector)
+                .map(|mut candidates| candidates.remove(0))
+        };
+
+        let (thread, blocked) = find(ThreadSelector::Any).unwrap();
+        assert_eq!(thread.tid, 1235);
+        assert_eq!(blocked.sp, 0x7f3a1b7fddc8);
+        assert_eq!(blocked.ip, 0x7f3a1c0e57fa);
+
+        assert!(matches!(
+            find(ThreadSelector::Name("victim".into())),
+            Err(Error::InstructionPointerNotFound)
+        ));
+
+        fixture.write("/proc/1234/task/1235/syscall", "running\n");
+        assert!(find(ThreadSelector::Any).is_err());
+    }
+
+    #[test]
+    fn finds_dlopen_in_the_library_of_the_host() {
+        let fixture = process();
+        let maps = std::fs::read(fixture.0.join("proc/1234/maps")).unwrap();
+        fixture.write("/proc/self/maps", maps);
+
+        let dlopen = fixture.fs().proc(1234).unwrap().find_dlopen().unwrap();
+        assert_eq!(dlopen.addr, 0x7f3a1c08a6b0);
+    }
+
+    #[test]
+    fn finds_no_blocked_thread_without_tasks() {
+        let fixture = Fixture::new();
+        fixture.write("/proc/1/syscall", "running\n");
+
+        assert!(fixture
+            .fs()
+            .proc(1)
+            .unwrap()
+            .find_candidates(&ThreadSelector::Any)
+            .is_err());
+    }
+}
@@ -1,19 +1,11 @@
 use std::{
-    io::{BufRead, BufReader, Read},
+    io::{BufRead, BufReader, Error as IoError, ErrorKind, Read},
+    os::unix::prelude::FileExt,
     path::PathBuf,
 };
 
 use goblin::elf::Elf;
 
-#[cfg(any(target_arch = "i386", target_arch = "x86_64"))]
-use goblin::elf::header::EM_386;
-#[cfg(target_arch = "aarch64")]
-use goblin::elf::header::EM_AARCH64;
-#[cfg(any(target_arch = "arm", target_arch = "aarch64"))]
-use goblin::elf::header::EM_ARM;
-#[cfg(target_arch = "x86_64")]
-use goblin::elf::header::EM_X86_64;
-
 #[cfg(target_os = "android")]
 mod android;
 mod intruducer;
@@ -23,8 +15,11 @@ pub(crate) use android::ProcAndroidExt;
 pub(crate) use intruducer::ProcIntruducerExt;
 
 use crate::{
-    os::VirtAddr,
-    proc::{Proc, ProcClass, ProcLib},
+    os::{pidfd_send_signal, VirtAddr},
+    proc::{
+        Arch, Auxv, Machine, Proc, ProcLib, ProcMap, ProcSyscall, Thread, AT_HWCAP, AT_HWCAP2,
+        AT_PLATFORM, AT_SYSINFO_EHDR,
+    },
 };
 
 /// A extension trait for [`Proc`].
@@ -34,21 +29,40 @@ pub(crate) trait ProcExt {
     /// Returns [`None`] if no library with the current name was found.
     fn find_lib_by_name(&self, lib_name: &str) -> Option<ProcLib>;
 
-    /// Determines the class of the current process - if it's running in 32 bit or 64 bit mode.
+    /// Finds the memory region of the current process which contains `addr`.
+    ///
+    /// Returns [`None`] if no region contains it, or `/proc/<id>/maps` couldn't be read.
+    fn find_map(&self, addr: VirtAddr) -> Option<ProcMap>;
+
+    /// Determines the architecture of the current process, e.g. its instruction set and whether it's running in 32 bit or 64 bit mode.
+    ///
+    /// Returns [`None`] if the auxiliary vector or the ELF header of the process couldn't be read.
+    fn arch(&self) -> Option<Arch>;
+
+    /// Describes the current task, e.g. its name, state and where it's blocked.
     ///
-    /// Returns [`None`] if the process instruction set is not supported.
-    fn class(&self) -> Option<ProcClass>;
+    /// Returns [`IoError`] if `/proc/<id>/stat` couldn't be read or is malformed.
+    fn thread(&self) -> Result<Thread, IoError>;
 
-    /// Gets the instruction pointer of the current process.
+    /// Describes every thread of the current process, starting from the current task. The threads which exit while being
+    /// described are left out.
     ///
-    /// Returns [`None`] if the process is not blocked.
-    fn ip(&self) -> Option<VirtAddr>;
+    /// Returns [`IoError`] if the current task couldn't be described or `/proc/<id>/task` couldn't be read.
+    fn threads(&self) -> Result<Vec<Thread>, IoError>;
 
-    /// Determines wheter the current process is priviliged, e.g. if its owner is the superuser.
-    fn privileged(&self) -> bool;
+    /// Gets when the current process started, in clock ticks since the system boot, which tells it apart from a later
+    /// process with the same identifier.
+    ///
+    /// Returns [`IoError`] if `/proc/<id>/stat` couldn't be read or is malformed.
+    fn start_time(&self) -> Result<u64, IoError>;
+
+    /// Determines whether the process the current [`Proc`] was opened for with [`crate::proc::ProcFs::open`] is still
+    /// running, e.g. it's not a zombie whose identifier may be reused, nor a later process with the same one.
+    fn still_running(&self) -> bool;
 }
 
 impl ProcExt for Proc {
+    #[allow(clippy::lines_filter_map_ok)] // An unreadable line is skipped, rather than ending the lookup.
     fn find_lib_by_name(&self, name: &str) -> Option<ProcLib> {
         BufReader::new(self.maps().ok()?)
             .lines()
@@ -60,43 +74,161 @@ impl ProcExt for Proc {
                     let base_add = line.split_once('-')?.0;
                     let base_add = VirtAddr::from_str_radix(base_add, 16).ok()?;
 
-                    Some(ProcLib::new(base_add, path))
+                    Some(ProcLib::new(base_add, self.fs.resolve(path)))
                 } else {
                     None
                 }
             })
     }
 
-    // TODO: it should not check if the instruction set is supported.
-    fn class(&self) -> Option<ProcClass> {
-        let mut header = [0_u8; 0x40];
-        self.exe().ok()?.read_exact(&mut header).ok()?;
-
-        let header = Elf::parse_header(&header).ok()?;
-
-        match header.e_machine {
-            #[cfg(any(target_arch = "arm", target_arch = "aarch64"))]
-            EM_ARM => Some(ProcClass::ThirtyTwo),
-            #[cfg(target_arch = "aarch64")]
-            EM_AARCH64 => Some(ProcClass::SixtyFour),
-            #[cfg(any(target_arch = "i386", target_arch = "x86_64"))]
-            EM_386 => Some(ProcClass::ThirtyTwo),
-            #[cfg(target_arch = "x86_64")]
-            EM_X86_64 => Some(ProcClass::SixtyFour),
-            _ => None,
-        }
+    fn find_map(&self, addr: VirtAddr) -> Option<ProcMap> {
+        BufReader::new(self.maps().ok()?)
+            .lines()
+            .map_while(Result::ok)
+            .filter_map(|line| ProcMap::parse(&line))
+            .find(|map| map.range.contains(&addr))
     }
 
-    fn ip(&self) -> Option<VirtAddr> {
-        let mut content = String::new();
-        self.syscall().ok()?.read_to_string(&mut content).ok()?;
+    fn arch(&self) -> Option<Arch> {
+        let mut buf = Vec::new();
+        self.auxv().ok()?.read_to_end(&mut buf).ok()?;
+
+        let auxv = Auxv::parse(&buf)?;
+
+        // The vDSO always matches the mode the process is running in, while the executable
+        // could be a loader of a different class; fall back to the latter if it can't be read.
+        let machine = auxv
+            .get(AT_SYSINFO_EHDR)
+            .and_then(|addr| {
+                let mut header = [0_u8; 0x40];
+                self.mem().ok()?.read_exact_at(&mut header, addr).ok()?;
+                Some(Elf::parse_header(&header).ok()?.e_machine)
+            })
+            .or_else(|| {
+                let mut header = [0_u8; 0x40];
+                self.exe().ok()?.read_exact(&mut header).ok()?;
+                Some(Elf::parse_header(&header).ok()?.e_machine)
+            })
+            .map(Machine::from_e_machine)?
+            .with_bits(auxv.bits);
+
+        let platform = auxv.get(AT_PLATFORM).and_then(|addr| {
+            let mut buf = [0_u8; 0x20];
+            self.mem().ok()?.read_at(&mut buf, addr).ok()?;
+
+            let len = buf.iter().position(|byte| *byte == 0)?;
+            String::from_utf8(buf[..len].to_vec()).ok()
+        });
+
+        Some(Arch {
+            machine,
+            bits: auxv.bits,
+            platform,
+            hwcap: auxv.get(AT_HWCAP),
+            hwcap2: auxv.get(AT_HWCAP2),
+        })
+    }
+
+    fn thread(&self) -> Result<Thread, IoError> {
+        let blocked = self.syscall().ok().and_then(|mut file| {
+            let mut content = String::new();
+            file.read_to_string(&mut content).ok()?;
+            ProcSyscall::parse(&content)
+        });
+
+        Thread::parse(&self.stat()?, blocked)
+            .ok_or_else(|| IoError::new(ErrorKind::InvalidData, "malformed stat"))
+    }
+
+    fn threads(&self) -> Result<Vec<Thread>, IoError> {
+        let current = self.thread()?;
+
+        let others = self
+            .tasks()?
+            .filter_map(|dir| self.task(dir.ok()?.path()).thread().ok())
+            .filter(|thread| thread.tid != current.tid)
+            .collect::<Vec<_>>();
+
+        Ok([vec![current], others].concat())
+    }
+
+    fn start_time(&self) -> Result<u64, IoError> {
+        // The fields which follow the name, which may contain spaces, start from the third one (the state).
+        self.stat()?
+            .rsplit_once(") ")
+            .and_then(|(_, fields)| fields.split_whitespace().nth(19)?.parse().ok())
+            .ok_or_else(|| IoError::new(ErrorKind::InvalidData, "malformed stat"))
+    }
+
+    fn still_running(&self) -> bool {
+        let Some(handles) = &self.handles else {
+            return self.path.exists();
+        };
+
+        handles
+            .pidfd
+            .as_ref()
+            .is_none_or(|pidfd| pidfd_send_signal(pidfd, 0).is_ok())
+            && self.start_time().ok() == Some(handles.start_time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::proc::{fixture::process, Machine};
+
+    use super::ProcExt;
+
+    #[test]
+    fn finds_libraries_by_name() {
+        let fixture = process();
+        let proc = fixture.fs().proc(1234).unwrap();
+
+        let lib = proc.find_lib_by_name("libc.so.6").unwrap();
+        assert_eq!(lib.base_addr, 0x7f3a1c000000);
+        assert_eq!(lib.path, fixture.0.join("usr/lib/libc.so.6"));
+        assert_eq!(lib.find_sym_addr(["dlopen"]).unwrap().addr, 0x7f3a1c08a6b0);
+        assert!(lib.find_sym_addr(["__libc_dlopen_mode"]).is_none());
+
+        assert!(proc.find_lib_by_name("libdl.so.2").is_none());
+    }
 
-        let ip = content.trim().rsplit_once('x')?.1;
+    #[test]
+    fn determines_the_architecture_from_the_executable() {
+        let fixture = process();
+        // There's no vDSO to read.
+        let arch = fixture.fs().proc(1234).unwrap().arch().unwrap();
 
-        VirtAddr::from_str_radix(ip, 16).ok()
+        assert_eq!(arch.machine, Machine::X86_64);
+        assert_eq!(arch.bits, 64);
+        assert_eq!(arch.hwcap, Some(0x178bfbff));
+        assert!(arch.platform.is_none());
     }
 
-    fn privileged(&self) -> bool {
-        self.owner().unwrap().0 == 0
+    #[test]
+    fn describes_the_threads() {
+        let fixture = process();
+        let proc = fixture.fs().proc(1234).unwrap();
+        assert!(!proc.thread().unwrap().is_blocked());
+
+        let threads = proc.threads().unwrap();
+        assert_eq!(
+            threads
+                .iter()
+                .map(|thread| (thread.tid, thread.name.as_str(), thread.state))
+                .collect::<Vec<_>>(),
+            [(1234, "victim", 'R'), (1235, "worker", 'S')]
+        );
+        assert_eq!(threads[1].blocked.as_ref().unwrap().ip, 0x7f3a1c0e57fa);
+
+        assert_eq!(proc.start_time().unwrap(), 4242);
+        assert!(proc
+            .task(fixture.0.join("proc/1234/task/1235"))
+            .start_time()
+            .is_err());
+
+        // The directory of the tasks is unreadable.
+        std::fs::remove_dir_all(fixture.0.join("proc/1234/task")).unwrap();
+        assert!(proc.threads().is_err());
     }
 }
@@ -1,27 +1,38 @@
 //! A Rust crate to load a shared library into a target process without using `ptrace`.
 //! This is a portable rewrite of [dlinject](https://github.com/DavidBuchanan314/dlinject).
 
-use std::fs::File;
-use std::io::Write;
-use std::ops::Not;
-use std::os::unix::prelude::FileExt;
-use std::path::PathBuf;
+use std::fs::OpenOptions;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 mod constants;
+mod diagnose;
 mod error;
 mod ext;
+mod mem;
+mod nudge;
+mod options;
 mod os;
 mod payloads;
 mod proc;
+mod seccomp;
+mod target;
 
 use constants::TMP_DIR;
-pub use error::Error;
+pub use diagnose::{diagnose, diagnose_with, Check, Outcome, Report};
+pub use error::{Denial, Error};
+pub use options::Options;
 use proc::ProcId;
+pub use proc::{Arch, Machine, Thread, ThreadSelector};
+pub use seccomp::SeccompFilter;
 
 use ext::ProcExt;
 use ext::ProcIntruducerExt;
-use os::{chown, PtraceScope};
-use proc::Proc;
+use mem::Memory;
+use os::{chown, get_context, set_context, Lock, VirtAddr};
+use proc::{Proc, ProcFs};
 
 /// Loads a shared library into the target process.
 ///
@@ -38,7 +49,8 @@ use proc::Proc;
 /// ```no_run
 /// use intruducer::intruduce;
 ///
-/// intruduce(1234, "/path/to/lib.so")?;
+/// intruduce(1234, "/path/to/lib.so".into())?;
+/// # Ok::<(), intruducer::Error>(())
 /// ```
 ///
 /// A system library can be provided throught a name.
@@ -46,21 +58,36 @@ use proc::Proc;
 /// ```no_run
 /// use intruducer::intruduce;
 ///
-/// intruduce(1234, "libsystem.so")?;
+/// intruduce(1234, "libsystem.so".into())?;
+/// # Ok::<(), intruducer::Error>(())
 /// ```
 pub fn intruduce(id: ProcId, lib_path: PathBuf) -> Result<(), Error> {
-    let proc = Proc::new(id).ok_or(Error::ProcessNotRunning)?;
-
-    match PtraceScope::current() {
-        // We must be superuser if the target is superuser
-        PtraceScope::All => proc.privileged().not() || Proc::current().privileged(),
-        // We must be superuser
-        PtraceScope::Restricted | PtraceScope::Admin => Proc::current().privileged(),
-        // There's nothing we can do about this
-        PtraceScope::None => false,
-    }
-    .then_some(())
-    .ok_or(Error::InsufficientPriviliges)?;
+    intruduce_with(id, lib_path, &Options::default()).map(drop)
+}
+
+/// A struct that represents how an intruduction went, which [`intruduce_with`] returns.
+///
+/// The library is loaded once the targeted thread returns from the syscall it's blocked in.
+#[derive(Clone, Debug)]
+pub struct Intruduction {
+    /// The thread the first payload was written for.
+    pub thread: Thread,
+    /// Whether the thread left its syscall once nudged, as [`Options::nudge`] asks. It's [`None`] if it was not nudged.
+    pub nudged: Option<bool>,
+}
+
+/// Loads a shared library into the target process, as [`intruduce`] does, but as `options` say.
+///
+/// Returns [`Error`] if the operation fails.
+pub fn intruduce_with(
+    id: ProcId,
+    lib_path: PathBuf,
+    options: &Options,
+) -> Result<Intruduction, Error> {
+    let fs = ProcFs::host();
+    let proc = fs.open(id).ok_or(Error::ProcessNotRunning)?;
+
+    proc.check_access()?;
 
     #[cfg(target_os = "android")]
     // Adjusts the library and second payload file path in case the target process is an Android application.
@@ -69,10 +96,11 @@ pub fn intruduce(id: ProcId, lib_path: PathBuf) -> Result<(), Error> {
 
         if let Some(lib_dir) = proc.get_app_lib_dir() {
             // We must be superuser if the target process is an Android application
-            Proc::current()
-                .privileged()
-                .then(|| ())
-                .ok_or(Error::InsufficientPriviliges)?;
+            if fs.current().status()?.uids[1] != 0 {
+                return Err(Error::InsufficientPrivileges {
+                    reason: Denial::NotSuperuser,
+                });
+            }
 
             let lib_path = lib_path
                 .canonicalize()
@@ -87,49 +115,264 @@ pub fn intruduce(id: ProcId, lib_path: PathBuf) -> Result<(), Error> {
                 std::fs::copy(&lib_path, &new_lib_path)?;
             }
 
-            return _intruduce(proc, new_lib_path, lib_dir);
+            return _intruduce(proc, new_lib_path, lib_dir, options);
         }
     }
 
-    _intruduce(proc, lib_path, PathBuf::from(TMP_DIR))
+    _intruduce(proc, lib_path, PathBuf::from(TMP_DIR), options)
 }
 
-fn _intruduce(proc: Proc, lib_path: PathBuf, second_payload_path: PathBuf) -> Result<(), Error> {
+/// Describes every thread of the target process, e.g. to choose the [`ThreadSelector`] [`Options::thread`] takes.
+///
+/// Returns [`Error`] if the process is not running or its threads couldn't be read.
+pub fn threads(id: ProcId) -> Result<Vec<Thread>, Error> {
+    let proc = ProcFs::host().open(id).ok_or(Error::ProcessNotRunning)?;
+
+    Ok(proc.threads()?)
+}
+
+fn _intruduce(
+    proc: Proc,
+    lib_path: PathBuf,
+    second_payload_path: PathBuf,
+    options: &Options,
+) -> Result<Intruduction, Error> {
     let lib_path = lib_path.canonicalize().unwrap_or(lib_path);
     let lib_path = lib_path.to_str().unwrap();
-    let second_payload_path = second_payload_path.join("payload.bin");
+    // Unique per intruduction, so that concurrent ones don't overwrite each other's second payload.
+    let second_payload_path = second_payload_path.join(format!(
+        "payload-{}-{}.bin",
+        process::id(),
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos())
+    ));
     let second_payload_path = second_payload_path.to_str().unwrap();
 
+    // Held until the first payload is written, so that a concurrent intruduction doesn't back it up as the original code.
+    // The start time tells the target process apart from a later one with the same identifier.
+    let _lock = Lock::try_new(PathBuf::from(TMP_DIR).join(format!(
+        "intruducer-{}-{}.lock",
+        proc.status()?.tgid,
+        proc.start_time()?
+    )))?
+    .ok_or(Error::IntruductionInProgress)?;
+
     let dlopen = proc.find_dlopen()?;
 
     #[cfg(debug_assertions)]
     println!("dlopen address: 0x{:x}", dlopen.addr);
 
-    let class = proc.class().ok_or(Error::UnsupportedArch)?;
+    let arch = proc.arch().ok_or(Error::UnknownArch)?;
 
-    let first_payload = payloads::gen_first(&class, second_payload_path);
+    #[cfg(debug_assertions)]
+    println!("architecture: {:?}", arch);
+
+    let class = arch.class()?;
+
+    let syscalls = seccomp::pick(&class, proc.status()?.seccomp, &options.seccomp_filters)?;
+
+    // The first payload length doesn't depend on the second payload size, which is not known yet.
+    let first_payload_len = payloads::gen_first(&class, syscalls, second_payload_path, 0)?
+        .code()
+        .len();
 
-    let mem = proc.mem()?;
+    let mem = Memory::open(&proc)?;
 
-    let mut original_code = vec![0; first_payload.len()];
+    let mut original_code = vec![0; first_payload_len];
+
+    let target::Target {
+        thread,
+        blocked,
+        addr: ip,
+    } = target::find(&proc, &mem, &options.thread, &arch, first_payload_len)?;
+    let task = proc.task(proc.path.join("task").join(thread.tid.to_string()));
+
+    if let Some(signal) = options.nudge {
+        nudge::check(&task, signal, options.nudge_sa_restart)?;
+    }
 
-    let ip = proc.find_ip()?;
+    // A compat task can't be resumed at an address that doesn't fit its registers.
+    blocked
+        .fits(arch.bits)
+        .then_some(())
+        .ok_or(Error::AddressOutOfRange(blocked.ip))?;
 
     #[cfg(debug_assertions)]
-    println!("instruction pointer: 0x{:x}", ip);
+    println!("instruction pointer: 0x{:x}", blocked.ip);
 
     mem.read_exact_at(&mut original_code, ip)?;
-    mem.write_all_at(&first_payload, ip)?;
 
-    let second_payload = payloads::gen_second(&class, &original_code, ip, lib_path, &dlopen);
+    // Backing up a first payload would make the second payload restore it.
+    if payloads::is_first(&class, &original_code)? {
+        return Err(Error::PendingIntruduction(ip));
+    }
+
+    // Generated before altering the target, since it fails if an address doesn't fit the target word size.
+    let second_payload =
+        payloads::gen_second(&class, syscalls, &original_code, ip, lib_path, &dlopen)?;
+
+    let first_payload = payloads::gen_first(
+        &class,
+        syscalls,
+        second_payload_path,
+        payloads::second_payload_size(second_payload.code())?,
+    )?;
+
+    // E.g. it exited, so that the second payload file would be given to the owner of a later process.
+    if !proc.still_running() {
+        return Err(Error::ProcessNotRunning);
+    }
+
+    #[cfg(debug_assertions)]
+    println!(
+        "first payload address: 0x{:x}\nfirst payload:\n{}\nsecond payload:\n{}",
+        ip,
+        first_payload.disassemble(),
+        second_payload.disassemble()
+    );
 
-    let mut file = File::create(second_payload_path)?;
+    // Staged before altering the target process, so that it's not left mapping a file it can't.
+    let result = stage(
+        &proc,
+        second_payload_path,
+        second_payload.code(),
+        lib_path,
+        options,
+    )
+    .and_then(|()| install(&proc, &mem, ip, first_payload.code(), &original_code));
+
+    if result.is_err() {
+        let _ = std::fs::remove_file(second_payload_path);
+    }
+    result?;
+
+    // From now on, the thread may be executing the payloads, so the original code is not restored anymore.
+    let nudged = options
+        .nudge
+        .map(|signal| nudge::nudge(&proc, &task, &mem, &blocked, ip, &original_code, signal))
+        .transpose()?;
+
+    Ok(Intruduction { thread, nudged })
+}
+
+/// Writes the first payload at `addr` of the target process, and reads it back. If either fails, `original_code` is
+/// written back, so that the target process is not left executing a partially written payload.
+///
+/// Returns [`Error::WriteMismatch`] if the first payload doesn't read back as written.
+fn install(
+    proc: &Proc,
+    mem: &Memory,
+    addr: VirtAddr,
+    first_payload: &[u8],
+    original_code: &[u8],
+) -> Result<(), Error> {
+    // Held for the whole sequence, e.g. so that the process is attached once.
+    mem.holding(|| {
+        let result = mem
+            .write_all_at(first_payload, addr)
+            .and_then(|()| {
+                let mut written = vec![0; first_payload.len()];
+                mem.read_exact_at(&mut written, addr).map(|()| written)
+            })
+            .and_then(|written| match written == first_payload {
+                true => Ok(()),
+                false => Err(Error::WriteMismatch {
+                    path: proc.path.join("mem"),
+                    offset: addr,
+                }),
+            });
+
+        if result.is_err() {
+            // The error which caused the rollback is more telling than a failed rollback.
+            let _ = mem.write_all_at(original_code, addr);
+        }
+
+        result
+    })
+}
+
+/// Writes the second payload file, which is given to the owner of the target process. If SELinux is enabled, it and
+/// the library are labelled with the context `options` say, if any.
+///
+/// Returns [`Error::SelinuxDenied`] if SELinux is enforcing and the target process is not allowed to map them.
+fn stage(
+    proc: &Proc,
+    second_payload_path: &str,
+    second_payload: &[u8],
+    lib_path: &str,
+    options: &Options,
+) -> Result<(), Error> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(second_payload_path)?;
 
     let (uid, gid) = proc.owner()?;
 
-    chown(second_payload_path, uid, gid).ok_or(Error::InsufficientPriviliges)?;
+    chown(second_payload_path, uid, gid).ok_or(Error::InsufficientPrivileges {
+        reason: Denial::Chown,
+    })?;
+
+    file.write_all(second_payload)?;
 
-    file.write_all(&second_payload)?;
+    if std::fs::read(second_payload_path)? != second_payload {
+        return Err(Error::WriteMismatch {
+            path: second_payload_path.into(),
+            offset: 0,
+        });
+    }
+
+    let Some(selinux) = proc.fs.selinux() else {
+        return Ok(());
+    };
+
+    // A library name is looked up by the dynamic linker, so only a library path is labelled.
+    let paths: Vec<_> = [Path::new(second_payload_path), Path::new(lib_path)]
+        .into_iter()
+        .filter(|path| path.exists())
+        .collect();
+
+    if let Some(context) = &options.selinux_context {
+        for path in &paths {
+            set_context(path, context).map_err(|err| match err.kind() {
+                ErrorKind::PermissionDenied => Error::SelinuxDenied {
+                    context: proc.fs.current().security_context().unwrap_or_default(),
+                    path: path.to_path_buf(),
+                    perms: vec!["relabelfrom", "relabelto"],
+                },
+                _ => err.into(),
+            })?;
+        }
+    }
+
+    if !selinux.enforcing() {
+        return Ok(());
+    }
+
+    let context = proc.security_context()?;
+
+    for path in paths {
+        let Ok(file_context) = get_context(path) else {
+            continue;
+        };
+
+        // If the policy can't be queried, the label is trusted.
+        if let Ok(perms) = selinux.denied(
+            &context,
+            &file_context,
+            "file",
+            &["read", "open", "map", "execute"],
+        ) {
+            if !perms.is_empty() {
+                return Err(Error::SelinuxDenied {
+                    context,
+                    path: path.to_path_buf(),
+                    perms,
+                });
+            }
+        }
+    }
 
     Ok(())
 }
@@ -0,0 +1,401 @@
+use std::{
+    fs::File,
+    io::{Error as IoError, ErrorKind},
+    os::unix::prelude::FileExt,
+};
+
+mod process_vm;
+#[cfg(feature = "ptrace")]
+mod ptrace;
+
+use process_vm::ProcessVm;
+#[cfg(feature = "ptrace")]
+use ptrace::Ptrace;
+
+use crate::{
+    ext::ProcExt,
+    os::{VirtAddr, ESRCH},
+    proc::{Proc, ProcMap},
+    Error,
+};
+
+/// A trait that represents a way to read and write the memory of the target process.
+pub(crate) trait MemoryAccess {
+    /// Gets the name of the way, which errors report.
+    fn name(&self) -> &'static str;
+
+    /// Reads into `buf` from `addr`, returning how many bytes were read.
+    fn read_at(&self, buf: &mut [u8], addr: VirtAddr) -> Result<usize, IoError>;
+
+    /// Writes `buf` at `addr`, returning how many bytes were written.
+    fn write_at(&self, buf: &[u8], addr: VirtAddr) -> Result<usize, IoError>;
+
+    /// Determines whether the region `map` describes can be written, e.g. even if it's not writable.
+    fn can_write(&self, map: &ProcMap) -> bool;
+
+    /// Keeps what the next accesses set up until [`MemoryAccess::release`], rather than setting it up for each of them,
+    /// e.g. an attached process. It's a no-op for the ways which set up nothing.
+    fn hold(&self) {}
+
+    /// Releases what has been set up since [`MemoryAccess::hold`].
+    fn release(&self) {}
+
+    /// Reads exactly `buf.len()` bytes from `addr`.
+    ///
+    /// Returns [`IoError`] if fewer bytes could be read, e.g. part of them is not mapped.
+    fn read_exact_at(&self, mut buf: &mut [u8], mut addr: VirtAddr) -> Result<(), IoError> {
+        while !buf.is_empty() {
+            match self.read_at(buf, addr)? {
+                0 => return Err(ErrorKind::UnexpectedEof.into()),
+                read => {
+                    buf = &mut buf[read..];
+                    addr += read as VirtAddr;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the whole `buf` at `addr`.
+    ///
+    /// Returns [`IoError`] if fewer bytes could be written, e.g. part of them is not writable.
+    fn write_all_at(&self, mut buf: &[u8], mut addr: VirtAddr) -> Result<(), IoError> {
+        while !buf.is_empty() {
+            match self.write_at(buf, addr)? {
+                0 => return Err(ErrorKind::WriteZero.into()),
+                written => {
+                    buf = &buf[written..];
+                    addr += written as VirtAddr;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A struct that represents the memory of a process accessed through `/proc/<id>/mem`, which is the preferred way, since
+/// it can usually write code which is not writable.
+struct ProcMem {
+    file: File,
+
+    /// Whether the regions which are not writable can be written, e.g. the kernel was not booted with
+    /// `proc_mem.force_override=never`.
+    force: bool,
+}
+
+impl MemoryAccess for ProcMem {
+    fn name(&self) -> &'static str {
+        "/proc/<id>/mem"
+    }
+
+    fn read_at(&self, buf: &mut [u8], addr: VirtAddr) -> Result<usize, IoError> {
+        self.file.read_at(buf, addr)
+    }
+
+    fn write_at(&self, buf: &[u8], addr: VirtAddr) -> Result<usize, IoError> {
+        self.file.write_at(buf, addr)
+    }
+
+    fn can_write(&self, map: &ProcMap) -> bool {
+        self.force || map.writable
+    }
+}
+
+/// A struct that represents a way which references the process by its identifier, e.g. [`ProcessVm`], so that it's only
+/// accessed while [`ProcExt::still_running`]: the process is checked both before and after every access, so that an
+/// access which reached a later process with the same identifier is not reported as successful.
+struct Pinned<A> {
+    access: A,
+
+    /// The process, which is checked through the handles it was opened with.
+    proc: Proc,
+}
+
+impl<A: MemoryAccess> Pinned<A> {
+    /// Creates a new [`Pinned`] which accesses the memory of `proc` through `access`.
+    fn new(access: A, proc: &Proc) -> Self {
+        Pinned {
+            access,
+            proc: proc.task(proc.path.clone()),
+        }
+    }
+
+    /// Performs `op` through the way, between the checks.
+    ///
+    /// Returns [`IoError`] with `ESRCH` if the process is not running before or after it.
+    fn checked<T>(&self, op: impl FnOnce(&A) -> Result<T, IoError>) -> Result<T, IoError> {
+        let check = || match self.proc.still_running() {
+            true => Ok(()),
+            false => Err(IoError::from_raw_os_error(ESRCH)),
+        };
+
+        check()?;
+        let value = op(&self.access)?;
+        check().map(|()| value)
+    }
+}
+
+impl<A: MemoryAccess> MemoryAccess for Pinned<A> {
+    fn name(&self) -> &'static str {
+        self.access.name()
+    }
+
+    fn read_at(&self, buf: &mut [u8], addr: VirtAddr) -> Result<usize, IoError> {
+        self.checked(|access| access.read_at(buf, addr))
+    }
+
+    fn write_at(&self, buf: &[u8], addr: VirtAddr) -> Result<usize, IoError> {
+        self.checked(|access| access.write_at(buf, addr))
+    }
+
+    fn can_write(&self, map: &ProcMap) -> bool {
+        self.access.can_write(map)
+    }
+
+    fn hold(&self) {
+        self.access.hold()
+    }
+
+    fn release(&self) {
+        self.access.release()
+    }
+}
+
+/// A struct that represents the memory of the target process, which is accessed through the first of the available
+/// ways which succeeds: `/proc/<id>/mem`, then `process_vm_readv` and `process_vm_writev`, then `ptrace` if the `ptrace`
+/// feature is enabled.
+pub(crate) struct Memory {
+    /// The available ways, in the order they're tried.
+    accesses: Vec<Box<dyn MemoryAccess>>,
+
+    /// The ways which are not available, and why.
+    unavailable: Vec<(&'static str, IoError)>,
+}
+
+impl Memory {
+    /// Sets up the ways the memory of the process `proc` references can be accessed through. Only `/proc/<id>/mem` is
+    /// available for a process of a mimicked filesystem, since the others take its identifier.
+    ///
+    /// Returns [`Error::MemoryInaccessible`] if none is.
+    pub(crate) fn open(proc: &Proc) -> Result<Self, Error> {
+        let mut memory = Memory {
+            accesses: Vec::new(),
+            unavailable: Vec::new(),
+        };
+
+        match proc.mem() {
+            Ok(file) => memory.accesses.push(Box::new(ProcMem {
+                file,
+                force: proc.fs.proc_mem_force(),
+            })),
+            Err(err) => memory.unavailable.push(("/proc/<id>/mem", err)),
+        }
+
+        if proc.fs.is_host() {
+            match proc.status() {
+                Ok(status) => {
+                    memory
+                        .accesses
+                        .push(Box::new(Pinned::new(ProcessVm::new(status.tgid), proc)));
+                    #[cfg(feature = "ptrace")]
+                    memory
+                        .accesses
+                        .push(Box::new(Pinned::new(Ptrace::new(status.tgid), proc)));
+                }
+                Err(err) => {
+                    #[cfg(feature = "ptrace")]
+                    memory
+                        .unavailable
+                        .push((ptrace::NAME, IoError::new(err.kind(), err.to_string())));
+                    memory.unavailable.push((process_vm::NAME, err));
+                }
+            }
+        }
+
+        match memory.accesses.is_empty() {
+            true => Err(memory.error(0, Vec::new())),
+            false => Ok(memory),
+        }
+    }
+
+    /// Gets the names of the available ways.
+    pub(crate) fn names(&self) -> Vec<&'static str> {
+        self.accesses.iter().map(|access| access.name()).collect()
+    }
+
+    /// Determines whether any of the available ways can write the region `map` describes, without writing it.
+    pub(crate) fn can_write(&self, map: &ProcMap) -> bool {
+        self.accesses.iter().any(|access| access.can_write(map))
+    }
+
+    /// Reads exactly `buf.len()` bytes from `addr`, as [`MemoryAccess::read_exact_at`] does.
+    ///
+    /// Returns [`Error::MemoryInaccessible`] if every way fails.
+    pub(crate) fn read_exact_at(&self, buf: &mut [u8], addr: VirtAddr) -> Result<(), Error> {
+        self.first(addr, |access| access.read_exact_at(buf, addr))
+    }
+
+    /// Writes the whole `buf` at `addr`, as [`MemoryAccess::write_all_at`] does.
+    ///
+    /// Returns [`Error::MemoryInaccessible`] if every way fails.
+    pub(crate) fn write_all_at(&self, buf: &[u8], addr: VirtAddr) -> Result<(), Error> {
+        self.first(addr, |access| access.write_all_at(buf, addr))
+    }
+
+    /// Performs `op`, while the ways keep what the accesses it makes set up, e.g. `ptrace` attaches the process once
+    /// rather than for each of them.
+    pub(crate) fn holding<T>(&self, op: impl FnOnce() -> T) -> T {
+        self.accesses.iter().for_each(|access| access.hold());
+        let value = op();
+        self.accesses.iter().for_each(|access| access.release());
+        value
+    }
+
+    /// Performs `op` through every way, in order, until one succeeds.
+    fn first<T>(
+        &self,
+        addr: VirtAddr,
+        mut op: impl FnMut(&dyn MemoryAccess) -> Result<T, IoError>,
+    ) -> Result<T, Error> {
+        let mut failed = Vec::new();
+
+        for access in &self.accesses {
+            match op(access.as_ref()) {
+                Ok(value) => return Ok(value),
+                Err(err) => failed.push((access.name(), err)),
+            }
+        }
+
+        Err(self.error(addr, failed))
+    }
+
+    /// Builds the error which reports that accessing `addr` `failed` through the available ways, along with why the
+    /// others are not available.
+    fn error(&self, addr: VirtAddr, failed: Vec<(&'static str, IoError)>) -> Error {
+        let unavailable = self
+            .unavailable
+            .iter()
+            .map(|(name, err)| (*name, IoError::new(err.kind(), err.to_string())));
+
+        Error::MemoryInaccessible {
+            addr,
+            tried: unavailable.chain(failed).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{File, OpenOptions},
+        io::Error as IoError,
+        process::Command,
+    };
+
+    use crate::{
+        os::{VirtAddr, ESRCH},
+        proc::{fixture::process, ProcFs, ProcMap},
+        Error,
+    };
+
+    use super::{Memory, MemoryAccess, Pinned, ProcMem};
+
+    /// A way which can't access anything.
+    struct Denied;
+
+    impl MemoryAccess for Denied {
+        fn name(&self) -> &'static str {
+            "denied"
+        }
+
+        fn read_at(&self, _: &mut [u8], _: VirtAddr) -> Result<usize, IoError> {
+            Err(IoError::from_raw_os_error(1))
+        }
+
+        fn write_at(&self, _: &[u8], _: VirtAddr) -> Result<usize, IoError> {
+            Err(IoError::from_raw_os_error(1))
+        }
+
+        fn can_write(&self, _: &ProcMap) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn falls_back_through_the_ways() {
+        let fixture = process();
+        let proc = fixture.fs().proc(1234).unwrap();
+
+        // There's no `/proc/1234/mem`, and the others are not available for a mimicked process.
+        match Memory::open(&proc) {
+            Err(Error::MemoryInaccessible { tried, .. }) => {
+                assert_eq!(tried.len(), 1);
+                assert_eq!(tried[0].0, "/proc/<id>/mem");
+            }
+            _ => panic!("the memory is accessible"),
+        }
+
+        fixture.write("/proc/1234/mem", [0_u8; 8]);
+        let mut memory = Memory::open(&proc).unwrap();
+        assert_eq!(memory.names(), ["/proc/<id>/mem"]);
+
+        let code =
+            ProcMap::parse("7f3a1c028000-7f3a1c1bd000 r-xp 00028000 fd:01 2051 /usr/lib/libc.so.6")
+                .unwrap();
+        let stack =
+            ProcMap::parse("7ffd4f392000-7ffd4f3b3000 rw-p 00000000 00:00 0 [stack]").unwrap();
+        assert!(memory.can_write(&code));
+        fixture.write(
+            "/proc/cmdline",
+            "root=/dev/vda1 proc_mem.force_override=never\n",
+        );
+        let never = Memory::open(&proc).unwrap();
+        assert!(!never.can_write(&code) && never.can_write(&stack));
+
+        memory.accesses.insert(0, Box::new(Denied));
+        memory.write_all_at(&[1, 2], 4).unwrap();
+        let mut buf = [0; 4];
+        memory.read_exact_at(&mut buf, 2).unwrap();
+        assert_eq!(buf, [0, 0, 1, 2]);
+
+        memory.accesses[1] = Box::new(ProcMem {
+            file: OpenOptions::new()
+                .read(true)
+                .open(fixture.0.join("proc/1234/mem"))
+                .unwrap(),
+            force: true,
+        });
+        match memory.write_all_at(&[3], 0) {
+            Err(Error::MemoryInaccessible { addr, tried }) => {
+                assert_eq!(addr, 0);
+                assert_eq!(
+                    tried.iter().map(|(name, _)| *name).collect::<Vec<_>>(),
+                    ["denied", "/proc/<id>/mem"]
+                );
+            }
+            _ => panic!("the memory is writable"),
+        }
+    }
+
+    #[test]
+    fn accesses_a_pinned_process_only_while_it_is_running() {
+        let mut child = Command::new("sleep").arg("10").spawn().unwrap();
+        let proc = ProcFs::host().open(child.id()).unwrap();
+        let zero = ProcMem {
+            file: File::open("/dev/zero").unwrap(),
+            force: true,
+        };
+        let memory = Pinned::new(zero, &proc);
+
+        let mut buf = [1; 4];
+        memory.read_exact_at(&mut buf, 0).unwrap();
+        assert_eq!(buf, [0; 4]);
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+        let err = memory.read_exact_at(&mut buf, 0).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(ESRCH));
+    }
+}
@@ -0,0 +1,126 @@
+use std::{
+    io::Error as IoError,
+    os::raw::{c_int, c_ulong, c_void},
+};
+
+use crate::{os::VirtAddr, proc::ProcMap};
+
+use super::MemoryAccess;
+
+/// The name [`ProcessVm`] is reported with.
+pub(super) const NAME: &str = "process_vm_readv/process_vm_writev";
+
+/// A struct that represents the memory of a process accessed through `process_vm_readv` and `process_vm_writev`, which
+/// honour its protections, e.g. they can't write code unless it's mapped writable.
+///
+/// The process is referenced by its identifier, so it's only accessed while [`crate::ext::ProcExt::still_running`],
+/// which [`super::Memory`] checks around every access.
+pub(super) struct ProcessVm {
+    pid: u32,
+}
+
+impl ProcessVm {
+    /// Creates a new [`ProcessVm`] which accesses the memory of the process identified by `pid`.
+    pub(super) fn new(pid: u32) -> Self {
+        ProcessVm { pid }
+    }
+}
+
+/// `struct iovec`.
+#[repr(C)]
+struct IoVec {
+    base: *mut c_void,
+    len: usize,
+}
+
+#[link(name = "c")]
+extern "C" {
+    fn process_vm_readv(
+        pid: c_int,
+        local_iov: *const IoVec,
+        liovcnt: c_ulong,
+        remote_iov: *const IoVec,
+        riovcnt: c_ulong,
+        flags: c_ulong,
+    ) -> isize;
+
+    fn process_vm_writev(
+        pid: c_int,
+        local_iov: *const IoVec,
+        liovcnt: c_ulong,
+        remote_iov: *const IoVec,
+        riovcnt: c_ulong,
+        flags: c_ulong,
+    ) -> isize;
+}
+
+impl MemoryAccess for ProcessVm {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn read_at(&self, buf: &mut [u8], addr: VirtAddr) -> Result<usize, IoError> {
+        let local = IoVec {
+            base: buf.as_mut_ptr().cast(),
+            len: buf.len(),
+        };
+        let remote = remote(addr, buf.len())?;
+
+        match unsafe { process_vm_readv(self.pid as c_int, &local, 1, &remote, 1, 0) } {
+            -1 => Err(IoError::last_os_error()),
+            read => Ok(read as usize),
+        }
+    }
+
+    fn write_at(&self, buf: &[u8], addr: VirtAddr) -> Result<usize, IoError> {
+        let local = IoVec {
+            base: buf.as_ptr() as *mut c_void,
+            len: buf.len(),
+        };
+        let remote = remote(addr, buf.len())?;
+
+        match unsafe { process_vm_writev(self.pid as c_int, &local, 1, &remote, 1, 0) } {
+            -1 => Err(IoError::last_os_error()),
+            written => Ok(written as usize),
+        }
+    }
+
+    fn can_write(&self, map: &ProcMap) -> bool {
+        map.writable
+    }
+}
+
+/// Describes the `len` bytes at `addr` of the remote process.
+///
+/// Returns [`IoError`] if `addr` doesn't fit the host word size.
+fn remote(addr: VirtAddr, len: usize) -> Result<IoVec, IoError> {
+    Ok(IoVec {
+        base: usize::try_from(addr).map_err(|_| IoError::from_raw_os_error(14))? as *mut c_void,
+        len,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{mem::MemoryAccess, os::VirtAddr};
+
+    use super::ProcessVm;
+
+    #[test]
+    fn accesses_the_memory_of_a_process() {
+        let mut data = *b"intruducer";
+        let addr = data.as_mut_ptr() as VirtAddr;
+        let memory = ProcessVm::new(std::process::id());
+
+        let mut buf = [0; 4];
+        memory.read_exact_at(&mut buf, addr).unwrap();
+        assert_eq!(&buf, b"intr");
+
+        memory.write_all_at(b"IN", addr).unwrap();
+        assert_eq!(&std::hint::black_box(data)[..4], b"INtr");
+
+        // The code is not writable.
+        let code = accesses_the_memory_of_a_process as fn() as usize as VirtAddr;
+        assert!(memory.write_all_at(&[0xcc], code).is_err());
+    }
+}
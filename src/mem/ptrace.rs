@@ -0,0 +1,289 @@
+use std::{
+    cell::Cell,
+    io::Error as IoError,
+    mem::size_of,
+    os::raw::{c_int, c_long, c_ulong},
+};
+
+use crate::{os::VirtAddr, proc::ProcMap};
+
+use super::MemoryAccess;
+
+/// The name [`Ptrace`] is reported with.
+pub(super) const NAME: &str = "ptrace";
+
+const PTRACE_PEEKDATA: c_long = 2;
+const PTRACE_POKEDATA: c_long = 5;
+const PTRACE_DETACH: c_long = 17;
+const PTRACE_SEIZE: c_long = 0x4206;
+const PTRACE_INTERRUPT: c_long = 0x4207;
+
+/// `__WALL`, which waits for any child, whether it's a thread or not.
+const WALL: c_int = 0x4000_0000;
+
+#[cfg(any(target_arch = "x86", target_arch = "arm"))]
+const SYS_PTRACE: c_long = 26;
+#[cfg(target_arch = "x86_64")]
+const SYS_PTRACE: c_long = 101;
+#[cfg(target_arch = "aarch64")]
+const SYS_PTRACE: c_long = 117;
+
+/// The size of a word `PTRACE_PEEKDATA` and `PTRACE_POKEDATA` transfer, which is the host one.
+const WORD: usize = size_of::<c_ulong>();
+
+#[link(name = "c")]
+extern "C" {
+    fn syscall(number: c_long, ...) -> c_long;
+    fn waitpid(pid: c_int, status: *mut c_int, options: c_int) -> c_int;
+}
+
+/// A struct that represents the memory of a process accessed through `PTRACE_PEEKDATA` and `PTRACE_POKEDATA`, which can
+/// write code which is not writable, even if `/proc/<id>/mem` can't. The process main thread is attached, and stopped,
+/// for every read and write - or once for all of them while held - but not the other threads: they keep running, so
+/// they may see a write while it's being done word by word.
+///
+/// The process is referenced by its identifier, so it's only accessed while [`crate::ext::ProcExt::still_running`],
+/// which [`super::Memory`] checks around every access.
+pub(super) struct Ptrace {
+    pid: u32,
+
+    /// Whether the process is left attached after an access, until [`MemoryAccess::release`].
+    holding: Cell<bool>,
+
+    /// Whether the process is attached.
+    attached: Cell<bool>,
+}
+
+impl Ptrace {
+    /// Creates a new [`Ptrace`] which accesses the memory of the process identified by `pid`.
+    pub(super) fn new(pid: u32) -> Self {
+        Ptrace {
+            pid,
+            holding: Cell::new(false),
+            attached: Cell::new(false),
+        }
+    }
+
+    /// Performs `op` while the process main thread is attached and stopped, which `PTRACE_PEEKDATA` and `PTRACE_POKEDATA`
+    /// require of the thread they're given. The other threads are left running. A syscall the main thread is blocked in
+    /// is restarted once it's detached, which happens right after `op` unless the process is held.
+    fn attached<T>(&self, op: impl FnOnce() -> Result<T, IoError>) -> Result<T, IoError> {
+        if !self.attached.get() {
+            self.ptrace(PTRACE_SEIZE, 0, 0)?;
+            self.attached.set(true);
+
+            let stopped = self.ptrace(PTRACE_INTERRUPT, 0, 0).and_then(|()| {
+                match unsafe { waitpid(self.pid as c_int, std::ptr::null_mut(), WALL) } {
+                    -1 => Err(IoError::last_os_error()),
+                    _ => Ok(()),
+                }
+            });
+            if let Err(err) = stopped {
+                self.detach();
+                return Err(err);
+            }
+        }
+
+        let result = op();
+
+        if !self.holding.get() {
+            self.detach();
+        }
+
+        result
+    }
+
+    /// Detaches the process, if it's attached.
+    fn detach(&self) {
+        if self.attached.replace(false) {
+            let _ = self.ptrace(PTRACE_DETACH, 0, 0);
+        }
+    }
+
+    /// Reads the word at `addr`, which is aligned.
+    fn peek(&self, addr: VirtAddr) -> Result<[u8; WORD], IoError> {
+        let mut word: c_ulong = 0;
+        // The raw syscall stores the word into `data`, rather than returning it.
+        self.ptrace(PTRACE_PEEKDATA, addr, &mut word as *mut c_ulong as VirtAddr)?;
+        Ok(word.to_ne_bytes())
+    }
+
+    /// Writes `word` at `addr`, which is aligned.
+    fn poke(&self, addr: VirtAddr, word: [u8; WORD]) -> Result<(), IoError> {
+        self.ptrace(
+            PTRACE_POKEDATA,
+            addr,
+            c_ulong::from_ne_bytes(word) as VirtAddr,
+        )
+    }
+
+    fn ptrace(&self, request: c_long, addr: VirtAddr, data: VirtAddr) -> Result<(), IoError> {
+        match unsafe {
+            syscall(
+                SYS_PTRACE,
+                request,
+                self.pid as c_long,
+                addr as c_ulong,
+                data as c_ulong,
+            )
+        } {
+            -1 => Err(IoError::last_os_error()),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl MemoryAccess for Ptrace {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn read_at(&self, buf: &mut [u8], addr: VirtAddr) -> Result<usize, IoError> {
+        self.attached(|| {
+            for (start, range) in words(addr, buf.len()) {
+                let word = self.peek(start)?;
+                buf[range.clone()]
+                    .copy_from_slice(&word[offset(addr, start, &range)..][..range.len()]);
+            }
+
+            Ok(buf.len())
+        })
+    }
+
+    fn write_at(&self, buf: &[u8], addr: VirtAddr) -> Result<usize, IoError> {
+        self.attached(|| {
+            for (start, range) in words(addr, buf.len()) {
+                // A word which is partially written is merged with what it holds.
+                let mut word = match range.len() {
+                    WORD => [0; WORD],
+                    _ => self.peek(start)?,
+                };
+                word[offset(addr, start, &range)..][..range.len()].copy_from_slice(&buf[range]);
+                self.poke(start, word)?;
+            }
+
+            Ok(buf.len())
+        })
+    }
+
+    fn can_write(&self, _: &ProcMap) -> bool {
+        true
+    }
+
+    fn hold(&self) {
+        self.holding.set(true);
+    }
+
+    fn release(&self) {
+        self.holding.set(false);
+        self.detach();
+    }
+}
+
+/// Splits the `len` bytes at `addr` into the aligned words which contain them, along with the range of the bytes each
+/// contains.
+fn words(addr: VirtAddr, len: usize) -> impl Iterator<Item = (VirtAddr, std::ops::Range<usize>)> {
+    let first = addr - addr % WORD as VirtAddr;
+
+    (first..addr + len as VirtAddr)
+        .step_by(WORD)
+        .map(move |start| {
+            let from = start.max(addr) - addr;
+            let to = (start + WORD as VirtAddr).min(addr + len as VirtAddr) - addr;
+            (start, from as usize..to as usize)
+        })
+}
+
+/// Gets the offset, inside the word at `start`, of the first byte of `range`, which is relative to `addr`.
+fn offset(addr: VirtAddr, start: VirtAddr, range: &std::ops::Range<usize>) -> usize {
+    (addr + range.start as VirtAddr - start) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::File,
+        os::unix::prelude::FileExt,
+        process::Command,
+        thread::sleep,
+        time::{Duration, Instant},
+    };
+
+    use crate::{
+        ext::ProcExt,
+        mem::MemoryAccess,
+        proc::{ProcFs, ProcSyscall},
+    };
+
+    use super::{words, Ptrace, WORD};
+
+    #[test]
+    fn splits_into_words() {
+        let word = WORD as u64;
+
+        assert_eq!(
+            words(word + 3, WORD).collect::<Vec<_>>(),
+            [(word, 0..WORD - 3), (2 * word, WORD - 3..WORD)]
+        );
+        assert_eq!(words(word, 2).collect::<Vec<_>>(), [(word, 0..2)]);
+        assert_eq!(words(word, 0).count(), 0);
+    }
+
+    #[test]
+    fn accesses_the_memory_of_a_process() {
+        let mut child = Command::new("sleep").arg("10").spawn().unwrap();
+        let proc = ProcFs::host().proc(child.id()).unwrap();
+        // Wait for it to block.
+        let start = Instant::now();
+        let blocked = loop {
+            if let Some(blocked) = std::fs::read_to_string(proc.path.join("syscall"))
+                .ok()
+                .and_then(|syscall| ProcSyscall::parse(&syscall))
+            {
+                break blocked;
+            }
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "the child didn't block"
+            );
+
+            sleep(Duration::from_millis(5));
+        };
+        assert!(!proc.find_map(blocked.ip).unwrap().writable);
+
+        let mut expected = [0; 13];
+        let mem = File::open(proc.path.join("mem")).unwrap();
+        FileExt::read_exact_at(&mem, &mut expected, blocked.ip - 5).unwrap();
+
+        let memory = Ptrace::new(child.id());
+        let mut code = [0; 13];
+        memory.read_exact_at(&mut code, blocked.ip - 5).unwrap();
+        assert_eq!(code, expected);
+
+        // The code is written back as it is.
+        memory.write_all_at(&code[3..9], blocked.ip - 2).unwrap();
+        memory.read_exact_at(&mut code, blocked.ip - 5).unwrap();
+        assert_eq!(code, expected);
+
+        // The process is attached for every access, unless it's held.
+        let tracer = || {
+            let status = std::fs::read_to_string(proc.path.join("status")).unwrap();
+            status
+                .lines()
+                .find_map(|line| line.strip_prefix("TracerPid:\t")?.parse::<u32>().ok())
+                .unwrap()
+        };
+        assert_eq!(tracer(), 0);
+        memory.hold();
+        memory.write_all_at(&code[3..9], blocked.ip - 2).unwrap();
+        // The tracer is the thread which attached it.
+        assert_ne!(tracer(), 0);
+        memory.read_exact_at(&mut code, blocked.ip - 5).unwrap();
+        assert_eq!(code, expected);
+        memory.release();
+        assert_eq!(tracer(), 0);
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+}
@@ -0,0 +1,159 @@
+use std::{
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    ext::ProcExt,
+    mem::Memory,
+    os::{tgkill, VirtAddr},
+    proc::{Proc, ProcSyscall},
+    Error,
+};
+
+/// How long the nudged thread is given to leave its syscall.
+const TIMEOUT: Duration = Duration::from_millis(500);
+
+/// How often the nudged thread is looked at.
+const INTERVAL: Duration = Duration::from_millis(5);
+
+/// The signals whose default action is to be ignored: `SIGCHLD`, `SIGCONT`, `SIGURG` and `SIGWINCH`.
+const IGNORED_BY_DEFAULT: [u8; 4] = [17, 18, 23, 28];
+
+/// Determines whether `signal` can nudge the thread `task` references, and is harmless to the process it belongs to: it
+/// must be caught, with a handler `sa_restart` declares to be installed with `SA_RESTART`.
+///
+/// Returns [`Error::IgnoredSignal`] if it's ignored, [`Error::UnrestartedSignal`] if it's caught without `sa_restart`,
+/// or [`Error::UnsafeSignal`] otherwise, e.g. it would terminate the process.
+pub(crate) fn check(task: &Proc, signal: u8, sa_restart: bool) -> Result<(), Error> {
+    let status = task.status()?;
+
+    match mask(signal) {
+        Some(bit) if status.sig_ign & bit != 0 => Err(Error::IgnoredSignal(signal)),
+        Some(bit) if status.sig_cgt & bit != 0 => match sa_restart {
+            true => Ok(()),
+            false => Err(Error::UnrestartedSignal(signal)),
+        },
+        Some(_) if IGNORED_BY_DEFAULT.contains(&signal) => Err(Error::IgnoredSignal(signal)),
+        _ => Err(Error::UnsafeSignal(signal)),
+    }
+}
+
+/// Sends `signal`, which has been checked with [`check`], to the thread `task` of `proc` references, which is blocked in a syscall
+/// as `blocked` says, so that it reaches the first payload written at `addr` right away rather than whenever the syscall
+/// completes.
+///
+/// A caught signal interrupts the syscall, unless its handler was installed with `SA_RESTART` and the syscall is
+/// restartable (e.g. `read` or `accept`, but not `epoll_wait` or `nanosleep`): the syscall is then re-entered. A signal
+/// the thread blocks doesn't wake it.
+///
+/// Returns whether the thread left the syscall, e.g. the second payload restored `original_code` or the thread is blocked
+/// somewhere else, or [`Error::ProcessNotRunning`] if `proc` is not running anymore.
+pub(crate) fn nudge(
+    proc: &Proc,
+    task: &Proc,
+    mem: &Memory,
+    blocked: &ProcSyscall,
+    addr: VirtAddr,
+    original_code: &[u8],
+    signal: u8,
+) -> Result<bool, Error> {
+    let status = task.status()?;
+
+    let left = || -> Result<bool, Error> {
+        let mut code = vec![0; original_code.len()];
+        mem.read_exact_at(&mut code, addr)?;
+
+        // A thread which is running may be about to re-enter a restarted syscall.
+        let elsewhere = task
+            .thread()
+            .ok()
+            .and_then(|thread| thread.blocked)
+            .is_some_and(|now| now.nr != blocked.nr || now.ip != blocked.ip);
+
+        Ok(code == original_code || elsewhere)
+    };
+
+    if left()? {
+        return Ok(true);
+    }
+
+    // A blocked signal would be left pending.
+    if mask(signal).is_some_and(|bit| status.sig_blk & bit != 0) {
+        return Ok(false);
+    }
+
+    // The identifiers are signaled, rather than the `pidfd` which can't single out a thread, so they must still be the
+    // target process's.
+    if !proc.still_running() {
+        return Err(Error::ProcessNotRunning);
+    }
+    tgkill(status.tgid, status.pid, signal)?;
+
+    let start = Instant::now();
+    while start.elapsed() < TIMEOUT {
+        if left()? {
+            return Ok(true);
+        }
+        sleep(INTERVAL);
+    }
+
+    Ok(false)
+}
+
+/// Gets the bit which represents `signal` in the signal masks of `/proc/<id>/status`.
+///
+/// Returns [`None`] if it's not a valid signal number.
+fn mask(signal: u8) -> Option<u64> {
+    1_u64.checked_shl(u32::from(signal).checked_sub(1)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        mem::Memory,
+        proc::{fixture::process, ProcSyscall},
+        Error,
+    };
+
+    use super::{check, nudge};
+
+    #[test]
+    fn only_nudges_with_harmless_signals() {
+        let fixture = process();
+        fixture
+            .write(
+                "/proc/1234/task/1235/status",
+                "Name:\tworker\nTgid:\t1234\nPid:\t1235\nPPid:\t987\nUid:\t0\t0\t0\t0\nGid:\t0\t0\t0\t0\n\
+                 SigBlk:\t0000000000000000\nSigIgn:\t0000000000001000\nSigCgt:\t0000000000000200\n\
+                 CapEff:\t0000000000000000\n",
+            )
+            .write("/proc/1234/mem", [0_u8; 16]);
+        let proc = fixture.fs().proc(1234).unwrap();
+        let task = proc.task(fixture.0.join("proc/1234/task/1235"));
+        let mem = Memory::open(&proc).unwrap();
+        let blocked = ProcSyscall::parse("230 0x0 0x0 0x0 0x0 0x0 0x0 0x8 0x4\n").unwrap();
+
+        for signal in [0, 9, 15, 65] {
+            assert!(matches!(
+                check(&task, signal, true),
+                Err(Error::UnsafeSignal(_))
+            ));
+        }
+        // `SIGPIPE` is ignored, and `SIGWINCH` is ignored by default.
+        for signal in [13, 28] {
+            assert!(matches!(
+                check(&task, signal, true),
+                Err(Error::IgnoredSignal(_))
+            ));
+        }
+        assert!(matches!(
+            check(&task, 10, false),
+            Err(Error::UnrestartedSignal(10))
+        ));
+        assert!(check(&task, 10, true).is_ok());
+
+        // The second payload already restored the original code, so no signal is sent.
+        assert!(nudge(&proc, &task, &mem, &blocked, 4, &[0, 0], 10).unwrap());
+    }
+}
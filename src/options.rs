@@ -0,0 +1,69 @@
+use crate::{SeccompFilter, ThreadSelector};
+
+/// A struct that represents how an intruduction is performed, which [`crate::intruduce_with`] takes.
+///
+/// Example:
+/// ```no_run
+/// use intruducer::{intruduce_with, Options, SeccompFilter};
+///
+/// let filter = SeccompFilter::from_bytes(&std::fs::read("/path/to/filter.bpf")?)?;
+///
+/// intruduce_with(1234, "/path/to/lib.so".into(), &Options::new().seccomp_filter(filter))?;
+/// # Ok::<(), intruducer::Error>(())
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    pub(crate) seccomp_filters: Vec<SeccompFilter>,
+    pub(crate) selinux_context: Option<String>,
+    pub(crate) thread: ThreadSelector,
+    pub(crate) nudge: Option<u8>,
+    pub(crate) nudge_sa_restart: bool,
+}
+
+impl Options {
+    /// Creates the default [`Options`], which [`crate::intruduce`] uses.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Supplies one of the seccomp filters the target process has installed, so that the payloads are generated with
+    /// syscalls it allows - e.g. `openat` rather than `open`. Every installed filter should be supplied, since a syscall
+    /// any of them denies is denied.
+    pub fn seccomp_filter(mut self, filter: SeccompFilter) -> Self {
+        self.seccomp_filters.push(filter);
+        self
+    }
+
+    /// Sets the SELinux context the second payload file and the library are labelled with, so that the target process
+    /// can map them - e.g. `u:object_r:apk_data_file:s0` for an Android application, or `system_u:object_r:lib_t:s0`
+    /// for a confined service. By default, they are left as they are.
+    pub fn selinux_context(mut self, context: impl Into<String>) -> Self {
+        self.selinux_context = Some(context.into());
+        self
+    }
+
+    /// Sets which thread of the target process is targeted. By default, it's [`ThreadSelector::Any`].
+    pub fn thread(mut self, selector: ThreadSelector) -> Self {
+        self.thread = selector;
+        self
+    }
+
+    /// Sends `signal` to the targeted thread once the first payload is written, so that it leaves its syscall right away
+    /// rather than whenever the syscall completes, e.g. an idle `accept`. [`crate::Intruduction::nudged`] tells whether it did.
+    ///
+    /// The signal must be caught by the target process with a handler installed with `SA_RESTART`, which
+    /// [`Options::nudge_sa_restart`] declares, otherwise the intruduction is refused. The thread is woken unless the syscall
+    /// is restarted, e.g. `epoll_wait` or `nanosleep` rather than `read` or `accept`.
+    pub fn nudge(mut self, signal: u8) -> Self {
+        self.nudge = Some(signal);
+        self
+    }
+
+    /// Declares that the target process catches the [`Options::nudge`] signal with a handler installed with `SA_RESTART`,
+    /// which can't be told from outside the process, so that a syscall it interrupts is either restarted or one the
+    /// target process expects to fail with `EINTR` anyway.
+    pub fn nudge_sa_restart(mut self) -> Self {
+        self.nudge_sa_restart = true;
+        self
+    }
+}
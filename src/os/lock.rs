@@ -0,0 +1,99 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{Error as IoError, ErrorKind},
+    os::{
+        raw::c_int,
+        unix::prelude::{AsRawFd, MetadataExt},
+    },
+    path::PathBuf,
+};
+
+/// `LOCK_EX`, which takes an exclusive lock.
+const LOCK_EX: c_int = 2;
+
+/// `LOCK_NB`, which makes `flock` fail rather than wait for the lock.
+const LOCK_NB: c_int = 4;
+
+/// A struct that represents an exclusive advisory lock (`flock`) on a file, which is held until it's dropped. The file
+/// is then removed.
+pub(crate) struct Lock {
+    path: PathBuf,
+    // Closing it releases the lock.
+    _file: File,
+}
+
+impl Lock {
+    /// Takes the lock on the file at `path`, which is created if it doesn't exist, without waiting for it.
+    ///
+    /// Returns [`None`] if another [`Lock`] holds it, or [`IoError`] if the file couldn't be created or locked.
+    pub(crate) fn try_new(path: PathBuf) -> Result<Option<Self>, IoError> {
+        loop {
+            let file = OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(&path)?;
+
+            if let Err(err) = flock(&file, LOCK_EX | LOCK_NB) {
+                return match err.kind() {
+                    ErrorKind::WouldBlock => Ok(None),
+                    _ => Err(err),
+                };
+            }
+
+            // The previous holder may have removed the file before it was locked here, so that another one may be
+            // locked by someone else.
+            let locked = file.metadata()?;
+            match std::fs::metadata(&path) {
+                Ok(found) if (found.dev(), found.ino()) == (locked.dev(), locked.ino()) => {
+                    return Ok(Some(Lock { path, _file: file }))
+                }
+                Ok(_) => continue,
+                Err(err) if err.kind() == ErrorKind::NotFound => continue,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        // Removed while it's still locked, see [`Lock::try_new`].
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+// TODO: wait for https://github.com/rust-lang/rust/issues/130994
+fn flock(file: &File, operation: c_int) -> Result<(), IoError> {
+    #[link(name = "c")]
+    extern "C" {
+        fn flock(fd: c_int, operation: c_int) -> c_int;
+    }
+
+    match unsafe { flock(file.as_raw_fd(), operation) } {
+        0 => Ok(()),
+        _ => Err(IoError::last_os_error()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::proc::fixture::Fixture;
+
+    use super::Lock;
+
+    #[test]
+    fn locks_exclusively() {
+        let fixture = Fixture::new();
+        fixture.write("/tmp/.keep", []);
+        let path = fixture.fs().resolve("/tmp/intruducer-1234.lock");
+
+        let lock = Lock::try_new(path.clone()).unwrap().unwrap();
+        assert!(path.exists());
+        assert!(Lock::try_new(path.clone()).unwrap().is_none());
+
+        drop(lock);
+        assert!(!path.exists());
+        assert!(Lock::try_new(path).unwrap().is_some());
+    }
+}
@@ -1,10 +1,14 @@
 mod gid;
+mod lock;
 mod ptrace_scope;
+mod selinux;
 mod uid;
 mod virt_addr;
 
 pub(crate) use gid::Gid;
+pub(crate) use lock::Lock;
 pub(crate) use ptrace_scope::PtraceScope;
+pub(crate) use selinux::{get_context, set_context, Selinux};
 pub(crate) use uid::Uid;
 pub(crate) use virt_addr::VirtAddr;
 
@@ -28,3 +32,73 @@ pub(crate) fn chown(path: &str, uid: Uid, gid: Gid) -> Option<()> {
         None
     }
 }
+
+// TODO: wait for https://github.com/rust-lang/rust/issues/88989
+pub(crate) fn tgkill(tgid: u32, tid: u32, signal: u8) -> Result<(), std::io::Error> {
+    use std::os::raw::c_int;
+
+    #[link(name = "c")]
+    extern "C" {
+        fn tgkill(tgid: c_int, tid: c_int, sig: c_int) -> c_int;
+    }
+
+    match unsafe { tgkill(tgid as c_int, tid as c_int, signal.into()) } {
+        0 => Ok(()),
+        _ => Err(std::io::Error::last_os_error()),
+    }
+}
+
+/// Returns the size of a memory page, which is the same for every process of the system.
+pub(crate) fn page_size() -> u64 {
+    use std::os::raw::c_int;
+
+    #[link(name = "c")]
+    extern "C" {
+        fn getpagesize() -> c_int;
+    }
+
+    unsafe { getpagesize() as u64 }
+}
+
+/// `ESRCH`, which the syscalls which take a process fail with if it's not running.
+pub(crate) const ESRCH: i32 = 3;
+
+/// `pidfd_open`, whose number is the same on every architecture.
+const SYS_PIDFD_OPEN: std::os::raw::c_long = 434;
+
+/// `pidfd_send_signal`, whose number is the same on every architecture.
+const SYS_PIDFD_SEND_SIGNAL: std::os::raw::c_long = 424;
+
+#[link(name = "c")]
+extern "C" {
+    fn syscall(number: std::os::raw::c_long, ...) -> std::os::raw::c_long;
+}
+
+// TODO: wait for https://github.com/rust-lang/rust/issues/82971
+/// Opens a file descriptor which refers to the process identified by `pid`, even once its identifier is reused.
+pub(crate) fn pidfd_open(pid: u32) -> Result<std::fs::File, std::io::Error> {
+    use std::os::{raw::c_int, unix::prelude::FromRawFd};
+
+    match unsafe { syscall(SYS_PIDFD_OPEN, pid as c_int, 0 as c_int) } {
+        -1 => Err(std::io::Error::last_os_error()),
+        fd => Ok(unsafe { std::fs::File::from_raw_fd(fd as c_int) }),
+    }
+}
+
+/// Sends `signal` to the process `pidfd` refers to: `0` only checks that it's still running.
+pub(crate) fn pidfd_send_signal(pidfd: &std::fs::File, signal: u8) -> Result<(), std::io::Error> {
+    use std::os::{raw::c_int, unix::prelude::AsRawFd};
+
+    match unsafe {
+        syscall(
+            SYS_PIDFD_SEND_SIGNAL,
+            pidfd.as_raw_fd(),
+            c_int::from(signal),
+            std::ptr::null::<()>(),
+            0 as c_int,
+        )
+    } {
+        0 => Ok(()),
+        _ => Err(std::io::Error::last_os_error()),
+    }
+}
@@ -1,4 +1,4 @@
-use std::fs::read_to_string;
+use std::{fs::read_to_string, path::Path};
 
 /// A enum that represents the content of `/proc/sys/kernel/yama/ptrace_scope`.
 ///
@@ -20,10 +20,10 @@ pub(crate) enum PtraceScope {
 }
 
 impl PtraceScope {
-    /// Gets the content of `/proc/sys/kernel/yama/ptrace_scope`, which determines whether we can read `/proc/<pid>` or not.
+    /// Reads `path`, e.g. `/proc/sys/kernel/yama/ptrace_scope`, which determines whether we can read `/proc/<pid>` or not.
     /// If the file doesn't exists, we assume the kernel was not built with the Yama Linux Security Module.
-    pub(crate) fn current() -> Self {
-        if let Ok(scope) = read_to_string("/proc/sys/kernel/yama/ptrace_scope") {
+    pub(crate) fn read(path: &Path) -> Self {
+        if let Ok(scope) = read_to_string(path) {
             match scope.trim() {
                 "0" => PtraceScope::All,
                 "1" => PtraceScope::Restricted,
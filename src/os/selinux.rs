@@ -0,0 +1,250 @@
+use std::{
+    ffi::CString,
+    fs::{read_to_string, OpenOptions},
+    io::{Error as IoError, ErrorKind, Read, Write},
+    os::{raw::c_int, unix::prelude::OsStrExt},
+    path::{Path, PathBuf},
+};
+
+/// The extended attribute files are labelled with.
+const XATTR: &str = "security.selinux";
+
+/// `AVD_FLAGS_PERMISSIVE`, which is set if the source domain is permissive, e.g. denials are only logged.
+const AVD_FLAGS_PERMISSIVE: u32 = 1;
+
+/// A struct that references the SELinux filesystem (`/sys/fs/selinux`), which exposes the loaded policy.
+///
+/// Source: https://github.com/SELinuxProject/selinux-notebook/blob/main/src/lsm_selinux.md
+pub(crate) struct Selinux {
+    path: PathBuf,
+}
+
+impl Selinux {
+    /// Creates a new [`Selinux`] which references the SELinux filesystem mounted at `path`.
+    ///
+    /// Returns [`None`] if it's not mounted, e.g. SELinux is disabled.
+    pub(crate) fn new(path: PathBuf) -> Option<Self> {
+        path.join("enforce").exists().then_some(Selinux { path })
+    }
+
+    /// Determines whether SELinux is enforcing, e.g. denials are not only logged.
+    pub(crate) fn enforcing(&self) -> bool {
+        read_to_string(self.path.join("enforce")).is_ok_and(|enforce| enforce.trim() != "0")
+    }
+
+    /// Computes which of `perms` the policy denies `source` on a `class` object labelled `target`, e.g. `file`.
+    /// The permissions the policy doesn't define are ignored, and nothing is denied to a permissive domain.
+    ///
+    /// Returns [`IoError`] if the policy couldn't be queried, e.g. the current process is not allowed to.
+    pub(crate) fn denied(
+        &self,
+        source: &str,
+        target: &str,
+        class: &str,
+        perms: &[&'static str],
+    ) -> Result<Vec<&'static str>, IoError> {
+        let class_path = self.path.join("class").join(class);
+        let index = read_to_string(class_path.join("index"))?;
+
+        let mut access = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(self.path.join("access"))?;
+        access.write_all(format!("{source} {target} {}", index.trim()).as_bytes())?;
+
+        let mut response = String::new();
+        access.read_to_string(&mut response)?;
+        let (allowed, flags) = parse_access(&response)
+            .ok_or_else(|| IoError::new(ErrorKind::InvalidData, "malformed access vector"))?;
+
+        if flags & AVD_FLAGS_PERMISSIVE != 0 {
+            return Ok(Vec::new());
+        }
+
+        Ok(perms
+            .iter()
+            .filter(|perm| perm_bit(&class_path, perm).is_some_and(|bit| allowed & bit == 0))
+            .copied()
+            .collect())
+    }
+}
+
+/// Gets the bit which represents `perm` in the access vectors of the class whose directory is `class_path`.
+///
+/// Returns [`None`] if the policy doesn't define it.
+fn perm_bit(class_path: &Path, perm: &str) -> Option<u32> {
+    let value: u32 = read_to_string(class_path.join("perms").join(perm))
+        .ok()?
+        .trim()
+        .parse()
+        .ok()?;
+
+    1_u32.checked_shl(value.checked_sub(1)?)
+}
+
+/// Parses the access vector the `access` file responds with: `<allowed> <decided> <auditallow> <auditdeny> <seqno> <flags>`,
+/// where every field but the sequence number is hexadecimal.
+///
+/// Returns the allowed permissions and the flags, or [`None`] if it's malformed.
+fn parse_access(response: &str) -> Option<(u32, u32)> {
+    let fields: Vec<_> = response.split_whitespace().collect();
+    let hex = |field: &str| u32::from_str_radix(field, 16).ok();
+
+    match fields[..] {
+        [allowed, _, _, _, _, flags] => Some((hex(allowed)?, hex(flags)?)),
+        // Kernels older than 4.x don't report the flags.
+        [allowed, _, _, _, _] => Some((hex(allowed)?, 0)),
+        _ => None,
+    }
+}
+
+/// Gets the SELinux context `path` is labelled with.
+pub(crate) fn get_context(path: &Path) -> Result<String, IoError> {
+    let context = get_xattr(path, XATTR)?;
+    Ok(String::from_utf8_lossy(&context)
+        .trim_end_matches('\0')
+        .to_owned())
+}
+
+/// Labels `path` with the SELinux `context`, e.g. `system_u:object_r:bin_t:s0`.
+pub(crate) fn set_context(path: &Path, context: &str) -> Result<(), IoError> {
+    set_xattr(path, XATTR, format!("{context}\0").as_bytes())
+}
+
+// TODO: wait for https://github.com/rust-lang/rust/issues/88989
+fn get_xattr(path: &Path, name: &str) -> Result<Vec<u8>, IoError> {
+    use std::os::raw::{c_char, c_void};
+
+    #[link(name = "c")]
+    extern "C" {
+        fn getxattr(
+            path: *const c_char,
+            name: *const c_char,
+            value: *mut c_void,
+            size: usize,
+        ) -> isize;
+    }
+
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let name = CString::new(name)?;
+    let mut value = vec![0_u8; 256];
+
+    loop {
+        let len = unsafe {
+            getxattr(
+                path.as_ptr(),
+                name.as_ptr(),
+                value.as_mut_ptr().cast(),
+                value.len(),
+            )
+        };
+
+        match usize::try_from(len) {
+            Ok(len) => {
+                value.truncate(len);
+                return Ok(value);
+            }
+            Err(_) => match IoError::last_os_error() {
+                // The value is longer than the buffer.
+                err if err.raw_os_error() == Some(34) => value.resize(value.len() * 2, 0),
+                err => return Err(err),
+            },
+        }
+    }
+}
+
+// TODO: wait for https://github.com/rust-lang/rust/issues/88989
+fn set_xattr(path: &Path, name: &str, value: &[u8]) -> Result<(), IoError> {
+    use std::os::raw::{c_char, c_void};
+
+    #[link(name = "c")]
+    extern "C" {
+        fn setxattr(
+            path: *const c_char,
+            name: *const c_char,
+            value: *const c_void,
+            size: usize,
+            flags: c_int,
+        ) -> c_int;
+    }
+
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let name = CString::new(name)?;
+
+    match unsafe {
+        setxattr(
+            path.as_ptr(),
+            name.as_ptr(),
+            value.as_ptr().cast(),
+            value.len(),
+            0,
+        )
+    } {
+        0 => Ok(()),
+        _ => Err(IoError::last_os_error()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+
+    use crate::proc::fixture::Fixture;
+
+    use super::{get_xattr, parse_access, perm_bit, set_xattr, Selinux};
+
+    #[test]
+    fn reads_the_policy() {
+        let fixture = Fixture::new();
+        assert!(Selinux::new(fixture.fs().resolve("/sys/fs/selinux")).is_none());
+
+        fixture
+            .write("/sys/fs/selinux/enforce", "1")
+            .write("/sys/fs/selinux/class/file/perms/read", "5\n")
+            .write("/sys/fs/selinux/class/file/perms/map", "21\n");
+        let path = fixture.fs().resolve("/sys/fs/selinux");
+
+        assert!(Selinux::new(path.clone()).unwrap().enforcing());
+        assert_eq!(perm_bit(&path.join("class/file"), "read"), Some(1 << 4));
+        assert_eq!(perm_bit(&path.join("class/file"), "map"), Some(1 << 20));
+        assert_eq!(perm_bit(&path.join("class/file"), "execute"), None);
+
+        fixture.write("/sys/fs/selinux/enforce", "0");
+        assert!(!Selinux::new(path).unwrap().enforcing());
+    }
+
+    #[test]
+    fn parses_access_vectors() {
+        assert_eq!(
+            parse_access("1fffff ffffffff 0 fffafb7d 137 1"),
+            Some((0x1fffff, 1))
+        );
+        assert_eq!(
+            parse_access("2000a ffffffff 0 ffffffff 4\n"),
+            Some((0x2000a, 0))
+        );
+        assert_eq!(parse_access(""), None);
+        assert_eq!(parse_access("zz ffffffff 0 0 1 0"), None);
+    }
+
+    #[test]
+    fn sets_extended_attributes() {
+        let fixture = Fixture::new();
+        fixture.write("/lib.so", []);
+        let path = fixture.fs().resolve("/lib.so");
+        let value = "x".repeat(300);
+
+        match set_xattr(&path, "user.intruducer", value.as_bytes()) {
+            Err(err) if err.kind() == ErrorKind::Unsupported => {
+                eprintln!("skipped: extended attributes are not supported");
+                return;
+            }
+            result => result.unwrap(),
+        }
+        assert_eq!(
+            get_xattr(&path, "user.intruducer").unwrap(),
+            value.as_bytes()
+        );
+        assert!(get_xattr(&path, "user.missing").is_err());
+    }
+}
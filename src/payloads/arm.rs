@@ -1,23 +1,39 @@
-use crate::{os::VirtAddr, proc::ProcSym};
+use crate::{os::VirtAddr, proc::ProcSym, Error};
 
-pub(crate) fn gen_first(second_payload_path: &str) -> Vec<u8> {
+use super::{narrow, Nrs, Payload, Syscalls, AT_FDCWD, STUB_OFFSET};
+
+pub(super) const NRS: Nrs = Nrs {
+    open: Some(5),
+    openat: 322,
+    close: 6,
+    unlink: Some(10),
+    unlinkat: 328,
+    mmap: ("mmap2", 192),
+    old_mmap: None,
+    mprotect: 125,
+    munmap: 91,
+    pwrite64: 181,
+    at_fdcwd: AT_FDCWD as u32 as u64,
+};
+
+pub(crate) fn gen_first(
+    syscalls: Syscalls,
+    second_payload_path: &str,
+    second_payload_size: u32,
+) -> Result<Payload, Error> {
     use tiny_asm::arm::{Reg::*, TinyAsm};
 
-    TinyAsm::new()
+    Ok(TinyAsm::new()
         // Push every general purpose register, plus the link register (r14).
         .push([r0, r1, r2, r3, r4, r5, r6, r7, r8, r9, r10, r11, r12, lr])
         // Open second payload file.
-        .movw(r7, 5)
-        .adrl(r0, "second_payload_path")
-        .movw(r1, 0)
-        .movw(r2, 0)
-        .svc(0)
+        .with(open(syscalls, "second_payload_path", 0))
         // Second payload file descriptor.
         .movr(r11, r0)
         // Map the Second payload file to memory.
-        .movw(r7, 192)
+        .movw(r7, NRS.mmap.1 as u16)
         .movw(r0, 0)
-        .movw(r1, 512)
+        .ldrl(r1, "second_payload_size")
         .movw(r2, 1 | 4)
         .movw(r3, 2)
         .movr(r4, r11)
@@ -26,45 +42,57 @@ pub(crate) fn gen_first(second_payload_path: &str) -> Vec<u8> {
         // Second payload code virtual address.
         .movr(r12, r0)
         // Close Second payload file.
-        .movw(r7, 6)
+        .movw(r7, NRS.close as u16)
         .movr(r0, r11)
         .svc(0)
+        // Delete Second payload file.
+        // Will fail on Android apps.
+        .with(unlink(syscalls, "second_payload_path"))
         // Execute second payload code.
         .movr(pc, r12)
         // Data
+        .label("second_payload_size")
+        .dword(second_payload_size)
         .label("second_payload_path")
         .asciiz(second_payload_path)
         .align::<4>()
-        .build()
+        .build_with_labels()
+        .map(Payload::new::<tiny_asm::arm::Op>)?)
 }
 
 pub(crate) fn gen_second(
+    syscalls: Syscalls,
     original_code: &[u8],
     original_ip: VirtAddr,
     lib_path: &str,
     dlopen: &ProcSym,
-) -> Vec<u8> {
-    use tiny_asm::arm::{Reg::*, TinyAsm};
+    second_payload_size: u32,
+    page_size: u64,
+) -> Result<Payload, Error> {
+    use tiny_asm::arm::{Cond::Ne, Reg::*, TinyAsm};
 
-    TinyAsm::new()
+    let original_ip = narrow(original_ip)?;
+    let dlopen_addr = narrow(dlopen.addr)?;
+
+    let stub = gen_stub(original_ip)?;
+    let stub_len = stub.len() as u16;
+
+    Ok(TinyAsm::new()
+        .label("second_payload")
         // Open memory file (/proc/self/mem).
-        .movw(r7, 5)
-        .adrl(r0, "mem_path")
-        .movw(r1, 2)
-        .movw(r2, 0)
-        .svc(0)
+        .with(open(syscalls, "mem_path", 2))
         // Memory file descriptor.
         .movr(r12, r0)
         // Restore the original code.
-        .movw(r7, 181)
+        .movw(r7, NRS.pwrite64 as u16)
         .movr(r0, r12)
         .adrl(r1, "original_code")
         .movw(r2, original_code.len() as u16)
-        .ldrl(r3, "original_ip")
         .ldrl(r4, "original_ip")
+        .movw(r5, 0)
         .svc(0)
         // Close memory file.
-        .movw(r7, 6)
+        .movw(r7, NRS.close as u16)
         .movr(r0, r12)
         .svc(0)
         // Call dlopen.
@@ -72,6 +100,44 @@ pub(crate) fn gen_second(
         .movw(r1, 1)
         .movr(lr, pc)
         .ldrl(pc, "dlopen_addr")
+        // Open memory file again, so that it's not inherited while the library is being loaded.
+        .with(open(syscalls, "mem_path", 2))
+        .movr(r10, r0)
+        // Write the stub below the pushed registers.
+        .movw(r7, NRS.pwrite64 as u16)
+        .movr(r0, r10)
+        .adrl(r1, "stub")
+        .movw(r2, stub_len)
+        .subi(r4, Some(sp), STUB_OFFSET.into())
+        .movw(r5, 0)
+        .svc(0)
+        .subi(r8, Some(r0), stub_len.into())
+        // Close memory file.
+        .movw(r7, NRS.close as u16)
+        .movr(r0, r10)
+        .svc(0)
+        .cmpi(r8, 0)
+        .b(Ne, "fallback")
+        // Make the stack pages the stub spans executable, which they are left.
+        .movw(r7, NRS.mprotect as u16)
+        .subi(r3, Some(sp), STUB_OFFSET.into())
+        .ldrl(r2, "page_mask")
+        .andr(r0, r3, r2)
+        .movw(r2, (page_size - 1) as u16)
+        .andr(r1, r3, r2)
+        .addi(r1, None, STUB_OFFSET.into())
+        .movw(r2, 1 | 2 | 4)
+        .svc(0)
+        .cmpi(r0, 0)
+        .b(Ne, "fallback")
+        // Execute the stub, which unmaps the second payload code.
+        .movw(r7, NRS.munmap as u16)
+        .adrl(r0, "second_payload")
+        .ldrl(r1, "second_payload_size")
+        .subi(sp, None, STUB_OFFSET.into())
+        .movr(pc, sp)
+        // The stub couldn't be executed: leave the second payload code mapped.
+        .label("fallback")
         // Pop every previously pushed register
         .pop([r0, r1, r2, r3, r4, r5, r6, r7, r8, r9, r10, r11, r12, lr])
         // Restore the original execution flow
@@ -84,13 +150,82 @@ pub(crate) fn gen_second(
         .bytes(original_code)
         .align::<4>()
         .label("original_ip")
-        .dword(original_ip.try_into().unwrap())
+        .dword(original_ip)
         .align::<4>()
+        .label("dlopen_addr")
+        .dword(dlopen_addr)
+        .label("second_payload_size")
+        .dword(second_payload_size)
+        .label("page_mask")
+        .dword(!(page_size as u32 - 1))
+        .label("stub")
+        .bytes(&stub)
+        // Kept last, so that its length doesn't push the other data out of reach.
         .label("lib_path")
         .asciiz(lib_path)
         .align::<4>()
-        .label("dlopen_addr")
-        .dword(dlopen.addr.try_into().unwrap())
-        .align::<4>()
-        .build()
+        .build_with_labels()
+        .map(Payload::new::<tiny_asm::arm::Op>)?)
+}
+
+/// Generates the stub which is written right below the pushed registers: it performs the
+/// `munmap` syscall set up by the second payload, then resumes the original execution flow.
+fn gen_stub(original_ip: u32) -> Result<Vec<u8>, Error> {
+    use tiny_asm::arm::{Reg::*, TinyAsm};
+
+    Ok(TinyAsm::new()
+        .svc(0)
+        .addi(sp, None, STUB_OFFSET.into())
+        // Pop every previously pushed register
+        .pop([r0, r1, r2, r3, r4, r5, r6, r7, r8, r9, r10, r11, r12, lr])
+        // Restore the original execution flow
+        .ldrl(pc, "original_ip")
+        .label("original_ip")
+        .dword(original_ip)
+        .build()?)
+}
+
+/// Opens the path located at `label` with `flags`, through `open` or `openat` as `syscalls` says.
+fn open(
+    syscalls: Syscalls,
+    label: &'static str,
+    flags: u16,
+) -> impl FnOnce(tiny_asm::arm::TinyAsm) -> tiny_asm::arm::TinyAsm {
+    use tiny_asm::arm::Reg::*;
+
+    move |asm| match NRS.open {
+        Some(nr) if !syscalls.at => asm
+            .movw(r7, nr as u16)
+            .adrl(r0, label)
+            .movw(r1, flags)
+            .movw(r2, 0)
+            .svc(0),
+        _ => asm
+            .movw(r7, NRS.openat as u16)
+            .movw(r0, AT_FDCWD as u16)
+            .movt(r0, (AT_FDCWD >> 16) as u16)
+            .adrl(r1, label)
+            .movw(r2, flags)
+            .movw(r3, 0)
+            .svc(0),
+    }
+}
+
+/// Deletes the path located at `label`, through `unlink` or `unlinkat` as `syscalls` says.
+fn unlink(
+    syscalls: Syscalls,
+    label: &'static str,
+) -> impl FnOnce(tiny_asm::arm::TinyAsm) -> tiny_asm::arm::TinyAsm {
+    use tiny_asm::arm::Reg::*;
+
+    move |asm| match NRS.unlink {
+        Some(nr) if !syscalls.at => asm.movw(r7, nr as u16).adrl(r0, label).svc(0),
+        _ => asm
+            .movw(r7, NRS.unlinkat as u16)
+            .movw(r0, AT_FDCWD as u16)
+            .movt(r0, (AT_FDCWD >> 16) as u16)
+            .adrl(r1, label)
+            .movw(r2, 0)
+            .svc(0),
+    }
 }
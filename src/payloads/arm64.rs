@@ -1,9 +1,30 @@
-use crate::{os::VirtAddr, proc::ProcSym};
+use crate::{os::VirtAddr, proc::ProcSym, Error};
 
-pub(crate) fn gen_first(second_payload_path: &str) -> Vec<u8> {
+use super::{Nrs, Payload, Syscalls, AT_FDCWD, STUB_OFFSET};
+
+pub(super) const NRS: Nrs = Nrs {
+    open: None,
+    openat: 56,
+    close: 57,
+    unlink: None,
+    unlinkat: 35,
+    mmap: ("mmap", 222),
+    old_mmap: None,
+    mprotect: 226,
+    munmap: 215,
+    pwrite64: 68,
+    at_fdcwd: AT_FDCWD as u64,
+};
+
+/// `openat` and `unlinkat` are always performed, so `_syscalls` is ignored.
+pub(crate) fn gen_first(
+    _syscalls: Syscalls,
+    second_payload_path: &str,
+    second_payload_size: u32,
+) -> Result<Payload, Error> {
     use tiny_asm::arm64::{AddrMode2::PreIndexed, Reg::*, TinyAsm};
 
-    TinyAsm::new()
+    Ok(TinyAsm::new()
         // Push every general purpose register; are other registers necessary to push?
         .stp(PreIndexed, x0, x1, sp, -16)
         .stp(PreIndexed, x2, x3, sp, -16)
@@ -22,8 +43,8 @@ pub(crate) fn gen_first(second_payload_path: &str) -> Vec<u8> {
         .stp(PreIndexed, x28, x29, sp, -16)
         .stri(PreIndexed, x30, sp, -16)
         // Open second payload file
-        .movi(x8, 56)
-        .movi(x0, 0)
+        .movi(x8, NRS.openat as i32)
+        .movi(x0, AT_FDCWD)
         .adr(x1, "second_payload_path")
         .movi(x2, 0)
         .movi(x3, 0)
@@ -31,9 +52,9 @@ pub(crate) fn gen_first(second_payload_path: &str) -> Vec<u8> {
         // Second payload file descriptor
         .movr(x14, x0)
         // Map the Second payload file to memory
-        .movi(x8, 222)
+        .movi(x8, NRS.mmap.1 as i32)
         .movi(x0, 0)
-        .movi(x1, 256)
+        .ldrl(x1, "second_payload_size")
         .movi(x2, 1 | 4)
         .movi(x3, 2)
         .movr(x4, x14)
@@ -42,30 +63,49 @@ pub(crate) fn gen_first(second_payload_path: &str) -> Vec<u8> {
         // Second payload code virtual address
         .movr(x15, x0)
         // Close Second payload file.
-        .movi(x8, 57)
+        .movi(x8, NRS.close as i32)
         .movr(x0, x14)
         .svc(0)
+        // Delete Second payload file.
+        // Will fail on Android apps.
+        .movi(x8, NRS.unlinkat as i32)
+        .movi(x0, AT_FDCWD)
+        .adr(x1, "second_payload_path")
+        .movi(x2, 0)
+        .svc(0)
         // Execute second payload code
         .br(x15)
         // Data
+        .align::<8>()
+        .label("second_payload_size")
+        .qword(second_payload_size.into())
         .label("second_payload_path")
         .asciiz(second_payload_path)
         .align::<4>()
-        .build()
+        .build_with_labels()
+        .map(Payload::new::<tiny_asm::arm64::Op>)?)
 }
 
+/// `openat` is always performed, so `_syscalls` is ignored.
 pub(crate) fn gen_second(
+    _syscalls: Syscalls,
     original_code: &[u8],
     original_ip: VirtAddr,
     lib_path: &str,
     dlopen: &ProcSym,
-) -> Vec<u8> {
-    use tiny_asm::arm64::{AddrMode2::PostIndexed, Reg::*, TinyAsm};
+    second_payload_size: u32,
+    page_size: u64,
+) -> Result<Payload, Error> {
+    use tiny_asm::arm64::{Reg::*, TinyAsm};
+
+    let stub = gen_stub(original_ip)?;
+    let stub_len = stub.len() as i32;
 
-    TinyAsm::new()
+    Ok(TinyAsm::new()
+        .label("second_payload")
         // Open memory file (/proc/self/mem).
-        .movi(x8, 56)
-        .movi(x0, 0)
+        .movi(x8, NRS.openat as i32)
+        .movi(x0, AT_FDCWD)
         .adr(x1, "mem_path")
         .movi(x2, 2)
         .movi(x3, 0)
@@ -73,14 +113,14 @@ pub(crate) fn gen_second(
         // Memory file descriptor.
         .movr(x15, x0)
         // Restore the original code.
-        .movi(x8, 68)
+        .movi(x8, NRS.pwrite64 as i32)
         .movr(x0, x15)
         .adr(x1, "original_code")
         .movi(x2, original_code.len().try_into().unwrap())
         .ldrl(x3, "original_ip")
         .svc(0)
         // Close memory file.
-        .movi(x8, 57)
+        .movi(x8, NRS.close as i32)
         .movr(x0, x15)
         .svc(0)
         // Call dlopen
@@ -88,26 +128,51 @@ pub(crate) fn gen_second(
         .movi(x1, 1)
         .ldrl(x28, "dlopen_addr")
         .blr(x28)
-        // Pop every previously pushed register
-        .ldri(PostIndexed, x30, sp, 16)
-        .ldp(PostIndexed, x28, x29, sp, 16)
-        .ldp(PostIndexed, x26, x27, sp, 16)
-        .ldp(PostIndexed, x24, x25, sp, 16)
-        .ldp(PostIndexed, x22, x23, sp, 16)
-        .ldp(PostIndexed, x20, x21, sp, 16)
-        .ldp(PostIndexed, x18, x19, sp, 16)
-        .ldp(PostIndexed, x16, x17, sp, 16)
-        .ldp(PostIndexed, x14, x15, sp, 16)
-        .ldp(PostIndexed, x12, x13, sp, 16)
-        .ldp(PostIndexed, x10, x11, sp, 16)
-        .ldp(PostIndexed, x8, x9, sp, 16)
-        .ldp(PostIndexed, x6, x7, sp, 16)
-        .ldp(PostIndexed, x4, x5, sp, 16)
-        .ldp(PostIndexed, x2, x3, sp, 16)
-        .ldp(PostIndexed, x0, x1, sp, 16)
-        // Restore the original execution flow
-        .ldrl(x28, "original_ip")
-        .br(x28)
+        // Open memory file again, so that it's not inherited while the library is being loaded.
+        .movi(x8, NRS.openat as i32)
+        .movi(x0, AT_FDCWD)
+        .adr(x1, "mem_path")
+        .movi(x2, 2)
+        .movi(x3, 0)
+        .svc(0)
+        .movr(x19, x0)
+        // Write the stub below the pushed registers.
+        .movi(x8, NRS.pwrite64 as i32)
+        .movr(x0, x19)
+        .adr(x1, "stub")
+        .movi(x2, stub_len)
+        .subi(x3, sp, STUB_OFFSET.into())
+        .svc(0)
+        .subi(x21, x0, stub_len as u32)
+        // Close memory file.
+        .movi(x8, NRS.close as i32)
+        .movr(x0, x19)
+        .svc(0)
+        .cbnz(x21, "fallback")
+        // Make the stack pages the stub spans executable, which they are left.
+        .movi(x8, NRS.mprotect as i32)
+        .subi(x3, sp, STUB_OFFSET.into())
+        .ldrl(x2, "page_mask")
+        .andsr(x0, x3, x2, None)
+        .movi(x2, (page_size - 1) as i32)
+        .andsr(x1, x3, x2, None)
+        .addi(x1, x1, STUB_OFFSET.into())
+        .movi(x2, 1 | 2 | 4)
+        .svc(0)
+        .cbnz(x0, "fallback")
+        // Execute the stub, which unmaps the second payload code.
+        .movi(x8, NRS.munmap as i32)
+        .adr(x0, "second_payload")
+        .ldrl(x1, "second_payload_size")
+        .subi(sp, sp, STUB_OFFSET.into())
+        .addi(x20, sp, 0)
+        .br(x20)
+        // The stub couldn't be executed: leave the second payload code mapped.
+        .label("fallback")
+        .with(pop_regs)
+        // Restore the original execution flow, through the intra-procedure-call scratch register x16
+        .ldrl(x16, "original_ip")
+        .br(x16)
         // Data
         .label("mem_path")
         .asciiz("/proc/self/mem")
@@ -123,6 +188,53 @@ pub(crate) fn gen_second(
         .align::<4>()
         .label("dlopen_addr")
         .qword(dlopen.addr)
-        .align::<4>()
-        .build()
+        .label("second_payload_size")
+        .qword(second_payload_size.into())
+        .label("page_mask")
+        .qword(!(page_size - 1))
+        .align::<8>()
+        .label("stub")
+        .bytes(&stub)
+        .build_with_labels()
+        .map(Payload::new::<tiny_asm::arm64::Op>)?)
+}
+
+/// Generates the stub which is written right below the pushed registers: it performs the
+/// `munmap` syscall set up by the second payload, then resumes the original execution flow.
+fn gen_stub(original_ip: VirtAddr) -> Result<Vec<u8>, Error> {
+    use tiny_asm::arm64::{Reg::*, TinyAsm};
+
+    Ok(TinyAsm::new()
+        .svc(0)
+        .addi(sp, sp, STUB_OFFSET.into())
+        .with(pop_regs)
+        // Restore the original execution flow, through the intra-procedure-call scratch register x16
+        .ldrl(x16, "original_ip")
+        .br(x16)
+        .align::<8>()
+        .label("original_ip")
+        .qword(original_ip)
+        .build()?)
+}
+
+/// Pops every register pushed by the first payload.
+fn pop_regs(asm: tiny_asm::arm64::TinyAsm) -> tiny_asm::arm64::TinyAsm {
+    use tiny_asm::arm64::{AddrMode2::PostIndexed, Reg::*};
+
+    asm.ldri(PostIndexed, x30, sp, 16)
+        .ldp(PostIndexed, x28, x29, sp, 16)
+        .ldp(PostIndexed, x26, x27, sp, 16)
+        .ldp(PostIndexed, x24, x25, sp, 16)
+        .ldp(PostIndexed, x22, x23, sp, 16)
+        .ldp(PostIndexed, x20, x21, sp, 16)
+        .ldp(PostIndexed, x18, x19, sp, 16)
+        .ldp(PostIndexed, x16, x17, sp, 16)
+        .ldp(PostIndexed, x14, x15, sp, 16)
+        .ldp(PostIndexed, x12, x13, sp, 16)
+        .ldp(PostIndexed, x10, x11, sp, 16)
+        .ldp(PostIndexed, x8, x9, sp, 16)
+        .ldp(PostIndexed, x6, x7, sp, 16)
+        .ldp(PostIndexed, x4, x5, sp, 16)
+        .ldp(PostIndexed, x2, x3, sp, 16)
+        .ldp(PostIndexed, x0, x1, sp, 16)
 }
@@ -0,0 +1,345 @@
+use crate::{
+    os::VirtAddr,
+    payloads::{Nrs, Payload, Syscalls},
+    proc::ProcSym,
+    Error,
+};
+
+use super::{sign_extend, Cpu, Fault, Memory, Nzcv, Step, Syscall, CLOBBERED, PAGE_SIZE};
+
+/// An ARMv7 processor in user mode, which executes A32 instructions only.
+pub(super) struct Arm {
+    r: [u32; 16],
+    flags: Nzcv,
+}
+
+const SP: usize = 13;
+const LR: usize = 14;
+const PC: usize = 15;
+
+impl Arm {
+    /// Gets the value of the register `n`, where the PC reads as the address of the instruction `ip` plus 8.
+    fn get(&self, n: u32, ip: u32) -> u32 {
+        match n as usize {
+            PC => ip + 8,
+            n => self.r[n],
+        }
+    }
+
+    fn set(&mut self, n: u32, value: u32) {
+        self.r[n as usize] = value;
+    }
+
+    /// Executes the instruction `word`, located at `ip`, whose condition holds.
+    fn exec(&mut self, mem: &mut Memory, ip: u32, word: u32) -> Result<Step, Fault> {
+        let undefined = Fault::Undefined(ip.into());
+        let (rn, rd, rm) = (word >> 16 & 0xf, word >> 12 & 0xf, word & 0xf);
+
+        match word & 0x0fffffff {
+            // BX, BLX (register).
+            word if word & 0x0fffffd0 == 0x012fff10 => {
+                let target = self.get(rm, ip);
+                if target & 1 != 0 {
+                    // The Thumb instruction set.
+                    return Err(undefined);
+                }
+                if word & 0x20 != 0 {
+                    self.r[LR] = ip + 4;
+                }
+                self.r[PC] = target;
+            }
+            // MRS, with the user mode bits.
+            word if word & 0x0fff0fff == 0x010f0000 => {
+                let Nzcv { n, z, c, v } = self.flags;
+                let apsr =
+                    (n as u32) << 31 | (z as u32) << 30 | (c as u32) << 29 | (v as u32) << 28;
+                self.set(rd, apsr | 0x10);
+            }
+            // MSR APSR_nzcvq.
+            word if word & 0x0ffffff0 == 0x0128f000 => {
+                let value = self.get(rm, ip);
+                self.flags = Nzcv {
+                    n: value >> 31 & 1 != 0,
+                    z: value >> 30 & 1 != 0,
+                    c: value >> 29 & 1 != 0,
+                    v: value >> 28 & 1 != 0,
+                };
+            }
+            // MOVW, MOVT.
+            word if word & 0x0fb00000 == 0x03000000 => {
+                let imm = (word >> 4 & 0xf000) | word & 0xfff;
+                match word & 0x00400000 {
+                    0 => self.set(rd, imm),
+                    _ => self.set(rd, self.r[rd as usize] & 0xffff | imm << 16),
+                }
+            }
+            // SVC.
+            word if word & 0x0f000000 == 0x0f000000 => return Ok(Step::Syscall),
+            // B, BL.
+            word if word & 0x0e000000 == 0x0a000000 => {
+                if word & 0x01000000 != 0 {
+                    self.r[LR] = ip + 4;
+                }
+                let imm = sign_extend((word & 0xffffff).into(), 24) << 2;
+                self.r[PC] = (ip + 8).wrapping_add(imm as u32);
+            }
+            // LDM, STM.
+            word if word & 0x0e400000 == 0x08000000 => {
+                let regs: Vec<_> = (0..16).filter(|reg| word >> reg & 1 != 0).collect();
+                let len = 4 * regs.len() as u32;
+                let base = self.get(rn, ip);
+                let (pre, up, wback, load) = (
+                    word >> 24 & 1 != 0,
+                    word >> 23 & 1 != 0,
+                    word >> 21 & 1 != 0,
+                    word >> 20 & 1 != 0,
+                );
+
+                let start = match (pre, up) {
+                    (false, true) => base,
+                    (true, true) => base + 4,
+                    (false, false) => base - len + 4,
+                    (true, false) => base - len,
+                };
+
+                for (index, reg) in regs.into_iter().enumerate() {
+                    let addr = VirtAddr::from(start + 4 * index as u32);
+
+                    match load {
+                        true => self.set(reg, mem.read_u32(addr)?),
+                        false => mem.write_u32(addr, self.get(reg, ip))?,
+                    }
+                }
+
+                if wback && !(load && word >> rn & 1 != 0) {
+                    self.set(rn, if up { base + len } else { base - len });
+                }
+            }
+            // LDR, STR (immediate).
+            word if word & 0x0e400000 == 0x04000000 => {
+                let (pre, up, wback, load) = (
+                    word >> 24 & 1 != 0,
+                    word >> 23 & 1 != 0,
+                    word >> 21 & 1 != 0,
+                    word >> 20 & 1 != 0,
+                );
+                if !pre && wback {
+                    // LDRT, STRT.
+                    return Err(undefined);
+                }
+
+                let base = self.get(rn, ip);
+                let offset = match up {
+                    true => base.wrapping_add(word & 0xfff),
+                    false => base.wrapping_sub(word & 0xfff),
+                };
+                let addr = VirtAddr::from(if pre { offset } else { base });
+
+                match load {
+                    true => self.set(rd, mem.read_u32(addr)?),
+                    false => mem.write_u32(addr, self.get(rd, ip))?,
+                }
+                if !pre || wback {
+                    self.set(rn, offset);
+                }
+            }
+            // Data-processing (immediate), data-processing (register) with no shift.
+            word if word & 0x0e000000 == 0x02000000 || word & 0x0e000ff0 == 0 => {
+                let (opcode, s) = (word >> 21 & 0xf, word >> 20 & 1 != 0);
+                if (8..12).contains(&opcode) && !s || s && rd as usize == PC {
+                    // The miscellaneous instructions, or an exception return.
+                    return Err(undefined);
+                }
+
+                let (op2, carry) = match word & 0x02000000 {
+                    0 => (self.get(rm, ip), self.flags.c),
+                    _ => {
+                        let value = (word & 0xff).rotate_right((word >> 8 & 0xf) * 2);
+                        let carry = match word & 0xf00 {
+                            0 => self.flags.c,
+                            _ => value >> 31 != 0,
+                        };
+                        (value, carry)
+                    }
+                };
+                let op1 = self.get(rn, ip);
+
+                let arith = |x: u32, y: u32, carry: bool| {
+                    let (value, flags) = Nzcv::add_with_carry(x.into(), y.into(), carry, 32);
+                    (value as u32, flags)
+                };
+                let logical = |value: u32| (value, self.flags.logical(value.into(), 32, carry));
+
+                let (value, flags) = match opcode {
+                    0 | 8 => logical(op1 & op2),
+                    1 | 9 => logical(op1 ^ op2),
+                    2 | 10 => arith(op1, !op2, true),
+                    3 => arith(op2, !op1, true),
+                    4 | 11 => arith(op1, op2, false),
+                    5 => arith(op1, op2, self.flags.c),
+                    6 => arith(op1, !op2, self.flags.c),
+                    7 => arith(op2, !op1, self.flags.c),
+                    12 => logical(op1 | op2),
+                    13 => logical(op2),
+                    14 => logical(op1 & !op2),
+                    _ => logical(!op2),
+                };
+
+                if s {
+                    self.flags = flags;
+                }
+                if !(8..12).contains(&opcode) {
+                    self.set(rd, value);
+                }
+            }
+            _ => return Err(undefined),
+        }
+
+        Ok(Step::Next)
+    }
+}
+
+impl Cpu for Arm {
+    fn new(ip: VirtAddr, sp: VirtAddr) -> Self {
+        let mut r: [u32; 16] = std::array::from_fn(|n| 0x01010101 * (n as u32 + 1));
+        r[SP] = sp as u32;
+        r[PC] = ip as u32;
+
+        Self {
+            r,
+            flags: Nzcv::default(),
+        }
+    }
+
+    fn ip(&self) -> VirtAddr {
+        self.r[PC].into()
+    }
+
+    fn regs(&self) -> Vec<u64> {
+        self.r[..PC].iter().copied().map(u64::from).collect()
+    }
+
+    fn step(&mut self, mem: &mut Memory) -> Result<Step, Fault> {
+        let ip = self.r[PC];
+        if !ip.is_multiple_of(4) {
+            return Err(Fault::Fetch(ip.into()));
+        }
+
+        let mut word = [0; 4];
+        mem.fetch(ip.into(), &mut word)?;
+        let word = u32::from_le_bytes(word);
+
+        self.r[PC] = ip + 4;
+
+        match word >> 28 {
+            // The unconditional instructions, e.g. BLX (immediate) which switches to Thumb.
+            0xf => Err(Fault::Undefined(ip.into())),
+            cond if !self.flags.holds(cond) => Ok(Step::Next),
+            _ => self.exec(mem, ip, word),
+        }
+    }
+
+    fn syscall(&self) -> Result<Syscall, Fault> {
+        let r = self.r.map(u64::from);
+
+        Ok(match r[7] {
+            5 => Syscall::Open {
+                dirfd: None,
+                path: r[0],
+            },
+            6 => Syscall::Close { fd: r[0] },
+            10 => Syscall::Unlink {
+                dirfd: None,
+                path: r[0],
+            },
+            322 => Syscall::Open {
+                dirfd: Some(r[0]),
+                path: r[1],
+            },
+            328 => Syscall::Unlink {
+                dirfd: Some(r[0]),
+                path: r[1],
+            },
+            91 => Syscall::Munmap {
+                addr: r[0],
+                len: r[1],
+            },
+            125 => Syscall::Mprotect {
+                addr: r[0],
+                len: r[1],
+                prot: r[2] as u32,
+            },
+            // The 64 bit offset is passed in an even register pair.
+            181 => Syscall::Pwrite {
+                fd: r[0],
+                buf: r[1],
+                count: r[2],
+                offset: r[4] | r[5] << 32,
+            },
+            // `mmap2`, whose offset is in pages.
+            192 => Syscall::Mmap {
+                len: r[1],
+                prot: r[2] as u32,
+                flags: r[3] as u32,
+                fd: r[4],
+                offset: r[5] * 4096,
+            },
+            number => return Err(Fault::Syscall(number)),
+        })
+    }
+
+    fn nr(&self) -> u64 {
+        u64::from(self.r[7])
+    }
+
+    fn set_ret(&mut self, value: i64) {
+        self.r[0] = value as u32;
+    }
+
+    fn args(&self, _: &Memory) -> Result<[u64; 2], Fault> {
+        // The stack must be 8 byte aligned at public interfaces.
+        match self.r[SP] % 8 {
+            0 => Ok([self.r[0].into(), self.r[1].into()]),
+            _ => Err(Fault::Misaligned(self.r[SP].into())),
+        }
+    }
+
+    fn ret(&mut self, _: &mut Memory, value: u64) -> Result<(), Fault> {
+        self.r[PC] = self.r[LR];
+        self.r[0] = value as u32;
+
+        for n in [1, 2, 3, 12, LR] {
+            self.r[n] = CLOBBERED as u32;
+        }
+        Ok(())
+    }
+
+    const NRS: &'static Nrs = &crate::payloads::arm::NRS;
+
+    fn gen_first(
+        syscalls: Syscalls,
+        second_payload_path: &str,
+        second_payload_size: u32,
+    ) -> Result<Payload, Error> {
+        crate::payloads::arm::gen_first(syscalls, second_payload_path, second_payload_size)
+    }
+
+    fn gen_second(
+        syscalls: Syscalls,
+        original_code: &[u8],
+        original_ip: VirtAddr,
+        lib_path: &str,
+        dlopen: &ProcSym,
+        second_payload_size: u32,
+    ) -> Result<Payload, Error> {
+        crate::payloads::arm::gen_second(
+            syscalls,
+            original_code,
+            original_ip,
+            lib_path,
+            dlopen,
+            second_payload_size,
+            PAGE_SIZE,
+        )
+    }
+}
@@ -0,0 +1,375 @@
+use crate::{
+    os::VirtAddr,
+    payloads::{Nrs, Payload, Syscalls},
+    proc::ProcSym,
+    Error,
+};
+
+use super::{mask, sign_extend, Cpu, Fault, Memory, Nzcv, Step, Syscall, CLOBBERED, PAGE_SIZE};
+
+/// An ARMv8 processor at EL0, which executes A64 instructions only.
+pub(super) struct Arm64 {
+    x: [u64; 31],
+    sp: u64,
+    pc: u64,
+    flags: Nzcv,
+}
+
+const LR: usize = 30;
+
+impl Arm64 {
+    /// Gets the value of the register `n`, where `31` is the zero register.
+    fn xr(&self, n: u32) -> u64 {
+        self.x.get(n as usize).copied().unwrap_or(0)
+    }
+
+    /// Gets the value of the register `n`, where `31` is the stack pointer.
+    fn xsp(&self, n: u32) -> u64 {
+        self.x.get(n as usize).copied().unwrap_or(self.sp)
+    }
+
+    /// Sets the register `n`, where `31` is the zero register - so the value is discarded.
+    fn set_xr(&mut self, n: u32, value: u64) {
+        if let Some(reg) = self.x.get_mut(n as usize) {
+            *reg = value;
+        }
+    }
+
+    /// Sets the register `n`, where `31` is the stack pointer.
+    fn set_xsp(&mut self, n: u32, value: u64) {
+        match self.x.get_mut(n as usize) {
+            Some(reg) => *reg = value,
+            None => self.sp = value,
+        }
+    }
+
+    /// Gets the base address of a load or store, which is the register `n` where `31` is the stack pointer.
+    ///
+    /// Returns [`Fault::Misaligned`] if it's the stack pointer, and it's not 16 byte aligned.
+    fn base(&self, n: u32) -> Result<u64, Fault> {
+        match self.xsp(n) {
+            sp if n == 31 && sp % 16 != 0 => Err(Fault::Misaligned(sp)),
+            base => Ok(base),
+        }
+    }
+
+    /// Gets the value of the register `n`, where `31` is the zero register, shifted as `word` encodes it.
+    fn shifted(&self, word: u32, bits: u32) -> Option<u64> {
+        let (value, amount) = (self.xr(word >> 16 & 0x1f) & mask(bits), word >> 10 & 0x3f);
+        if amount >= bits {
+            return None;
+        }
+
+        Some(
+            match word >> 22 & 3 {
+                0 => value << amount,
+                1 => value >> amount,
+                2 => (sign_extend(value, bits) >> amount) as u64,
+                _ => value >> amount | value.checked_shl(bits - amount).unwrap_or(0),
+            } & mask(bits),
+        )
+    }
+
+    /// Executes the instruction `word`, located at `ip`.
+    fn exec(&mut self, mem: &mut Memory, ip: u64, word: u32) -> Result<Step, Fault> {
+        let undefined = Fault::Undefined(ip);
+        let (rt, rn) = (word & 0x1f, word >> 5 & 0x1f);
+        let bits = if word >> 31 != 0 { 64 } else { 32 };
+        let imm19 = || (sign_extend((word >> 5 & 0x7ffff).into(), 19) << 2) as u64;
+
+        match word {
+            // B, BL.
+            _ if word & 0x7c000000 == 0x14000000 => {
+                if word >> 31 != 0 {
+                    self.x[LR] = ip + 4;
+                }
+                self.pc = ip.wrapping_add((sign_extend((word & 0x3ffffff).into(), 26) << 2) as u64);
+            }
+            // B.cond.
+            _ if word & 0xff000010 == 0x54000000 => {
+                if self.flags.holds(word & 0xf) {
+                    self.pc = ip.wrapping_add(imm19());
+                }
+            }
+            // CBZ, CBNZ.
+            _ if word & 0x7e000000 == 0x34000000 => {
+                if (self.xr(rt) & mask(bits) == 0) == (word >> 24 & 1 == 0) {
+                    self.pc = ip.wrapping_add(imm19());
+                }
+            }
+            // BR, BLR, RET.
+            _ if word & 0xffdffc1f == 0xd61f0000 || word & 0xfffffc1f == 0xd65f0000 => {
+                let target = self.xr(rn);
+                if word & 0x00200000 != 0 {
+                    self.x[LR] = ip + 4;
+                }
+                self.pc = target;
+            }
+            // SVC.
+            _ if word & 0xffe0001f == 0xd4000001 => return Ok(Step::Syscall),
+            // ADR.
+            _ if word & 0x9f000000 == 0x10000000 => {
+                let imm = (word >> 3 & 0x1ffffc) | (word >> 29 & 3);
+                self.set_xr(rt, ip.wrapping_add(sign_extend(imm.into(), 21) as u64));
+            }
+            // ADD, ADDS, SUB, SUBS (immediate).
+            _ if word & 0x1f800000 == 0x11000000 => {
+                let imm = u64::from(word >> 10 & 0xfff) << ((word >> 22 & 1) * 12);
+                let (value, flags) = self.add_sub(word, self.xsp(rn), imm, bits);
+
+                match word >> 29 & 1 {
+                    0 => self.set_xsp(rt, value),
+                    _ => {
+                        self.flags = flags;
+                        self.set_xr(rt, value);
+                    }
+                }
+            }
+            // ADD, ADDS, SUB, SUBS (shifted register).
+            _ if word & 0x1f200000 == 0x0b000000 && word >> 22 & 3 != 3 => {
+                let op2 = self.shifted(word, bits).ok_or(undefined)?;
+                let (value, flags) = self.add_sub(word, self.xr(rn), op2, bits);
+
+                if word >> 29 & 1 != 0 {
+                    self.flags = flags;
+                }
+                self.set_xr(rt, value);
+            }
+            // AND, ORR, EOR, ANDS (shifted register), and their inverted variants.
+            _ if word & 0x1f000000 == 0x0a000000 => {
+                let mut op2 = self.shifted(word, bits).ok_or(undefined)?;
+                if word >> 21 & 1 != 0 {
+                    op2 = !op2 & mask(bits);
+                }
+                let op1 = self.xr(rn) & mask(bits);
+
+                let value = match word >> 29 & 3 {
+                    0 | 3 => op1 & op2,
+                    1 => op1 | op2,
+                    _ => op1 ^ op2,
+                };
+                if word >> 29 & 3 == 3 {
+                    self.flags = Nzcv::default().logical(value, bits, false);
+                }
+                self.set_xr(rt, value);
+            }
+            // MOVN, MOVZ, MOVK.
+            _ if word & 0x1f800000 == 0x12800000 => {
+                let shift = (word >> 21 & 3) * 16;
+                let imm = u64::from(word >> 5 & 0xffff) << shift;
+                if shift >= bits {
+                    return Err(undefined);
+                }
+
+                let value = match word >> 29 & 3 {
+                    0 => !imm,
+                    2 => imm,
+                    3 => self.xr(rt) & !(0xffff << shift) | imm,
+                    _ => return Err(undefined),
+                };
+                self.set_xr(rt, value & mask(bits));
+            }
+            // LDR (literal).
+            _ if word & 0xff000000 == 0x58000000 => {
+                let value = mem.read_u64(ip.wrapping_add(imm19()))?;
+                self.set_xr(rt, value);
+            }
+            // LDR, STR (immediate, unsigned offset).
+            _ if word & 0xffc00000 == 0xf9400000 || word & 0xffc00000 == 0xf9000000 => {
+                let addr = self.base(rn)? + u64::from(word >> 10 & 0xfff) * 8;
+                self.load_store(mem, word >> 22 & 1 != 0, rt, addr)?;
+            }
+            // LDR, STR (immediate, pre-indexed and post-indexed).
+            _ if word & 0xffa00400 == 0xf8000400 => {
+                let base = self.base(rn)?;
+                let offset = base.wrapping_add(sign_extend((word >> 12 & 0x1ff).into(), 9) as u64);
+                let pre = word >> 11 & 1 != 0;
+
+                self.load_store(
+                    mem,
+                    word >> 22 & 1 != 0,
+                    rt,
+                    if pre { offset } else { base },
+                )?;
+                self.set_xsp(rn, offset);
+            }
+            // LDP, STP.
+            _ if word & 0xfe000000 == 0xa8000000 && word >> 23 & 3 != 0 => {
+                let base = self.base(rn)?;
+                let offset =
+                    base.wrapping_add((sign_extend((word >> 15 & 0x7f).into(), 7) * 8) as u64);
+                let addr = match word >> 23 & 3 {
+                    1 => base,
+                    _ => offset,
+                };
+                let load = word >> 22 & 1 != 0;
+
+                self.load_store(mem, load, rt, addr)?;
+                self.load_store(mem, load, word >> 10 & 0x1f, addr + 8)?;
+                if word >> 23 & 3 != 2 {
+                    self.set_xsp(rn, offset);
+                }
+            }
+            _ => return Err(undefined),
+        }
+
+        Ok(Step::Next)
+    }
+
+    /// Adds or subtracts - as `word` encodes it - `op2` to `op1`, returning the result and its flags.
+    fn add_sub(&self, word: u32, op1: u64, op2: u64, bits: u32) -> (u64, Nzcv) {
+        match word >> 30 & 1 {
+            0 => Nzcv::add_with_carry(op1, op2, false, bits),
+            _ => Nzcv::add_with_carry(op1, !op2, true, bits),
+        }
+    }
+
+    /// Loads the register `rt`, where `31` is the zero register, from `addr` or stores it there.
+    fn load_store(
+        &mut self,
+        mem: &mut Memory,
+        load: bool,
+        rt: u32,
+        addr: u64,
+    ) -> Result<(), Fault> {
+        match load {
+            true => self.set_xr(rt, mem.read_u64(addr)?),
+            false => mem.write_u64(addr, self.xr(rt))?,
+        }
+        Ok(())
+    }
+}
+
+impl Cpu for Arm64 {
+    fn new(ip: VirtAddr, sp: VirtAddr) -> Self {
+        Self {
+            x: std::array::from_fn(|n| 0x0101010101010101 * (n as u64 + 1)),
+            sp,
+            pc: ip,
+            flags: Nzcv::default(),
+        }
+    }
+
+    fn ip(&self) -> VirtAddr {
+        self.pc
+    }
+
+    /// An absolute branch needs a register, so the original execution flow is resumed through x16, which the AAPCS64
+    /// lets any branch clobber.
+    fn resumed(ip: VirtAddr, sp: VirtAddr) -> Self {
+        let mut cpu = Self::new(ip, sp);
+        cpu.x[16] = ip;
+        cpu
+    }
+
+    fn regs(&self) -> Vec<u64> {
+        self.x.iter().copied().chain([self.sp]).collect()
+    }
+
+    fn step(&mut self, mem: &mut Memory) -> Result<Step, Fault> {
+        let ip = self.pc;
+        if !ip.is_multiple_of(4) {
+            return Err(Fault::Fetch(ip));
+        }
+
+        let mut word = [0; 4];
+        mem.fetch(ip, &mut word)?;
+
+        self.pc = ip + 4;
+        self.exec(mem, ip, u32::from_le_bytes(word))
+    }
+
+    fn syscall(&self) -> Result<Syscall, Fault> {
+        let x = self.x;
+
+        Ok(match x[8] {
+            35 => Syscall::Unlink {
+                dirfd: Some(x[0]),
+                path: x[1],
+            },
+            56 => Syscall::Open {
+                dirfd: Some(x[0]),
+                path: x[1],
+            },
+            57 => Syscall::Close { fd: x[0] },
+            68 => Syscall::Pwrite {
+                fd: x[0],
+                buf: x[1],
+                count: x[2],
+                offset: x[3],
+            },
+            215 => Syscall::Munmap {
+                addr: x[0],
+                len: x[1],
+            },
+            222 => Syscall::Mmap {
+                len: x[1],
+                prot: x[2] as u32,
+                flags: x[3] as u32,
+                fd: x[4],
+                offset: x[5],
+            },
+            226 => Syscall::Mprotect {
+                addr: x[0],
+                len: x[1],
+                prot: x[2] as u32,
+            },
+            number => return Err(Fault::Syscall(number)),
+        })
+    }
+
+    fn nr(&self) -> u64 {
+        self.x[8]
+    }
+
+    fn set_ret(&mut self, value: i64) {
+        self.x[0] = value as u64;
+    }
+
+    fn args(&self, _: &Memory) -> Result<[u64; 2], Fault> {
+        match self.sp % 16 {
+            0 => Ok([self.x[0], self.x[1]]),
+            _ => Err(Fault::Misaligned(self.sp)),
+        }
+    }
+
+    fn ret(&mut self, _: &mut Memory, value: u64) -> Result<(), Fault> {
+        self.pc = self.x[LR];
+        self.x[0] = value;
+
+        // Every register but the platform one (x18) and the callee-saved ones.
+        for n in (1..18).chain([LR]) {
+            self.x[n] = CLOBBERED;
+        }
+        Ok(())
+    }
+
+    const NRS: &'static Nrs = &crate::payloads::arm64::NRS;
+
+    fn gen_first(
+        syscalls: Syscalls,
+        second_payload_path: &str,
+        second_payload_size: u32,
+    ) -> Result<Payload, Error> {
+        crate::payloads::arm64::gen_first(syscalls, second_payload_path, second_payload_size)
+    }
+
+    fn gen_second(
+        syscalls: Syscalls,
+        original_code: &[u8],
+        original_ip: VirtAddr,
+        lib_path: &str,
+        dlopen: &ProcSym,
+        second_payload_size: u32,
+    ) -> Result<Payload, Error> {
+        crate::payloads::arm64::gen_second(
+            syscalls,
+            original_code,
+            original_ip,
+            lib_path,
+            dlopen,
+            second_payload_size,
+            PAGE_SIZE,
+        )
+    }
+}
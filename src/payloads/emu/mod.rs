@@ -0,0 +1,779 @@
+//! A small interpreter of the instructions the payloads are made of, so that they can be executed on any host.
+//!
+//! The target process is made of a [`Memory`], a [`Kernel`] which fakes the syscalls the payloads perform, and a [`Cpu`]
+//! of the target architecture. `dlopen` is mocked: it's never mapped, and calling it records its arguments.
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{os::VirtAddr, proc::ProcSym, Error};
+
+use super::{Nrs, Payload, Syscalls};
+
+mod arm;
+mod arm64;
+mod x86;
+
+/// The value the registers a function or syscall clobbers are set to.
+const CLOBBERED: u64 = 0xdead_beef_dead_beef;
+
+/// The page protection bits, as `mmap` and `mprotect` take them.
+pub(super) const READ: u32 = 1;
+pub(super) const WRITE: u32 = 2;
+pub(super) const EXEC: u32 = 4;
+
+const PAGE_SIZE: u64 = super::PAGE_SIZE as u64;
+
+/// The errors which may occur while executing a payload.
+#[derive(Debug, PartialEq)]
+pub(super) enum Fault {
+    /// It occurs when an address which is not mapped, or not readable, is read.
+    Read(VirtAddr),
+    /// It occurs when an address which is not mapped, or not writable, is written.
+    Write(VirtAddr),
+    /// It occurs when an address which is not mapped, or not executable, is executed.
+    Fetch(VirtAddr),
+    /// It occurs when the instruction located at the address is not one the interpreter knows.
+    Undefined(VirtAddr),
+    /// It occurs when the stack pointer is not aligned as the architecture requires, e.g. when `dlopen` is called.
+    Misaligned(VirtAddr),
+    /// It occurs when a syscall the kernel doesn't fake is performed.
+    Syscall(u64),
+    /// It occurs when the execution doesn't get back to the original instruction pointer.
+    Timeout,
+}
+
+/// What happened when an instruction was executed.
+pub(super) enum Step {
+    Next,
+    /// The instruction was a syscall, which must be performed before the next one is executed.
+    Syscall,
+}
+
+/// The condition flags of the ARM architectures.
+#[derive(Clone, Copy, Default)]
+pub(super) struct Nzcv {
+    n: bool,
+    z: bool,
+    c: bool,
+    v: bool,
+}
+
+impl Nzcv {
+    /// Checks whether the condition encoded as the 4 bit field `cond` holds, as both A32 and A64 encode it.
+    fn holds(self, cond: u32) -> bool {
+        let Nzcv { n, z, c, v } = self;
+        let holds = match cond >> 1 {
+            0 => z,
+            1 => c,
+            2 => n,
+            3 => v,
+            4 => c && !z,
+            5 => n == v,
+            6 => n == v && !z,
+            _ => return true,
+        };
+
+        holds != (cond & 1 != 0)
+    }
+
+    /// Gets the flags of the `bits` bit `value` a logical operation results in, e.g. `ANDS`.
+    fn logical(self, value: u64, bits: u32, c: bool) -> Self {
+        Nzcv {
+            n: value >> (bits - 1) & 1 != 0,
+            z: value & mask(bits) == 0,
+            c,
+            v: self.v,
+        }
+    }
+
+    /// Adds the `bits` bit values `x`, `y` and `carry`, returning the result and its flags.
+    fn add_with_carry(x: u64, y: u64, carry: bool, bits: u32) -> (u64, Self) {
+        let signed = |value: u64| sign_extend(value, bits);
+
+        let unsigned_sum = u128::from(x & mask(bits)) + u128::from(y & mask(bits)) + carry as u128;
+        let signed_sum = i128::from(signed(x)) + i128::from(signed(y)) + carry as i128;
+        let result = unsigned_sum as u64 & mask(bits);
+
+        let flags = Nzcv {
+            n: result >> (bits - 1) & 1 != 0,
+            z: result == 0,
+            c: unsigned_sum != u128::from(result),
+            v: signed_sum != i128::from(signed(result)),
+        };
+        (result, flags)
+    }
+}
+
+/// Gets the mask of the lowest `bits` bits.
+fn mask(bits: u32) -> u64 {
+    u64::MAX >> (64 - bits)
+}
+
+/// Sign-extends the lowest `bits` bits of `value`.
+fn sign_extend(value: u64, bits: u32) -> i64 {
+    (value << (64 - bits)) as i64 >> (64 - bits)
+}
+
+/// The syscalls the kernel fakes, once their arguments have been read from the registers.
+#[derive(Debug)]
+pub(super) enum Syscall {
+    /// `open`, or `openat` if `dirfd` is given.
+    Open {
+        dirfd: Option<u64>,
+        path: VirtAddr,
+    },
+    Close {
+        fd: u64,
+    },
+    /// `unlink`, or `unlinkat` if `dirfd` is given.
+    Unlink {
+        dirfd: Option<u64>,
+        path: VirtAddr,
+    },
+    Mmap {
+        len: u64,
+        prot: u32,
+        flags: u32,
+        fd: u64,
+        offset: u64,
+    },
+    /// `mmap` on `x86`, whose arguments are the 32 bit fields of a struct located at `args`.
+    OldMmap {
+        args: VirtAddr,
+    },
+    Mprotect {
+        addr: VirtAddr,
+        len: u64,
+        prot: u32,
+    },
+    Munmap {
+        addr: VirtAddr,
+        len: u64,
+    },
+    Pwrite {
+        fd: u64,
+        buf: VirtAddr,
+        count: u64,
+        offset: u64,
+    },
+}
+
+/// The processor of an architecture the payloads are generated for.
+pub(super) trait Cpu: Sized {
+    /// Creates the processor, with every register set to a distinct value.
+    fn new(ip: VirtAddr, sp: VirtAddr) -> Self;
+
+    /// Creates the processor the way the payloads leave it once they resume the execution at `ip`, which is the way
+    /// [`Cpu::new`] creates it unless a register is needed to branch there.
+    fn resumed(ip: VirtAddr, sp: VirtAddr) -> Self {
+        Self::new(ip, sp)
+    }
+
+    fn ip(&self) -> VirtAddr;
+
+    /// Gets the registers the payloads must preserve, including the stack pointer.
+    fn regs(&self) -> Vec<u64>;
+
+    /// Executes the instruction located at the instruction pointer.
+    fn step(&mut self, mem: &mut Memory) -> Result<Step, Fault>;
+
+    /// Gets the syscall the last executed instruction performs.
+    ///
+    /// Returns [`Fault::Syscall`] if it's not one of the faked ones.
+    fn syscall(&self) -> Result<Syscall, Fault>;
+
+    /// Gets the number of the syscall the last executed instruction performs.
+    fn nr(&self) -> u64;
+
+    /// Sets the value a syscall returns.
+    fn set_ret(&mut self, value: i64);
+
+    /// Gets the first two arguments of the function which has just been called.
+    fn args(&self, mem: &Memory) -> Result<[u64; 2], Fault>;
+
+    /// Returns `value` from the function which has just been called, clobbering the registers it's allowed to.
+    fn ret(&mut self, mem: &mut Memory, value: u64) -> Result<(), Fault>;
+
+    /// The syscall numbers of the architecture.
+    const NRS: &'static Nrs;
+
+    fn gen_first(
+        syscalls: Syscalls,
+        second_payload_path: &str,
+        second_payload_size: u32,
+    ) -> Result<Payload, Error>;
+
+    fn gen_second(
+        syscalls: Syscalls,
+        original_code: &[u8],
+        original_ip: VirtAddr,
+        lib_path: &str,
+        dlopen: &ProcSym,
+        second_payload_size: u32,
+    ) -> Result<Payload, Error>;
+}
+
+struct Page {
+    bytes: Box<[u8]>,
+    prot: u32,
+}
+
+/// The address space of the target process, made of pages.
+#[derive(Default)]
+pub(super) struct Memory {
+    pages: BTreeMap<VirtAddr, Page>,
+}
+
+impl Memory {
+    /// Maps the pages which `len` bytes starting at `addr` span, filled with zeros.
+    pub(super) fn map(&mut self, addr: VirtAddr, len: u64, prot: u32) {
+        for page in pages(addr, len) {
+            self.pages.insert(
+                page,
+                Page {
+                    bytes: vec![0; PAGE_SIZE as usize].into(),
+                    prot,
+                },
+            );
+        }
+    }
+
+    /// Unmaps the pages which `len` bytes starting at `addr` span, even if some of them are not mapped.
+    fn unmap(&mut self, addr: VirtAddr, len: u64) {
+        for page in pages(addr, len) {
+            self.pages.remove(&page);
+        }
+    }
+
+    /// Changes the protection of the pages which `len` bytes starting at `addr` span.
+    ///
+    /// Returns `false` if one of them is not mapped, in which case none is changed.
+    fn protect(&mut self, addr: VirtAddr, len: u64, prot: u32) -> bool {
+        if !pages(addr, len).all(|page| self.pages.contains_key(&page)) {
+            return false;
+        }
+
+        for page in pages(addr, len) {
+            self.pages.get_mut(&page).unwrap().prot = prot;
+        }
+        true
+    }
+
+    /// Gets the address of every mapped page, with its protection.
+    pub(super) fn layout(&self) -> Vec<(VirtAddr, u32)> {
+        self.pages
+            .iter()
+            .map(|(addr, page)| (*addr, page.prot))
+            .collect()
+    }
+
+    /// Copies the bytes located at `addr` into `buf`, regardless of the protection - e.g. as `/proc/<id>/mem` does.
+    ///
+    /// Returns [`Fault::Read`] if one of them is not mapped.
+    pub(super) fn peek(&self, addr: VirtAddr, buf: &mut [u8]) -> Result<(), Fault> {
+        self.load(addr, buf, 0).map_err(Fault::Read)
+    }
+
+    /// Copies `bytes` at `addr`, regardless of the protection - e.g. as `/proc/<id>/mem` does.
+    ///
+    /// Returns [`Fault::Write`] if one of them is not mapped.
+    pub(super) fn poke(&mut self, addr: VirtAddr, bytes: &[u8]) -> Result<(), Fault> {
+        self.store(addr, bytes, 0).map_err(Fault::Write)
+    }
+
+    /// Reads the bytes located at `addr` into `buf`.
+    ///
+    /// Returns [`Fault::Read`] if one of them is not readable.
+    pub(super) fn read(&self, addr: VirtAddr, buf: &mut [u8]) -> Result<(), Fault> {
+        self.load(addr, buf, READ).map_err(Fault::Read)
+    }
+
+    /// Writes `bytes` at `addr`.
+    ///
+    /// Returns [`Fault::Write`] if one of them is not writable, in which case none is written.
+    pub(super) fn write(&mut self, addr: VirtAddr, bytes: &[u8]) -> Result<(), Fault> {
+        self.store(addr, bytes, WRITE).map_err(Fault::Write)
+    }
+
+    /// Fetches the bytes of the instruction located at `addr` into `buf`.
+    ///
+    /// Returns [`Fault::Fetch`] if one of them is not executable.
+    pub(super) fn fetch(&self, addr: VirtAddr, buf: &mut [u8]) -> Result<(), Fault> {
+        self.load(addr, buf, EXEC).map_err(Fault::Fetch)
+    }
+
+    pub(super) fn read_u32(&self, addr: VirtAddr) -> Result<u32, Fault> {
+        let mut bytes = [0; 4];
+        self.read(addr, &mut bytes)?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    pub(super) fn read_u64(&self, addr: VirtAddr) -> Result<u64, Fault> {
+        let mut bytes = [0; 8];
+        self.read(addr, &mut bytes)?;
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    pub(super) fn write_u32(&mut self, addr: VirtAddr, value: u32) -> Result<(), Fault> {
+        self.write(addr, &value.to_le_bytes())
+    }
+
+    pub(super) fn write_u64(&mut self, addr: VirtAddr, value: u64) -> Result<(), Fault> {
+        self.write(addr, &value.to_le_bytes())
+    }
+
+    /// Reads the NUL terminated string located at `addr`.
+    fn read_c_str(&self, addr: VirtAddr) -> Result<String, Fault> {
+        let mut bytes = Vec::new();
+
+        loop {
+            let mut byte = [0];
+            self.read(addr + bytes.len() as u64, &mut byte)?;
+
+            match byte {
+                [0] => return Ok(String::from_utf8_lossy(&bytes).into()),
+                [byte] => bytes.push(byte),
+            }
+        }
+    }
+
+    /// Copies the bytes located at `addr` into `buf`, if their pages are mapped with the `prot` protection bits.
+    ///
+    /// Returns the address of the first byte which can't be copied.
+    fn load(&self, addr: VirtAddr, buf: &mut [u8], prot: u32) -> Result<(), VirtAddr> {
+        for (index, byte) in buf.iter_mut().enumerate() {
+            let addr = addr.wrapping_add(index as u64);
+
+            match self.pages.get(&(addr & !(PAGE_SIZE - 1))) {
+                Some(page) if page.prot & prot == prot => {
+                    *byte = page.bytes[(addr % PAGE_SIZE) as usize]
+                }
+                _ => return Err(addr),
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies `bytes` at `addr`, if their pages are mapped with the `prot` protection bits: otherwise, none is.
+    ///
+    /// Returns the address of the first byte which can't be copied.
+    fn store(&mut self, addr: VirtAddr, bytes: &[u8], prot: u32) -> Result<(), VirtAddr> {
+        let page = |addr: VirtAddr| addr & !(PAGE_SIZE - 1);
+
+        if let Some(addr) = (0..bytes.len() as u64)
+            .map(|index| addr.wrapping_add(index))
+            .find(|addr| {
+                !matches!(self.pages.get(&page(*addr)), Some(page) if page.prot & prot == prot)
+            })
+        {
+            return Err(addr);
+        }
+
+        for (index, value) in bytes.iter().enumerate() {
+            let addr = addr.wrapping_add(index as u64);
+            self.pages.get_mut(&page(addr)).unwrap().bytes[(addr % PAGE_SIZE) as usize] = *value;
+        }
+        Ok(())
+    }
+}
+
+/// Gets the address of every page which `len` bytes starting at `addr` span.
+fn pages(addr: VirtAddr, len: u64) -> impl Iterator<Item = VirtAddr> {
+    let first = addr & !(PAGE_SIZE - 1);
+    (first..addr + len).step_by(PAGE_SIZE as usize)
+}
+
+/// An open file descriptor.
+enum Open {
+    File(Vec<u8>),
+    /// `/proc/self/mem`.
+    Mem,
+}
+
+/// The fake kernel, which performs the syscalls of the payloads on the [`Memory`] and a small file system.
+pub(super) struct Kernel {
+    /// The files, by path.
+    pub(super) files: HashMap<String, Vec<u8>>,
+    fds: HashMap<u64, Open>,
+    /// The address the next mapping is placed at.
+    next_mapping: VirtAddr,
+    /// The arguments every `dlopen` call was given.
+    pub(super) dlopened: Vec<(String, u64)>,
+    /// Whether `mprotect` fails, e.g. because of a security policy.
+    pub(super) deny_mprotect: bool,
+    /// The number of every performed syscall.
+    pub(super) performed: Vec<u64>,
+}
+
+const MAP_ANONYMOUS: u32 = 0x20;
+const AT_FDCWD: i32 = -100;
+
+const ENOENT: i64 = 2;
+const EIO: i64 = 5;
+const EBADF: i64 = 9;
+const ENOMEM: i64 = 12;
+const EACCES: i64 = 13;
+const EFAULT: i64 = 14;
+const EINVAL: i64 = 22;
+
+impl Kernel {
+    fn new(next_mapping: VirtAddr) -> Self {
+        Self {
+            files: HashMap::new(),
+            fds: HashMap::new(),
+            next_mapping,
+            dlopened: Vec::new(),
+            deny_mprotect: false,
+            performed: Vec::new(),
+        }
+    }
+
+    /// Gets how many file descriptors are open.
+    pub(super) fn open_fds(&self) -> usize {
+        self.fds.len()
+    }
+
+    /// Performs `syscall`, returning either its result or a negated error number.
+    fn perform(&mut self, mem: &mut Memory, syscall: Syscall) -> Result<i64, Fault> {
+        Ok(match syscall {
+            // Paths are only resolved against the current directory, so that a path relative to any other directory is
+            // noticed even though the payloads only use absolute ones.
+            Syscall::Open {
+                dirfd: Some(dirfd), ..
+            }
+            | Syscall::Unlink {
+                dirfd: Some(dirfd), ..
+            } if dirfd as i32 != AT_FDCWD => -EBADF,
+            Syscall::Open { path, .. } => {
+                let open = match mem.read_c_str(path)? {
+                    path if path == "/proc/self/mem" => Open::Mem,
+                    path => match self.files.get(&path) {
+                        Some(bytes) => Open::File(bytes.clone()),
+                        None => return Ok(-ENOENT),
+                    },
+                };
+                let fd = (3..).find(|fd| !self.fds.contains_key(fd)).unwrap();
+
+                self.fds.insert(fd, open);
+                fd as i64
+            }
+            Syscall::Close { fd } => match self.fds.remove(&fd) {
+                Some(_) => 0,
+                None => -EBADF,
+            },
+            Syscall::Unlink { path, .. } => match self.files.remove(&mem.read_c_str(path)?) {
+                Some(_) => 0,
+                None => -ENOENT,
+            },
+            Syscall::Mmap {
+                len,
+                prot,
+                flags,
+                fd,
+                offset,
+            } => {
+                let bytes = match self.fds.get(&fd) {
+                    _ if flags & MAP_ANONYMOUS != 0 => &[][..],
+                    Some(Open::File(bytes)) => bytes,
+                    _ => return Ok(-EBADF),
+                };
+                if len == 0 || offset % PAGE_SIZE != 0 {
+                    return Ok(-EINVAL);
+                }
+
+                let addr = self.next_mapping;
+                let len = len.div_ceil(PAGE_SIZE) * PAGE_SIZE;
+                let bytes = bytes.get(offset as usize..).unwrap_or_default();
+
+                mem.map(addr, len, prot);
+                mem.poke(addr, &bytes[..bytes.len().min(len as usize)])?;
+                self.next_mapping += len;
+                addr as i64
+            }
+            Syscall::OldMmap { args } => {
+                let [_, len, prot, flags, fd, offset] =
+                    [0, 4, 8, 12, 16, 20].map(|field| mem.read_u32(args + field).map(u64::from));
+
+                return self.perform(
+                    mem,
+                    Syscall::Mmap {
+                        len: len?,
+                        prot: prot? as u32,
+                        flags: flags? as u32,
+                        fd: fd?,
+                        offset: offset?,
+                    },
+                );
+            }
+            Syscall::Mprotect { .. } if self.deny_mprotect => -EACCES,
+            Syscall::Mprotect { addr, .. } | Syscall::Munmap { addr, .. }
+                if addr % PAGE_SIZE != 0 =>
+            {
+                -EINVAL
+            }
+            Syscall::Mprotect { addr, len, prot } => match mem.protect(addr, len, prot) {
+                true => 0,
+                false => -ENOMEM,
+            },
+            Syscall::Munmap { addr, len } => {
+                mem.unmap(addr, len);
+                0
+            }
+            Syscall::Pwrite {
+                fd,
+                buf,
+                count,
+                offset,
+            } => {
+                let Some(Open::Mem) = self.fds.get(&fd) else {
+                    return Ok(-EBADF);
+                };
+                let mut bytes = vec![0; count as usize];
+
+                if mem.read(buf, &mut bytes).is_err() {
+                    return Ok(-EFAULT);
+                }
+                match mem.poke(offset, &bytes) {
+                    Ok(()) => count as i64,
+                    Err(_) => -EIO,
+                }
+            }
+        })
+    }
+}
+
+/// The target process.
+pub(super) struct Target<C> {
+    pub(super) cpu: C,
+    pub(super) mem: Memory,
+    pub(super) kernel: Kernel,
+    /// The address `dlopen` is mocked at.
+    dlopen: VirtAddr,
+}
+
+impl<C: Cpu> Target<C> {
+    /// Executes the instructions starting from the instruction pointer, until it gets back to `ip`.
+    ///
+    /// Returns [`Fault::Timeout`] if it doesn't within `steps` instructions.
+    fn run_until(&mut self, ip: VirtAddr, steps: usize) -> Result<(), Fault> {
+        for _ in 0..steps {
+            if self.cpu.ip() == self.dlopen {
+                let [path, flags] = self.cpu.args(&self.mem)?;
+                let path = self.mem.read_c_str(path)?;
+
+                self.kernel.dlopened.push((path, flags));
+                // Any non-NULL handle.
+                self.cpu.ret(&mut self.mem, 0x5a5a0)?;
+            } else if let Step::Syscall = self.cpu.step(&mut self.mem)? {
+                self.kernel.performed.push(self.cpu.nr());
+                let value = self.kernel.perform(&mut self.mem, self.cpu.syscall()?)?;
+                self.cpu.set_ret(value);
+            }
+
+            if self.cpu.ip() == ip {
+                return Ok(());
+            }
+        }
+
+        Err(Fault::Timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{os::VirtAddr, payloads::Syscalls, proc::ProcSym};
+
+    use super::{
+        arm::Arm, arm64::Arm64, x86::X86, Cpu, Kernel, Memory, Target, EXEC, PAGE_SIZE, READ, WRITE,
+    };
+
+    /// The address the target process is blocked at.
+    const IP: VirtAddr = 0x10000 + 0x344;
+    /// The code the target process is blocked in.
+    const CODE: (VirtAddr, u64) = (0x10000, 0x2000);
+    /// The stack of the target process, whose pointer is somewhere in the middle of a page.
+    const STACK: (VirtAddr, u64) = (0x7ff0_0000, 0x4000);
+    const SP: VirtAddr = 0x7ff0_2a30;
+    const DLOPEN: VirtAddr = 0x4000_1230;
+    /// The address the mappings the payloads make are placed from.
+    const MAPPINGS: VirtAddr = 0x2000_0000;
+    const LIB_PATH: &str = "/tmp/libevil.so";
+    const SECOND_PAYLOAD_PATH: &str = "/tmp/payload-1-2.bin";
+
+    /// Every combination of the alternative syscalls, including those an architecture ignores.
+    const SYSCALLS: [Syscalls; 4] = [
+        Syscalls {
+            at: false,
+            old_mmap: false,
+        },
+        Syscalls {
+            at: true,
+            old_mmap: false,
+        },
+        Syscalls {
+            at: false,
+            old_mmap: true,
+        },
+        Syscalls {
+            at: true,
+            old_mmap: true,
+        },
+    ];
+
+    /// Injects the payloads generated with `syscalls` into a target process the way [`crate::intruduce`] does, then
+    /// executes them.
+    ///
+    /// Returns the target process once the execution gets back to the original instruction pointer.
+    fn intruduce<C: Cpu>(syscalls: Syscalls, deny_mprotect: bool) -> Target<C> {
+        let mut target = Target {
+            cpu: C::new(IP, SP),
+            mem: Memory::default(),
+            kernel: Kernel::new(MAPPINGS),
+            dlopen: DLOPEN,
+        };
+        target.kernel.deny_mprotect = deny_mprotect;
+
+        target.mem.map(CODE.0, CODE.1, READ | EXEC);
+        target.mem.map(STACK.0, STACK.1, READ | WRITE);
+
+        target.mem.poke(CODE.0, &noise(CODE)).unwrap();
+        target.mem.poke(STACK.0, &noise(STACK)).unwrap();
+
+        let first_payload_len = C::gen_first(syscalls, SECOND_PAYLOAD_PATH, 0)
+            .unwrap()
+            .code()
+            .len();
+        let mut original_code = vec![0; first_payload_len];
+        target.mem.peek(IP, &mut original_code).unwrap();
+
+        let second_payload = crate::payloads::fit_second(|size| {
+            C::gen_second(
+                syscalls,
+                &original_code,
+                IP,
+                LIB_PATH,
+                &ProcSym::new(DLOPEN),
+                size,
+            )
+        })
+        .unwrap();
+        let first_payload = C::gen_first(
+            syscalls,
+            SECOND_PAYLOAD_PATH,
+            crate::payloads::second_payload_size(second_payload.code()).unwrap(),
+        )
+        .unwrap();
+
+        target.mem.poke(IP, first_payload.code()).unwrap();
+        target
+            .kernel
+            .files
+            .insert(SECOND_PAYLOAD_PATH.into(), second_payload.code().to_vec());
+
+        if let Err(fault) = target.run_until(IP, 100_000) {
+            panic!(
+                "{:?} at {:#x}\nfirst payload:\n{}\nsecond payload:\n{}",
+                fault,
+                target.cpu.ip(),
+                first_payload.disassemble(),
+                second_payload.disassemble()
+            );
+        }
+
+        target
+    }
+
+    /// Checks that the target process is back to its original state once the payloads have been executed, but for
+    /// the executable stack pages the stub is run from or - if `mapped` - the second payload mapping, which is not
+    /// writable.
+    fn assert_restored<C: Cpu>(target: &mut Target<C>, mapped: bool) {
+        // The condition flags are not preserved, so they are not compared.
+        assert_eq!(target.cpu.regs(), C::resumed(IP, SP).regs(), "registers");
+
+        let mut code = vec![0; CODE.1 as usize];
+        target.mem.peek(CODE.0, &mut code).unwrap();
+        assert!(code == noise(CODE), "original code");
+
+        // What's below the stack pointer is free to be clobbered.
+        let mut stack = vec![0; (STACK.0 + STACK.1 - SP) as usize];
+        target.mem.peek(SP, &mut stack).unwrap();
+        assert!(stack == noise(STACK)[(SP - STACK.0) as usize..], "stack");
+
+        assert_eq!(target.kernel.dlopened, [(LIB_PATH.to_string(), 1)]);
+        assert!(target.kernel.files.is_empty(), "second payload file");
+        assert_eq!(target.kernel.open_fds(), 0, "file descriptors");
+
+        let (mut left, mut executable_stack) = (Vec::new(), false);
+        for (addr, prot) in target.mem.layout() {
+            if (CODE.0..CODE.0 + CODE.1).contains(&addr) {
+                assert_eq!(prot, READ | EXEC, "code protection");
+            } else if (STACK.0..STACK.0 + STACK.1).contains(&addr) {
+                if prot == READ | WRITE | EXEC && addr < SP {
+                    executable_stack = true;
+                } else {
+                    assert_eq!(prot, READ | WRITE, "stack protection");
+                }
+            } else {
+                assert_eq!(prot, READ | EXEC, "mapping protection");
+                left.push(addr);
+            }
+        }
+
+        // Nothing is left mapped, but the second payload if the stub couldn't be run.
+        let second_payload = (MAPPINGS..target.kernel.next_mapping).step_by(PAGE_SIZE as usize);
+        assert_eq!(executable_stack, !mapped, "executable stack");
+        assert_eq!(
+            left,
+            second_payload.filter(|_| mapped).collect::<Vec<_>>(),
+            "mappings left"
+        );
+    }
+
+    /// Gets the distinct bytes the `(addr, len)` region is filled with, so that any corruption is noticed.
+    fn noise((addr, len): (VirtAddr, u64)) -> Vec<u8> {
+        (addr..addr + len)
+            .map(|addr| (addr.wrapping_mul(0x9e3779b1) >> 11) as u8)
+            .collect()
+    }
+
+    /// Checks that the syscalls the payloads perform are the described ones, which seccomp filters are evaluated on.
+    fn assert_described<C: Cpu>(target: &Target<C>, syscalls: Syscalls) {
+        let described: Vec<_> = C::NRS
+            .calls(syscalls)
+            .iter()
+            .map(|call| u64::from(call.nr))
+            .collect();
+
+        assert_eq!(target.kernel.performed, described, "{:?}", syscalls);
+    }
+
+    #[test]
+    fn restores_the_target() {
+        for syscalls in SYSCALLS {
+            assert_restored(&mut intruduce::<Arm>(syscalls, false), false);
+            assert_restored(&mut intruduce::<Arm64>(syscalls, false), false);
+            assert_restored(&mut intruduce::<X86<false>>(syscalls, false), false);
+            assert_restored(&mut intruduce::<X86<true>>(syscalls, false), false);
+        }
+    }
+
+    #[test]
+    fn restores_the_target_if_the_stack_can_not_be_made_executable() {
+        for syscalls in SYSCALLS {
+            assert_restored(&mut intruduce::<Arm>(syscalls, true), true);
+            assert_restored(&mut intruduce::<Arm64>(syscalls, true), true);
+            assert_restored(&mut intruduce::<X86<false>>(syscalls, true), true);
+            assert_restored(&mut intruduce::<X86<true>>(syscalls, true), true);
+        }
+    }
+
+    #[test]
+    fn performs_the_described_syscalls() {
+        for syscalls in SYSCALLS {
+            assert_described(&intruduce::<Arm>(syscalls, false), syscalls);
+            assert_described(&intruduce::<Arm64>(syscalls, false), syscalls);
+            assert_described(&intruduce::<X86<false>>(syscalls, false), syscalls);
+            assert_described(&intruduce::<X86<true>>(syscalls, false), syscalls);
+        }
+    }
+}
@@ -0,0 +1,614 @@
+use crate::{
+    os::VirtAddr,
+    payloads::{Nrs, Payload, Syscalls},
+    proc::ProcSym,
+    Error,
+};
+
+use super::{mask, Cpu, Fault, Memory, Step, Syscall, CLOBBERED, PAGE_SIZE};
+
+/// An `x86` processor in protected mode, or an `x86-64` one in long mode if `LONG`.
+pub(super) struct X86<const LONG: bool> {
+    regs: [u64; 16],
+    ip: u64,
+    flags: Flags,
+}
+
+#[derive(Default)]
+struct Flags {
+    cf: bool,
+    zf: bool,
+    sf: bool,
+    of: bool,
+    pf: bool,
+}
+
+impl Flags {
+    /// Gets the flags of the `bits` bit `value` a logical operation results in.
+    fn logical(value: u64, bits: u32) -> Self {
+        Flags {
+            cf: false,
+            zf: value & mask(bits) == 0,
+            sf: value >> (bits - 1) & 1 != 0,
+            of: false,
+            pf: (value as u8).count_ones().is_multiple_of(2),
+        }
+    }
+
+    /// Checks whether the condition `cc` of a `J<cc>` holds.
+    fn holds(&self, cc: u8) -> bool {
+        let holds = match cc >> 1 {
+            0 => self.of,
+            1 => self.cf,
+            2 => self.zf,
+            3 => self.cf || self.zf,
+            4 => self.sf,
+            5 => self.pf,
+            6 => self.sf != self.of,
+            _ => self.zf || self.sf != self.of,
+        };
+
+        holds != (cc & 1 != 0)
+    }
+}
+
+const RAX: usize = 0;
+const RCX: usize = 1;
+const RSP: usize = 4;
+
+/// The `r/m` operand of a `ModRM` encoded instruction.
+enum Rm {
+    Reg(usize),
+    /// A memory operand, whose address is relative to the next instruction if RIP-relative.
+    Mem {
+        addr: u64,
+        rip: bool,
+    },
+}
+
+/// The bytes of the instruction being executed, which are fetched beforehand.
+struct Cursor {
+    bytes: Vec<u8>,
+    ip: u64,
+    len: u64,
+}
+
+impl Cursor {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], Fault> {
+        let start = self.len as usize;
+        let bytes = self
+            .bytes
+            .get(start..start + N)
+            .ok_or(Fault::Fetch(self.ip + self.len))?;
+        self.len += N as u64;
+        Ok(bytes.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8, Fault> {
+        self.take().map(u8::from_le_bytes)
+    }
+
+    fn i8(&mut self) -> Result<i64, Fault> {
+        self.take().map(|bytes| i8::from_le_bytes(bytes).into())
+    }
+
+    fn i32(&mut self) -> Result<i64, Fault> {
+        self.take().map(|bytes| i32::from_le_bytes(bytes).into())
+    }
+
+    fn u64(&mut self) -> Result<u64, Fault> {
+        self.take().map(u64::from_le_bytes)
+    }
+
+    /// Gets the address of the next instruction.
+    fn next(&self) -> u64 {
+        self.ip + self.len
+    }
+}
+
+impl<const LONG: bool> X86<LONG> {
+    /// The size of addresses and of the stack slots, in bits.
+    const BITS: u32 = if LONG { 64 } else { 32 };
+
+    fn get(&self, reg: usize, bits: u32) -> u64 {
+        self.regs[reg] & mask(bits)
+    }
+
+    /// Sets the register `reg`: a 32 bit value is zero-extended.
+    fn set(&mut self, reg: usize, value: u64, bits: u32) {
+        self.regs[reg] = value & mask(bits);
+    }
+
+    fn push(&mut self, mem: &mut Memory, value: u64) -> Result<(), Fault> {
+        let sp = self.regs[RSP].wrapping_sub(u64::from(Self::BITS / 8)) & mask(Self::BITS);
+        self.store(mem, sp, value, Self::BITS)?;
+        self.regs[RSP] = sp;
+        Ok(())
+    }
+
+    fn pop(&mut self, mem: &Memory) -> Result<u64, Fault> {
+        let value = self.load(mem, self.regs[RSP], Self::BITS)?;
+        self.regs[RSP] = (self.regs[RSP] + u64::from(Self::BITS / 8)) & mask(Self::BITS);
+        Ok(value)
+    }
+
+    fn load(&self, mem: &Memory, addr: u64, bits: u32) -> Result<u64, Fault> {
+        match bits {
+            64 => mem.read_u64(addr),
+            _ => mem.read_u32(addr).map(u64::from),
+        }
+    }
+
+    fn store(&self, mem: &mut Memory, addr: u64, value: u64, bits: u32) -> Result<(), Fault> {
+        match bits {
+            64 => mem.write_u64(addr, value),
+            _ => mem.write_u32(addr, value as u32),
+        }
+    }
+
+    /// Decodes `<ModRM> [SIB] [disp]`, returning the `reg` field (either a register or an opcode extension)
+    /// and the `r/m` operand.
+    fn modrm(&self, cur: &mut Cursor, rex: u8) -> Result<(usize, Rm), Fault> {
+        let modrm = cur.u8()?;
+        let (mode, reg, rm) = (
+            modrm >> 6,
+            usize::from(modrm >> 3 & 7 | (rex & 4) << 1),
+            modrm & 7,
+        );
+        let rex_b = usize::from(rex & 1) << 3;
+
+        if mode == 3 {
+            return Ok((reg, Rm::Reg(usize::from(rm) | rex_b)));
+        }
+
+        let (base, index, rip) = match rm {
+            4 => {
+                let sib = cur.u8()?;
+                let index = match usize::from(sib >> 3 & 7) | usize::from(rex & 2) << 2 {
+                    4 => 0,
+                    index => self.regs[index] << (sib >> 6),
+                };
+
+                match sib & 7 {
+                    5 if mode == 0 => (0, index, false),
+                    base => (self.regs[usize::from(base) | rex_b], index, false),
+                }
+            }
+            5 if mode == 0 => (0, 0, LONG),
+            base => (self.regs[usize::from(base) | rex_b], 0, false),
+        };
+
+        let disp = match (mode, rm) {
+            (0, 5) => cur.i32()?,
+            (0, 4) if base == 0 && index == 0 => cur.i32()?,
+            (0, _) => 0,
+            (1, _) => cur.i8()?,
+            _ => cur.i32()?,
+        };
+
+        let addr = base.wrapping_add(index).wrapping_add(disp as u64) & mask(Self::BITS);
+        Ok((reg, Rm::Mem { addr, rip }))
+    }
+
+    /// Gets the address of a memory operand, once every byte of its instruction has been decoded.
+    fn addr(cur: &Cursor, addr: u64, rip: bool) -> u64 {
+        match rip {
+            true => cur.next().wrapping_add(addr),
+            false => addr,
+        }
+    }
+
+    fn read_rm(&self, mem: &Memory, cur: &Cursor, rm: &Rm, bits: u32) -> Result<u64, Fault> {
+        match *rm {
+            Rm::Reg(reg) => Ok(self.get(reg, bits)),
+            Rm::Mem { addr, rip } => self.load(mem, Self::addr(cur, addr, rip), bits),
+        }
+    }
+
+    fn write_rm(
+        &mut self,
+        mem: &mut Memory,
+        cur: &Cursor,
+        rm: &Rm,
+        value: u64,
+        bits: u32,
+    ) -> Result<(), Fault> {
+        match *rm {
+            Rm::Reg(reg) => self.set(reg, value, bits),
+            Rm::Mem { addr, rip } => self.store(mem, Self::addr(cur, addr, rip), value, bits)?,
+        }
+        Ok(())
+    }
+
+    /// Performs the arithmetic or logical operation `/digit` of the `0x80`-`0x83` group on `dst` and `src`.
+    ///
+    /// Returns the result, unless it's a comparison.
+    fn alu(&mut self, digit: u8, dst: u64, src: u64, bits: u32) -> Option<Option<u64>> {
+        let sign = |value: u64| value >> (bits - 1) & 1 != 0;
+
+        let value = match digit {
+            0 => {
+                let value = dst.wrapping_add(src) & mask(bits);
+                self.flags = Flags {
+                    cf: value < dst,
+                    of: sign(!(dst ^ src) & (dst ^ value)),
+                    ..Flags::logical(value, bits)
+                };
+                return Some(Some(value));
+            }
+            5 | 7 => {
+                let value = dst.wrapping_sub(src) & mask(bits);
+                self.flags = Flags {
+                    cf: dst < src,
+                    of: sign((dst ^ src) & (dst ^ value)),
+                    ..Flags::logical(value, bits)
+                };
+                return Some((digit == 5).then_some(value));
+            }
+            1 => dst | src,
+            4 => dst & src,
+            6 => dst ^ src,
+            _ => return None,
+        };
+
+        self.flags = Flags::logical(value, bits);
+        Some(Some(value))
+    }
+
+    /// Executes the instruction located at the instruction pointer, which is decoded by `cur`.
+    fn exec(&mut self, mem: &mut Memory, cur: &mut Cursor) -> Result<Step, Fault> {
+        let undefined = Fault::Undefined(cur.ip);
+
+        let mut opcode = cur.u8()?;
+        let mut rex = 0;
+        if LONG && opcode & 0xf0 == 0x40 {
+            rex = opcode;
+            opcode = cur.u8()?;
+        }
+        let bits = if rex & 8 != 0 { 64 } else { 32 };
+        let rex_b = usize::from(rex & 1) << 3;
+
+        match opcode {
+            0x50..=0x57 => {
+                let value = self.get(usize::from(opcode & 7) | rex_b, Self::BITS);
+                self.push(mem, value)?;
+            }
+            0x58..=0x5f => {
+                let value = self.pop(mem)?;
+                self.set(usize::from(opcode & 7) | rex_b, value, Self::BITS);
+            }
+            0x6a | 0x68 => {
+                let imm = if opcode == 0x6a {
+                    cur.i8()?
+                } else {
+                    cur.i32()?
+                };
+                self.push(mem, imm as u64 & mask(Self::BITS))?;
+            }
+            // The `<r/m>, <reg>` forms of ADD, OR, AND, SUB, XOR, CMP, TEST and MOV.
+            0x01 | 0x09 | 0x21 | 0x29 | 0x31 | 0x39 | 0x85 | 0x89 => {
+                let (reg, rm) = self.modrm(cur, rex)?;
+                let (dst, src) = (self.read_rm(mem, cur, &rm, bits)?, self.get(reg, bits));
+
+                let value = match opcode {
+                    0x85 => {
+                        self.flags = Flags::logical(dst & src, bits);
+                        None
+                    }
+                    0x89 => Some(src),
+                    _ => self.alu(opcode >> 3, dst, src, bits).ok_or(undefined)?,
+                };
+                if let Some(value) = value {
+                    self.write_rm(mem, cur, &rm, value, bits)?;
+                }
+            }
+            // MOV, LEA.
+            0x8b | 0x8d => {
+                let (reg, rm) = self.modrm(cur, rex)?;
+
+                let value = match (opcode, &rm) {
+                    (0x8b, _) => self.read_rm(mem, cur, &rm, bits)?,
+                    (_, Rm::Mem { addr, rip }) => Self::addr(cur, *addr, *rip),
+                    _ => return Err(undefined),
+                };
+                self.set(reg, value, bits);
+            }
+            // The `0x80`-`0x83` group, with a sign-extended immediate.
+            0x81 | 0x83 => {
+                let (digit, rm) = self.modrm(cur, rex)?;
+                let imm = if opcode == 0x83 {
+                    cur.i8()?
+                } else {
+                    cur.i32()?
+                };
+                let dst = self.read_rm(mem, cur, &rm, bits)?;
+
+                let value = self
+                    .alu(digit as u8, dst, imm as u64 & mask(bits), bits)
+                    .ok_or(undefined)?;
+                if let Some(value) = value {
+                    self.write_rm(mem, cur, &rm, value, bits)?;
+                }
+            }
+            // The `EAX, <imm32>` forms.
+            0x05 | 0x0d | 0x25 | 0x2d | 0x35 | 0x3d => {
+                let imm = cur.i32()? as u64 & mask(bits);
+                let dst = self.get(RAX, bits);
+
+                if let Some(value) = self.alu(opcode >> 3, dst, imm, bits).ok_or(undefined)? {
+                    self.set(RAX, value, bits);
+                }
+            }
+            0xc7 => match self.modrm(cur, rex)? {
+                (0, rm) => {
+                    let imm = cur.i32()? as u64 & mask(bits);
+                    self.write_rm(mem, cur, &rm, imm, bits)?;
+                }
+                _ => return Err(undefined),
+            },
+            0xb8..=0xbf => {
+                let imm = if bits == 64 {
+                    cur.u64()?
+                } else {
+                    cur.i32()? as u64
+                };
+                self.set(usize::from(opcode & 7) | rex_b, imm, bits);
+            }
+            0xe8 | 0xe9 | 0xeb | 0x70..=0x7f => {
+                let rel = if opcode >= 0xe8 && opcode != 0xeb {
+                    cur.i32()?
+                } else {
+                    cur.i8()?
+                };
+                let target = cur.next().wrapping_add(rel as u64) & mask(Self::BITS);
+
+                match opcode {
+                    0xe8 => {
+                        self.push(mem, cur.next())?;
+                        self.ip = target;
+                    }
+                    0xe9 | 0xeb => self.ip = target,
+                    cc if self.flags.holds(cc & 0xf) => self.ip = target,
+                    _ => self.ip = cur.next(),
+                }
+                return Ok(Step::Next);
+            }
+            0x0f => match cur.u8()? {
+                // SYSCALL, which clobbers RCX and R11.
+                0x05 if LONG => {
+                    self.regs[RCX] = cur.next();
+                    self.regs[11] = CLOBBERED;
+                    self.ip = cur.next();
+                    return Ok(Step::Syscall);
+                }
+                cc @ 0x80..=0x8f => {
+                    let rel = cur.i32()?;
+                    self.ip = match self.flags.holds(cc & 0xf) {
+                        true => cur.next().wrapping_add(rel as u64) & mask(Self::BITS),
+                        false => cur.next(),
+                    };
+                    return Ok(Step::Next);
+                }
+                _ => return Err(undefined),
+            },
+            0xcd if !LONG => match cur.u8()? {
+                0x80 => {
+                    self.ip = cur.next();
+                    return Ok(Step::Syscall);
+                }
+                _ => return Err(undefined),
+            },
+            // CALL, JMP (`<r/m>`), whose operand is always of the address size.
+            0xff => {
+                let (digit, rm) = self.modrm(cur, rex)?;
+                let target = self.read_rm(mem, cur, &rm, Self::BITS)?;
+
+                match digit {
+                    2 => self.push(mem, cur.next())?,
+                    4 => (),
+                    _ => return Err(undefined),
+                }
+                self.ip = target;
+                return Ok(Step::Next);
+            }
+            0xc3 => {
+                self.ip = self.pop(mem)?;
+                return Ok(Step::Next);
+            }
+            0x90 => (),
+            _ => return Err(undefined),
+        }
+
+        self.ip = cur.next();
+        Ok(Step::Next)
+    }
+}
+
+impl<const LONG: bool> Cpu for X86<LONG> {
+    fn new(ip: VirtAddr, sp: VirtAddr) -> Self {
+        let mut regs: [u64; 16] =
+            std::array::from_fn(|n| (0x0101010101010101 * (n as u64 + 1)) & mask(Self::BITS));
+        regs[RSP] = sp;
+
+        Self {
+            regs,
+            ip,
+            flags: Flags::default(),
+        }
+    }
+
+    fn ip(&self) -> VirtAddr {
+        self.ip
+    }
+
+    fn regs(&self) -> Vec<u64> {
+        self.regs[..if LONG { 16 } else { 8 }].to_vec()
+    }
+
+    fn step(&mut self, mem: &mut Memory) -> Result<Step, Fault> {
+        // An instruction is at most 15 bytes long, and may end right before a non executable page.
+        let mut bytes = Vec::new();
+        let mut byte = [0];
+        while bytes.len() < 15 && mem.fetch(self.ip + bytes.len() as u64, &mut byte).is_ok() {
+            bytes.push(byte[0]);
+        }
+
+        let mut cur = Cursor {
+            bytes,
+            ip: self.ip,
+            len: 0,
+        };
+        self.exec(mem, &mut cur)
+    }
+
+    fn syscall(&self) -> Result<Syscall, Fault> {
+        let r = self.regs;
+
+        Ok(match (LONG, r[RAX]) {
+            (false, 5) | (true, 2) => Syscall::Open {
+                dirfd: None,
+                path: r[if LONG { 7 } else { 3 }],
+            },
+            (false, 295) | (true, 257) => Syscall::Open {
+                dirfd: Some(r[if LONG { 7 } else { 3 }]),
+                path: r[if LONG { 6 } else { 1 }],
+            },
+            (false, 6) | (true, 3) => Syscall::Close {
+                fd: r[if LONG { 7 } else { 3 }],
+            },
+            (false, 10) | (true, 87) => Syscall::Unlink {
+                dirfd: None,
+                path: r[if LONG { 7 } else { 3 }],
+            },
+            (false, 301) | (true, 263) => Syscall::Unlink {
+                dirfd: Some(r[if LONG { 7 } else { 3 }]),
+                path: r[if LONG { 6 } else { 1 }],
+            },
+            // `mmap`, which takes its arguments from memory.
+            (false, 90) => Syscall::OldMmap { args: r[3] },
+            // `mmap2`, whose offset is in pages.
+            (false, 192) => Syscall::Mmap {
+                len: r[1],
+                prot: r[2] as u32,
+                flags: r[6] as u32,
+                fd: r[7],
+                offset: r[5] * 4096,
+            },
+            (true, 9) => Syscall::Mmap {
+                len: r[6],
+                prot: r[2] as u32,
+                flags: r[10] as u32,
+                fd: r[8],
+                offset: r[9],
+            },
+            (false, 125) => Syscall::Mprotect {
+                addr: r[3],
+                len: r[1],
+                prot: r[2] as u32,
+            },
+            (true, 10) => Syscall::Mprotect {
+                addr: r[7],
+                len: r[6],
+                prot: r[2] as u32,
+            },
+            (false, 91) => Syscall::Munmap {
+                addr: r[3],
+                len: r[1],
+            },
+            (true, 11) => Syscall::Munmap {
+                addr: r[7],
+                len: r[6],
+            },
+            // The 64 bit offset is split into two registers.
+            (false, 181) => Syscall::Pwrite {
+                fd: r[3],
+                buf: r[1],
+                count: r[2],
+                offset: r[6] | r[7] << 32,
+            },
+            (true, 18) => Syscall::Pwrite {
+                fd: r[7],
+                buf: r[6],
+                count: r[2],
+                offset: r[10],
+            },
+            (_, number) => return Err(Fault::Syscall(number)),
+        })
+    }
+
+    fn nr(&self) -> u64 {
+        self.regs[RAX]
+    }
+
+    fn set_ret(&mut self, value: i64) {
+        self.set(RAX, value as u64, Self::BITS);
+    }
+
+    fn args(&self, mem: &Memory) -> Result<[u64; 2], Fault> {
+        let sp = self.regs[RSP];
+
+        match LONG {
+            // The stack must be 16 byte aligned before the return address is pushed.
+            true if !(sp + 8).is_multiple_of(16) => Err(Fault::Misaligned(sp)),
+            true => Ok([self.regs[7], self.regs[6]]),
+            false => Ok([mem.read_u32(sp + 4)?.into(), mem.read_u32(sp + 8)?.into()]),
+        }
+    }
+
+    fn ret(&mut self, mem: &mut Memory, value: u64) -> Result<(), Fault> {
+        self.ip = self.pop(mem)?;
+        self.set(RAX, value, Self::BITS);
+
+        let clobbered: &[usize] = if LONG {
+            &[1, 2, 6, 7, 8, 9, 10, 11]
+        } else {
+            &[1, 2]
+        };
+        for reg in clobbered {
+            self.set(*reg, CLOBBERED, Self::BITS);
+        }
+        Ok(())
+    }
+
+    const NRS: &'static Nrs = match LONG {
+        true => &crate::payloads::x86_64::NRS,
+        false => &crate::payloads::x86::NRS,
+    };
+
+    fn gen_first(
+        syscalls: Syscalls,
+        second_payload_path: &str,
+        second_payload_size: u32,
+    ) -> Result<Payload, Error> {
+        let gen = match LONG {
+            true => crate::payloads::x86_64::gen_first,
+            false => crate::payloads::x86::gen_first,
+        };
+
+        gen(syscalls, second_payload_path, second_payload_size)
+    }
+
+    fn gen_second(
+        syscalls: Syscalls,
+        original_code: &[u8],
+        original_ip: VirtAddr,
+        lib_path: &str,
+        dlopen: &ProcSym,
+        second_payload_size: u32,
+    ) -> Result<Payload, Error> {
+        let gen = match LONG {
+            true => crate::payloads::x86_64::gen_second,
+            false => crate::payloads::x86::gen_second,
+        };
+
+        gen(
+            syscalls,
+            original_code,
+            original_ip,
+            lib_path,
+            dlopen,
+            second_payload_size,
+            PAGE_SIZE,
+        )
+    }
+}
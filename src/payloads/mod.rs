@@ -1,45 +1,528 @@
+use tiny_asm::{Decode, LabelOffsets};
+
 use crate::{
-    os::VirtAddr,
+    constants::PAGE_SIZE,
+    os::{self, VirtAddr},
     proc::{ProcClass, ProcSym},
+    Error,
 };
 
-#[cfg(any(target_arch = "arm", target_arch = "aarch64"))]
+#[cfg(any(target_arch = "arm", target_arch = "aarch64", test))]
 mod arm;
-#[cfg(target_arch = "aarch64")]
+#[cfg(any(target_arch = "aarch64", test))]
 mod arm64;
-#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[cfg(test)]
+mod emu;
+#[cfg(any(target_arch = "x86", target_arch = "x86_64", test))]
 mod x86;
-#[cfg(target_arch = "x86_64")]
+#[cfg(any(target_arch = "x86_64", test))]
 mod x86_64;
 
-pub(crate) fn gen_first(class: &ProcClass, second_payload_path: &str) -> Vec<u8> {
+/// A struct that represents a generated payload: its code and the labels put into it.
+pub(crate) struct Payload {
+    code: Vec<u8>,
+    labels: LabelOffsets,
+    disassemble: fn(&Payload) -> String,
+}
+
+impl Payload {
+    /// Creates a payload out of the code and labels [`tiny_asm::TinyAsm::build_with_labels`] returned, which
+    /// are disassembled with the `T` backend.
+    fn new<T: Decode>((code, labels): (Vec<u8>, LabelOffsets)) -> Self {
+        Self {
+            code,
+            labels,
+            disassemble: |payload| tiny_asm::disassemble::<T>(&payload.code, &payload.labels),
+        }
+    }
+
+    /// Gets the bytes which are written into the target process.
+    pub(crate) fn code(&self) -> &[u8] {
+        &self.code
+    }
+
+    /// Gets the offset the label named `name` was put at.
+    ///
+    /// Returns [`None`] if it was not put.
+    fn label(&self, name: &str) -> Option<usize> {
+        self.labels
+            .iter()
+            .find_map(|(offset, label)| (label.name() == name).then_some(*offset))
+    }
+
+    /// Disassembles the payload, annotating it with its labels - e.g. to log the exact instruction stream
+    /// written into the target process.
+    pub(crate) fn disassemble(&self) -> String {
+        (self.disassemble)(self)
+    }
+}
+
+/// A payload is debugged as its disassembly, which tells more than its bytes.
+impl std::fmt::Debug for Payload {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.disassemble())
+    }
+}
+
+/// A struct that represents the alternative syscalls the payloads can be generated with, so that the seccomp filters of
+/// the target process allow them. The default ones are the most widely available.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Syscalls {
+    /// Whether paths are opened and deleted with `openat` and `unlinkat`, rather than `open` and `unlink`.
+    /// It's ignored on `aarch64`, where the latter don't exist.
+    pub(crate) at: bool,
+    /// Whether the second payload file is mapped with `mmap`, which takes its arguments from memory, rather than `mmap2`.
+    /// It's only honoured on `x86`, since `arm` lacks the former and the 64 bit architectures lack the latter.
+    pub(crate) old_mmap: bool,
+}
+
+impl Syscalls {
+    /// Gets the alternatives the payloads of `class` can be generated with, the default one first.
+    pub(crate) fn alternatives(class: &ProcClass) -> Vec<Syscalls> {
+        let (at, old_mmap): (&[bool], &[bool]) = match class {
+            #[cfg(any(target_arch = "arm", target_arch = "aarch64"))]
+            ProcClass::ThirtyTwo => (&[false, true], &[false]),
+            #[cfg(target_arch = "aarch64")]
+            ProcClass::SixtyFour => (&[false], &[false]),
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            ProcClass::ThirtyTwo => (&[false, true], &[false, true]),
+            #[cfg(target_arch = "x86_64")]
+            ProcClass::SixtyFour => (&[false, true], &[false]),
+        };
+
+        old_mmap
+            .iter()
+            .flat_map(|&old_mmap| at.iter().map(move |&at| Syscalls { at, old_mmap }))
+            .collect()
+    }
+}
+
+/// A struct that represents a syscall the payloads perform, as a seccomp filter sees it: the arguments which are not
+/// known in advance, e.g. addresses and file descriptors, are zero.
+#[derive(Debug)]
+pub(crate) struct Call {
+    pub(crate) name: &'static str,
+    pub(crate) nr: u32,
+    pub(crate) args: [u64; 6],
+    /// Whether the payloads can't recover from its failure, e.g. the original code couldn't be restored.
+    pub(crate) required: bool,
+}
+
+/// The syscall numbers of an architecture the payloads are generated for.
+#[cfg(any(
+    target_arch = "x86",
+    target_arch = "x86_64",
+    target_arch = "arm",
+    target_arch = "aarch64",
+    test
+))]
+struct Nrs {
+    /// `open`, which is [`None`] if the architecture lacks it.
+    open: Option<u32>,
+    openat: u32,
+    close: u32,
+    /// `unlink`, which is [`None`] if the architecture lacks it.
+    unlink: Option<u32>,
+    unlinkat: u32,
+    /// `mmap2` on the 32 bit architectures, `mmap` on the 64 bit ones, by name.
+    mmap: (&'static str, u32),
+    /// `mmap` on the 32 bit architectures which take its arguments from memory.
+    old_mmap: Option<u32>,
+    mprotect: u32,
+    munmap: u32,
+    pwrite64: u32,
+    /// [`AT_FDCWD`], as the seccomp filters see it: it's zero extended on the 32 bit architectures.
+    at_fdcwd: u64,
+}
+
+#[cfg(any(
+    target_arch = "x86",
+    target_arch = "x86_64",
+    target_arch = "arm",
+    target_arch = "aarch64",
+    test
+))]
+impl Nrs {
+    /// Describes the syscalls the payloads generated with `syscalls` perform, in order.
+    fn calls(&self, syscalls: Syscalls) -> Vec<Call> {
+        const O_RDONLY: u64 = 0;
+        const O_RDWR: u64 = 2;
+        const PROT_READ_EXEC: u64 = 1 | 4;
+        const PROT_READ_WRITE_EXEC: u64 = 1 | 2 | 4;
+        const MAP_PRIVATE: u64 = 2;
+
+        let call = |name, nr, args: &[u64], required| {
+            let mut all = [0; 6];
+            all[..args.len()].copy_from_slice(args);
+            Call {
+                name,
+                nr,
+                args: all,
+                required,
+            }
+        };
+        let open = |flags, required| match self.open {
+            Some(nr) if !syscalls.at => call("open", nr, &[0, flags, 0], required),
+            _ => call(
+                "openat",
+                self.openat,
+                &[self.at_fdcwd, 0, flags, 0],
+                required,
+            ),
+        };
+        let unlink = match self.unlink {
+            Some(nr) if !syscalls.at => call("unlink", nr, &[], false),
+            _ => call("unlinkat", self.unlinkat, &[self.at_fdcwd], false),
+        };
+        let mmap = match self.old_mmap {
+            Some(nr) if syscalls.old_mmap => call("mmap", nr, &[], true),
+            _ => call(
+                self.mmap.0,
+                self.mmap.1,
+                &[0, 0, PROT_READ_EXEC, MAP_PRIVATE],
+                true,
+            ),
+        };
+        let close = || call("close", self.close, &[], false);
+        let pwrite64 = |required| call("pwrite64", self.pwrite64, &[], required);
+
+        vec![
+            // The first payload.
+            open(O_RDONLY, true),
+            mmap,
+            close(),
+            unlink,
+            // The second payload, which restores the original code.
+            open(O_RDWR, true),
+            pwrite64(true),
+            close(),
+            // The second payload, which writes the stub that unmaps it below the pushed registers.
+            open(O_RDWR, false),
+            pwrite64(false),
+            close(),
+            call(
+                "mprotect",
+                self.mprotect,
+                &[0, 0, PROT_READ_WRITE_EXEC],
+                false,
+            ),
+            call("munmap", self.munmap, &[], false),
+        ]
+    }
+}
+
+/// Describes the syscalls the payloads of `class` generated with `syscalls` perform, in order.
+pub(crate) fn calls(class: &ProcClass, syscalls: Syscalls) -> Vec<Call> {
+    match class {
+        #[cfg(any(target_arch = "arm", target_arch = "aarch64"))]
+        ProcClass::ThirtyTwo => arm::NRS.calls(syscalls),
+        #[cfg(target_arch = "aarch64")]
+        ProcClass::SixtyFour => arm64::NRS.calls(syscalls),
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        ProcClass::ThirtyTwo => x86::NRS.calls(syscalls),
+        #[cfg(target_arch = "x86_64")]
+        ProcClass::SixtyFour => x86_64::NRS.calls(syscalls),
+    }
+}
+
+/// Generates the first payload, which maps `second_payload_size` bytes of the second payload file and executes it.
+///
+/// The payload length doesn't depend on `second_payload_size`, so it can be known before the second payload is generated.
+pub(crate) fn gen_first(
+    class: &ProcClass,
+    syscalls: Syscalls,
+    second_payload_path: &str,
+    second_payload_size: u32,
+) -> Result<Payload, Error> {
     match class {
         #[cfg(any(target_arch = "arm", target_arch = "aarch64"))]
-        ProcClass::ThirtyTwo => arm::gen_first(second_payload_path),
+        ProcClass::ThirtyTwo => arm::gen_first(syscalls, second_payload_path, second_payload_size),
         #[cfg(target_arch = "aarch64")]
-        ProcClass::SixtyFour => arm64::gen_first(second_payload_path),
+        ProcClass::SixtyFour => {
+            arm64::gen_first(syscalls, second_payload_path, second_payload_size)
+        }
         #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
-        ProcClass::ThirtyTwo => x86::gen_first(second_payload_path),
+        ProcClass::ThirtyTwo => x86::gen_first(syscalls, second_payload_path, second_payload_size),
         #[cfg(target_arch = "x86_64")]
-        ProcClass::SixtyFour => x86_64::gen_first(second_payload_path),
+        ProcClass::SixtyFour => {
+            x86_64::gen_first(syscalls, second_payload_path, second_payload_size)
+        }
     }
 }
 
+/// Determines whether `code` is a first payload of `class`, whichever syscalls, second payload path and size it was
+/// generated with, e.g. a previous intruduction is waiting for the targeted thread to execute it.
+pub(crate) fn is_first(class: &ProcClass, code: &[u8]) -> Result<bool, Error> {
+    for syscalls in Syscalls::alternatives(class) {
+        let first_payload = gen_first(class, syscalls, "/a", 0)?;
+        // Every bit a page aligned size can have is set, so that every byte which depends on the size differs.
+        let other = gen_first(
+            class,
+            syscalls,
+            "/b",
+            i32::MAX as u32 & !(PAGE_SIZE as u32 - 1),
+        )?;
+
+        // The signature is the whole code up to the embedded path, except the bytes which depend on the size, e.g. its
+        // immediates. The path is absolute, so it starts with `/`.
+        let Some(path_offset) = first_payload.label("second_payload_path") else {
+            continue;
+        };
+        let Some((code, path)) = code
+            .get(..=path_offset)
+            .map(|code| code.split_at(path_offset))
+        else {
+            continue;
+        };
+
+        let matches = code
+            .iter()
+            .zip(first_payload.code().iter().zip(other.code()))
+            .all(|(byte, (expected, other))| expected != other || byte == expected);
+
+        if matches && path == b"/" {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+/// The directory file descriptor `openat` and `unlinkat` are given, so that a relative path is resolved against the
+/// current directory.
+#[cfg(any(
+    target_arch = "x86",
+    target_arch = "x86_64",
+    target_arch = "arm",
+    target_arch = "aarch64",
+    test
+))]
+const AT_FDCWD: i32 = -100;
+
+/// The distance below the pushed registers at which the second payload writes the stub that unmaps it,
+/// which must not be shorter than the stub itself.
+#[cfg(any(
+    target_arch = "x86",
+    target_arch = "x86_64",
+    target_arch = "arm",
+    target_arch = "aarch64",
+    test
+))]
+const STUB_OFFSET: u16 = 128;
+
+/// Generates the second payload, which restores `original_code` at `original_ip`, calls `dlopen`, unmaps itself and resumes the execution.
+///
+/// The payload embeds its own mapping size, which is computed from a first generation - the payload length doesn't depend on it.
 pub(crate) fn gen_second(
     class: &ProcClass,
+    syscalls: Syscalls,
     original_code: &[u8],
     original_ip: VirtAddr,
     lib_path: &str,
     dlopen: &ProcSym,
-) -> Vec<u8> {
-    match class {
+) -> Result<Payload, Error> {
+    let page_size = os::page_size();
+    let gen = |second_payload_size| match class {
         #[cfg(any(target_arch = "arm", target_arch = "aarch64"))]
-        ProcClass::ThirtyTwo => arm::gen_second(original_code, original_ip, lib_path, dlopen),
+        ProcClass::ThirtyTwo => arm::gen_second(
+            syscalls,
+            original_code,
+            original_ip,
+            lib_path,
+            dlopen,
+            second_payload_size,
+            page_size,
+        ),
         #[cfg(target_arch = "aarch64")]
-        ProcClass::SixtyFour => arm64::gen_second(original_code, original_ip, lib_path, dlopen),
+        ProcClass::SixtyFour => arm64::gen_second(
+            syscalls,
+            original_code,
+            original_ip,
+            lib_path,
+            dlopen,
+            second_payload_size,
+            page_size,
+        ),
         #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
-        ProcClass::ThirtyTwo => x86::gen_second(original_code, original_ip, lib_path, dlopen),
+        ProcClass::ThirtyTwo => x86::gen_second(
+            syscalls,
+            original_code,
+            original_ip,
+            lib_path,
+            dlopen,
+            second_payload_size,
+            page_size,
+        ),
         #[cfg(target_arch = "x86_64")]
-        ProcClass::SixtyFour => x86_64::gen_second(original_code, original_ip, lib_path, dlopen),
+        ProcClass::SixtyFour => x86_64::gen_second(
+            syscalls,
+            original_code,
+            original_ip,
+            lib_path,
+            dlopen,
+            second_payload_size,
+            page_size,
+        ),
+    };
+
+    fit_second(gen)
+}
+
+/// Generates the second payload with `gen`, which is given the size it must be mapped with: it's computed from a
+/// first generation.
+fn fit_second(gen: impl Fn(u32) -> Result<Payload, Error>) -> Result<Payload, Error> {
+    gen(second_payload_size(gen(0)?.code())?)
+}
+
+/// Computes the size the second payload must be mapped with, e.g. its length rounded up to [`PAGE_SIZE`].
+///
+/// Returns [`Error::PayloadTooLarge`] if it doesn't fit the first payload size slot, which is a signed 32 bit immediate on `x86-64`.
+pub(crate) fn second_payload_size(second_payload: &[u8]) -> Result<u32, Error> {
+    let size = second_payload.len().div_ceil(PAGE_SIZE) * PAGE_SIZE;
+
+    i32::try_from(size)
+        .map(|size| size as u32)
+        .map_err(|_| Error::PayloadTooLarge(size))
+}
+
+/// Narrows `addr` so that it can be stored into a 32 bit slot of a payload.
+///
+/// Returns [`Error::AddressOutOfRange`] instead of silently truncating it.
+#[cfg(any(
+    target_arch = "x86",
+    target_arch = "x86_64",
+    target_arch = "arm",
+    target_arch = "aarch64",
+    test
+))]
+fn narrow(addr: VirtAddr) -> Result<u32, Error> {
+    addr.try_into().map_err(|_| Error::AddressOutOfRange(addr))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{constants::PAGE_SIZE, proc::ProcClass, proc::ProcSym};
+
+    use super::{gen_first, gen_second, is_first, second_payload_size, Syscalls};
+
+    fn classes() -> Vec<ProcClass> {
+        vec![
+            #[cfg(any(
+                target_arch = "x86",
+                target_arch = "x86_64",
+                target_arch = "arm",
+                target_arch = "aarch64"
+            ))]
+            ProcClass::ThirtyTwo,
+            #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+            ProcClass::SixtyFour,
+        ]
+    }
+
+    #[test]
+    fn maps_whole_second_payload_with_long_lib_path() {
+        let lib_path = format!("/{}", "a".repeat(4095));
+        let second_payload_path = "/tmp/payload.bin";
+
+        for class in classes() {
+            let first_payload_len = gen_first(&class, Syscalls::default(), second_payload_path, 0)
+                .unwrap()
+                .code()
+                .len();
+            let original_code = vec![0; first_payload_len];
+
+            let second_payload = gen_second(
+                &class,
+                Syscalls::default(),
+                &original_code,
+                0x1000,
+                &lib_path,
+                &ProcSym::new(0x2000),
+            )
+            .unwrap();
+            let size = second_payload_size(second_payload.code()).unwrap();
+
+            assert!(second_payload.code().len() > 4096);
+            assert!(size as usize >= second_payload.code().len());
+            assert_eq!(size as usize % PAGE_SIZE, 0);
+
+            let first_payload =
+                gen_first(&class, Syscalls::default(), second_payload_path, size).unwrap();
+
+            assert_eq!(first_payload.code().len(), first_payload_len);
+            assert!(first_payload
+                .code()
+                .windows(4)
+                .any(|window| window == size.to_le_bytes()));
+        }
+    }
+
+    #[test]
+    fn second_payload_unmaps_its_whole_mapping() {
+        for class in classes() {
+            let second_payload = gen_second(
+                &class,
+                Syscalls::default(),
+                &[0; 64],
+                0x1000,
+                "/tmp/lib.so",
+                &ProcSym::new(0x2000),
+            )
+            .unwrap();
+            let size = second_payload_size(second_payload.code()).unwrap();
+
+            assert!(second_payload
+                .code()
+                .windows(4)
+                .any(|window| window == size.to_le_bytes()));
+        }
+    }
+
+    #[test]
+    fn disassembles_payloads_with_their_labels() {
+        for class in classes() {
+            let listing = gen_first(&class, Syscalls::default(), "/tmp/payload.bin", 0x1000)
+                .unwrap()
+                .disassemble();
+
+            // The label is put, and it names the offset the instructions refer to.
+            assert!(listing.contains("second_payload_path:\n"), "{}", listing);
+            assert!(
+                listing.matches("second_payload_path").count() > 1,
+                "{}",
+                listing
+            );
+            assert!(!listing.starts_with("    .byte"), "{}", listing);
+        }
+    }
+
+    #[test]
+    fn recognizes_first_payloads() {
+        for class in classes() {
+            for syscalls in Syscalls::alternatives(&class) {
+                let first_payload =
+                    gen_first(&class, syscalls, "/tmp/payload-1234-5678.bin", 0x13000).unwrap();
+                assert!(is_first(&class, first_payload.code()).unwrap());
+            }
+
+            let second_payload = gen_second(
+                &class,
+                Syscalls::default(),
+                &[0; 64],
+                0x1000,
+                "/tmp/lib.so",
+                &ProcSym::new(0x2000),
+            )
+            .unwrap();
+            assert!(!is_first(&class, second_payload.code()).unwrap());
+            assert!(!is_first(&class, &[0; 64]).unwrap());
+
+            // Ordinary code which happens to start like the first payload, e.g. by pushing the same registers.
+            let first_payload = gen_first(&class, Syscalls::default(), "/a", 0).unwrap();
+            let mut code = first_payload.code()[..8].to_vec();
+            code.resize(first_payload.code().len(), 0x90);
+            assert!(!is_first(&class, &code).unwrap());
+            assert!(!is_first(&class, &first_payload.code()[..8]).unwrap());
+        }
     }
 }
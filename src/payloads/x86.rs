@@ -1,237 +1,217 @@
-use crate::{os::VirtAddr, proc::ProcSym};
+use tiny_asm::x86::{Cond, Mem, Reg, Reg::*, TinyAsm};
 
-pub(crate) fn gen_first(second_payload_path: &str) -> Vec<u8> {
-    use tiny_asm::x86::TinyAsm;
+use crate::{os::VirtAddr, proc::ProcSym, Error};
 
-    TinyAsm::new()
+use super::{narrow, Nrs, Payload, Syscalls, AT_FDCWD, STUB_OFFSET};
+
+pub(super) const NRS: Nrs = Nrs {
+    open: Some(5),
+    openat: 295,
+    close: 6,
+    unlink: Some(10),
+    unlinkat: 301,
+    mmap: ("mmap2", 192),
+    old_mmap: Some(90),
+    mprotect: 125,
+    munmap: 91,
+    pwrite64: 181,
+    at_fdcwd: AT_FDCWD as u32 as u64,
+};
+
+pub(crate) fn gen_first(
+    syscalls: Syscalls,
+    second_payload_path: &str,
+    second_payload_size: u32,
+) -> Result<Payload, Error> {
+    Ok(TinyAsm::new()
         //
         // Push every general purpose register; are other registers necessary to push?
         //
-        // push eax
-        .instr([0x50])
-        // push ebx
-        .instr([0x53])
-        // push ecx
-        .instr([0x51])
-        // push edx
-        .instr([0x52])
-        // push ebp
-        .instr([0x55])
-        // push esi
-        .instr([0x56])
-        // push edi
-        .instr([0x57])
+        .push(eax)
+        .push(ebx)
+        .push(ecx)
+        .push(edx)
+        .push(ebp)
+        .push(esi)
+        .push(edi)
         //
         // Open second payload file.
         //
-        // mov eax, 5
-        .instr([0xb8, 0x05, 0x00, 0x00, 0x00])
-        // call 5
-        .instr([0xe8, 0x00, 0x00, 0x00, 0x00])
-        // next1: pop ebx
-        .label("next1")
-        .instr([0x5b])
-        // sub ebx, next1
-        .instr_with_ref([0x81, 0xeb], "next1")
-        // add ebx, second_payload_path
-        .instr_with_ref([0x81, 0xc3], "second_payload_path")
-        // mov ecx, 0
-        .instr([0xb9, 0x00, 0x00, 0x00, 0x00])
-        // mov edx, 0
-        .instr([0xba, 0x00, 0x00, 0x00, 0x00])
-        // int 0x80
-        .instr([0xcd, 0x80])
+        .with(open(syscalls, "second_payload_path", 0))
         //
         // Second payload file descriptor.
         //
-        // mov edi, eax
-        .instr([0x89, 0xc7])
+        .movr(edi, eax)
         //
         // Map the Second payload file to memory.
         //
-        // mov eax, 192
-        .instr([0xb8, 0xc0, 0x00, 0x00, 0x00])
-        // mov ebx, 0
-        .instr([0xbb, 0x00, 0x00, 0x00, 0x00])
-        // mov ecx, 512
-        .instr([0xb9, 0x00, 0x02, 0x00, 0x00])
-        // mov edx, 1 | 4
-        .instr([0xba, 0x05, 0x00, 0x00, 0x00])
-        // mov esi, 2
-        .instr([0xbe, 0x02, 0x00, 0x00, 0x00])
-        // mov ebp, 0
-        .instr([0xbd, 0x00, 0x00, 0x00, 0x00])
-        // int 0x80
-        .instr([0xcd, 0x80])
+        .with(|asm| match NRS.old_mmap.filter(|_| syscalls.old_mmap) {
+            None => asm
+                .movi(eax, NRS.mmap.1.into())
+                .movi(ebx, 0)
+                .movi(ecx, second_payload_size.into())
+                .movi(edx, 1 | 4)
+                .movi(esi, 2)
+                .movi(ebp, 0)
+                .int(0x80),
+            //
+            // The arguments are pushed in reverse order, so that they make the struct `mmap` takes.
+            // The size is pushed from a register, since a small immediate would shorten the payload.
+            //
+            Some(nr) => asm
+                .pushi(0)
+                .push(edi)
+                .pushi(2)
+                .pushi(1 | 4)
+                .movi(ecx, second_payload_size.into())
+                .push(ecx)
+                .pushi(0)
+                .movi(eax, nr.into())
+                .movr(ebx, esp)
+                .int(0x80)
+                .addi(esp, 24),
+        })
         //
         // Second payload code virtual address.
         //
-        // mov ebp, eax
-        .instr([0x89, 0xc5])
+        .movr(ebp, eax)
         //
         // Close Second payload file.
         //
-        // mov eax, 6
-        .instr([0xb8, 0x06, 0x00, 0x00, 0x00])
-        // mov ebx, edi
-        .instr([0x89, 0xfb])
-        // int 0x80
-        .instr([0xcd, 0x80])
+        .movi(eax, NRS.close.into())
+        .movr(ebx, edi)
+        .int(0x80)
         //
         // Delete Second payload file.
         // Will fail on Android apps.
         //
-        // mov eax, 10
-        .instr([0xb8, 0x0a, 0x00, 0x00, 0x00])
-        // call 5
-        .instr([0xe8, 0x00, 0x00, 0x00, 0x00])
-        // next2: pop ebx
-        .label("next2")
-        .instr([0x5b])
-        // sub ebx, next2
-        .instr_with_ref([0x81, 0xeb], "next2")
-        // add ebx, second_payload_path
-        .instr_with_ref([0x81, 0xc3], "second_payload_path")
-        // int 0x80
-        .instr([0xcd, 0x80])
+        .with(unlink(syscalls, "second_payload_path"))
         //
         // Execute second payload code.
         //
-        // jmp ebp
-        .instr([0xff, 0xe5])
+        .jmpr(ebp)
         //
         // Data
         //
         .label("second_payload_path")
         .asciiz(second_payload_path)
-        .build()
+        .build_with_labels()
+        .map(Payload::new::<tiny_asm::x86::Op>)?)
 }
 
 pub(crate) fn gen_second(
+    syscalls: Syscalls,
     original_code: &[u8],
     original_ip: VirtAddr,
     lib_path: &str,
     dlopen: &ProcSym,
-) -> Vec<u8> {
-    use tiny_asm::x86::TinyAsm;
+    second_payload_size: u32,
+    page_size: u64,
+) -> Result<Payload, Error> {
+    let original_ip = narrow(original_ip)?;
+    let dlopen_addr = narrow(dlopen.addr)?;
+
+    let stub = gen_stub(original_ip)?;
+    let stub_len = stub.len() as i32;
+    let stub_offset = i32::from(STUB_OFFSET);
+    let page_mask = -(page_size as i32);
 
-    TinyAsm::new()
+    Ok(TinyAsm::new()
+        .label("second_payload")
         //
         // Open memory file (/proc/self/mem).
         //
-        // mov eax, 5
-        .instr([0xb8, 0x05, 0x00, 0x00, 0x00])
-        // call 5
-        .instr([0xe8, 0x00, 0x00, 0x00, 0x00])
-        // next0: pop ebx
-        .label("next0")
-        .instr([0x5b])
-        // sub ebx, next0
-        .instr_with_ref([0x81, 0xeb], "next0")
-        // add ebx, proc_self_mem
-        .instr_with_ref([0x81, 0xc3], "mem_path")
-        // mov ecx, 2
-        .instr([0xb9, 0x02, 0x00, 0x00, 0x00])
-        // mov edx, 0
-        .instr([0xba, 0x00, 0x00, 0x00, 0x00])
-        // int 0x80
-        .instr([0xcd, 0x80])
+        .with(open(syscalls, "mem_path", 2))
         //
         // Memory file descriptor.
         //
-        // mov ebx, eax
-        .instr([0x89, 0xc3])
+        .movr(ebx, eax)
         //
         // Restore the original code.
         //
-        // mov eax, 181
-        .instr([0xb8, 0xb5, 0x00, 0x00, 0x00])
-        // call 5
-        .instr([0xe8, 0x00, 0x00, 0x00, 0x00])
-        // next1: pop ecx
-        .label("next1")
-        .instr([0x59])
-        // sub ecx, next1
-        .instr_with_ref([0x81, 0xe9], "next1")
-        // add ecx, original_code
-        .instr_with_ref([0x81, 0xc1], "original_code")
-        // mov edx, original_code_len
-        .instr([0xba])
-        .instr((original_code.len() as u32).to_le_bytes())
-        // mov esi, instruction_pointer
-        .instr([0xbe])
-        .instr((original_ip as u32).to_le_bytes())
-        // mov edi, 0
-        .instr([0xbf, 0x00, 0x00, 0x00, 0x00])
-        // int 0x80
-        .instr([0xcd, 0x80])
+        .movi(eax, NRS.pwrite64.into())
+        .with(addr_of(ecx, "original_code"))
+        .movi(edx, original_code.len() as i64)
+        .movi(esi, original_ip.into())
+        .movi(edi, 0)
+        .int(0x80)
         //
         // Close memory file.
         //
-        // mov eax, 6
-        .instr([0xb8, 0x06, 0x00, 0x00, 0x00])
-        // int 0x80
-        .instr([0xcd, 0x80])
+        .movi(eax, NRS.close.into())
+        .int(0x80)
         //
         // Make a new call frame.
         //
-        // push ebp
-        .instr([0x55])
-        // mov ebp, esp
-        .instr([0x89, 0xe5])
+        .push(ebp)
+        .movr(ebp, esp)
         //
         // Call dlopen.
         //
-        // mov eax, dlopen_addr
-        .instr([0xb8])
-        .instr((dlopen.addr as u32).to_le_bytes())
-        // push 1
-        .instr([0x6a, 0x01])
-        // call 5
-        .instr([0xe8, 0x00, 0x00, 0x00, 0x00])
-        // next2: pop ebx
-        .label("next2")
-        .instr([0x5b])
-        // sub ebx, next2
-        .instr_with_ref([0x81, 0xeb], "next2")
-        // add ebx, lib_path
-        .instr_with_ref([0x81, 0xc3], "lib_path")
-        // push ebx
-        .instr([0x53])
-        // call eax
-        .instr([0xff, 0xd0])
+        .movi(eax, dlopen_addr.into())
+        .pushi(1)
+        .with(addr_of(ebx, "lib_path"))
+        .push(ebx)
+        .callr(eax)
         //
         // Restore the old call frame
         //
-        // mov esp, ebp
-        .instr([0x89, 0xec])
-        // pop ebp
-        .instr([0x5d])
-        //
-        // Pop every previously pushed register.
-        //
-        // pop edi
-        .instr([0x5f])
-        // pop esi
-        .instr([0x5e])
-        // pop ebp
-        .instr([0x5d])
-        // pop edx
-        .instr([0x5a])
-        // pop ecx
-        .instr([0x59])
-        // pop ebx
-        .instr([0x5b])
-        // pop eax
-        .instr([0x58])
+        .movr(esp, ebp)
+        .pop(ebp)
+        //
+        // Open memory file again, so that it's not inherited while the library is being loaded.
+        //
+        .with(open(syscalls, "mem_path", 2))
+        .movr(ebp, eax)
+        //
+        // Write the stub below the pushed registers.
+        //
+        .movi(eax, NRS.pwrite64.into())
+        .movr(ebx, ebp)
+        .with(addr_of(ecx, "stub"))
+        .movi(edx, stub_len.into())
+        .lea(esi, Mem::base(esp).disp(-stub_offset))
+        .movi(edi, 0)
+        .int(0x80)
+        .movr(esi, eax)
+        //
+        // Close memory file.
+        //
+        .movi(eax, NRS.close.into())
+        .movr(ebx, ebp)
+        .int(0x80)
+        .cmpi(esi, stub_len)
+        .jcc(Cond::Ne, "fallback")
+        //
+        // Make the stack pages the stub spans executable, which they are left.
+        //
+        .movi(eax, NRS.mprotect.into())
+        .lea(ebx, Mem::base(esp).disp(-stub_offset))
+        .andi(ebx, page_mask)
+        .movr(ecx, esp)
+        .subr(ecx, ebx)
+        .movi(edx, 1 | 2 | 4)
+        .int(0x80)
+        .testr(eax, eax)
+        .jcc(Cond::Ne, "fallback")
+        //
+        // Execute the stub, which unmaps the second payload code.
+        //
+        .movi(eax, NRS.munmap.into())
+        .with(addr_of(ebx, "second_payload"))
+        .movi(ecx, second_payload_size.into())
+        .lea(esp, Mem::base(esp).disp(-stub_offset))
+        .jmpr(esp)
+        //
+        // The stub couldn't be executed: leave the second payload code mapped.
+        //
+        .label("fallback")
+        .with(pop_regs)
         //
         // Restore the original execution flow.
         //
-        // push original_ip
-        .instr([0x68])
-        .instr((original_ip as u32).to_le_bytes())
-        // ret
-        .instr([0xc3])
+        .pushi(original_ip as i32)
+        .ret()
         //
         // Data
         //
@@ -241,5 +221,150 @@ pub(crate) fn gen_second(
         .bytes(original_code)
         .label("lib_path")
         .asciiz(lib_path)
-        .build()
+        .label("stub")
+        .bytes(&stub)
+        .build_with_labels()
+        .map(Payload::new::<tiny_asm::x86::Op>)?)
+}
+
+/// Generates the stub which is written right below the pushed registers: it performs the
+/// `munmap` syscall set up by the second payload, then resumes the original execution flow.
+fn gen_stub(original_ip: u32) -> Result<Vec<u8>, Error> {
+    Ok(TinyAsm::new()
+        .int(0x80)
+        .lea(esp, Mem::base(esp).disp(STUB_OFFSET.into()))
+        .with(pop_regs)
+        //
+        // Restore the original execution flow.
+        //
+        .pushi(original_ip as i32)
+        .ret()
+        .build()?)
+}
+
+/// Opens the path located at `label` with `flags`, through `open` or `openat` as `syscalls` says.
+fn open(syscalls: Syscalls, label: &'static str, flags: i64) -> impl FnOnce(TinyAsm) -> TinyAsm {
+    move |asm| match NRS.open {
+        Some(nr) if !syscalls.at => asm
+            .movi(eax, nr.into())
+            .with(addr_of(ebx, label))
+            .movi(ecx, flags)
+            .movi(edx, 0)
+            .int(0x80),
+        _ => asm
+            .movi(eax, NRS.openat.into())
+            .movi(ebx, AT_FDCWD.into())
+            .with(addr_of(ecx, label))
+            .movi(edx, flags)
+            .movi(esi, 0)
+            .int(0x80),
+    }
+}
+
+/// Deletes the path located at `label`, through `unlink` or `unlinkat` as `syscalls` says.
+fn unlink(syscalls: Syscalls, label: &'static str) -> impl FnOnce(TinyAsm) -> TinyAsm {
+    move |asm| match NRS.unlink {
+        Some(nr) if !syscalls.at => asm.movi(eax, nr.into()).with(addr_of(ebx, label)).int(0x80),
+        _ => asm
+            .movi(eax, NRS.unlinkat.into())
+            .movi(ebx, AT_FDCWD.into())
+            .with(addr_of(ecx, label))
+            .movi(edx, 0)
+            .int(0x80),
+    }
+}
+
+/// Pops every register pushed by the first payload.
+fn pop_regs(asm: TinyAsm) -> TinyAsm {
+    asm.pop(edi)
+        .pop(esi)
+        .pop(ebp)
+        .pop(edx)
+        .pop(ecx)
+        .pop(ebx)
+        .pop(eax)
+}
+
+/// Loads the address of `label` into `reg`: the payloads don't know where they are mapped,
+/// so the address of a call return is used as base.
+fn addr_of(reg: Reg, label: &'static str) -> impl FnOnce(TinyAsm) -> TinyAsm {
+    move |asm| {
+        asm.call("1f")
+            .label("1")
+            .pop(reg)
+            .subl(reg, "1b")
+            .addl(reg, label)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{proc::ProcSym, Error};
+
+    use super::{gen_second, gen_stub, Syscalls, STUB_OFFSET};
+
+    #[test]
+    fn stub_fits_below_pushed_registers() {
+        assert!(gen_stub(0xf7f1b579).unwrap().len() <= STUB_OFFSET.into());
+    }
+
+    #[test]
+    fn embeds_compat_addresses() {
+        let payload = gen_second(
+            Syscalls::default(),
+            &[0x90; 8],
+            0xf7f1b579,
+            "/tmp/lib.so",
+            &ProcSym::new(0xf7d2a0c0),
+            0x1000,
+            0x1000,
+        )
+        .unwrap();
+
+        let contains = |value: u32| {
+            payload
+                .code()
+                .windows(4)
+                .any(|window| window == value.to_le_bytes())
+        };
+
+        assert!(contains(0xf7f1b579));
+        assert!(contains(0xf7d2a0c0));
+    }
+
+    #[test]
+    fn rejects_wide_original_ip() {
+        let result = gen_second(
+            Syscalls::default(),
+            &[0x90; 8],
+            0x7f1c2a0e57fa,
+            "/tmp/lib.so",
+            &ProcSym::new(0xf7d2a0c0),
+            0x1000,
+            0x1000,
+        );
+
+        assert!(matches!(
+            result,
+            Err(Error::AddressOutOfRange(0x7f1c2a0e57fa))
+        ));
+    }
+
+    #[test]
+    fn rejects_wide_dlopen_addr() {
+        let result = gen_second(
+            Syscalls::default(),
+            &[0x90; 8],
+            0xf7f1b579,
+            "/tmp/lib.so",
+            &ProcSym::new(0x1_0000_0000),
+            0x1000,
+            0x1000,
+        );
+
+        assert!(matches!(
+            result,
+            Err(Error::AddressOutOfRange(0x1_0000_0000))
+        ));
+    }
 }
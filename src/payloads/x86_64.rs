@@ -1,226 +1,200 @@
-use crate::{os::VirtAddr, proc::ProcSym};
+use tiny_asm::x86_64::{Cond, Mem, Reg::*, TinyAsm};
 
-pub(crate) fn gen_first(second_payload_path: &str) -> Vec<u8> {
-    use tiny_asm::x86_64::TinyAsm;
+use crate::{os::VirtAddr, proc::ProcSym, Error};
 
-    TinyAsm::new()
+use super::{Nrs, Payload, Syscalls, AT_FDCWD, STUB_OFFSET};
+
+pub(super) const NRS: Nrs = Nrs {
+    open: Some(2),
+    openat: 257,
+    close: 3,
+    unlink: Some(87),
+    unlinkat: 263,
+    mmap: ("mmap", 9),
+    old_mmap: None,
+    mprotect: 10,
+    munmap: 11,
+    pwrite64: 18,
+    at_fdcwd: AT_FDCWD as u64,
+};
+
+pub(crate) fn gen_first(
+    syscalls: Syscalls,
+    second_payload_path: &str,
+    second_payload_size: u32,
+) -> Result<Payload, Error> {
+    Ok(TinyAsm::new()
         //
         // Push every general purpose register; are other registers necessary to push?
         //
-        // push rax
-        .instr([0x50])
-        // push rbx
-        .instr([0x53])
-        // push rcx
-        .instr([0x51])
-        // push rdx
-        .instr([0x52])
-        // push rbp
-        .instr([0x55])
-        // push rsi
-        .instr([0x56])
-        // push rdi
-        .instr([0x57])
-        // push r8
-        .instr([0x41, 0x50])
-        // push r9
-        .instr([0x41, 0x51])
-        // push r10
-        .instr([0x41, 0x52])
-        // push r11
-        .instr([0x41, 0x53])
-        // push r12
-        .instr([0x41, 0x54])
-        // push r13
-        .instr([0x41, 0x55])
-        // push r14
-        .instr([0x41, 0x56])
-        // push r15
-        .instr([0x41, 0x57])
+        .push(rax)
+        .push(rbx)
+        .push(rcx)
+        .push(rdx)
+        .push(rbp)
+        .push(rsi)
+        .push(rdi)
+        .push(r8)
+        .push(r9)
+        .push(r10)
+        .push(r11)
+        .push(r12)
+        .push(r13)
+        .push(r14)
+        .push(r15)
         //
         // Open second payload file
         //
-        // mov rax, 2
-        .instr([0x48, 0xc7, 0xc0, 0x02, 0x00, 0x00, 0x00])
-        // lea rdi, [rip + second_payload_path]
-        .instr_with_ref([0x48, 0x8d, 0x3d], "second_payload_path")
-        // mov rsi, 0
-        .instr([0x48, 0xc7, 0xc6, 0x00, 0x00, 0x00, 0x00])
-        // mov rdx, 0
-        .instr([0x48, 0xc7, 0xc2, 0x00, 0x00, 0x00, 0x00])
-        // syscall
-        .instr([0x0f, 0x05])
+        .with(open(syscalls, "second_payload_path", 0))
         //
         // Second payload file descriptor
         //
-        // mov r14, rax
-        .instr([0x49, 0x89, 0xc6])
+        .movr(r14, rax)
         //
         // Map the Second payload file to memory
         //
-        // mov rax, 9
-        .instr([0x48, 0xc7, 0xc0, 0x09, 0x00, 0x00, 0x00])
-        // mov rdi, 0
-        .instr([0x48, 0xc7, 0xc7, 0x00, 0x00, 0x00, 0x00])
-        // mov rsi, 512
-        .instr([0x48, 0xc7, 0xc6, 0x00, 0x02, 0x00, 0x00])
-        // mov rdx, 1 | 4
-        .instr([0x48, 0xc7, 0xc2, 0x05, 0x00, 0x00, 0x00])
-        // mov r10, 2
-        .instr([0x49, 0xc7, 0xc2, 0x02, 0x00, 0x00, 0x00])
-        // mov r8, r14
-        .instr([0x4d, 0x89, 0xf0])
-        // mov r9, 0
-        .instr([0x49, 0xc7, 0xc1, 0x00, 0x00, 0x00, 0x00])
-        // syscall
-        .instr([0x0f, 0x05])
+        .movi(rax, NRS.mmap.1.into())
+        .movi(rdi, 0)
+        .movi(rsi, second_payload_size.into())
+        .movi(rdx, 1 | 4)
+        .movi(r10, 2)
+        .movr(r8, r14)
+        .movi(r9, 0)
+        .syscall()
         //
         // Second payload code virtual address
         //
-        // mov r15, rax
-        .instr([0x49, 0x89, 0xc7])
+        .movr(r15, rax)
         //
         // Close Second payload file
         //
-        // mov rax, 3
-        .instr([0x48, 0xc7, 0xc0, 0x03, 0x00, 0x00, 0x00])
-        // mov rdi, r14
-        .instr([0x4c, 0x89, 0xf7])
-        // syscall
-        .instr([0x0f, 0x05])
+        .movi(rax, NRS.close.into())
+        .movr(rdi, r14)
+        .syscall()
         //
         // Delete Second payload file.
         // Will fail on Android apps.
         //
-        // mov rax, 87
-        .instr([0x48, 0xc7, 0xc0, 0x57, 0x00, 0x00, 0x00])
-        // lea rdi, [rip + second_payload_path]
-        .instr_with_ref([0x48, 0x8d, 0x3d], "second_payload_path")
-        // syscall
-        .instr([0x0f, 0x05])
+        .with(unlink(syscalls, "second_payload_path"))
         //
         // Execute second payload code.
         //
-        // jmp r15
-        .instr([0x41, 0xff, 0xe7])
+        .jmpr(r15)
         //
         // Data
         //
         .label("second_payload_path")
         .asciiz(second_payload_path)
-        .build()
+        .build_with_labels()
+        .map(Payload::new::<tiny_asm::x86_64::Op>)?)
 }
 
 pub(crate) fn gen_second(
+    syscalls: Syscalls,
     original_code: &[u8],
     original_ip: VirtAddr,
     lib_path: &str,
     dlopen: &ProcSym,
-) -> Vec<u8> {
-    use tiny_asm::x86_64::TinyAsm;
+    second_payload_size: u32,
+    page_size: u64,
+) -> Result<Payload, Error> {
+    let stub = gen_stub(original_ip)?;
+    let stub_len = stub.len() as i32;
+    let stub_offset = i32::from(STUB_OFFSET);
+    let page_mask = -(page_size as i32);
 
-    TinyAsm::new()
+    Ok(TinyAsm::new()
+        .label("second_payload")
         //
         // Open memory file
         //
-        // mov rax, 2
-        .instr([0x48, 0xc7, 0xc0, 0x02, 0x00, 0x00, 0x00])
-        // lea rdi, [rip + proc_self_mem]
-        .instr_with_ref([0x48, 0x8d, 0x3d], "mem_path")
-        // mov rsi, 2
-        .instr([0x48, 0xc7, 0xc6, 0x02, 0x00, 0x00, 0x00])
-        // mov rdx, 0
-        .instr([0x48, 0xc7, 0xc2, 0x00, 0x00, 0x00, 0x00])
-        // syscall
-        .instr([0x0f, 0x05])
+        .with(open(syscalls, "mem_path", 2))
         //
         // Memory file descriptor
         //
-        // mov r15, rax
-        .instr([0x49, 0x89, 0xc7])
+        .movr(r15, rax)
         //
         // Restore the original code
         //
-        // mov rax, 18
-        .instr([0x48, 0xc7, 0xc0, 0x12, 0x00, 0x00, 0x00])
-        // mov rdi, r15
-        .instr([0x4c, 0x89, 0xff])
-        // lea rsi, [rip + original_code]
-        .instr_with_ref([0x48, 0x8d, 0x35], "original_code")
-        // mov rdx, [rip + original_code_len]
-        .instr_with_ref([0x48, 0x8b, 0x15], "original_code_len")
-        // mov r10, [rip + original_ip]
-        .instr_with_ref([0x4c, 0x8b, 0x15], "original_ip")
-        // syscall
-        .instr([0x0f, 0x05])
+        .movi(rax, NRS.pwrite64.into())
+        .movr(rdi, r15)
+        .lea(rsi, Mem::rip("original_code"))
+        .movrm(rdx, Mem::rip("original_code_len"))
+        .movrm(r10, Mem::rip("original_ip"))
+        .syscall()
         //
         // Close memory file.
         //
-        // mov rax, 3
-        .instr([0x48, 0xc7, 0xc0, 0x03, 0x00, 0x00, 0x00])
-        // mov rdi, r15
-        .instr([0x4c, 0x89, 0xff])
-        // syscall
-        .instr([0x0f, 0x05])
+        .movi(rax, NRS.close.into())
+        .movr(rdi, r15)
+        .syscall()
         //
         // Align the stack to a 16 byte boundary
         //
-        // mov rbp, rsp
-        .instr([0x48, 0x89, 0xe5])
-        // and rsp, -16
-        .instr([0x48, 0x83, 0xe4, 0xf0])
+        .movr(rbp, rsp)
+        .andi(rsp, -16)
         //
         // Call dlopen
         //
-        // lea rdi, [rip + lib_path])
-        .instr_with_ref([0x48, 0x8d, 0x3d], "lib_path")
-        // mov rsi, 1
-        .instr([0x48, 0xc7, 0xc6, 0x01, 0x00, 0x00, 0x00])
-        // call [rip + dlopen_addr]
-        .instr_with_ref([0xff, 0x15], "dlopen_addr")
+        .lea(rdi, Mem::rip("lib_path"))
+        .movi(rsi, 1)
+        .callm(Mem::rip("dlopen_addr"))
         //
         // Restore the stack
         //
-        // mov rsp, rbp
-        .instr([0x48, 0x89, 0xec])
-        //
-        // Pop every previously pushed register
-        //
-        // pop r15
-        .instr([0x41, 0x5f])
-        // pop r14
-        .instr([0x41, 0x5e])
-        // pop r13
-        .instr([0x41, 0x5d])
-        // pop r12
-        .instr([0x41, 0x5c])
-        // pop r11
-        .instr([0x41, 0x5b])
-        // pop r10
-        .instr([0x41, 0x5a])
-        // pop r9
-        .instr([0x41, 0x59])
-        // pop r8
-        .instr([0x41, 0x58])
-        // pop rdi
-        .instr([0x5f])
-        // pop rsi
-        .instr([0x5e])
-        // pop rbp
-        .instr([0x5d])
-        // pop rdx
-        .instr([0x5a])
-        // pop rcx
-        .instr([0x59])
-        // pop rbx
-        .instr([0x5b])
-        // pop rax
-        .instr([0x58])
+        .movr(rsp, rbp)
+        //
+        // Open memory file again, so that it's not inherited while the library is being loaded.
+        //
+        .with(open(syscalls, "mem_path", 2))
+        .movr(r15, rax)
+        //
+        // Write the stub below the pushed registers
+        //
+        .movi(rax, NRS.pwrite64.into())
+        .movr(rdi, r15)
+        .lea(rsi, Mem::rip("stub"))
+        .movi(rdx, stub_len.into())
+        .lea(r10, Mem::base(rsp).disp(-stub_offset))
+        .syscall()
+        .movr(r13, rax)
+        //
+        // Close memory file.
+        //
+        .movi(rax, NRS.close.into())
+        .movr(rdi, r15)
+        .syscall()
+        .cmpi(r13, stub_len)
+        .jcc(Cond::Ne, "fallback")
+        //
+        // Make the stack pages the stub spans executable, which they are left
+        //
+        .movi(rax, NRS.mprotect.into())
+        .lea(rdi, Mem::base(rsp).disp(-stub_offset))
+        .andi(rdi, page_mask)
+        .movr(rsi, rsp)
+        .subr(rsi, rdi)
+        .movi(rdx, 1 | 2 | 4)
+        .syscall()
+        .testr(rax, rax)
+        .jcc(Cond::Ne, "fallback")
+        //
+        // Execute the stub, which unmaps the second payload code
+        //
+        .movi(rax, NRS.munmap.into())
+        .lea(rdi, Mem::rip("second_payload"))
+        .movi(rsi, second_payload_size.into())
+        .lea(rsp, Mem::base(rsp).disp(-stub_offset))
+        .jmpr(rsp)
+        //
+        // The stub couldn't be executed: leave the second payload code mapped
+        //
+        .label("fallback")
+        .with(pop_regs)
         //
         // Restore the original execution flow
         //
-        // jmp [rip + original_ip]
-        .instr_with_ref([0xff, 0x25], "original_ip")
+        .jmpm(Mem::rip("original_ip"))
         //
         // Data
         //
@@ -236,5 +210,75 @@ pub(crate) fn gen_second(
         .asciiz(lib_path)
         .label("dlopen_addr")
         .qword(dlopen.addr)
-        .build()
+        .label("stub")
+        .bytes(&stub)
+        .build_with_labels()
+        .map(Payload::new::<tiny_asm::x86_64::Op>)?)
+}
+
+/// Generates the stub which is written right below the pushed registers: it performs the
+/// `munmap` syscall set up by the second payload, then resumes the original execution flow.
+fn gen_stub(original_ip: VirtAddr) -> Result<Vec<u8>, Error> {
+    Ok(TinyAsm::new()
+        .syscall()
+        .lea(rsp, Mem::base(rsp).disp(STUB_OFFSET.into()))
+        .with(pop_regs)
+        //
+        // Restore the original execution flow
+        //
+        .jmpm(Mem::rip("original_ip"))
+        .label("original_ip")
+        .qword(original_ip)
+        .build()?)
+}
+
+/// Opens the path located at `label` with `flags`, through `open` or `openat` as `syscalls` says.
+fn open(syscalls: Syscalls, label: &'static str, flags: i64) -> impl FnOnce(TinyAsm) -> TinyAsm {
+    move |asm| match NRS.open {
+        Some(nr) if !syscalls.at => asm
+            .movi(rax, nr.into())
+            .lea(rdi, Mem::rip(label))
+            .movi(rsi, flags)
+            .movi(rdx, 0)
+            .syscall(),
+        _ => asm
+            .movi(rax, NRS.openat.into())
+            .movi(rdi, AT_FDCWD.into())
+            .lea(rsi, Mem::rip(label))
+            .movi(rdx, flags)
+            .movi(r10, 0)
+            .syscall(),
+    }
+}
+
+/// Deletes the path located at `label`, through `unlink` or `unlinkat` as `syscalls` says.
+fn unlink(syscalls: Syscalls, label: &'static str) -> impl FnOnce(TinyAsm) -> TinyAsm {
+    move |asm| match NRS.unlink {
+        Some(nr) if !syscalls.at => asm.movi(rax, nr.into()).lea(rdi, Mem::rip(label)).syscall(),
+        _ => asm
+            .movi(rax, NRS.unlinkat.into())
+            .movi(rdi, AT_FDCWD.into())
+            .lea(rsi, Mem::rip(label))
+            .movi(rdx, 0)
+            .syscall(),
+    }
+}
+
+/// Pops every register pushed by the first payload.
+fn pop_regs(asm: TinyAsm) -> TinyAsm {
+    asm.pop(r15)
+        .pop(r14)
+        .pop(r13)
+        .pop(r12)
+        .pop(r11)
+        .pop(r10)
+        .pop(r9)
+        .pop(r8)
+        .pop(rdi)
+        .pop(rsi)
+        .pop(rbp)
+        .pop(rdx)
+        .pop(rcx)
+        .pop(rbx)
+        .pop(rax)
 }
@@ -0,0 +1,83 @@
+use crate::Error;
+
+use super::ProcClass;
+
+/// A enum that represents the instruction set a process is executing, e.g. the `e_machine` field of its ELF header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Machine {
+    /// `EM_386`.
+    X86,
+    /// `EM_X86_64`.
+    X86_64,
+    /// `EM_ARM`.
+    Arm,
+    /// `EM_AARCH64`.
+    Aarch64,
+    /// Any other `e_machine` value.
+    Other(u16),
+}
+
+impl Machine {
+    /// Gets the [`Machine`] that corresponds to the given `e_machine` value.
+    pub(crate) fn from_e_machine(e_machine: u16) -> Self {
+        use goblin::elf::header::{EM_386, EM_AARCH64, EM_ARM, EM_X86_64};
+
+        match e_machine {
+            EM_386 => Machine::X86,
+            EM_X86_64 => Machine::X86_64,
+            EM_ARM => Machine::Arm,
+            EM_AARCH64 => Machine::Aarch64,
+            other => Machine::Other(other),
+        }
+    }
+
+    /// Gets the [`Machine`] of the same family which runs in `bits` mode, e.g. `x86` for a 32 bit `x86-64` process.
+    pub(crate) fn with_bits(self, bits: u8) -> Self {
+        match (self, bits) {
+            (Machine::X86_64, 32) => Machine::X86,
+            (Machine::X86, 64) => Machine::X86_64,
+            (Machine::Aarch64, 32) => Machine::Arm,
+            (Machine::Arm, 64) => Machine::Aarch64,
+            (machine, _) => machine,
+        }
+    }
+}
+
+/// A struct that represents the architecture of a process, as seen by the process itself.
+///
+/// The values are retrieved from the process auxiliary vector (`/proc/<id>/auxv`) and from the ELF header
+/// of the code it's executing, so that they don't depend on the architecture the intruducer was built for.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Arch {
+    /// The instruction set.
+    pub machine: Machine,
+    /// The word size, either `32` or `64`.
+    pub bits: u8,
+    /// The `AT_PLATFORM` string, e.g. `x86_64`, `i686`, `aarch64` or `v7l`.
+    pub platform: Option<String>,
+    /// The `AT_HWCAP` bit mask.
+    pub hwcap: Option<u64>,
+    /// The `AT_HWCAP2` bit mask.
+    pub hwcap2: Option<u64>,
+}
+
+impl Arch {
+    /// Determines which payloads family can be used to target a process of the current [`Arch`].
+    ///
+    /// Returns [`Error::UnsupportedArch`] if the current architecture is not supported by this build.
+    pub(crate) fn class(&self) -> Result<ProcClass, Error> {
+        match (self.machine, self.bits) {
+            #[cfg(any(target_arch = "arm", target_arch = "aarch64"))]
+            (Machine::Arm, 32) => Ok(ProcClass::ThirtyTwo),
+            #[cfg(target_arch = "aarch64")]
+            (Machine::Aarch64, 64) => Ok(ProcClass::SixtyFour),
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            (Machine::X86, 32) => Ok(ProcClass::ThirtyTwo),
+            #[cfg(target_arch = "x86_64")]
+            (Machine::X86_64, 64) => Ok(ProcClass::SixtyFour),
+            _ => Err(Error::UnsupportedArch {
+                found: self.clone(),
+            }),
+        }
+    }
+}
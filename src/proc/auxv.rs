@@ -0,0 +1,107 @@
+/// `AT_NULL`, the end of vector marker.
+pub(crate) const AT_NULL: u64 = 0;
+/// `AT_PLATFORM`, the address of a string identifying the hardware platform.
+pub(crate) const AT_PLATFORM: u64 = 15;
+/// `AT_HWCAP`, the architecture dependent hints about processor capabilities.
+pub(crate) const AT_HWCAP: u64 = 16;
+/// `AT_HWCAP2`, the extension of `AT_HWCAP`.
+pub(crate) const AT_HWCAP2: u64 = 26;
+/// `AT_SYSINFO_EHDR`, the address of the vDSO ELF header.
+pub(crate) const AT_SYSINFO_EHDR: u64 = 33;
+
+/// The greatest key a well-formed auxiliary vector is expected to contain.
+const AT_MAX: u64 = 64;
+
+/// A struct that represents the auxiliary vector of a process (`/proc/<id>/auxv`).
+///
+/// Source: https://man7.org/linux/man-pages/man3/getauxval.3.html
+pub(crate) struct Auxv {
+    /// The word size of the process which the vector belongs to, either `32` or `64`.
+    pub(crate) bits: u8,
+
+    /// The `(key, value)` pairs, without the `AT_NULL` terminator.
+    entries: Vec<(u64, u64)>,
+}
+
+impl Auxv {
+    /// Parses the raw content of `/proc/<id>/auxv`.
+    ///
+    /// The word size is not known in advance - a 32 bit process running on a 64 bit kernel has 32 bit entries -
+    /// so the content is first read as a 64 bit vector, then as a 32 bit one.
+    ///
+    /// Returns [`None`] if the content is malformed.
+    pub(crate) fn parse(buf: &[u8]) -> Option<Self> {
+        Self::parse_with::<8>(buf, 64).or_else(|| Self::parse_with::<4>(buf, 32))
+    }
+
+    fn parse_with<const N: usize>(buf: &[u8], bits: u8) -> Option<Self> {
+        let mut words = buf.chunks_exact(N).map(|chunk| {
+            let mut word = [0; 8];
+            word[..N].copy_from_slice(chunk);
+            u64::from_le_bytes(word)
+        });
+
+        let mut entries = Vec::new();
+
+        loop {
+            match (words.next()?, words.next()?) {
+                (AT_NULL, _) => break,
+                (key, _) if key > AT_MAX => return None,
+                entry => entries.push(entry),
+            }
+        }
+
+        entries
+            .iter()
+            .any(|(key, _)| *key == AT_PLATFORM || *key == AT_HWCAP)
+            .then_some(Auxv { bits, entries })
+    }
+
+    /// Gets the value associated with `key`.
+    ///
+    /// Returns [`None`] if the key is not present.
+    pub(crate) fn get(&self, key: u64) -> Option<u64> {
+        self.entries
+            .iter()
+            .find_map(|(cur_key, value)| (*cur_key == key).then_some(*value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Auxv, AT_HWCAP, AT_HWCAP2, AT_NULL, AT_PLATFORM, AT_SYSINFO_EHDR};
+
+    /// Builds a vector whose words are `N` bytes long.
+    fn vector<const N: usize>(entries: &[(u64, u64)]) -> Vec<u8> {
+        entries
+            .iter()
+            .flat_map(|(key, value)| [*key, *value])
+            .flat_map(|word| word.to_le_bytes()[..N].to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn parses_64_and_32_bit_vectors() {
+        let entries = [
+            (AT_SYSINFO_EHDR, 0xf7f9e000),
+            (AT_HWCAP, 0x178bfbff),
+            (AT_PLATFORM, 0xffd4f59b),
+            (AT_NULL, 0),
+        ];
+
+        for (buf, bits) in [(vector::<8>(&entries), 64), (vector::<4>(&entries), 32)] {
+            let auxv = Auxv::parse(&buf).unwrap();
+            assert_eq!(auxv.bits, bits);
+            assert_eq!(auxv.get(AT_SYSINFO_EHDR), Some(0xf7f9e000));
+            assert_eq!(auxv.get(AT_HWCAP), Some(0x178bfbff));
+            assert_eq!(auxv.get(AT_HWCAP2), None);
+        }
+
+        // The terminator is missing.
+        assert!(Auxv::parse(&vector::<8>(&entries[..3])).is_none());
+        // Neither the platform nor the capabilities are present.
+        assert!(
+            Auxv::parse(&vector::<8>(&[(AT_SYSINFO_EHDR, 0xf7f9e000), (AT_NULL, 0)])).is_none()
+        );
+    }
+}
@@ -0,0 +1,365 @@
+use std::{
+    fs::File,
+    os::unix::prelude::AsRawFd,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    ext::{PathBufExt, ProcExt},
+    os::{pidfd_open, pidfd_send_signal, PtraceScope, Selinux, ESRCH},
+};
+
+use super::{Proc, ProcHandles, ProcId};
+
+/// A struct that references the filesystem processes are looked up in, e.g. where [`/proc`](https://man7.org/linux/man-pages/man5/proc.5.html)
+/// and `/data/system/packages.list` are located at.
+///
+/// It's rooted at `/`, but it can be rooted at a directory which mimics it - so that process discovery can be tested.
+#[derive(Clone, Debug)]
+pub(crate) struct ProcFs {
+    root: PathBuf,
+}
+
+impl ProcFs {
+    /// Creates a new [`ProcFs`] rooted at `root`.
+    pub(crate) fn new(root: PathBuf) -> Self {
+        ProcFs { root }
+    }
+
+    /// Creates a new [`ProcFs`] that references the host filesystem.
+    pub(crate) fn host() -> Self {
+        Self::new(PathBuf::root())
+    }
+
+    /// Determines whether the current [`ProcFs`] references the host filesystem, rather than a mimicked one.
+    pub(crate) fn is_host(&self) -> bool {
+        self.root == PathBuf::root()
+    }
+
+    /// Resolves the absolute `path` - as seen by a process - inside the current [`ProcFs`].
+    pub(crate) fn resolve(&self, path: impl AsRef<Path>) -> PathBuf {
+        let path = path.as_ref();
+        self.root.join(path.strip_prefix("/").unwrap_or(path))
+    }
+
+    /// Creates a new [`Proc`] that references the host process.
+    pub(crate) fn current(&self) -> Proc {
+        Proc {
+            path: self.resolve("/proc/self"),
+            fs: self.clone(),
+            handles: None,
+        }
+    }
+
+    /// Creates a new [`Proc`] that references the task identified by `id`.
+    ///
+    /// Returns [`None`] if the path `/proc/<id>` does not exist.
+    pub(crate) fn proc(&self, id: ProcId) -> Option<Proc> {
+        let path = self.resolve("/proc").join(id.to_string());
+
+        path.exists().then(|| Proc {
+            path,
+            fs: self.clone(),
+            handles: None,
+        })
+    }
+
+    /// Creates a new [`Proc`] that references the process identified by `id`, as [`ProcFs::proc`] does, but keeps
+    /// referencing it if it exits and its identifier is reused: `/proc/<id>` is opened once, and accessed through
+    /// `/proc/self/fd/<fd>` afterwards.
+    ///
+    /// Returns [`None`] if the process is not running.
+    pub(crate) fn open(&self, id: ProcId) -> Option<Proc> {
+        // A `pidfd` refers to a process of the host, so it's not opened for a mimicked one.
+        let pidfd = match self.is_host() {
+            true => match pidfd_open(id) {
+                Ok(pidfd) => Some(pidfd),
+                Err(err) if err.raw_os_error() == Some(ESRCH) => return None,
+                // E.g. `ENOSYS`.
+                Err(_) => None,
+            },
+            false => None,
+        };
+
+        let dir = File::open(self.resolve("/proc").join(id.to_string())).ok()?;
+
+        // The process the `pidfd` refers to is still running, so the directory opened afterwards is its own.
+        if let Some(pidfd) = &pidfd {
+            pidfd_send_signal(pidfd, 0).ok()?;
+        }
+
+        let mut proc = Proc {
+            path: PathBuf::from(format!("/proc/self/fd/{}", dir.as_raw_fd())),
+            fs: self.clone(),
+            handles: None,
+        };
+
+        let start_time = proc.start_time().ok()?;
+        proc.handles = Some(Arc::new(ProcHandles {
+            _dir: dir,
+            pidfd,
+            start_time,
+        }));
+
+        Some(proc)
+    }
+
+    /// Gets the content of `/proc/sys/kernel/yama/ptrace_scope`.
+    pub(crate) fn ptrace_scope(&self) -> PtraceScope {
+        PtraceScope::read(&self.resolve("/proc/sys/kernel/yama/ptrace_scope"))
+    }
+
+    /// Determines whether `/proc/<id>/mem` can write the regions which are not writable, e.g. the kernel was not booted
+    /// with `proc_mem.force_override=never` - or `ptrace`, which only allows the tracer to. The default the kernel was
+    /// built with is assumed to allow it.
+    pub(crate) fn proc_mem_force(&self) -> bool {
+        std::fs::read_to_string(self.resolve("/proc/cmdline")).map_or(true, |cmdline| {
+            !cmdline.split_whitespace().any(|param| {
+                matches!(
+                    param,
+                    "proc_mem.force_override=never" | "proc_mem.force_override=ptrace"
+                )
+            })
+        })
+    }
+
+    /// Gets the SELinux filesystem, `/sys/fs/selinux`.
+    ///
+    /// Returns [`None`] if it's not mounted, e.g. SELinux is disabled.
+    pub(crate) fn selinux(&self) -> Option<Selinux> {
+        Selinux::new(self.resolve("/sys/fs/selinux"))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod fixture {
+    use std::{
+        fs,
+        path::PathBuf,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use goblin::elf::header::EM_X86_64;
+
+    use crate::proc::{auxv::AT_NULL, AT_HWCAP};
+
+    use super::ProcFs;
+
+    /// A directory which mimics `/`, removed when dropped.
+    pub(crate) struct Fixture(pub(crate) PathBuf);
+
+    impl Fixture {
+        pub(crate) fn new() -> Self {
+            static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+            let root = std::env::temp_dir().join(format!(
+                "intruducer-procfs-{}-{}",
+                std::process::id(),
+                COUNT.fetch_add(1, Ordering::Relaxed)
+            ));
+            let _ = fs::remove_dir_all(&root);
+
+            Fixture(root)
+        }
+
+        /// Writes `contents` to the absolute `path`, creating its parent directories.
+        pub(crate) fn write(&self, path: &str, contents: impl AsRef<[u8]>) -> &Self {
+            let path = self.fs().resolve(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+            self
+        }
+
+        pub(crate) fn fs(&self) -> ProcFs {
+            ProcFs::new(self.0.clone())
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Builds a 64 bit auxiliary vector out of `entries`.
+    pub(crate) fn auxv(entries: &[(u64, u64)]) -> Vec<u8> {
+        entries
+            .iter()
+            .chain(&[(AT_NULL, 0)])
+            .flat_map(|(key, value)| [key.to_le_bytes(), value.to_le_bytes()])
+            .flatten()
+            .collect()
+    }
+
+    /// Builds a 64 bit little endian ELF file whose symbol table holds `syms`, e.g. `(name, value)` pairs.
+    pub(crate) fn elf(e_machine: u16, syms: &[(&str, u64)]) -> Vec<u8> {
+        const EHDR_SIZE: usize = 0x40;
+        const SYM_SIZE: usize = 0x18;
+        const SHDR_SIZE: usize = 0x40;
+
+        let mut strtab = vec![0];
+        let mut symtab = vec![0; SYM_SIZE];
+        for (name, value) in syms {
+            let mut sym = [0; SYM_SIZE];
+            sym[..4].copy_from_slice(&(strtab.len() as u32).to_le_bytes());
+            // STB_GLOBAL, STT_FUNC.
+            sym[4] = 0x12;
+            sym[8..16].copy_from_slice(&value.to_le_bytes());
+            symtab.extend(sym);
+
+            strtab.extend(name.bytes().chain([0]));
+        }
+
+        let symtab_offset = EHDR_SIZE;
+        let strtab_offset = symtab_offset + symtab.len();
+        let shdrs_offset = (strtab_offset + strtab.len() + 7) & !7;
+
+        let mut buf = vec![0; shdrs_offset];
+        buf[..4].copy_from_slice(b"\x7fELF");
+        // ELFCLASS64, ELFDATA2LSB, EV_CURRENT.
+        buf[4..7].copy_from_slice(&[2, 1, 1]);
+        // ET_DYN.
+        buf[0x10..0x12].copy_from_slice(&3_u16.to_le_bytes());
+        buf[0x12..0x14].copy_from_slice(&e_machine.to_le_bytes());
+        buf[0x14..0x18].copy_from_slice(&1_u32.to_le_bytes());
+        buf[0x28..0x30].copy_from_slice(&(shdrs_offset as u64).to_le_bytes());
+        buf[0x34..0x36].copy_from_slice(&(EHDR_SIZE as u16).to_le_bytes());
+        buf[0x3a..0x3c].copy_from_slice(&(SHDR_SIZE as u16).to_le_bytes());
+        // The null, symbol table and string table sections.
+        buf[0x3c..0x3e].copy_from_slice(&3_u16.to_le_bytes());
+        buf[symtab_offset..strtab_offset].copy_from_slice(&symtab);
+        buf[strtab_offset..strtab_offset + strtab.len()].copy_from_slice(&strtab);
+
+        // (sh_type, sh_offset, sh_size, sh_link, sh_entsize) of SHT_SYMTAB and SHT_STRTAB.
+        let sections = [
+            (2_u32, symtab_offset, symtab.len(), 2_u32, SYM_SIZE),
+            (3, strtab_offset, strtab.len(), 0, 0),
+        ];
+        buf.extend([0; SHDR_SIZE]);
+        for (sh_type, offset, size, link, entsize) in sections {
+            let mut shdr = [0; SHDR_SIZE];
+            shdr[0x04..0x08].copy_from_slice(&sh_type.to_le_bytes());
+            shdr[0x18..0x20].copy_from_slice(&(offset as u64).to_le_bytes());
+            shdr[0x20..0x28].copy_from_slice(&(size as u64).to_le_bytes());
+            shdr[0x28..0x2c].copy_from_slice(&link.to_le_bytes());
+            shdr[0x38..0x40].copy_from_slice(&(entsize as u64).to_le_bytes());
+            buf.extend(shdr);
+        }
+
+        buf
+    }
+
+    /// Mimics a multi-threaded process `1234`, whose main thread is running.
+    pub(crate) fn process() -> Fixture {
+        let fixture = Fixture::new();
+        fixture
+            .write(
+                "/proc/1234/maps",
+                "\
+55d0c0a00000-55d0c0a01000 r--p 00000000 fd:01 1053                       /usr/bin/victim
+7f3a1c000000-7f3a1c028000 r--p 00000000 fd:01 2051                       /usr/lib/libc.so.6
+7f3a1c028000-7f3a1c1bd000 r-xp 00028000 fd:01 2051                       /usr/lib/libc.so.6
+7ffd4f392000-7ffd4f3b3000 rw-p 00000000 00:00 0                          [stack]
+",
+            )
+            .write(
+                "/proc/1234/stat",
+                "1234 (victim) R 987 1234 987 0 -1 4194560 0 0 0 0 5 3 0 0 20 0 2 0 4242 10485760",
+            )
+            .write("/proc/1234/syscall", "running\n")
+            .write(
+                "/proc/1234/task/1234/stat",
+                "1234 (victim) R 987 1234 987 0 -1 4194560 0 0",
+            )
+            .write("/proc/1234/task/1234/syscall", "running\n")
+            .write(
+                "/proc/1234/task/1235/stat",
+                "1235 (worker) S 987 1234 987 0 -1 4194624 0 0",
+            )
+            .write(
+                "/proc/1234/task/1235/syscall",
+                "230 0x1 0x0 0x7f3a1b7fdde0 0x7f3a1b7fdde0 0x0 0x0 0x7f3a1b7fddc8 0x7f3a1c0e57fa\n",
+            )
+            .write("/proc/1234/auxv", auxv(&[(AT_HWCAP, 0x178bfbff)]))
+            .write("/proc/1234/exe", elf(EM_X86_64, &[]))
+            .write(
+                "/proc/1234/status",
+                "Name:\tvictim\nUid:\t1000\t1000\t1000\t1000\n",
+            )
+            .write(
+                "/usr/lib/libc.so.6",
+                elf(EM_X86_64, &[("malloc", 0x9a0f0), ("dlopen", 0x8a6b0)]),
+            );
+        fixture
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::MetadataExt;
+
+    use crate::{ext::ProcExt, os::PtraceScope};
+
+    use super::{
+        fixture::{process, Fixture},
+        ProcFs,
+    };
+
+    #[test]
+    fn looks_processes_up_in_the_root() {
+        let fixture = process();
+        let fs = fixture.fs();
+
+        assert_eq!(fs.proc(1234).unwrap().path, fixture.0.join("proc/1234"));
+        assert!(fs.proc(1235).is_none());
+        assert_eq!(fs.current().path, fixture.0.join("proc/self"));
+        assert_eq!(fs.resolve("/data/app"), fixture.0.join("data/app"));
+    }
+
+    #[test]
+    fn opens_processes_once() {
+        let fixture = process();
+        let proc = fixture.fs().open(1234).unwrap();
+        assert!(proc.path.starts_with("/proc/self/fd"));
+        assert!(proc.handles.as_ref().unwrap().pidfd.is_none());
+        assert!(proc.still_running());
+
+        // The directory is replaced by a later process with the same identifier.
+        std::fs::rename(fixture.0.join("proc/1234"), fixture.0.join("proc/1236")).unwrap();
+        fixture.write(
+            "/proc/1234/stat",
+            "1234 (victim) R 987 1234 987 0 -1 4194560 0 0 0 0 5 3 0 0 20 0 2 0 4343 10485760",
+        );
+        assert_eq!(proc.start_time().unwrap(), 4242);
+        assert!(proc.still_running());
+
+        std::fs::remove_dir_all(fixture.0.join("proc/1236")).unwrap();
+        assert!(!proc.still_running());
+        assert!(fixture.fs().open(1235).is_none());
+
+        let current = ProcFs::host().open(std::process::id()).unwrap();
+        assert!(current.still_running());
+        assert_eq!(current.status().unwrap().pid, std::process::id());
+    }
+
+    #[test]
+    fn reads_the_ptrace_scope() {
+        let fixture = Fixture::new();
+        assert!(matches!(fixture.fs().ptrace_scope(), PtraceScope::All));
+
+        fixture.write("/proc/sys/kernel/yama/ptrace_scope", "2\n");
+        assert!(matches!(fixture.fs().ptrace_scope(), PtraceScope::Admin));
+    }
+
+    #[test]
+    fn gets_the_owner() {
+        let fixture = process();
+        let metadata = fixture.0.join("proc/1234").metadata().unwrap();
+
+        assert_eq!(
+            fixture.fs().proc(1234).unwrap().owner().unwrap(),
+            (metadata.uid(), metadata.gid())
+        );
+    }
+}
@@ -0,0 +1,91 @@
+use std::ops::Range;
+
+use crate::os::VirtAddr;
+
+/// A struct that represents a line of `/proc/<id>/maps`, e.g. a memory region of a process.
+///
+/// Source: https://man7.org/linux/man-pages/man5/proc.5.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct ProcMap {
+    /// The addresses the region spans.
+    pub(crate) range: Range<VirtAddr>,
+
+    /// Whether the region is readable.
+    pub(crate) readable: bool,
+
+    /// Whether the region is writable.
+    pub(crate) writable: bool,
+
+    /// Whether the region is executable.
+    pub(crate) executable: bool,
+
+    /// The file the region maps, or a pseudo-path such as `[stack]` or `[vdso]`. It's [`None`] for an anonymous mapping.
+    pub(crate) path: Option<String>,
+}
+
+impl ProcMap {
+    /// Parses a line of `/proc/<id>/maps`, which is made of `<start>-<end> <perms> <offset> <dev> <inode>`, optionally
+    /// followed by a path that may contain spaces.
+    ///
+    /// Returns [`None`] if it's malformed.
+    pub(crate) fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.splitn(6, ' ');
+
+        let (start, end) = fields.next()?.split_once('-')?;
+        let perms = fields.next()?.as_bytes();
+        if perms.len() != 4 {
+            return None;
+        }
+        let path = fields.nth(3).map(str::trim_start).unwrap_or_default();
+
+        Some(ProcMap {
+            range: VirtAddr::from_str_radix(start, 16).ok()?
+                ..VirtAddr::from_str_radix(end, 16).ok()?,
+            readable: perms[0] == b'r',
+            writable: perms[1] == b'w',
+            executable: perms[2] == b'x',
+            path: (!path.is_empty()).then(|| path.to_owned()),
+        })
+    }
+
+    /// Determines whether the region is the vDSO, whose code is shared by every process of the system.
+    pub(crate) fn is_vdso(&self) -> bool {
+        self.path.as_deref() == Some("[vdso]")
+    }
+
+    /// Gets the name of the region, e.g. its path.
+    pub(crate) fn name(&self) -> &str {
+        self.path.as_deref().unwrap_or("an anonymous mapping")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ProcMap;
+
+    #[test]
+    fn parses_maps() {
+        let map = ProcMap::parse(
+            "7f3a1c028000-7f3a1c1bd000 r-xp 00028000 fd:01 2051                       /usr/lib/my lib.so",
+        )
+        .unwrap();
+        assert_eq!(map.range, 0x7f3a1c028000..0x7f3a1c1bd000);
+        assert!(map.readable && !map.writable && map.executable);
+        assert_eq!(map.path.as_deref(), Some("/usr/lib/my lib.so"));
+        assert!(!map.is_vdso());
+
+        let map = ProcMap::parse(
+            "7ffd4f3f4000-7ffd4f3f6000 r-xp 00000000 00:00 0                          [vdso]",
+        )
+        .unwrap();
+        assert!(map.is_vdso());
+
+        let map = ProcMap::parse("7f3a1b000000-7f3a1b800000 rw-p 00000000 00:00 0 ").unwrap();
+        assert_eq!(map.path, None);
+        assert_eq!(map.name(), "an anonymous mapping");
+
+        assert!(ProcMap::parse("").is_none());
+        assert!(ProcMap::parse("7f3a1b000000 rw-p 00000000 00:00 0").is_none());
+        assert!(ProcMap::parse("7f3a1b000000-7f3a1b800000 rw 00000000 00:00 0").is_none());
+    }
+}
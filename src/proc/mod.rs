@@ -1,56 +1,99 @@
 use std::{
     fs::{File, OpenOptions, ReadDir},
-    io::Error as IoError,
+    io::{Error as IoError, ErrorKind},
     os::unix::prelude::MetadataExt,
     path::PathBuf,
+    sync::Arc,
 };
 
+mod arch;
+mod auxv;
 mod class;
+mod fs;
 mod id;
 mod lib;
+mod map;
+mod status;
 mod sym;
+mod syscall;
+mod thread;
 
-use crate::{
-    ext::PathBufExt,
-    os::{Gid, Uid},
-};
+use crate::os::{Gid, Uid};
 
+pub use arch::{Arch, Machine};
+pub(crate) use auxv::{Auxv, AT_HWCAP, AT_HWCAP2, AT_PLATFORM, AT_SYSINFO_EHDR};
 pub(crate) use class::ProcClass;
+#[cfg(test)]
+pub(crate) use fs::fixture;
+pub(crate) use fs::ProcFs;
 pub(crate) use id::ProcId;
 pub(crate) use lib::ProcLib;
+pub(crate) use map::ProcMap;
+pub(crate) use status::{ProcStatus, CAP_SYS_PTRACE};
 pub(crate) use sym::ProcSym;
+pub(crate) use syscall::ProcSyscall;
+pub use thread::{Thread, ThreadSelector};
 
-/// A newtype that references the [`/proc/<id>`](https://man7.org/linux/man-pages/man5/proc.5.html) directory.
-pub(crate) struct Proc(pub(crate) PathBuf);
+/// A struct that references the [`/proc/<id>`](https://man7.org/linux/man-pages/man5/proc.5.html) directory.
+pub(crate) struct Proc {
+    /// The path of the directory.
+    pub(crate) path: PathBuf,
 
-impl Proc {
-    /// Creates a new [`Proc`] that references the host process.
-    pub(crate) fn current() -> Self {
-        Proc(PathBuf::root().join("proc").join("self"))
-    }
+    /// The filesystem the directory belongs to.
+    pub(crate) fs: ProcFs,
+
+    /// The handles the directory is accessed through, if it was opened with [`ProcFs::open`]. They're shared with its
+    /// tasks.
+    pub(crate) handles: Option<Arc<ProcHandles>>,
+}
+
+/// A struct that represents the handles which keep referencing a process, rather than a later one with the same
+/// identifier.
+pub(crate) struct ProcHandles {
+    /// The `/proc/<id>` directory, which is accessed through `/proc/self/fd/<fd>` as long as it's open.
+    pub(crate) _dir: File,
 
-    /// Creates a new [`Proc`] that references the task identified by `id`.
-    ///
-    /// Returns [`None`] if the path `/proc/<id>` does not exist.
-    pub(crate) fn new(id: ProcId) -> Option<Self> {
-        let path = PathBuf::root().join("proc").join(id.to_string());
-        path.exists().then_some(Proc(path))
+    /// The `pidfd`, which is [`None`] if the kernel doesn't support it (before Linux 5.3).
+    pub(crate) pidfd: Option<File>,
+
+    /// When the process started, in clock ticks since the system boot.
+    pub(crate) start_time: u64,
+}
+
+impl Proc {
+    /// Creates a new [`Proc`] that references the task whose directory is `path`, e.g. an entry of `/proc/<id>/task`.
+    pub(crate) fn task(&self, path: PathBuf) -> Self {
+        Proc {
+            path,
+            fs: self.fs.clone(),
+            handles: self.handles.clone(),
+        }
     }
 
     /// Gets the owner of the current [`Proc`].
     pub(crate) fn owner(&self) -> Result<(Uid, Gid), IoError> {
-        let metadata = self.0.metadata()?;
+        let metadata = self.path.metadata()?;
         Ok((metadata.uid(), metadata.gid()))
     }
 
+    /// Reads `/proc/<id>/auxv` of the current [`Proc`].
+    pub(crate) fn auxv(&self) -> Result<File, IoError> {
+        File::open(self.path.join("auxv"))
+    }
+
     /// Reads `/proc/<id>/exe` of the current [`Proc`].
     pub(crate) fn exe(&self) -> Result<File, IoError> {
-        File::open(self.0.join("exe"))
+        File::open(self.path.join("exe"))
     }
 
     /// Reads `/proc/<id>/maps` of the current [`Proc`].
     pub(crate) fn maps(&self) -> Result<File, IoError> {
-        File::open(self.0.join("maps"))
+        File::open(self.path.join("maps"))
+    }
+
+    /// Reads `/proc/<id>/mountinfo` of the current [`Proc`].
+    pub(crate) fn mountinfo(&self) -> Result<File, IoError> {
+        File::open(self.path.join("mountinfo"))
     }
 
     /// Reads `/proc/<id>/mem` of the current [`Proc`].
@@ -58,16 +101,38 @@ impl Proc {
         OpenOptions::new()
             .read(true)
             .write(true)
-            .open(self.0.join("mem"))
+            .open(self.path.join("mem"))
+    }
+
+    /// Reads and parses `/proc/<id>/status` of the current [`Proc`].
+    pub(crate) fn status(&self) -> Result<ProcStatus, IoError> {
+        ProcStatus::parse(&std::fs::read_to_string(self.path.join("status"))?)
+            .ok_or_else(|| IoError::new(ErrorKind::InvalidData, "malformed status"))
+    }
+
+    /// Reads `/proc/<id>/attr/current` of the current [`Proc`], e.g. its SELinux context.
+    pub(crate) fn security_context(&self) -> Result<String, IoError> {
+        let context = std::fs::read_to_string(self.path.join("attr").join("current"))?;
+        Ok(context.trim_end_matches(['\0', '\n']).to_owned())
+    }
+
+    /// Reads the `/proc/<id>/ns/user` link of the current [`Proc`], which identifies its user namespace.
+    pub(crate) fn user_ns(&self) -> Result<PathBuf, IoError> {
+        std::fs::read_link(self.path.join("ns").join("user"))
+    }
+
+    /// Reads `/proc/<id>/stat` of the current [`Proc`].
+    pub(crate) fn stat(&self) -> Result<String, IoError> {
+        std::fs::read_to_string(self.path.join("stat"))
     }
 
     /// Reads `/proc/<id>/syscall` of the current [`Proc`].
     pub(crate) fn syscall(&self) -> Result<File, IoError> {
-        File::open(self.0.join("syscall"))
+        File::open(self.path.join("syscall"))
     }
 
     /// Reads `/proc/<id>/task` of the current [`Proc`].
-    pub(crate) fn task(&self) -> Result<ReadDir, IoError> {
-        std::fs::read_dir(self.0.join("task"))
+    pub(crate) fn tasks(&self) -> Result<ReadDir, IoError> {
+        std::fs::read_dir(self.path.join("task"))
     }
 }
@@ -0,0 +1,124 @@
+use crate::os::{Gid, Uid};
+
+use super::ProcId;
+
+/// `CAP_SYS_PTRACE`, the capability which overrides the access checks of `/proc/<id>/mem`.
+pub(crate) const CAP_SYS_PTRACE: u32 = 19;
+
+/// A struct that represents the fields of `/proc/<id>/status` the access checks and the nudge depend on.
+///
+/// Source: https://man7.org/linux/man-pages/man5/proc.5.html
+pub(crate) struct ProcStatus {
+    /// The thread group identifier, e.g. the process identifier.
+    pub(crate) tgid: ProcId,
+
+    /// The thread identifier, which is the thread group one for the main thread.
+    pub(crate) pid: ProcId,
+
+    /// The identifier of the parent process, which is `0` for the initial process.
+    pub(crate) ppid: ProcId,
+
+    /// The real, effective, saved set and filesystem user identifiers.
+    pub(crate) uids: [Uid; 4],
+
+    /// The real, effective, saved set and filesystem group identifiers.
+    pub(crate) gids: [Gid; 4],
+
+    /// The effective capabilities bit mask.
+    pub(crate) cap_eff: u64,
+
+    /// The bit mask of the signals the thread blocks, where the bit `n - 1` stands for the signal `n`.
+    pub(crate) sig_blk: u64,
+
+    /// The bit mask of the signals the process ignores.
+    pub(crate) sig_ign: u64,
+
+    /// The bit mask of the signals the process catches, e.g. it has installed a handler for.
+    pub(crate) sig_cgt: u64,
+
+    /// The seccomp mode: `0` if disabled, `1` if strict, `2` if filtered. It's [`None`] if the kernel doesn't support seccomp.
+    pub(crate) seccomp: Option<u8>,
+
+    /// The number of seccomp filters installed. It's [`None`] if the kernel doesn't report it.
+    pub(crate) seccomp_filters: Option<u32>,
+}
+
+impl ProcStatus {
+    /// Parses the content of `/proc/<id>/status`, which is made of `<name>:\t<value>` lines.
+    ///
+    /// Returns [`None`] if one of the needed fields is missing or malformed.
+    pub(crate) fn parse(content: &str) -> Option<Self> {
+        let field = |name: &str| {
+            content.lines().find_map(|line| {
+                let (cur_name, value) = line.split_once(':')?;
+                (cur_name == name).then(|| value.trim())
+            })
+        };
+        let ids = |name: &str| -> Option<[u32; 4]> {
+            let ids = field(name)?
+                .split_whitespace()
+                .map(|id| id.parse().ok())
+                .collect::<Option<Vec<_>>>()?;
+            ids.try_into().ok()
+        };
+
+        let mask = |name: &str| u64::from_str_radix(field(name)?, 16).ok();
+
+        Some(ProcStatus {
+            tgid: field("Tgid")?.parse().ok()?,
+            pid: field("Pid")?.parse().ok()?,
+            ppid: field("PPid")?.parse().ok()?,
+            uids: ids("Uid")?,
+            gids: ids("Gid")?,
+            cap_eff: mask("CapEff")?,
+            sig_blk: mask("SigBlk")?,
+            sig_ign: mask("SigIgn")?,
+            sig_cgt: mask("SigCgt")?,
+            seccomp: field("Seccomp").and_then(|mode| mode.parse().ok()),
+            seccomp_filters: field("Seccomp_filters").and_then(|count| count.parse().ok()),
+        })
+    }
+
+    /// Determines whether the effective capabilities hold `cap`.
+    pub(crate) fn has_cap(&self, cap: u32) -> bool {
+        self.cap_eff >> cap & 1 != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ProcStatus, CAP_SYS_PTRACE};
+
+    #[test]
+    fn parses_status() {
+        let status = ProcStatus::parse(
+            "Name:\tvictim\nUmask:\t0022\nState:\tS (sleeping)\nTgid:\t1234\nNgid:\t0\nPid:\t1234\nPPid:\t987\n\
+             TracerPid:\t0\nUid:\t1000\t1000\t1000\t1000\nGid:\t100\t100\t100\t100\nFDSize:\t64\n\
+             SigQ:\t0/63432\nSigPnd:\t0000000000000000\nShdPnd:\t0000000000000000\nSigBlk:\t0000000000000000\n\
+             SigIgn:\t0000000000001000\nSigCgt:\t0000000180000200\nCapInh:\t0000000000000000\nCapPrm:\t0000000000080000\nCapEff:\t0000000000080000\n\
+             NoNewPrivs:\t0\nSeccomp:\t2\nSeccomp_filters:\t1\n",
+        )
+        .unwrap();
+
+        assert_eq!(status.tgid, 1234);
+        assert_eq!(status.pid, 1234);
+        assert_eq!(status.ppid, 987);
+        assert_eq!(status.uids, [1000; 4]);
+        assert_eq!(status.gids, [100; 4]);
+        assert!(status.has_cap(CAP_SYS_PTRACE));
+        assert!(!status.has_cap(0));
+        assert_eq!(status.seccomp, Some(2));
+        assert_eq!(status.seccomp_filters, Some(1));
+        assert_eq!(status.sig_ign, 1 << (13 - 1));
+        assert_eq!(status.sig_cgt, 0x180000200);
+    }
+
+    #[test]
+    fn rejects_malformed_status() {
+        assert!(ProcStatus::parse("").is_none());
+        assert!(ProcStatus::parse(
+            "Pid:\t1\nPPid:\t0\nUid:\t0\t0\t0\nGid:\t0\t0\t0\t0\nCapEff:\t000001ffffffffff\n"
+        )
+        .is_none());
+    }
+}
@@ -0,0 +1,109 @@
+use crate::os::VirtAddr;
+
+/// A struct that represents the content of `/proc/<id>/syscall`.
+///
+/// For a 32 bit task running on a 64 bit kernel (a compat task), the syscall number belongs to the compat ABI
+/// (e.g. `int 0x80` numbers on `x86`) and every value is zero-extended from 32 bits.
+///
+/// Source: https://man7.org/linux/man-pages/man5/proc.5.html
+#[derive(Clone, Debug)]
+pub(crate) struct ProcSyscall {
+    /// The syscall number. It's [`None`] if the task is blocked, but not in a syscall.
+    pub(crate) nr: Option<u64>,
+
+    /// The syscall arguments, which are all zero if the task is not in a syscall.
+    pub(crate) args: [u64; 6],
+
+    /// The stack pointer.
+    pub(crate) sp: VirtAddr,
+
+    /// The instruction pointer, which is right after the syscall instruction.
+    pub(crate) ip: VirtAddr,
+}
+
+impl ProcSyscall {
+    /// Parses the content of `/proc/<id>/syscall`, which is either `running`, `-1 <sp> <ip>`
+    /// or `<nr> <arg0> ... <arg5> <sp> <ip>`.
+    ///
+    /// Returns [`None`] if the task is running or the content is malformed.
+    pub(crate) fn parse(content: &str) -> Option<Self> {
+        let mut fields = content.split_whitespace();
+
+        let nr: i64 = fields.next()?.parse().ok()?;
+
+        let values = fields
+            .map(|field| VirtAddr::from_str_radix(field.strip_prefix("0x")?, 16).ok())
+            .collect::<Option<Vec<_>>>()?;
+
+        match (nr, values.as_slice()) {
+            (-1, [sp, ip]) => Some(ProcSyscall {
+                nr: None,
+                args: [0; 6],
+                sp: *sp,
+                ip: *ip,
+            }),
+            (0.., [args @ .., sp, ip]) if args.len() == 6 => Some(ProcSyscall {
+                nr: Some(nr as u64),
+                args: args.try_into().ok()?,
+                sp: *sp,
+                ip: *ip,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Determines whether both the stack and the instruction pointers fit into `bits`, e.g. they
+    /// can be used by a task running in 32 bit mode.
+    pub(crate) fn fits(&self, bits: u8) -> bool {
+        bits >= 64 || (self.sp >> bits == 0 && self.ip >> bits == 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ProcSyscall;
+
+    #[test]
+    fn parses_native_task() {
+        let syscall = ProcSyscall::parse(
+            "230 0x1 0x0 0x7ffd4f3b1e10 0x7ffd4f3b1e10 0x0 0x0 0x7ffd4f3b1df8 0x7f1c2a0e57fa\n",
+        )
+        .unwrap();
+
+        assert_eq!(syscall.nr, Some(230));
+        assert_eq!(syscall.args, [1, 0, 0x7ffd4f3b1e10, 0x7ffd4f3b1e10, 0, 0]);
+        assert_eq!(syscall.sp, 0x7ffd4f3b1df8);
+        assert_eq!(syscall.ip, 0x7f1c2a0e57fa);
+        assert!(syscall.fits(64));
+        assert!(!syscall.fits(32));
+    }
+
+    #[test]
+    fn parses_compat_task() {
+        // clock_nanosleep_time64 (407) issued through `int 0x80` by an i386 task on a x86-64 kernel.
+        let syscall =
+            ProcSyscall::parse("407 0x0 0x0 0xffd2e1a8 0xffd2e1a8 0x0 0x0 0xffd2e16c 0xf7f1b579\n")
+                .unwrap();
+
+        assert_eq!(syscall.sp, 0xffd2e16c);
+        assert_eq!(syscall.ip, 0xf7f1b579);
+        assert!(syscall.fits(32));
+    }
+
+    #[test]
+    fn parses_task_not_in_syscall() {
+        let syscall = ProcSyscall::parse("-1 0xffd2e16c 0x565561f0\n").unwrap();
+
+        assert_eq!(syscall.nr, None);
+        assert_eq!(syscall.sp, 0xffd2e16c);
+        assert_eq!(syscall.ip, 0x565561f0);
+    }
+
+    #[test]
+    fn rejects_running_or_malformed_task() {
+        assert!(ProcSyscall::parse("running\n").is_none());
+        assert!(ProcSyscall::parse("").is_none());
+        assert!(ProcSyscall::parse("-1 0xffd2e16c\n").is_none());
+        assert!(ProcSyscall::parse("407 0x0 0x0 0xffd2e16c 0xf7f1b579\n").is_none());
+    }
+}
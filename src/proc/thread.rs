@@ -0,0 +1,199 @@
+use crate::Error;
+
+use super::{ProcId, ProcSyscall};
+
+/// A struct that represents a thread of the target process, as `/proc/<id>/task/<tid>` describes it.
+#[derive(Clone, Debug)]
+pub struct Thread {
+    /// The thread identifier.
+    pub tid: ProcId,
+    /// The name (`comm`), which the kernel truncates to 15 bytes.
+    pub name: String,
+    /// The state, e.g. `R` (running), `S` (interruptible sleep) or `D` (uninterruptible sleep).
+    pub state: char,
+    /// Where the thread is blocked. It's [`None`] if it's running or `/proc/<id>/task/<tid>/syscall` couldn't be read.
+    pub(crate) blocked: Option<ProcSyscall>,
+}
+
+impl Thread {
+    /// Parses the content of `/proc/<id>/task/<tid>/stat`, which is made of `<tid> (<name>) <state> ...` - the name may
+    /// contain both spaces and parentheses - and takes where the thread is `blocked`.
+    ///
+    /// Returns [`None`] if the content is malformed.
+    pub(crate) fn parse(stat: &str, blocked: Option<ProcSyscall>) -> Option<Self> {
+        let (tid, rest) = stat.split_once(" (")?;
+        let (name, rest) = rest.rsplit_once(") ")?;
+
+        Some(Thread {
+            tid: tid.parse().ok()?,
+            name: name.to_owned(),
+            state: rest.chars().next()?,
+            blocked,
+        })
+    }
+
+    /// Gets the number of the syscall the thread is blocked in, which belongs to the compat ABI for a 32 bit thread
+    /// running on a 64 bit kernel.
+    ///
+    /// Returns [`None`] if it's not blocked in a syscall.
+    pub fn syscall(&self) -> Option<u64> {
+        self.blocked.as_ref()?.nr
+    }
+
+    /// Gets the arguments of the syscall the thread is blocked in.
+    ///
+    /// Returns [`None`] if it's not blocked in a syscall.
+    pub fn args(&self) -> Option<[u64; 6]> {
+        let blocked = self.blocked.as_ref()?;
+        blocked.nr.map(|_| blocked.args)
+    }
+
+    /// Determines whether the thread is blocked, e.g. its instruction pointer is known and it can be targeted.
+    pub fn is_blocked(&self) -> bool {
+        self.blocked.is_some()
+    }
+}
+
+/// A enum that represents which thread of the target process is targeted, e.g. whose instruction pointer the first
+/// payload is written at. Only a blocked thread can be targeted.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum ThreadSelector {
+    /// The thread the identifier given to [`crate::intruduce`] refers to if it's blocked, any other blocked thread otherwise.
+    #[default]
+    Any,
+    /// The thread with the given identifier.
+    Tid(ProcId),
+    /// A thread blocked in the syscall with the given number, e.g. `230` (`clock_nanosleep`) on `x86_64`.
+    Syscall(u64),
+    /// A thread with the given name.
+    Name(String),
+    /// A thread in an interruptible sleep, whose syscall is restarted if the first payload interrupts it, or any other
+    /// blocked thread if there's none.
+    PreferInterruptible,
+}
+
+impl ThreadSelector {
+    /// Selects every blocked thread among `threads`, which are ordered by preference, that matches, along with where
+    /// it's blocked.
+    ///
+    /// Returns [`Error::ThreadNotFound`] if none matches, or [`Error::InstructionPointerNotFound`] if none of the
+    /// matching ones is blocked.
+    pub(crate) fn candidates(
+        &self,
+        threads: Vec<Thread>,
+    ) -> Result<Vec<(Thread, ProcSyscall)>, Error> {
+        let matching: Vec<_> = threads
+            .into_iter()
+            .filter(|thread| match self {
+                ThreadSelector::Any | ThreadSelector::PreferInterruptible => true,
+                ThreadSelector::Tid(tid) => thread.tid == *tid,
+                ThreadSelector::Syscall(nr) => thread.syscall() == Some(*nr),
+                ThreadSelector::Name(name) => thread.name == *name,
+            })
+            .collect();
+
+        if matching.is_empty() {
+            return Err(Error::ThreadNotFound);
+        }
+
+        let preferred = |thread: &&Thread| match self {
+            ThreadSelector::PreferInterruptible => thread.state == 'S',
+            _ => false,
+        };
+
+        let candidates: Vec<_> = matching
+            .iter()
+            .filter(preferred)
+            .chain(matching.iter().filter(|thread| !preferred(thread)))
+            .filter_map(|thread| Some((thread.clone(), thread.blocked.clone()?)))
+            .collect();
+
+        match candidates.is_empty() {
+            true => Err(Error::InstructionPointerNotFound),
+            false => Ok(candidates),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{proc::ProcSyscall, Error};
+
+    use super::{Thread, ThreadSelector};
+
+    fn thread(tid: u32, name: &str, state: char, syscall: &str) -> Thread {
+        Thread::parse(
+            &format!("{tid} ({name}) {state} 1234 1234 0 0 -1 4194624 0 0"),
+            ProcSyscall::parse(syscall),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn parses_threads() {
+        let parsed = thread(
+            1235,
+            "a (b) c",
+            'S',
+            "230 0x1 0x0 0x7f3a1b7fdde0 0x7f3a1b7fdde0 0x0 0x0 0x7f3a1b7fddc8 0x7f3a1c0e57fa\n",
+        );
+        assert_eq!(parsed.tid, 1235);
+        assert_eq!(parsed.name, "a (b) c");
+        assert_eq!(parsed.state, 'S');
+        assert_eq!(parsed.syscall(), Some(230));
+        assert_eq!(
+            parsed.args(),
+            Some([1, 0, 0x7f3a1b7fdde0, 0x7f3a1b7fdde0, 0, 0])
+        );
+
+        let parsed = thread(1234, "victim", 'R', "running\n");
+        assert!(!parsed.is_blocked());
+        assert_eq!(parsed.syscall(), None);
+
+        assert!(Thread::parse("1234 victim R", None).is_none());
+    }
+
+    #[test]
+    fn selects_threads() {
+        let threads = vec![
+            thread(1234, "victim", 'R', "running\n"),
+            thread(1235, "worker", 'D', "-1 0x7f3a1b7fddc8 0x55d0c0a00f10\n"),
+            thread(
+                1236,
+                "worker",
+                'S',
+                "7 0x7f3a1b7fdde0 0x1 0xffffffff 0x0 0x0 0x0 0x7f3a1affddc8 0x7f3a1c0f18bf\n",
+            ),
+        ];
+        let select = |selector: ThreadSelector| {
+            selector
+                .candidates(threads.clone())
+                .map(|candidates| (candidates[0].0.tid, candidates[0].1.ip))
+        };
+
+        assert_eq!(select(ThreadSelector::Any).unwrap(), (1235, 0x55d0c0a00f10));
+        assert_eq!(
+            select(ThreadSelector::PreferInterruptible).unwrap(),
+            (1236, 0x7f3a1c0f18bf)
+        );
+        assert_eq!(select(ThreadSelector::Syscall(7)).unwrap().0, 1236);
+        assert_eq!(
+            select(ThreadSelector::Name("worker".into())).unwrap().0,
+            1235
+        );
+        assert_eq!(select(ThreadSelector::Tid(1236)).unwrap().0, 1236);
+
+        assert!(matches!(
+            select(ThreadSelector::Tid(1234)),
+            Err(Error::InstructionPointerNotFound)
+        ));
+        assert!(matches!(
+            select(ThreadSelector::Tid(1237)),
+            Err(Error::ThreadNotFound)
+        ));
+        assert!(matches!(
+            select(ThreadSelector::Syscall(230)),
+            Err(Error::ThreadNotFound)
+        ));
+    }
+}
@@ -0,0 +1,305 @@
+//! The subset of classic BPF seccomp filters are made of, as [`seccomp(2)`](https://man7.org/linux/man-pages/man2/seccomp.2.html)
+//! restricts it.
+
+/// The size of `struct sock_filter`, e.g. of an encoded instruction.
+const INSN_SIZE: usize = 8;
+
+/// The maximum number of instructions a program is made of (`BPF_MAXINSNS`).
+const MAX_INSNS: usize = 4096;
+
+/// The number of words of the scratch memory (`BPF_MEMWORDS`).
+const MEM_WORDS: usize = 16;
+
+/// The size of `struct seccomp_data`, which programs load words from.
+pub(super) const DATA_SIZE: usize = 64;
+
+/// The operand of an instruction: either its immediate or the index register.
+#[derive(Clone, Copy, Debug)]
+enum Src {
+    K(u32),
+    X,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum AluOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Or,
+    And,
+    Lsh,
+    Rsh,
+    Xor,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum JmpOp {
+    Eq,
+    Gt,
+    Ge,
+    Set,
+}
+
+/// A decoded instruction.
+#[derive(Clone, Copy, Debug)]
+enum Op {
+    /// Loads the word located at the offset into the accumulator.
+    LdAbs(usize),
+    /// Loads the immediate into the accumulator, e.g. the data length for `BPF_LEN`.
+    LdImm(u32),
+    LdxImm(u32),
+    LdMem(usize),
+    LdxMem(usize),
+    St(usize),
+    Stx(usize),
+    Alu(AluOp, Src),
+    Neg,
+    /// Jumps forward by the offset.
+    Ja(usize),
+    /// Jumps forward by the first offset if the condition holds, by the second one otherwise.
+    Jmp(JmpOp, Src, usize, usize),
+    /// Returns either the immediate or the accumulator.
+    Ret(Option<u32>),
+    Tax,
+    Txa,
+}
+
+/// A program which has been checked the way the kernel does when a filter is installed, so that it always terminates
+/// without faulting.
+#[derive(Clone, Debug)]
+pub(super) struct Program(Vec<Op>);
+
+impl Program {
+    /// Decodes an array of `struct sock_filter`, whose fields are in native byte order.
+    ///
+    /// Returns [`None`] if the kernel would reject it, e.g. it jumps out of bounds or doesn't end with a return.
+    pub(super) fn parse(bytes: &[u8]) -> Option<Self> {
+        if bytes.is_empty()
+            || !bytes.len().is_multiple_of(INSN_SIZE)
+            || bytes.len() / INSN_SIZE > MAX_INSNS
+        {
+            return None;
+        }
+        let len = bytes.len() / INSN_SIZE;
+
+        let ops = bytes
+            .chunks_exact(INSN_SIZE)
+            .enumerate()
+            .map(|(pc, insn)| {
+                let code = u16::from_ne_bytes([insn[0], insn[1]]);
+                let (jt, jf) = (usize::from(insn[2]), usize::from(insn[3]));
+                let k = u32::from_ne_bytes([insn[4], insn[5], insn[6], insn[7]]);
+                // How many instructions follow this one.
+                let left = len - pc - 1;
+
+                decode(code, jt, jf, k).filter(|op| match *op {
+                    Op::Ja(off) => off < left,
+                    Op::Jmp(_, _, jt, jf) => jt < left && jf < left,
+                    _ => true,
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        matches!(ops.last(), Some(Op::Ret(_))).then_some(Program(ops))
+    }
+
+    /// Runs the program over `data`, the bytes of a `struct seccomp_data`, returning what it returns.
+    pub(super) fn run(&self, data: &[u8; DATA_SIZE]) -> u32 {
+        let (mut a, mut x, mut mem) = (0_u32, 0_u32, [0_u32; MEM_WORDS]);
+        let mut pc = 0;
+
+        loop {
+            let op = self.0[pc];
+            pc += 1;
+
+            let src = |src| match src {
+                Src::K(k) => k,
+                Src::X => x,
+            };
+
+            match op {
+                Op::LdAbs(offset) => {
+                    a = u32::from_ne_bytes(data[offset..offset + 4].try_into().unwrap())
+                }
+                Op::LdImm(k) => a = k,
+                Op::LdxImm(k) => x = k,
+                Op::LdMem(n) => a = mem[n],
+                Op::LdxMem(n) => x = mem[n],
+                Op::St(n) => mem[n] = a,
+                Op::Stx(n) => mem[n] = x,
+                Op::Alu(op, operand) => {
+                    let operand = src(operand);
+                    a = match op {
+                        AluOp::Add => a.wrapping_add(operand),
+                        AluOp::Sub => a.wrapping_sub(operand),
+                        AluOp::Mul => a.wrapping_mul(operand),
+                        // A division by a zero index register makes the program return 0, as the kernel does.
+                        AluOp::Div => match a.checked_div(operand) {
+                            Some(quotient) => quotient,
+                            None => return 0,
+                        },
+                        AluOp::Or => a | operand,
+                        AluOp::And => a & operand,
+                        AluOp::Lsh => a.checked_shl(operand).unwrap_or(0),
+                        AluOp::Rsh => a.checked_shr(operand).unwrap_or(0),
+                        AluOp::Xor => a ^ operand,
+                    }
+                }
+                Op::Neg => a = a.wrapping_neg(),
+                Op::Ja(off) => pc += off,
+                Op::Jmp(op, operand, jt, jf) => {
+                    let operand = src(operand);
+                    let holds = match op {
+                        JmpOp::Eq => a == operand,
+                        JmpOp::Gt => a > operand,
+                        JmpOp::Ge => a >= operand,
+                        JmpOp::Set => a & operand != 0,
+                    };
+                    pc += if holds { jt } else { jf };
+                }
+                Op::Ret(k) => return k.unwrap_or(a),
+                Op::Tax => x = a,
+                Op::Txa => a = x,
+            }
+        }
+    }
+}
+
+/// Decodes an instruction, if it's one seccomp allows and its immediate is in range.
+fn decode(code: u16, jt: usize, jf: usize, k: u32) -> Option<Op> {
+    let src = match code & 0x08 {
+        0 => Src::K(k),
+        _ => Src::X,
+    };
+    let word = usize::try_from(k).ok().filter(|&n| n < MEM_WORDS);
+
+    if code > 0xff {
+        return None;
+    }
+
+    Some(match code {
+        // BPF_LD | BPF_W | BPF_ABS, which must load a whole word of `struct seccomp_data`.
+        0x20 if k.is_multiple_of(4) && (k as usize) < DATA_SIZE => Op::LdAbs(k as usize),
+        // BPF_LD | BPF_W | BPF_LEN and BPF_LDX | BPF_W | BPF_LEN.
+        0x80 => Op::LdImm(DATA_SIZE as u32),
+        0x81 => Op::LdxImm(DATA_SIZE as u32),
+        // BPF_LD | BPF_IMM and BPF_LDX | BPF_IMM.
+        0x00 => Op::LdImm(k),
+        0x01 => Op::LdxImm(k),
+        // BPF_LD | BPF_MEM, BPF_LDX | BPF_MEM, BPF_ST and BPF_STX.
+        0x60 => Op::LdMem(word?),
+        0x61 => Op::LdxMem(word?),
+        0x02 => Op::St(word?),
+        0x03 => Op::Stx(word?),
+        // BPF_ALU | BPF_NEG.
+        0x84 => Op::Neg,
+        // BPF_ALU, whose immediate can't be a zero divisor or a shift out of the word.
+        _ if code & 0x07 == 0x04 => {
+            let op = match code & 0xf0 {
+                0x00 => AluOp::Add,
+                0x10 => AluOp::Sub,
+                0x20 => AluOp::Mul,
+                0x30 => AluOp::Div,
+                0x40 => AluOp::Or,
+                0x50 => AluOp::And,
+                0x60 => AluOp::Lsh,
+                0x70 => AluOp::Rsh,
+                0xa0 => AluOp::Xor,
+                _ => return None,
+            };
+            match (op, src) {
+                (AluOp::Div, Src::K(0)) => return None,
+                (AluOp::Lsh | AluOp::Rsh, Src::K(32..)) => return None,
+                _ => Op::Alu(op, src),
+            }
+        }
+        // BPF_JMP | BPF_JA.
+        0x05 => Op::Ja(k as usize),
+        // BPF_JMP, whose offsets are checked once the program length is known.
+        _ if code & 0x07 == 0x05 => {
+            let op = match code & 0xf0 {
+                0x10 => JmpOp::Eq,
+                0x20 => JmpOp::Gt,
+                0x30 => JmpOp::Ge,
+                0x40 => JmpOp::Set,
+                _ => return None,
+            };
+            Op::Jmp(op, src, jt, jf)
+        }
+        // BPF_RET | BPF_K and BPF_RET | BPF_A.
+        0x06 => Op::Ret(Some(k)),
+        0x16 => Op::Ret(None),
+        // BPF_MISC | BPF_TAX and BPF_MISC | BPF_TXA.
+        0x07 => Op::Tax,
+        0x87 => Op::Txa,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::seccomp::tests::insn;
+
+    use super::{Program, DATA_SIZE};
+
+    #[test]
+    fn rejects_what_the_kernel_rejects() {
+        let ret = insn(0x06, 0, 0, 0);
+        let parse = |insns: &[Vec<u8>]| Program::parse(&insns.concat());
+
+        assert!(parse(&[]).is_none());
+        assert!(Program::parse(&ret[..6]).is_none());
+        assert!(parse(&[insn(0x20, 0, 0, 0)]).is_none(), "no return");
+        assert!(parse(&[insn(0x20, 0, 0, 2), ret.clone()]).is_none());
+        assert!(parse(&[insn(0x20, 0, 0, 64), ret.clone()]).is_none());
+        assert!(parse(&[insn(0x15, 0, 1, 0), ret.clone()]).is_none());
+        assert!(parse(&[insn(0x05, 0, 0, 1), ret.clone()]).is_none());
+        assert!(parse(&[insn(0x34, 0, 0, 0), ret.clone()]).is_none());
+        assert!(parse(&[insn(0x60, 0, 0, 16), ret.clone()]).is_none());
+        // BPF_LD | BPF_B | BPF_ABS, which seccomp doesn't allow.
+        assert!(parse(&[insn(0x30, 0, 0, 0), ret.clone()]).is_none());
+        assert!(parse(&vec![ret; 4097]).is_none());
+    }
+
+    #[test]
+    fn runs_programs() {
+        let mut data = [0; DATA_SIZE];
+        data[16..20].copy_from_slice(&6_u32.to_ne_bytes());
+
+        // Returns `(args[0] * 7 + 1) << 4` if `args[0] & 2` is set, through the scratch memory and the index register.
+        let program = Program::parse(
+            &[
+                insn(0x20, 0, 0, 16),
+                insn(0x45, 0, 8, 2),
+                insn(0x24, 0, 0, 7),
+                insn(0x02, 0, 0, 3),
+                insn(0x01, 0, 0, 1),
+                insn(0x61, 0, 0, 3),
+                insn(0x87, 0, 0, 0),
+                insn(0x04, 0, 0, 1),
+                insn(0x64, 0, 0, 4),
+                insn(0x16, 0, 0, 0),
+                insn(0x06, 0, 0, 0),
+            ]
+            .concat(),
+        )
+        .unwrap();
+        assert_eq!(program.run(&data), (6 * 7 + 1) << 4);
+
+        data[16] = 4;
+        assert_eq!(program.run(&data), 0);
+
+        // A division by a zero index register returns 0.
+        let program = Program::parse(
+            &[
+                insn(0x00, 0, 0, 5),
+                insn(0x3c, 0, 0, 0),
+                insn(0x06, 0, 0, 1),
+            ]
+            .concat(),
+        )
+        .unwrap();
+        assert_eq!(program.run(&data), 0);
+    }
+}
@@ -0,0 +1,289 @@
+use crate::{
+    payloads::{self, Call, Syscalls},
+    proc::ProcClass,
+    Error,
+};
+
+mod bpf;
+
+use bpf::{Program, DATA_SIZE};
+
+/// The mask of the action a filter returns, which excludes its data (`SECCOMP_RET_ACTION_FULL`).
+const RET_ACTION_FULL: u32 = 0xffff_0000;
+const RET_ERRNO: u32 = 0x0005_0000;
+const RET_USER_NOTIF: u32 = 0x7fc0_0000;
+const RET_TRACE: u32 = 0x7ff0_0000;
+const RET_LOG: u32 = 0x7ffc_0000;
+const RET_ALLOW: u32 = 0x7fff_0000;
+
+/// A struct that represents a seccomp filter the target process has installed, so that the payloads can be generated
+/// with syscalls it allows.
+///
+/// The kernel doesn't expose the filters through `/proc/<id>`: they can only be dumped with the `PTRACE_SECCOMP_GET_FILTER`
+/// [`ptrace`](https://man7.org/linux/man-pages/man2/ptrace.2.html) request, e.g. through `seccomp-tools dump -f raw`.
+#[derive(Clone, Debug)]
+pub struct SeccompFilter {
+    program: Program,
+}
+
+impl SeccompFilter {
+    /// Parses the array of `struct sock_filter` a filter is made of, as `PTRACE_SECCOMP_GET_FILTER` dumps it.
+    ///
+    /// Returns [`Error::InvalidSeccompFilter`] if the kernel wouldn't install it, e.g. its length is not a multiple of 8 bytes.
+    ///
+    /// Example:
+    /// ```no_run
+    /// use intruducer::SeccompFilter;
+    ///
+    /// let filter = SeccompFilter::from_bytes(&std::fs::read("/path/to/filter.bpf")?)?;
+    /// # Ok::<(), intruducer::Error>(())
+    /// ```
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        Program::parse(bytes)
+            .map(|program| SeccompFilter { program })
+            .ok_or(Error::InvalidSeccompFilter)
+    }
+
+    /// Runs the filter over the syscall `nr` performed with `args`, returning its action and data.
+    fn evaluate(&self, arch: u32, nr: u32, args: &[u64; 6]) -> u32 {
+        // `struct seccomp_data`, whose instruction pointer is unknown.
+        let mut data = [0; DATA_SIZE];
+        data[0..4].copy_from_slice(&nr.to_ne_bytes());
+        data[4..8].copy_from_slice(&arch.to_ne_bytes());
+        for (index, arg) in args.iter().enumerate() {
+            data[16 + index * 8..24 + index * 8].copy_from_slice(&arg.to_ne_bytes());
+        }
+
+        self.program.run(&data)
+    }
+}
+
+/// A enum that represents what happens to a syscall, as the seccomp filters decide.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Verdict {
+    /// The syscall is performed.
+    Allow,
+    /// The syscall fails, e.g. it returns an error number or there's no tracer to notify.
+    Fail,
+    /// The process or thread is killed, e.g. by a signal it doesn't expect.
+    Kill,
+}
+
+/// Runs every filter over `call`, and decides what happens to it as the kernel does: the action of highest precedence wins.
+fn judge(filters: &[SeccompFilter], arch: u32, call: &Call) -> Verdict {
+    let ret = filters
+        .iter()
+        .map(|filter| filter.evaluate(arch, call.nr, &call.args))
+        .min_by_key(|ret| (ret & RET_ACTION_FULL) as i32)
+        .unwrap_or(RET_ALLOW);
+
+    match ret & RET_ACTION_FULL {
+        RET_ALLOW | RET_LOG => Verdict::Allow,
+        RET_ERRNO | RET_TRACE | RET_USER_NOTIF => Verdict::Fail,
+        // `SECCOMP_RET_KILL_PROCESS`, `SECCOMP_RET_KILL_THREAD`, `SECCOMP_RET_TRAP` and the unknown actions, which are
+        // treated as the first.
+        _ => Verdict::Kill,
+    }
+}
+
+/// Gets the value the filters of a `class` process see as `seccomp_data.arch`, e.g. `AUDIT_ARCH_X86_64`.
+fn audit_arch(class: &ProcClass) -> u32 {
+    match class {
+        #[cfg(any(target_arch = "arm", target_arch = "aarch64"))]
+        ProcClass::ThirtyTwo => 0x4000_0028,
+        #[cfg(target_arch = "aarch64")]
+        ProcClass::SixtyFour => 0xc000_00b7,
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        ProcClass::ThirtyTwo => 0x4000_0003,
+        #[cfg(target_arch = "x86_64")]
+        ProcClass::SixtyFour => 0xc000_003e,
+    }
+}
+
+/// Picks the syscalls the payloads of a `class` process are generated with, so that its seccomp `mode` and `filters` allow them.
+/// A syscall the payloads recover from may fail, but it must not kill the process.
+///
+/// If the process is filtered but no filter has been supplied, the default syscalls are picked, since nothing else is known.
+///
+/// Returns [`Error::SyscallDenied`] with the first syscall of the default alternative which is denied, if no alternative is allowed.
+pub(crate) fn pick(
+    class: &ProcClass,
+    mode: Option<u8>,
+    filters: &[SeccompFilter],
+) -> Result<Syscalls, Error> {
+    let arch = audit_arch(class);
+    let allowed = |call: &Call| match judge(filters, arch, call) {
+        Verdict::Allow => true,
+        Verdict::Fail => !call.required,
+        Verdict::Kill => false,
+    };
+
+    match mode {
+        // Only `read`, `write`, `_exit` and `sigreturn` are allowed.
+        Some(1) => Err(Error::SyscallDenied {
+            syscall: payloads::calls(class, Syscalls::default())[0].name,
+        }),
+        Some(2) => {
+            let alternatives = Syscalls::alternatives(class);
+
+            match alternatives
+                .iter()
+                .find(|syscalls| payloads::calls(class, **syscalls).iter().all(allowed))
+            {
+                Some(syscalls) => Ok(*syscalls),
+                None => Err(Error::SyscallDenied {
+                    syscall: payloads::calls(class, alternatives[0])
+                        .into_iter()
+                        .find(|call| !allowed(call))
+                        .unwrap()
+                        .name,
+                }),
+            }
+        }
+        _ => Ok(Syscalls::default()),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use crate::{payloads::Syscalls, proc::ProcClass, Error};
+
+    use super::{audit_arch, pick, SeccompFilter, RET_ALLOW, RET_ERRNO};
+
+    const RET_KILL_PROCESS: u32 = 0x8000_0000;
+
+    /// Encodes a `struct sock_filter`.
+    pub(crate) fn insn(code: u16, jt: u8, jf: u8, k: u32) -> Vec<u8> {
+        [&code.to_ne_bytes()[..], &[jt, jf], &k.to_ne_bytes()].concat()
+    }
+
+    /// Builds a filter which returns `ret` for the syscalls `nrs` of `arch`, allows the other ones and kills the process
+    /// if the architecture doesn't match, as filters usually do.
+    fn deny(arch: u32, nrs: &[u32], ret: u32) -> SeccompFilter {
+        let count = nrs.len() as u8;
+        let mut bytes = [
+            insn(0x20, 0, 0, 4),
+            insn(0x15, 0, count + 3, arch),
+            insn(0x20, 0, 0, 0),
+        ]
+        .concat();
+        for (index, nr) in nrs.iter().enumerate() {
+            bytes.extend(insn(0x15, count - index as u8, 0, *nr));
+        }
+        bytes.extend(insn(0x06, 0, 0, RET_ALLOW));
+        bytes.extend(insn(0x06, 0, 0, ret));
+        bytes.extend(insn(0x06, 0, 0, RET_KILL_PROCESS));
+
+        SeccompFilter::from_bytes(&bytes).unwrap()
+    }
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn picks_the_default_syscalls_unless_filters_deny_them() {
+        let arch = audit_arch(&ProcClass::SixtyFour);
+        let kill_all = [deny(0, &[], RET_KILL_PROCESS)];
+
+        assert_eq!(
+            pick(&ProcClass::SixtyFour, Some(0), &kill_all).unwrap(),
+            Syscalls::default()
+        );
+        assert_eq!(
+            pick(&ProcClass::SixtyFour, None, &kill_all).unwrap(),
+            Syscalls::default()
+        );
+        assert_eq!(
+            pick(&ProcClass::SixtyFour, Some(2), &[]).unwrap(),
+            Syscalls::default()
+        );
+        assert_eq!(
+            pick(
+                &ProcClass::SixtyFour,
+                Some(2),
+                &[deny(arch, &[59, 101], RET_KILL_PROCESS)]
+            )
+            .unwrap(),
+            Syscalls::default()
+        );
+    }
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn picks_the_allowed_alternative() {
+        let filter = deny(
+            audit_arch(&ProcClass::SixtyFour),
+            &[2, 87],
+            RET_KILL_PROCESS,
+        );
+        assert_eq!(
+            pick(&ProcClass::SixtyFour, Some(2), &[filter]).unwrap(),
+            Syscalls {
+                at: true,
+                old_mmap: false
+            }
+        );
+
+        let filter = deny(audit_arch(&ProcClass::ThirtyTwo), &[192], RET_ERRNO | 1);
+        assert_eq!(
+            pick(&ProcClass::ThirtyTwo, Some(2), &[filter]).unwrap(),
+            Syscalls {
+                at: false,
+                old_mmap: true
+            }
+        );
+    }
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn tolerates_the_failures_the_payloads_recover_from() {
+        let arch = audit_arch(&ProcClass::SixtyFour);
+
+        // `mprotect` and `unlink`.
+        let filter = deny(arch, &[10, 87], RET_ERRNO | 1);
+        assert_eq!(
+            pick(&ProcClass::SixtyFour, Some(2), &[filter]).unwrap(),
+            Syscalls::default()
+        );
+
+        // The highest precedence action wins.
+        let filters = [
+            deny(arch, &[10], RET_ERRNO | 1),
+            deny(arch, &[10], RET_KILL_PROCESS),
+        ];
+        assert!(matches!(
+            pick(&ProcClass::SixtyFour, Some(2), &filters),
+            Err(Error::SyscallDenied {
+                syscall: "mprotect"
+            })
+        ));
+    }
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn refuses_if_every_alternative_is_denied() {
+        let arch = audit_arch(&ProcClass::SixtyFour);
+
+        assert!(matches!(
+            pick(&ProcClass::SixtyFour, Some(1), &[]),
+            Err(Error::SyscallDenied { syscall: "open" })
+        ));
+        assert!(matches!(
+            pick(
+                &ProcClass::SixtyFour,
+                Some(2),
+                &[deny(arch, &[18], RET_ERRNO | 1)]
+            ),
+            Err(Error::SyscallDenied {
+                syscall: "pwrite64"
+            })
+        ));
+        // The filter of a compat process is given another architecture.
+        assert!(matches!(
+            pick(
+                &ProcClass::ThirtyTwo,
+                Some(2),
+                &[deny(arch, &[], RET_ALLOW)]
+            ),
+            Err(Error::SyscallDenied { syscall: "open" })
+        ));
+    }
+}
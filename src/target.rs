@@ -0,0 +1,258 @@
+use crate::{
+    ext::{ProcExt, ProcIntruducerExt},
+    mem::Memory,
+    os::VirtAddr,
+    proc::{Arch, Machine, Proc, ProcSyscall, Thread, ThreadSelector},
+    Error,
+};
+
+/// The largest distance between the start of the function a thread is blocked in, e.g. a syscall wrapper, and its
+/// instruction pointer, for a return address to be trusted.
+const MAX_FUNCTION_LEN: VirtAddr = 0x1000;
+
+/// A struct that represents where the first payload is written, and which thread executes it.
+pub(crate) struct Target {
+    /// The thread which executes the first payload.
+    pub(crate) thread: Thread,
+
+    /// Where the thread is blocked.
+    pub(crate) blocked: ProcSyscall,
+
+    /// The address the first payload is written at: either the instruction pointer of the thread, or a return address
+    /// it reaches once the functions it's in return.
+    pub(crate) addr: VirtAddr,
+}
+
+/// Finds where the first payload, which is `len` bytes long, can be written, among the threads of `proc` `selector`
+/// selects. The instruction pointers are preferred, then the return addresses of the functions the threads are blocked
+/// in, e.g. if their code can't be written. The vDSO is never written. Nothing is written.
+///
+/// Returns [`Error::BlockedInVdso`] if the preferred thread is blocked in the vDSO and there's no such address, or
+/// [`Error::UnwritableCode`] if it's blocked elsewhere.
+pub(crate) fn find(
+    proc: &Proc,
+    mem: &Memory,
+    selector: &ThreadSelector,
+    arch: &Arch,
+    len: usize,
+) -> Result<Target, Error> {
+    let candidates = proc.find_candidates(selector)?;
+    let target = |(thread, blocked): &(Thread, ProcSyscall), addr| Target {
+        thread: thread.clone(),
+        blocked: blocked.clone(),
+        addr,
+    };
+
+    let in_vdso = |ip| proc.find_map(ip).is_some_and(|map| map.is_vdso());
+    if let Some(candidate) = candidates
+        .iter()
+        .find(|(_, blocked)| !in_vdso(blocked.ip) && writable(proc, mem, blocked.ip, len))
+    {
+        return Ok(target(candidate, candidate.1.ip));
+    }
+
+    candidates
+        .iter()
+        .find_map(|candidate| {
+            find_return_addr(proc, mem, &candidate.1, arch, len).map(|addr| target(candidate, addr))
+        })
+        .ok_or_else(|| {
+            let ip = candidates[0].1.ip;
+            if in_vdso(ip) {
+                return Error::BlockedInVdso(ip);
+            }
+
+            Error::UnwritableCode {
+                addr: ip,
+                region: proc
+                    .find_map(ip)
+                    .map_or("an unknown mapping".into(), |map| map.name().to_owned()),
+            }
+        })
+}
+
+/// Determines whether `len` bytes can be written at `addr`, as the permissions of the region which contains them and
+/// the ways `mem` accesses the memory through say.
+fn writable(proc: &Proc, mem: &Memory, addr: VirtAddr, len: usize) -> bool {
+    proc.find_map(addr)
+        .is_some_and(|map| map.range.end - addr >= len as VirtAddr && mem.can_write(&map))
+}
+
+/// Gets the return address of the function a thread blocked as `blocked` says is in, which is reached once it returns,
+/// provided that it's in code outside the vDSO where `len` bytes can be written.
+///
+/// Returns [`None`] if it can't be trusted, as [`return_addr`] says.
+fn find_return_addr(
+    proc: &Proc,
+    mem: &Memory,
+    blocked: &ProcSyscall,
+    arch: &Arch,
+    len: usize,
+) -> Option<VirtAddr> {
+    let addr = return_addr(arch.machine, arch.bits, blocked, |addr, buf| {
+        mem.read_exact_at(buf, addr).is_ok()
+    })?;
+
+    (proc
+        .find_map(addr)
+        .is_some_and(|map| map.executable && !map.is_vdso())
+        && writable(proc, mem, addr, len))
+    .then_some(addr)
+}
+
+/// Gets the return address of the function a thread blocked as `blocked` says is in, reading its memory with `read`.
+/// It's the word at the top of the stack, provided that it follows a call - directly, or through the PLT or the GOT - to
+/// a function which starts right before the instruction pointer, e.g. a syscall wrapper which doesn't touch the stack.
+///
+/// Returns [`None`] if it can't be trusted, e.g. the word is a stale return address, or if the architecture is not
+/// `x86` or `x86-64` - the return address is held by a register on `arm` and `aarch64`, which `/proc/<id>/syscall`
+/// doesn't expose.
+fn return_addr(
+    machine: Machine,
+    bits: u8,
+    blocked: &ProcSyscall,
+    read: impl Fn(VirtAddr, &mut [u8]) -> bool,
+) -> Option<VirtAddr> {
+    if !matches!(machine, Machine::X86 | Machine::X86_64) {
+        return None;
+    }
+
+    let word = |addr| {
+        let mut buf = [0; 8];
+        let len = usize::from(bits / 8);
+        read(addr, &mut buf[..len]).then(|| u64::from_le_bytes(buf))
+    };
+    let rel = |addr: VirtAddr, disp: &[u8]| {
+        addr.wrapping_add_signed(i32::from_le_bytes(disp.try_into().unwrap()).into())
+    };
+
+    let addr = word(blocked.sp)?;
+    let mut code = [0; 8];
+    if !read(addr.checked_sub(8)?, &mut code) {
+        return None;
+    }
+
+    let callee = match code {
+        // `call rel32`.
+        [.., 0xe8, _, _, _, _] => rel(addr, &code[4..]),
+        // `call [rip + disp32]`.
+        [.., 0xff, 0x15, _, _, _, _] if bits == 64 => word(rel(addr, &code[4..]))?,
+        _ => return None,
+    };
+
+    // A PLT entry, which may start with `endbr64` and be prefixed by `bnd`, jumps to `[rip + disp32]` (`[disp32]` on
+    // `x86`).
+    let mut plt = [0; 11];
+    let function = match read(callee, &mut plt) {
+        true => {
+            let start = match plt[..4] == [0xf3, 0x0f, 0x1e, 0xfa] {
+                true => 4,
+                false => 0,
+            };
+            let start = start + usize::from(plt[start] == 0xf2);
+
+            match plt[start..start + 2] == [0xff, 0x25] {
+                true if bits == 64 => word(rel(
+                    callee + start as VirtAddr + 6,
+                    &plt[start + 2..start + 6],
+                ))?,
+                true => {
+                    word(u32::from_le_bytes(plt[start + 2..start + 6].try_into().unwrap()).into())?
+                }
+                false => callee,
+            }
+        }
+        false => callee,
+    };
+
+    (function <= blocked.ip && blocked.ip - function < MAX_FUNCTION_LEN).then_some(addr)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        mem::Memory,
+        proc::{fixture::process, Arch, Machine, ProcSyscall, ThreadSelector},
+        Error,
+    };
+
+    use super::{find, return_addr};
+
+    #[test]
+    fn never_writes_the_vdso() {
+        let fixture = process();
+        fixture.write("/proc/1234/mem", [0_u8; 8]);
+        let proc = fixture.fs().proc(1234).unwrap();
+        let arch = Arch {
+            machine: Machine::X86_64,
+            bits: 64,
+            platform: None,
+            hwcap: None,
+            hwcap2: None,
+        };
+        let find = || {
+            find(
+                &proc,
+                &Memory::open(&proc)?,
+                &ThreadSelector::Any,
+                &arch,
+                16,
+            )
+        };
+
+        assert_eq!(find().unwrap().addr, 0x7f3a1c0e57fa);
+
+        // The worker is blocked in the vDSO, and its stack can't be read.
+        fixture.write(
+            "/proc/1234/maps",
+            "7f3a1c0e5000-7f3a1c0e7000 r-xp 00000000 00:00 0                          [vdso]\n",
+        );
+        assert!(matches!(find(), Err(Error::BlockedInVdso(0x7f3a1c0e57fa))));
+    }
+
+    #[test]
+    fn trusts_only_the_return_address_of_the_current_function() {
+        // The syscall wrapper, which is blocked at `0x2010`, its PLT entry, its GOT entry, and the callers.
+        let mut memory = vec![0_u8; 0x3000];
+        memory[0x1100..0x1110].copy_from_slice(&[
+            0xf3, 0x0f, 0x1e, 0xfa, 0xf2, 0xff, 0x25, 0xf5, 0x0e, 0x00, 0x00, 0, 0, 0, 0, 0,
+        ]);
+        memory[0x2000..0x2008].copy_from_slice(&0x2000_u64.to_le_bytes());
+        // `call 0x2000` returning to `0x1005`, `call 0x1100` (the PLT entry) returning to `0x1205`, and
+        // `call 0x2400` (an unrelated function) returning to `0x1305`.
+        memory[0x1000..0x1005].copy_from_slice(&[0xe8, 0xfb, 0x0f, 0x00, 0x00]);
+        memory[0x1200..0x1205].copy_from_slice(&[0xe8, 0xfb, 0xfe, 0xff, 0xff]);
+        memory[0x1300..0x1305].copy_from_slice(&[0xe8, 0xfb, 0x10, 0x00, 0x00]);
+        let read = |memory: &Vec<u8>, addr: u64, buf: &mut [u8]| {
+            let addr = addr as usize;
+            match memory.get(addr..addr + buf.len()) {
+                Some(bytes) => {
+                    buf.copy_from_slice(bytes);
+                    true
+                }
+                None => false,
+            }
+        };
+        let find = |memory: &Vec<u8>, stack: [u64; 2], machine| {
+            let mut memory = memory.clone();
+            memory[0x2800..0x2810]
+                .copy_from_slice(&[stack[0].to_le_bytes(), stack[1].to_le_bytes()].concat());
+            let blocked = ProcSyscall::parse("0 0x0 0x0 0x0 0x0 0x0 0x0 0x2800 0x2010\n").unwrap();
+            return_addr(machine, 64, &blocked, |addr, buf| read(&memory, addr, buf))
+        };
+
+        assert_eq!(
+            find(&memory, [0x1005, 0x1305], Machine::X86_64),
+            Some(0x1005)
+        );
+        assert_eq!(
+            find(&memory, [0x1205, 0x1305], Machine::X86_64),
+            Some(0x1205)
+        );
+        assert_eq!(find(&memory, [0x1005, 0x1305], Machine::Aarch64), None);
+        // A stale return address sits above a word which is not one.
+        assert_eq!(find(&memory, [0x2fff, 0x1005], Machine::X86_64), None);
+        // The return address of a function which is not the current one.
+        assert_eq!(find(&memory, [0x1305, 0x1005], Machine::X86_64), None);
+    }
+}
@@ -0,0 +1,20 @@
+//! The library the integration tests load: it appends the process identifier to the file named by the
+//! `INTRUDUCER_MARKER` environment variable.
+
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::process;
+
+fn main() {
+    if let Ok(path) = std::env::var("INTRUDUCER_MARKER") {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap();
+        writeln!(file, "{}", process::id()).unwrap();
+    }
+}
+
+#[link_section = ".init_array"]
+pub static INITIALIZE: fn() = main;
@@ -0,0 +1,189 @@
+//! A victim of the integration tests: `victim <read|poll|nanosleep|futex> <threads> <millis>`.
+//!
+//! Its main thread repeatedly blocks in the given syscall for `millis` milliseconds, while `threads` workers keep
+//! computing checksums. It exits with a failure if a checksum ever changes.
+//!
+//! The workers pause through another syscall than the main thread, since the library is not expected to be loaded
+//! safely if several threads return to the first payload.
+//!
+//! If `$VICTIM_SECCOMP_FILTER` is set, the seccomp filter it points to is installed first. `SIGUSR1` is caught with
+//! `SA_RESTART`, so it interrupts `poll` but restarts `read`.
+
+use std::env::args;
+use std::hint::black_box;
+use std::io::{stdin, BufRead};
+use std::process::exit;
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const TABLE: [u64; 256] = table();
+
+const fn table() -> [u64; 256] {
+    let mut table = [0; 256];
+    let mut n = 0;
+    while n < table.len() {
+        table[n] = (n as u64 + 1).wrapping_mul(0x9e3779b97f4a7c15);
+        n += 1;
+    }
+    table
+}
+
+fn checksum(values: &[u64]) -> u64 {
+    values.iter().fold(0xcbf29ce484222325, |sum, value| {
+        (sum ^ value).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Blocks the calling thread for a while, in `nanosleep` or in `futex` - whichever `mode` is not.
+fn pause(mode: &str, duration: Duration) {
+    match mode {
+        "nanosleep" => {
+            let (lock, condvar) = (Mutex::new(()), Condvar::new());
+            drop(
+                condvar
+                    .wait_timeout(lock.lock().unwrap(), duration)
+                    .unwrap(),
+            );
+        }
+        _ => thread::sleep(duration),
+    }
+}
+
+fn work(mode: &str, seed: u64, deadline: Instant) {
+    let local: Vec<_> = TABLE.iter().map(|value| value ^ seed).collect();
+    let expected = (checksum(&TABLE), checksum(&local));
+
+    while Instant::now() < deadline {
+        if (checksum(black_box(&TABLE)), checksum(black_box(&local))) != expected {
+            eprintln!("worker {seed}: checksum changed");
+            exit(1);
+        }
+        pause(mode, Duration::from_millis(1));
+    }
+}
+
+/// Installs the seccomp filter located at `$VICTIM_SECCOMP_FILTER`, an array of `struct sock_filter`, if it's set.
+fn install_seccomp_filter() {
+    #[repr(C)]
+    struct SockFprog {
+        len: u16,
+        filter: *const u8,
+    }
+
+    extern "C" {
+        fn prctl(option: i32, ...) -> i32;
+    }
+
+    const PR_SET_SECCOMP: i32 = 22;
+    const PR_SET_NO_NEW_PRIVS: i32 = 38;
+    const SECCOMP_MODE_FILTER: u64 = 2;
+
+    let Some(path) = std::env::var_os("VICTIM_SECCOMP_FILTER") else {
+        return;
+    };
+    let filter = std::fs::read(path).unwrap();
+    let prog = SockFprog {
+        len: (filter.len() / 8) as u16,
+        filter: filter.as_ptr(),
+    };
+
+    unsafe {
+        assert_eq!(prctl(PR_SET_NO_NEW_PRIVS, 1_u64, 0_u64, 0_u64, 0_u64), 0);
+        assert_eq!(
+            prctl(PR_SET_SECCOMP, SECCOMP_MODE_FILTER, &prog as *const SockFprog),
+            0
+        );
+    }
+}
+
+/// Catches `SIGUSR1` with a handler which does nothing, with `SA_RESTART` as `signal` installs it.
+fn catch_sigusr1() {
+    extern "C" fn handle(_: i32) {}
+
+    extern "C" {
+        fn signal(signum: i32, handler: extern "C" fn(i32)) -> usize;
+    }
+
+    const SIGUSR1: i32 = 10;
+
+    unsafe {
+        assert_ne!(signal(SIGUSR1, handle), usize::MAX);
+    }
+}
+
+/// Waits until the standard input is readable, or `timeout` elapses. Unlike `read`, `poll` is never restarted once a
+/// handler has run.
+fn poll_stdin(timeout: Duration) -> bool {
+    #[repr(C)]
+    struct PollFd {
+        fd: i32,
+        events: i16,
+        revents: i16,
+    }
+
+    extern "C" {
+        fn poll(fds: *mut PollFd, nfds: u64, timeout: i32) -> i32;
+    }
+
+    const POLLIN: i16 = 1;
+
+    let mut fd = PollFd {
+        fd: 0,
+        events: POLLIN,
+        revents: 0,
+    };
+    unsafe { poll(&mut fd, 1, timeout.as_millis() as i32) > 0 }
+}
+
+fn main() {
+    install_seccomp_filter();
+    catch_sigusr1();
+
+    let args: Vec<_> = args().collect();
+    let [_, mode, threads, millis] = &args[..] else {
+        panic!("usage: victim <read|poll|nanosleep|futex> <threads> <millis>");
+    };
+    let deadline = Instant::now() + Duration::from_millis(millis.parse().unwrap());
+
+    let workers: Vec<_> = (0..threads.parse().unwrap())
+        .map(|seed| {
+            let mode = mode.clone();
+            thread::spawn(move || work(&mode, seed, deadline))
+        })
+        .collect();
+
+    // Kept alive across the blocking syscalls, so that a clobbered register or stack slot is noticed.
+    let local = [
+        0x0123456789abcdef_u64,
+        0xfedcba9876543210,
+        0x5555aaaa5555aaaa,
+    ];
+    let sum = checksum(&local);
+    println!("ready {sum:016x}");
+
+    let (lock, condvar) = (Mutex::new(()), Condvar::new());
+    let mut lines = stdin().lock().lines();
+    while Instant::now() < deadline {
+        match mode.as_str() {
+            "read" => drop(lines.next()),
+            "poll" => {
+                if poll_stdin(deadline.saturating_duration_since(Instant::now())) {
+                    drop(lines.next());
+                }
+            }
+            "nanosleep" => thread::sleep(Duration::from_millis(50)),
+            "futex" => drop(
+                condvar
+                    .wait_timeout(lock.lock().unwrap(), Duration::from_millis(50))
+                    .unwrap(),
+            ),
+            _ => panic!("unknown mode {mode}"),
+        }
+    }
+
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    println!("done {:016x}", checksum(black_box(&local)));
+}
@@ -0,0 +1,395 @@
+//! Intruduces the `tests/fixtures/evil.rs` library into real `tests/fixtures/victim.rs` processes.
+//!
+//! The fixtures are built with `rustc` (or `$RUSTC`) on the first use.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::Mutex;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use intruducer::{intruduce_with, Error, Intruduction, Options, SeccompFilter, ThreadSelector};
+
+/// How long a victim keeps running, in milliseconds.
+const LIFETIME: u64 = 3000;
+/// How long a victim must survive once the library has been loaded.
+const SURVIVAL: Duration = Duration::from_secs(1);
+/// How long the library may take to be loaded.
+const TIMEOUT: Duration = Duration::from_secs(3);
+
+/// The way a victim is built.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct Build {
+    /// The target triple, or the host one if [`None`].
+    target: Option<&'static str>,
+    pie: bool,
+}
+
+const PIE: Build = Build {
+    target: None,
+    pie: true,
+};
+
+/// Builds the victim and the library as `build` says, once.
+///
+/// Returns [`None`] if the standard library of `build.target` is not installed.
+fn fixtures(build: Build) -> Option<(PathBuf, PathBuf)> {
+    static BUILT: Mutex<Option<HashMap<Build, (PathBuf, PathBuf)>>> = Mutex::new(None);
+
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".into());
+
+    if let Some(target) = build.target {
+        let output = Command::new(&rustc)
+            .args(["--print", "sysroot"])
+            .output()
+            .unwrap();
+        let sysroot = PathBuf::from(String::from_utf8(output.stdout).unwrap().trim());
+
+        if !sysroot.join("lib/rustlib").join(target).exists() {
+            return None;
+        }
+    }
+
+    let mut built = BUILT.lock().unwrap();
+    if let Some(fixtures) = built.get_or_insert_with(HashMap::new).get(&build) {
+        return Some(fixtures.clone());
+    }
+
+    let out_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(format!(
+        "fixtures-{}-{}",
+        build.target.unwrap_or("host"),
+        if build.pie { "pie" } else { "no-pie" }
+    ));
+    let fixtures_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+
+    let rustc = |src: &str, args: &[&str]| {
+        let mut command = Command::new(&rustc);
+        command
+            .arg(fixtures_dir.join(src))
+            .arg("--out-dir")
+            .arg(&out_dir)
+            .args(["--edition", "2021"])
+            .args(args);
+        if let Some(target) = build.target {
+            command.args(["--target", target]);
+        }
+        assert!(command.status().unwrap().success(), "building {src}");
+    };
+
+    rustc("evil.rs", &["--crate-type", "cdylib"]);
+    rustc(
+        "victim.rs",
+        if build.pie {
+            &[]
+        } else {
+            &["-C", "relocation-model=static"]
+        },
+    );
+
+    let fixtures = (out_dir.join("victim"), out_dir.join("libevil.so"));
+    built.as_mut().unwrap().insert(build, fixtures.clone());
+    Some(fixtures)
+}
+
+/// A running victim, which is killed when dropped.
+struct Victim {
+    child: Child,
+    marker: PathBuf,
+    /// The checksum it printed before blocking.
+    ready: String,
+}
+
+impl Victim {
+    /// Spawns the victim, which installs the seccomp filter located at `seccomp_filter` if any.
+    fn spawn(victim: &Path, mode: &str, threads: usize, seccomp_filter: Option<&Path>) -> Self {
+        let marker = Path::new(env!("CARGO_TARGET_TMPDIR")).join(format!(
+            "marker-{}-{}-{}",
+            std::process::id(),
+            mode,
+            threads
+        ));
+        let _ = fs::remove_file(&marker);
+
+        let mut command = Command::new(victim);
+        command
+            .args([mode, &threads.to_string(), &LIFETIME.to_string()])
+            .env("INTRUDUCER_MARKER", &marker)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped());
+        if let Some(seccomp_filter) = seccomp_filter {
+            command.env("VICTIM_SECCOMP_FILTER", seccomp_filter);
+        }
+        let mut child = command.spawn().unwrap();
+
+        let ready = Self::line(&mut child, "ready");
+
+        Self {
+            child,
+            marker,
+            ready,
+        }
+    }
+
+    /// Reads the next line the victim prints, which must start with `prefix`, returning the rest.
+    fn line(child: &mut Child, prefix: &str) -> String {
+        let mut line = String::new();
+        BufReader::new(child.stdout.as_mut().unwrap())
+            .read_line(&mut line)
+            .unwrap();
+
+        line.trim()
+            .strip_prefix(prefix)
+            .unwrap_or_else(|| panic!("expected {prefix:?}, found {line:?}"))
+            .trim()
+            .to_owned()
+    }
+
+    /// Waits for `duration`, waking the victim up if it's blocked in `read` and checking it's still running.
+    fn wait(&mut self, duration: Duration, until: impl Fn(&Self) -> bool) {
+        let start = Instant::now();
+
+        while start.elapsed() < duration && !until(self) {
+            let _ = self.child.stdin.as_mut().unwrap().write_all(b"\n");
+            assert!(
+                self.child.try_wait().unwrap().is_none(),
+                "the victim terminated"
+            );
+            sleep(Duration::from_millis(20));
+        }
+    }
+
+    /// Waits for the victim to reach its end, still waking it up, returning whether it succeeded and the checksum it
+    /// printed after blocking.
+    fn finish(&mut self) -> (bool, String) {
+        let start = Instant::now();
+
+        let status = loop {
+            if let Some(status) = self.child.try_wait().unwrap() {
+                break status;
+            }
+            assert!(
+                start.elapsed() < Duration::from_millis(2 * LIFETIME),
+                "the victim hung"
+            );
+
+            let _ = self.child.stdin.as_mut().unwrap().write_all(b"\n");
+            sleep(Duration::from_millis(20));
+        };
+
+        (status.success(), Self::line(&mut self.child, "done"))
+    }
+
+    fn loaded(&self) -> bool {
+        fs::read_to_string(&self.marker).is_ok_and(|marker| !marker.is_empty())
+    }
+}
+
+impl Drop for Victim {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = fs::remove_file(&self.marker);
+    }
+}
+
+/// Intruduces the library into a victim built as `build`, which blocks in `mode` while running `threads` workers.
+fn check(build: Build, mode: &str, threads: usize) {
+    check_with(build, mode, threads, None, Options::new());
+}
+
+/// Intruduces the library as [`check`] does, but with `options`, into a victim which installs the seccomp filter
+/// `seccomp_filter` if any. The filter is supplied to the intruducer as well.
+///
+/// Returns what [`intruduce_with`] returned, or [`None`] if the check was skipped.
+fn check_with(
+    build: Build,
+    mode: &str,
+    threads: usize,
+    seccomp_filter: Option<&[u8]>,
+    mut options: Options,
+) -> Option<Intruduction> {
+    let Some((victim, lib)) = fixtures(build) else {
+        eprintln!(
+            "skipped: the {} target is not installed",
+            build.target.unwrap()
+        );
+        return None;
+    };
+    let seccomp_filter = seccomp_filter.map(|filter| {
+        let path = Path::new(env!("CARGO_TARGET_TMPDIR"))
+            .join(format!("seccomp-filter-{}.bpf", std::process::id()));
+        fs::write(&path, filter).unwrap();
+
+        options = options
+            .clone()
+            .seccomp_filter(SeccompFilter::from_bytes(filter).unwrap());
+        path
+    });
+
+    let mut victim = Victim::spawn(&victim, mode, threads, seccomp_filter.as_deref());
+    // Lets the victim get into the syscall.
+    sleep(Duration::from_millis(100));
+
+    let intruduction = match intruduce_with(victim.child.id(), lib, &options) {
+        Err(Error::InsufficientPrivileges { .. }) => {
+            eprintln!("skipped: insufficient privileges");
+            return None;
+        }
+        result => result.unwrap(),
+    };
+
+    victim.wait(TIMEOUT, Victim::loaded);
+    assert_eq!(
+        fs::read_to_string(&victim.marker).unwrap_or_default(),
+        format!("{}\n", victim.child.id()),
+        "the library was not loaded exactly once"
+    );
+
+    victim.wait(SURVIVAL, |_| false);
+
+    let (success, done) = victim.finish();
+    assert!(success, "a worker checksum changed");
+    assert_eq!(done, victim.ready, "the main thread checksum changed");
+
+    Some(intruduction)
+}
+
+#[test]
+fn single_threaded_blocked_in_read() {
+    check(PIE, "read", 0);
+}
+
+#[test]
+fn single_threaded_blocked_in_nanosleep() {
+    check(PIE, "nanosleep", 0);
+}
+
+#[test]
+fn single_threaded_blocked_in_futex() {
+    check(PIE, "futex", 0);
+}
+
+#[test]
+fn multi_threaded_blocked_in_read() {
+    check(PIE, "read", 64);
+}
+
+#[test]
+fn multi_threaded_blocked_in_nanosleep() {
+    check(PIE, "nanosleep", 64);
+}
+
+#[test]
+fn multi_threaded_blocked_in_futex() {
+    check(PIE, "futex", 64);
+}
+
+#[test]
+fn non_pie() {
+    check(
+        Build {
+            target: None,
+            pie: false,
+        },
+        "nanosleep",
+        8,
+    );
+}
+
+#[test]
+#[cfg(target_arch = "x86_64")]
+fn compat_32_bit() {
+    check(
+        Build {
+            target: Some("i686-unknown-linux-gnu"),
+            pie: true,
+        },
+        "nanosleep",
+        8,
+    );
+}
+
+#[test]
+#[cfg(target_arch = "x86_64")]
+fn seccomp_filtered() {
+    /// Encodes a `struct sock_filter`.
+    fn insn(code: u16, jt: u8, jf: u8, k: u32) -> Vec<u8> {
+        [&code.to_ne_bytes()[..], &[jt, jf], &k.to_ne_bytes()].concat()
+    }
+
+    // Kills the process if it performs `open` or `unlink`, so that `openat` and `unlinkat` must be picked.
+    let filter = [
+        // seccomp_data.arch, which must be AUDIT_ARCH_X86_64.
+        insn(0x20, 0, 0, 4),
+        insn(0x15, 1, 0, 0xc000_003e),
+        insn(0x06, 0, 0, 0x8000_0000),
+        // seccomp_data.nr.
+        insn(0x20, 0, 0, 0),
+        insn(0x15, 2, 0, 2),
+        insn(0x15, 1, 0, 87),
+        insn(0x06, 0, 0, 0x7fff_0000),
+        insn(0x06, 0, 0, 0x8000_0000),
+    ]
+    .concat();
+
+    check_with(PIE, "nanosleep", 8, Some(&filter), Options::new());
+}
+
+#[test]
+#[cfg(target_arch = "x86_64")]
+fn thread_blocked_in_a_given_syscall() {
+    // The main thread blocks in `read`, while the workers pause in `clock_nanosleep`.
+    check_with(
+        PIE,
+        "read",
+        8,
+        None,
+        Options::new().thread(ThreadSelector::Syscall(0)),
+    );
+}
+
+#[test]
+fn nudged_out_of_poll() {
+    // The victim is not written to until the intruduction returns, so only `SIGUSR1` can interrupt its `poll`.
+    let options = Options::new().nudge(10).nudge_sa_restart();
+    if let Some(intruduction) = check_with(PIE, "poll", 8, None, options) {
+        assert_eq!(intruduction.nudged, Some(true));
+    }
+}
+
+#[test]
+fn not_nudged_out_of_a_restarted_read() {
+    // `read` is re-entered once `SIGUSR1` has been handled, so the library is loaded once the victim is written to.
+    let options = Options::new().nudge(10).nudge_sa_restart();
+    if let Some(intruduction) = check_with(PIE, "read", 0, None, options) {
+        assert_eq!(intruduction.nudged, Some(false));
+    }
+}
+
+#[test]
+fn refuses_to_nudge_with_signals_which_would_be_discarded_or_unexpected() {
+    let Some((victim, lib)) = fixtures(PIE) else {
+        return;
+    };
+    let victim = Victim::spawn(&victim, "read", 0, None);
+    let intruduce = |options: Options| intruduce_with(victim.child.id(), lib.clone(), &options);
+
+    // The handler is not declared to be installed with `SA_RESTART`.
+    match intruduce(Options::new().nudge(10)) {
+        Err(Error::InsufficientPrivileges { .. }) => {
+            eprintln!("skipped: insufficient privileges");
+            return;
+        }
+        Err(Error::UnrestartedSignal(10)) => (),
+        result => panic!("{:?}", result.map(drop)),
+    }
+
+    // `SIGWINCH` is ignored by default.
+    assert!(matches!(
+        intruduce(Options::new().nudge(28).nudge_sa_restart()),
+        Err(Error::IgnoredSignal(28))
+    ));
+}
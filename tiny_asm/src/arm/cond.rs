@@ -0,0 +1,63 @@
+use std::ops::Shl;
+
+/// The condition codes an instruction can be executed under.
+#[derive(Clone, Copy)]
+pub enum Cond {
+    Eq = 0,
+    Ne = 1,
+    Hs = 2,
+    Lo = 3,
+    Mi = 4,
+    Pl = 5,
+    Vs = 6,
+    Vc = 7,
+    Hi = 8,
+    Ls = 9,
+    Ge = 10,
+    Lt = 11,
+    Gt = 12,
+    Le = 13,
+    Al = 14,
+}
+
+impl Cond {
+    /// Gets the condition encoded as the 4 bit field `val`.
+    ///
+    /// Returns [`None`] if it's the unconditional instructions space (`0b1111`).
+    pub(crate) fn from_val(val: u32) -> Option<Self> {
+        use Cond::*;
+
+        [Eq, Ne, Hs, Lo, Mi, Pl, Vs, Vc, Hi, Ls, Ge, Lt, Gt, Le, Al]
+            .into_iter()
+            .nth((val & 0xf) as usize)
+    }
+
+    /// Gets the mnemonic suffix of the condition, which is empty for [`Cond::Al`].
+    pub(crate) fn suffix(&self) -> &'static str {
+        match self {
+            Cond::Eq => "eq",
+            Cond::Ne => "ne",
+            Cond::Hs => "hs",
+            Cond::Lo => "lo",
+            Cond::Mi => "mi",
+            Cond::Pl => "pl",
+            Cond::Vs => "vs",
+            Cond::Vc => "vc",
+            Cond::Hi => "hi",
+            Cond::Ls => "ls",
+            Cond::Ge => "ge",
+            Cond::Lt => "lt",
+            Cond::Gt => "gt",
+            Cond::Le => "le",
+            Cond::Al => "",
+        }
+    }
+}
+
+impl Shl<u32> for Cond {
+    type Output = u32;
+
+    fn shl(self, rhs: u32) -> Self::Output {
+        (self as Self::Output) << rhs
+    }
+}
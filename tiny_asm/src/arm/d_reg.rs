@@ -0,0 +1,74 @@
+use std::{fmt, str::FromStr};
+
+/// The 64 bit floating-point and Advanced SIMD registers.
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, PartialEq)]
+pub enum DReg {
+    d0 = 0,
+    d1 = 1,
+    d2 = 2,
+    d3 = 3,
+    d4 = 4,
+    d5 = 5,
+    d6 = 6,
+    d7 = 7,
+    d8 = 8,
+    d9 = 9,
+    d10 = 10,
+    d11 = 11,
+    d12 = 12,
+    d13 = 13,
+    d14 = 14,
+    d15 = 15,
+    d16 = 16,
+    d17 = 17,
+    d18 = 18,
+    d19 = 19,
+    d20 = 20,
+    d21 = 21,
+    d22 = 22,
+    d23 = 23,
+    d24 = 24,
+    d25 = 25,
+    d26 = 26,
+    d27 = 27,
+    d28 = 28,
+    d29 = 29,
+    d30 = 30,
+    d31 = 31,
+}
+
+const NUMBERED: [DReg; 32] = {
+    use DReg::*;
+    [
+        d0, d1, d2, d3, d4, d5, d6, d7, d8, d9, d10, d11, d12, d13, d14, d15, d16, d17, d18, d19,
+        d20, d21, d22, d23, d24, d25, d26, d27, d28, d29, d30, d31,
+    ]
+};
+
+impl DReg {
+    /// Gets the register encoded as the 5 bit field `val`.
+    pub(crate) fn from_val(val: u32) -> Self {
+        NUMBERED[(val & 0x1f) as usize]
+    }
+
+    /// Splits the register number into the `D` bit and the 4 bit `Vd` field of its encoding.
+    pub(super) fn split(self) -> (u32, u32) {
+        (self as u32 >> 4, self as u32 & 0xf)
+    }
+}
+
+impl FromStr for DReg {
+    type Err = ();
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let number: usize = name.strip_prefix('d').ok_or(())?.parse().map_err(|_| ())?;
+        NUMBERED.get(number).copied().ok_or(())
+    }
+}
+
+impl fmt::Display for DReg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "d{}", *self as u32)
+    }
+}
@@ -0,0 +1,326 @@
+use crate::{
+    disasm::{imm, Labels},
+    Decode,
+};
+
+use super::{op::rot_imm, AddrMode, AddrMode2, Cond, DReg, Op, Reg};
+
+impl Op {
+    /// Decodes `word` into the instruction it's the encoding of, e.g. `0xe2800b01` into `Addi(r0, r0, 0x400)`.
+    /// Label references are decoded into their relative variants, e.g. `Bi` instead of `Bl`.
+    ///
+    /// Returns [`None`] if `word` is not the encoding of an instruction, or it's not the one [`TinyAsm`](super::TinyAsm)
+    /// would emit: encoding the decoded instruction always gives `word` back.
+    pub fn decode(word: u32) -> Option<Self> {
+        let reg = |shift: u32| Reg::from_val(word >> shift);
+        let (rd, rn, rm) = (reg(12), reg(16), reg(0));
+        let pc = word >> 16 & 0xf == Reg::pc.val();
+
+        Some(match word {
+            _ if word & 0x0f000000 == 0x0a000000 => {
+                Op::Bi(Cond::from_val(word >> 28)?, (word << 8) as i32 >> 6)
+            }
+            _ if word & 0x0f000000 == 0x0b000000 => {
+                Op::Bli(Cond::from_val(word >> 28)?, (word << 8) as i32 >> 6)
+            }
+            _ if word & 0xfe000000 == 0xfa000000 => {
+                Op::Blxi((word << 8) as i32 >> 6 | (word >> 23 & 2) as i32)
+            }
+            _ if word >> 28 < 0xe => match Op::decode(word & 0x0fffffff | 0xe0000000)? {
+                // Their condition is part of the instruction, not of the wrapper.
+                Op::Bi(..) | Op::Bli(..) | Op::Blxi(..) => return None,
+                op => Op::If(Cond::from_val(word >> 28)?, Box::new(op)),
+            },
+            _ if word & 0xfff00000 == 0xe2800000 => match (pc, i32::try_from(rot(word)?)) {
+                (true, Ok(imm)) => Op::Adri(rd, imm),
+                _ => Op::Addi(rd, rn, rot(word)?),
+            },
+            _ if word & 0xfff00000 == 0xe2400000 => match (pc, i32::try_from(rot(word)?)) {
+                (true, Ok(imm)) if imm != 0 => Op::Adri(rd, -imm),
+                _ => Op::Subi(rd, rn, rot(word)?),
+            },
+            _ if word & 0xfff00ff0 == 0xe0000000 => Op::Andr(rd, rn, rm),
+            _ if word & 0xfffffff0 == 0xe12fff30 => Op::Blxr(rm),
+            _ if word & 0xfffffff0 == 0xe12fff10 => Op::Bx(rm),
+            _ if word & 0xfff0f000 == 0xe3500000 => Op::Cmpi(rn, rot(word)?),
+            _ if word & 0xfff0fff0 == 0xe1500000 => Op::Cmpr(rn, rm),
+            _ if word & 0xfe500000 == 0xe8100000 => {
+                Op::Ldm(addr_mode(word), rn, word >> 21 & 1 != 0, reg_list(word))
+            }
+            _ if word & 0xfe500000 == 0xe8000000 => {
+                Op::Stm(addr_mode(word), rn, word >> 21 & 1 != 0, reg_list(word))
+            }
+            _ if word & 0xfe500000 == 0xe4100000 => {
+                let mode = match (word >> 24 & 1, word >> 21 & 1) {
+                    (1, 0) => AddrMode2::Offset,
+                    (1, _) => AddrMode2::PreIndexed,
+                    (0, 0) => AddrMode2::PostIndexed,
+                    // LDRT, which is not encoded.
+                    _ => return None,
+                };
+                let imm = (word & 0xfff) as i32;
+
+                match word >> 23 & 1 {
+                    1 => Op::Ldri(mode, rd, rn, imm),
+                    // The offset zero is always encoded as a positive one.
+                    _ if imm == 0 => return None,
+                    _ => Op::Ldri(mode, rd, rn, -imm),
+                }
+            }
+            _ if word & 0xffff0ff0 == 0xe1a00000 => Op::Movr(rd, rm),
+            _ if word & 0xfff00000 == 0xe3400000 => {
+                Op::Movt(rd, (word >> 16 & 0xf) << 12 | word & 0xfff)
+            }
+            _ if word & 0xfff00000 == 0xe3000000 => {
+                Op::Movw(rd, (word >> 16 & 0xf) << 12 | word & 0xfff)
+            }
+            _ if word & 0xffff0fff == 0xe10f0000 => Op::Mrs(rd),
+            _ if word & 0xfffffff0 == 0xe128f000 => Op::Msr(rm),
+            _ if word & 0xff000000 == 0xef000000 => Op::Svc(word & 0xffffff),
+            _ if word & 0xffbf0f00 == 0xecbd0b00 => {
+                let (first, count) = d_reg_list(word)?;
+                Op::Vpop(first, count)
+            }
+            _ if word & 0xffbf0f00 == 0xed2d0b00 => {
+                let (first, count) = d_reg_list(word)?;
+                Op::Vpush(first, count)
+            }
+            _ => return None,
+        })
+    }
+
+    /// Gets the offset the instruction, located at `offset`, refers to.
+    fn target(&self, offset: usize) -> Option<i64> {
+        // The PC reads as the address of the current instruction plus 8.
+        match self {
+            Op::Adri(_, imm) | Op::Bi(_, imm) | Op::Bli(_, imm) | Op::Blxi(imm) => {
+                Some(offset as i64 + 8 + i64::from(*imm))
+            }
+            Op::Ldri(AddrMode2::Offset, _, rn, imm) if rn.val() == Reg::pc.val() => {
+                Some(offset as i64 + 8 + i64::from(*imm))
+            }
+            Op::If(_, op) => op.target(offset),
+            _ => None,
+        }
+    }
+
+    /// Formats the instruction, located at `offset`, in the text syntax.
+    ///
+    /// Returns [`None`] if it's a branch to an offset no label was put at, which can't be written.
+    fn text(&self, offset: usize, labels: &Labels) -> Option<String> {
+        let target = self.target(offset).unwrap_or_default();
+
+        Some(match self {
+            Op::Addi(rd, rn, imm) => format!("add {}, {}, #{}", rd, rn, fmt_imm(*imm)),
+            Op::Adri(rd, imm) => match labels.get(offset, target) {
+                Some(label) => format!("adr {}, {}", rd, label),
+                None if *imm < 0 => format!("sub {}, pc, #{}", rd, fmt_imm(imm.unsigned_abs())),
+                None => format!("add {}, pc, #{}", rd, fmt_imm(*imm as u32)),
+            },
+            Op::Adrl(rd, label) => format!("adr {}, {}", rd, label),
+            Op::Andr(rd, rn, rm) => format!("and {}, {}, {}", rd, rn, rm),
+            Op::Bi(cond, _) => format!("b{} {}", cond.suffix(), labels.get(offset, target)?),
+            Op::Bl(cond, label) => format!("b{} {}", cond.suffix(), label),
+            Op::Bli(cond, _) => format!("bl{} {}", cond.suffix(), labels.get(offset, target)?),
+            Op::Bll(cond, label) => format!("bl{} {}", cond.suffix(), label),
+            Op::Blxi(_) => format!("blx {}", labels.get(offset, target)?),
+            Op::Blxl(label) => format!("blx {}", label),
+            Op::Blxr(rm) => format!("blx {}", rm),
+            Op::Bx(rm) => format!("bx {}", rm),
+            Op::Cmpi(rn, imm) => format!("cmp {}, #{}", rn, fmt_imm(*imm)),
+            Op::Cmpr(rn, rm) => format!("cmp {}, {}", rn, rm),
+            Op::If(cond, op) => {
+                let text = op.text(offset, labels)?;
+                let (mnemonic, operands) = text.split_once(' ').unwrap_or((&text, ""));
+                format!("{}{} {}", mnemonic, cond.suffix(), operands)
+            }
+            Op::Ldm(AddrMode::IncrAfter, rn, true, regs) if rn.val() == Reg::sp.val() => {
+                format!("pop {}", fmt_reg_list(regs))
+            }
+            Op::Ldm(mode, rn, wb, regs) => format!(
+                "ldm{} {}{}, {}",
+                addr_mode_suffix(mode),
+                rn,
+                if *wb { "!" } else { "" },
+                fmt_reg_list(regs)
+            ),
+            Op::Ldri(AddrMode2::Offset, rt, rn, imm) if rn.val() == Reg::pc.val() => {
+                match labels.get(offset, target) {
+                    Some(label) => format!("ldr {}, {}", rt, label),
+                    None => format!("ldr {}, [pc, #{}]", rt, fmt_imm(*imm)),
+                }
+            }
+            Op::Ldri(mode, rt, rn, imm) => match mode {
+                AddrMode2::Offset if *imm == 0 => format!("ldr {}, [{}]", rt, rn),
+                AddrMode2::Offset => format!("ldr {}, [{}, #{}]", rt, rn, fmt_imm(*imm)),
+                AddrMode2::PreIndexed => format!("ldr {}, [{}, #{}]!", rt, rn, fmt_imm(*imm)),
+                AddrMode2::PostIndexed => format!("ldr {}, [{}], #{}", rt, rn, fmt_imm(*imm)),
+            },
+            Op::Ldrl(rt, label) => format!("ldr {}, {}", rt, label),
+            Op::Movr(rd, rm) => format!("mov {}, {}", rd, rm),
+            Op::Movt(rd, imm) => format!("movt {}, #{}", rd, fmt_imm(*imm)),
+            Op::Movw(rd, imm) => format!("movw {}, #{}", rd, fmt_imm(*imm)),
+            Op::Mrs(rd) => format!("mrs {}, apsr", rd),
+            Op::Msr(rn) => format!("msr apsr_nzcvq, {}", rn),
+            Op::Subi(rd, rn, imm) => format!("sub {}, {}, #{}", rd, rn, fmt_imm(*imm)),
+            Op::Stm(AddrMode::DecrBefore, rn, true, regs) if rn.val() == Reg::sp.val() => {
+                format!("push {}", fmt_reg_list(regs))
+            }
+            Op::Stm(mode, rn, wb, regs) => format!(
+                "stm{} {}{}, {}",
+                addr_mode_suffix(mode),
+                rn,
+                if *wb { "!" } else { "" },
+                fmt_reg_list(regs)
+            ),
+            Op::Svc(imm) => format!("svc #{}", fmt_imm(*imm)),
+            Op::Vpop(first, count) => format!("vpop {}", fmt_d_reg_list(*first, *count)),
+            Op::Vpush(first, count) => format!("vpush {}", fmt_d_reg_list(*first, *count)),
+            Op::Placeholder => ".long 0".into(),
+        })
+    }
+}
+
+impl Decode for Op {
+    fn decode(code: &[u8], offset: usize, labels: &Labels) -> Option<(usize, String)> {
+        if !offset.is_multiple_of(4) {
+            return None;
+        }
+
+        let word = u32::from_le_bytes(code.get(..4)?.try_into().ok()?);
+        let op = Op::decode(word)?;
+
+        match op.target(offset) {
+            Some(target) if !(0..=(offset + code.len()) as i64).contains(&target) => None,
+            _ => Some((4, op.text(offset, labels)?)),
+        }
+    }
+}
+
+/// Decodes the modified immediate constant of `word`.
+///
+/// Returns [`None`] if it's not the rotation [`rot_imm`] would choose.
+fn rot(word: u32) -> Option<u32> {
+    let imm = (word & 0xff).rotate_right((word >> 8 & 0xf) * 2);
+    (rot_imm(imm).ok()? == word & 0xfff).then_some(imm)
+}
+
+fn addr_mode(word: u32) -> AddrMode {
+    match word >> 23 & 3 {
+        0 => AddrMode::DecrAfter,
+        1 => AddrMode::IncrAfter,
+        2 => AddrMode::DecrBefore,
+        _ => AddrMode::IncrBefore,
+    }
+}
+
+fn addr_mode_suffix(mode: &AddrMode) -> &'static str {
+    match mode {
+        AddrMode::DecrAfter => "da",
+        AddrMode::IncrAfter => "ia",
+        AddrMode::DecrBefore => "db",
+        AddrMode::IncrBefore => "ib",
+    }
+}
+
+fn reg_list(word: u32) -> Vec<Reg> {
+    (0..16)
+        .filter(|reg| word >> reg & 1 != 0)
+        .map(Reg::from_val)
+        .collect()
+}
+
+fn fmt_reg_list(regs: &[Reg]) -> String {
+    let regs: Vec<_> = regs.iter().map(Reg::to_string).collect();
+    format!("{{{}}}", regs.join(", "))
+}
+
+/// Decodes the list of consecutive registers of a `VPUSH` or `VPOP`, as its first register and its length.
+///
+/// Returns [`None`] if it's not one [`TinyAsm`](super::TinyAsm) would encode.
+fn d_reg_list(word: u32) -> Option<(DReg, u8)> {
+    let first = (word >> 18 & 0x10) | (word >> 12 & 0xf);
+    let words = word & 0xff;
+
+    (words.is_multiple_of(2) && (2..=32).contains(&words) && first + words / 2 <= 32)
+        .then(|| (DReg::from_val(first), (words / 2) as u8))
+}
+
+fn fmt_d_reg_list(first: DReg, count: u8) -> String {
+    let regs: Vec<_> = (0..u32::from(count))
+        .map(|index| DReg::from_val(first as u32 + index).to_string())
+        .collect();
+    format!("{{{}}}", regs.join(", "))
+}
+
+fn fmt_imm(value: impl Into<i64>) -> String {
+    imm(value.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        arm::{AddrMode2::*, Cond, DReg::*, Op, Reg::*, TinyAsm},
+        disassemble,
+    };
+
+    #[test]
+    fn decodes_what_it_encodes() {
+        let (code, labels) = TinyAsm::new()
+            .label("loop")
+            .push([r0, r1, lr])
+            .adrl(r0, "path")
+            .ldrl(r1, "len")
+            .ldri(Offset, r1, r2, 0)
+            .ldri(PostIndexed, r1, r2, -4)
+            .ldri(PreIndexed, r1, sp, -8)
+            .addi(r0, None, 0x400)
+            .subi(sp, None, 128)
+            .andr(r0, r1, r2)
+            .cmpi(r1, 0)
+            .b(Cond::Ne, "loop")
+            .when(Cond::Eq, |asm| {
+                asm.movr(r0, r7).ldrl(r2, "len").pop([r4, pc])
+            })
+            .bl(Cond::Lo, "loop")
+            .blx("loop")
+            .blxr(r3)
+            .bx(lr)
+            .cmpr(r0, r1)
+            .movr(r0, r7)
+            .movw(r7, 0xffff)
+            .movt(r7, 0x1234)
+            .mrs(r0)
+            .msr(r1)
+            .vpush(d8, 8)
+            .vpop(d16, 1)
+            .svc(0)
+            .pop([r0, r1, pc])
+            .label("path")
+            .asciiz("/tmp/lib.so")
+            .align::<4>()
+            .label("len")
+            .dword(0xffffffff)
+            .build_with_labels()
+            .unwrap();
+
+        let listing = disassemble::<Op>(&code, &labels);
+
+        assert!(listing.contains("bne loop"), "{}", listing);
+        assert!(listing.contains("ldreq r2, len"), "{}", listing);
+        assert_eq!(
+            TinyAsm::new().asm(&listing).build(),
+            Ok(code),
+            "{}",
+            listing
+        );
+    }
+
+    #[test]
+    fn rejects_what_it_does_not_encode() {
+        // LDRT, a negative zero offset, a rotation which is not the first one, DMB and an odd VPUSH list.
+        for word in [0xe4321004, 0xe5120000, 0xe2800f01, 0xf57ff05f, 0xed2d0b03] {
+            assert!(Op::decode(word).is_none(), "{:#x}", word);
+        }
+    }
+}
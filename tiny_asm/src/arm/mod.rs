@@ -1,10 +1,16 @@
 mod addr_mode;
 mod addr_mode_2;
+mod cond;
+mod d_reg;
+mod dec;
 mod op;
 mod reg;
+mod syntax;
 
 pub use addr_mode::AddrMode;
 pub use addr_mode_2::AddrMode2;
+pub use cond::Cond;
+pub use d_reg::DReg;
 pub use op::Op;
 pub use reg::Reg;
 
@@ -12,17 +18,65 @@ use super::{Encodable, Label};
 
 /// https://documentation-service.arm.com/static/5f8daeb7f86e16515cdb8c4e
 impl TinyAsm {
-    /// Encoding of ADD (immediate): `ADD <Rd>, <Rn>, #<uimm12>`.
-    pub fn addi(self, rd: Reg, rn: Option<Reg>, imm: u16) -> Self {
-        self.op(Op::Addi(rd, rn.unwrap_or(rd), imm as u32))
+    /// Encoding of ADD (immediate): `ADD <Rd>, <Rn>, #<const>`, where `<const>` is a 8 bit value rotated right by an even amount.
+    pub fn addi(self, rd: Reg, rn: Option<Reg>, imm: u32) -> Self {
+        self.op(Op::Addi(rd, rn.unwrap_or(rd), imm))
     }
 
     /// Encoding of ADR: `ADR <Rd>, <label>`.
-    pub fn adrl(mut self, rd: Reg, label: Label) -> Self {
+    pub fn adrl(mut self, rd: Reg, label: impl Into<Label>) -> Self {
+        let label = self.refer(label);
         self.relocs.push((self.buf.len(), Op::Adrl(rd, label)));
         self.op(Op::Placeholder)
     }
 
+    /// Encoding of AND (register): `AND <Rd>, <Rn>, <Rm>`.
+    pub fn andr(self, rd: Reg, rn: Reg, rm: Reg) -> Self {
+        self.op(Op::Andr(rd, rn, rm))
+    }
+
+    /// Encoding of B: `B<c> <label>`.
+    pub fn b(mut self, cond: Cond, label: impl Into<Label>) -> Self {
+        let label = self.refer(label);
+        self.relocs.push((self.buf.len(), Op::Bl(cond, label)));
+        self.op(Op::Placeholder)
+    }
+
+    /// Encoding of BL: `BL<c> <label>`.
+    pub fn bl(mut self, cond: Cond, label: impl Into<Label>) -> Self {
+        let label = self.refer(label);
+        self.relocs.push((self.buf.len(), Op::Bll(cond, label)));
+        self.op(Op::Placeholder)
+    }
+
+    /// Encoding of BLX (immediate): `BLX <label>`, which switches to the Thumb instruction set.
+    /// It can't be made conditional.
+    pub fn blx(mut self, label: impl Into<Label>) -> Self {
+        let label = self.refer(label);
+        self.relocs.push((self.buf.len(), Op::Blxl(label)));
+        self.op(Op::Placeholder)
+    }
+
+    /// Encoding of BLX (register): `BLX <Rm>`.
+    pub fn blxr(self, rm: Reg) -> Self {
+        self.op(Op::Blxr(rm))
+    }
+
+    /// Encoding of BX: `BX <Rm>`.
+    pub fn bx(self, rm: Reg) -> Self {
+        self.op(Op::Bx(rm))
+    }
+
+    /// Encoding of CMP (immediate): `CMP <Rn>, #<const>`, where `<const>` is a 8 bit value rotated right by an even amount.
+    pub fn cmpi(self, rn: Reg, imm: u32) -> Self {
+        self.op(Op::Cmpi(rn, imm))
+    }
+
+    /// Encoding of CMP (register): `CMP <Rn>, <Rm>`.
+    pub fn cmpr(self, rn: Reg, rm: Reg) -> Self {
+        self.op(Op::Cmpr(rn, rm))
+    }
+
     /// Encoding of LDMIA: `LDMIA <Rn>{!}, <registers>`.
     pub fn ldmia<const T: usize>(self, rn: Reg, wb: bool, regs: [Reg; T]) -> Self {
         self.op(Op::Ldm(AddrMode::IncrAfter, rn, wb, regs.to_vec()))
@@ -33,18 +87,34 @@ impl TinyAsm {
         self.op(Op::Movr(rd, rm))
     }
 
+    /// Encoding of MOVT: `MOVT <Rd>, #<imm16>`, which writes the top halfword only.
+    pub fn movt(self, rd: Reg, imm: u16) -> Self {
+        self.op(Op::Movt(rd, imm as u32))
+    }
+
     /// Encoding of MOVW (immediate): `MOVW <Rd>, #<imm16>`.
     pub fn movw(self, rd: Reg, imm: u16) -> Self {
         self.op(Op::Movw(rd, imm as u32))
     }
 
+    /// Encoding of MRS: `MRS <Rd>, APSR`, which reads the condition flags.
+    pub fn mrs(self, rd: Reg) -> Self {
+        self.op(Op::Mrs(rd))
+    }
+
+    /// Encoding of MSR (register): `MSR APSR_nzcvq, <Rn>`, which writes the condition flags.
+    pub fn msr(self, rn: Reg) -> Self {
+        self.op(Op::Msr(rn))
+    }
+
     /// Encoding of LDR (immediate): `LDR <Rt>, [<Rn>{, #+/-<imm12>}]`, `LDR<Rt>, [<Rn>], #+/-<imm12>`, `LDR <Rt>, [<Rn>, #+/-<imm12>]!`.
-    pub fn ldri(self, mode: AddrMode2, rn: Reg, rt: Reg, imm: i16) -> Self {
-        self.op(Op::Ldri(mode, rn, rt, imm))
+    pub fn ldri(self, mode: AddrMode2, rt: Reg, rn: Reg, imm: i16) -> Self {
+        self.op(Op::Ldri(mode, rt, rn, imm.into()))
     }
 
     /// Encoding of LDR (label): `LDR <Rt>, <label>`.
-    pub fn ldrl(mut self, rn: Reg, label: Label) -> Self {
+    pub fn ldrl(mut self, rn: Reg, label: impl Into<Label>) -> Self {
+        let label = self.refer(label);
         self.relocs.push((self.buf.len(), Op::Ldrl(rn, label)));
         self.op(Op::Placeholder)
     }
@@ -64,15 +134,59 @@ impl TinyAsm {
         self.op(Op::Stm(AddrMode::DecrBefore, rn, wb, regs.to_vec()))
     }
 
-    /// Encoding of SUB (immediate): `SUB <Rd>, <Rn>, #<uimm12>`.
-    pub fn subi(self, rd: Reg, rn: Option<Reg>, imm: u16) -> Self {
-        self.op(Op::Subi(rd, rn.unwrap_or(rd), imm as u32))
+    /// Encoding of SUB (immediate): `SUB <Rd>, <Rn>, #<const>`, where `<const>` is a 8 bit value rotated right by an even amount.
+    pub fn subi(self, rd: Reg, rn: Option<Reg>, imm: u32) -> Self {
+        self.op(Op::Subi(rd, rn.unwrap_or(rd), imm))
     }
 
     /// Encoding of SVC: `SVC #<imm24>`.
     pub fn svc(self, imm: u32) -> Self {
         self.op(Op::Svc(imm))
     }
+
+    /// Encoding of VPOP: `VPOP <list>`, where `<list>` is made of `count` consecutive registers starting from `first`.
+    pub fn vpop(self, first: DReg, count: u8) -> Self {
+        self.op(Op::Vpop(first, count))
+    }
+
+    /// Encoding of VPUSH: `VPUSH <list>`, where `<list>` is made of `count` consecutive registers starting from `first`.
+    pub fn vpush(self, first: DReg, count: u8) -> Self {
+        self.op(Op::Vpush(first, count))
+    }
+
+    /// Makes every instruction pushed by `f` conditional, e.g. `MOV<c> <Rd>, <Rm>`: they must be always executed ones.
+    /// `f` is expected to push instructions only.
+    pub fn when(self, cond: Cond, f: impl FnOnce(Self) -> Self) -> Self {
+        let (start, first_reloc) = (self.buf.len(), self.relocs.len());
+        let mut asm = f(self);
+
+        let relocs = asm.relocs.split_off(first_reloc);
+        let pending: Vec<_> = relocs.iter().map(|(offset, _)| *offset).collect();
+
+        for offset in (start..asm.buf.len()).step_by(4) {
+            let Some(bytes) = asm.buf.get_mut(offset..offset + 4) else {
+                break;
+            };
+
+            if pending.contains(&offset) {
+                continue;
+            }
+
+            match op::conditional(cond, u32::from_le_bytes((*bytes).try_into().unwrap())) {
+                Ok(word) => bytes.copy_from_slice(&word.to_le_bytes()),
+                Err(err) => {
+                    asm.error.get_or_insert(err);
+                }
+            }
+        }
+
+        asm.relocs.extend(
+            relocs
+                .into_iter()
+                .map(|(offset, op)| (offset, Op::If(cond, Box::new(op)))),
+        );
+        asm
+    }
 }
 
 pub type TinyAsm = super::TinyAsm<Op, 4>;
@@ -1,32 +1,68 @@
-use super::{AddrMode, AddrMode2, Encodable, Label, Reg};
+use super::{AddrMode, AddrMode2, Cond, DReg, Encodable, Label, Reg};
+use crate::{
+    encodable::{signed, unsigned},
+    AsmError,
+};
 use std::collections::HashMap;
 
 pub enum Op {
     Addi(Reg, Reg, u32),
     Adrl(Reg, Label),
     Adri(Reg, i32),
+    Andr(Reg, Reg, Reg),
+    Bi(Cond, i32),
+    Bl(Cond, Label),
+    Bli(Cond, i32),
+    Bll(Cond, Label),
+    Blxi(i32),
+    Blxl(Label),
+    Blxr(Reg),
+    Bx(Reg),
+    Cmpi(Reg, u32),
+    Cmpr(Reg, Reg),
+    /// The instruction, executed only if the condition holds.
+    If(Cond, Box<Op>),
     Ldm(AddrMode, Reg, bool, Vec<Reg>),
-    Ldri(AddrMode2, Reg, Reg, i16),
+    Ldri(AddrMode2, Reg, Reg, i32),
     Ldrl(Reg, Label),
     Movr(Reg, Reg),
+    Movt(Reg, u32),
     Movw(Reg, u32),
+    Mrs(Reg),
+    Msr(Reg),
     Subi(Reg, Reg, u32),
     Stm(AddrMode, Reg, bool, Vec<Reg>),
     Svc(u32),
+    Vpop(DReg, u8),
+    Vpush(DReg, u8),
     Placeholder,
 }
 
-impl From<Op> for u32 {
-    fn from(op: Op) -> u32 {
-        match op {
-            Op::Addi(rd, rn, imm) => 0xe2800000 | rn << 16 | rd << 12 | imm,
+impl TryFrom<Op> for u32 {
+    type Error = AsmError;
+
+    fn try_from(op: Op) -> Result<u32, AsmError> {
+        Ok(match op {
+            Op::Addi(rd, rn, imm) => 0xe2800000 | rn << 16 | rd << 12 | rot_imm(imm)?,
             Op::Adri(rn, imm) => {
                 if imm < 0 {
-                    Op::Subi(rn, Reg::pc, -imm as u32).into()
+                    Op::Subi(rn, Reg::pc, imm.unsigned_abs()).try_into()?
                 } else {
-                    Op::Addi(rn, Reg::pc, imm as u32).into()
+                    Op::Addi(rn, Reg::pc, imm as u32).try_into()?
                 }
             }
+            Op::Andr(rd, rn, rm) => 0xe0000000 | rn << 16 | rd << 12 | rm,
+            Op::Bi(cond, imm) => cond << 28 | 0x0a000000 | signed(imm.into(), 24, 4)?,
+            Op::Bli(cond, imm) => cond << 28 | 0x0b000000 | signed(imm.into(), 24, 4)?,
+            Op::Blxi(imm) => {
+                let imm = signed(imm.into(), 25, 2)?;
+                0xfa000000 | (imm & 1) << 24 | imm >> 1
+            }
+            Op::Blxr(rm) => 0xe12fff30 | rm,
+            Op::Bx(rm) => 0xe12fff10 | rm,
+            Op::Cmpi(rn, imm) => 0xe3500000 | rn << 16 | rot_imm(imm)?,
+            Op::Cmpr(rn, rm) => 0xe1500000 | rn << 16 | rm,
+            Op::If(cond, op) => conditional(cond, (*op).try_into()?)?,
             Op::Ldm(mode, rn, wb, regs) => regs.into_iter().fold(
                 0xe8100000 | mode << 23 | (wb as u32) << 21 | rn << 16,
                 |acc, rn| acc | 1 << rn,
@@ -35,7 +71,7 @@ impl From<Op> for u32 {
                 let (index, wback) = match mode {
                     AddrMode2::Offset => (1, 0),
                     AddrMode2::PreIndexed => (1, 1),
-                    AddrMode2::PostIndexed => (0, 1),
+                    AddrMode2::PostIndexed => (0, 0),
                 };
 
                 0xe4100000
@@ -44,37 +80,174 @@ impl From<Op> for u32 {
                     | wback << 21
                     | rn << 16
                     | rt << 12
-                    | imm.abs() as u32
+                    | unsigned(imm.unsigned_abs().into(), 12, 1)?
             }
             Op::Movr(rd, rm) => 0xe1a00000 | rd << 12 | rm,
-            Op::Movw(rd, imm) => 0xe3000000 | (imm >> 12) << 16 | rd << 12 | ((1 << 12) - 1) & imm,
+            Op::Movt(rd, imm) => {
+                let imm = unsigned(imm.into(), 16, 1)?;
+                0xe3400000 | (imm >> 12) << 16 | rd << 12 | ((1 << 12) - 1) & imm
+            }
+            Op::Movw(rd, imm) => {
+                let imm = unsigned(imm.into(), 16, 1)?;
+                0xe3000000 | (imm >> 12) << 16 | rd << 12 | ((1 << 12) - 1) & imm
+            }
+            Op::Mrs(rd) => 0xe10f0000 | rd << 12,
+            Op::Msr(rn) => 0xe128f000 | rn,
             Op::Stm(mode, rn, wb, regs) => regs.into_iter().fold(
                 0xe8000000 | mode << 23 | (wb as u32) << 21 | rn << 16,
                 |acc, rn| acc | 1 << rn,
             ),
-            Op::Subi(rd, rn, imm) => 0xe2400000 | rn << 16 | rd << 12 | imm,
-            Op::Svc(imm) => 0xef000000 | imm,
+            Op::Subi(rd, rn, imm) => 0xe2400000 | rn << 16 | rd << 12 | rot_imm(imm)?,
+            Op::Svc(imm) => 0xef000000 | unsigned(imm.into(), 24, 1)?,
+            Op::Vpop(first, count) => 0xecbd0b00 | d_reg_list(first, count)?,
+            Op::Vpush(first, count) => 0xed2d0b00 | d_reg_list(first, count)?,
             _ => 0,
-        }
+        })
     }
 }
 
 impl Encodable<4> for Op {
-    fn enc(self, off: usize, labs: &HashMap<Label, usize>) -> [u8; 4] {
-        u32::from(match self {
-            Op::Adrl(rn, label) => Op::Adri(rn, Self::res_lab(label, labs, off)),
+    fn enc(self, off: usize, labs: &HashMap<Label, usize>) -> Result<[u8; 4], AsmError> {
+        u32::try_from(match self {
+            Op::If(cond, op) => {
+                let word = u32::from_le_bytes(op.enc(off, labs)?);
+                return conditional(cond, word).map(u32::to_le_bytes);
+            }
+            Op::Adrl(rn, label) => Op::Adri(rn, Self::res_lab(label, labs, off)?),
+            Op::Bl(cond, label) => Op::Bi(cond, Self::res_lab(label, labs, off)?),
+            Op::Bll(cond, label) => Op::Bli(cond, Self::res_lab(label, labs, off)?),
+            Op::Blxl(label) => Op::Blxi(Self::res_lab(label, labs, off)?),
             Op::Ldrl(rt, label) => Op::Ldri(
                 AddrMode2::Offset,
                 rt,
                 Reg::pc,
-                Self::res_lab(label, labs, off) as i16,
+                Self::res_lab(label, labs, off)?,
             ),
             op => op,
         })
-        .to_le_bytes()
+        .map(u32::to_le_bytes)
     }
 
     fn calc_offset(op_offset: i32, label_offset: i32) -> i32 {
         label_offset - op_offset - 8
     }
 }
+
+/// Encodes `imm` as a modified immediate constant, e.g. a 8 bit value rotated right by an even amount.
+///
+/// Returns [`AsmError::ImmOutOfRange`] if there's no such encoding.
+pub(super) fn rot_imm(imm: u32) -> Result<u32, AsmError> {
+    (0..16)
+        .find_map(|rot| {
+            let value = imm.rotate_left(rot * 2);
+            (value <= 0xff).then_some(rot << 8 | value)
+        })
+        .ok_or(AsmError::ImmOutOfRange(imm.into()))
+}
+
+/// Makes the always executed instruction encoded as `word` conditional, replacing its condition field.
+///
+/// Returns [`AsmError::InvalidOperand`] if it's already conditional, or it can't be made so (e.g. `BLX <label>`).
+pub(super) fn conditional(cond: Cond, word: u32) -> Result<u32, AsmError> {
+    match word >> 28 {
+        0xe => Ok(cond << 28 | word & 0x0fffffff),
+        _ => Err(AsmError::InvalidOperand(
+            "only an always executed instruction can be made conditional",
+        )),
+    }
+}
+
+/// Encodes the list of `count` consecutive registers starting from `first`, as `D:Vd` and the number of words.
+///
+/// Returns [`AsmError::InvalidOperand`] if the list is empty, longer than 16 registers or goes beyond `d31`.
+fn d_reg_list(first: DReg, count: u8) -> Result<u32, AsmError> {
+    if !(1..=16).contains(&count) || first as u32 + u32::from(count) > 32 {
+        return Err(AsmError::InvalidOperand(
+            "the register list must hold 1 to 16 registers up to d31",
+        ));
+    }
+
+    let (d, vd) = first.split();
+    Ok(d << 22 | vd << 12 | (u32::from(count) * 2))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        arm::{Cond, DReg::*, Reg::*, TinyAsm},
+        AsmError,
+    };
+
+    #[test]
+    fn encodes_rotated_immediates() {
+        let code = TinyAsm::new().addi(r0, None, 0x400).build().unwrap();
+
+        assert_eq!(code, 0xe2800b01_u32.to_le_bytes());
+    }
+
+    #[test]
+    fn rejects_unencodable_immediates() {
+        let result = TinyAsm::new().subi(r0, None, 0x101).build();
+
+        assert_eq!(result, Err(AsmError::ImmOutOfRange(0x101)));
+    }
+
+    #[test]
+    fn encodes_conditional_instructions() {
+        let code = TinyAsm::new()
+            .label("loop")
+            .when(Cond::Ne, |asm| asm.movr(r0, r1).b(Cond::Al, "loop"))
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            code,
+            [0x11a00001_u32, 0x1afffffd].map(u32::to_le_bytes).concat()
+        );
+    }
+
+    #[test]
+    fn rejects_already_conditional_instructions() {
+        let invalid = Err(AsmError::InvalidOperand(
+            "only an always executed instruction can be made conditional",
+        ));
+
+        let result = TinyAsm::new()
+            .label("loop")
+            .when(Cond::Ne, |asm| asm.b(Cond::Eq, "loop"))
+            .build();
+        assert_eq!(result, invalid);
+
+        let result = TinyAsm::new()
+            .label("loop")
+            .when(Cond::Ne, |asm| asm.blx("loop"))
+            .build();
+        assert_eq!(result, invalid);
+    }
+
+    #[test]
+    fn rejects_invalid_register_lists() {
+        for (first, count) in [(d0, 0), (d0, 17), (d30, 3)] {
+            let result = TinyAsm::new().vpush(first, count).build();
+
+            assert_eq!(
+                result,
+                Err(AsmError::InvalidOperand(
+                    "the register list must hold 1 to 16 registers up to d31"
+                ))
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_unreachable_literal() {
+        let result = TinyAsm::new()
+            .ldrl(r0, "literal")
+            .bytes(&[0; 4100])
+            .label("literal")
+            .dword(0)
+            .build();
+
+        assert_eq!(result, Err(AsmError::ImmOutOfRange(4096)));
+    }
+}
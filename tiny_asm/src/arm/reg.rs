@@ -1,4 +1,8 @@
-use std::ops::{BitOr, Shl};
+use std::{
+    fmt,
+    ops::{BitOr, Shl},
+    str::FromStr,
+};
 
 #[allow(non_camel_case_types)]
 #[derive(Clone, Copy, PartialEq)]
@@ -24,8 +28,21 @@ pub enum Reg {
     pc,
 }
 
+/// The registers by number.
+const NUMBERED: [Reg; 16] = {
+    use Reg::*;
+    [
+        r0, r1, r2, r3, r4, r5, r6, r7, r8, r9, r10, r11, r12, r13, r14, r15,
+    ]
+};
+
 impl Reg {
-    const fn val(self) -> u32 {
+    /// Gets the register encoded as the 4 bit field `val`.
+    pub(crate) const fn from_val(val: u32) -> Self {
+        NUMBERED[(val & 0xf) as usize]
+    }
+
+    pub(super) const fn val(self) -> u32 {
         match self {
             Reg::sp => Reg::r13.val(),
             Reg::lr => Reg::r14.val(),
@@ -58,3 +75,32 @@ impl Shl<u32> for Reg {
         self.val() << rhs as Self::Output
     }
 }
+
+impl FromStr for Reg {
+    type Err = ();
+
+    /// Parses a register name, either `r<n>` or one of the `sp`, `lr` and `pc` aliases.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "sp" => Ok(Reg::sp),
+            "lr" => Ok(Reg::lr),
+            "pc" => Ok(Reg::pc),
+            _ => {
+                let number: usize = name.strip_prefix('r').ok_or(())?.parse().map_err(|_| ())?;
+                NUMBERED.get(number).copied().ok_or(())
+            }
+        }
+    }
+}
+
+impl fmt::Display for Reg {
+    /// Formats the register name, using the `sp`, `lr` and `pc` aliases.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.val() {
+            13 => f.write_str("sp"),
+            14 => f.write_str("lr"),
+            15 => f.write_str("pc"),
+            val => write!(f, "r{}", val),
+        }
+    }
+}
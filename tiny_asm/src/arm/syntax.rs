@@ -0,0 +1,314 @@
+use crate::{
+    text::{parse_indexed, parse_int, parse_label, parse_reg, parse_reg_list, Indexing},
+    Syntax, TinyAsm,
+};
+
+use super::{AddrMode, AddrMode2, Cond, DReg, Op, Reg};
+
+/// The mnemonics which are made conditional by a suffix, e.g. `movne`; `b` and `bl` parse their own.
+const MNEMONICS: [&str; 23] = [
+    "add", "sub", "adr", "and", "blx", "bx", "cmp", "ldm", "ldmia", "ldmfd", "stmdb", "stmfd",
+    "push", "pop", "mov", "movt", "movw", "mrs", "msr", "ldr", "svc", "vpop", "vpush",
+];
+
+impl Syntax<4> for Op {
+    fn instr(asm: &mut TinyAsm<Self, 4>, mnemonic: &str, operands: &[&str]) -> Result<(), String> {
+        if let Some(cond) = mnemonic.strip_prefix('b').and_then(cond) {
+            let [label] = operands else {
+                return Err(format!("invalid operands for `{}`", mnemonic));
+            };
+            let label = parse_label(label)?;
+            asm.emit(|asm| asm.b(cond, label));
+            return Ok(());
+        }
+
+        if let Some(cond) = mnemonic.strip_prefix("bl").and_then(cond) {
+            let [label] = operands else {
+                return Err(format!("invalid operands for `{}`", mnemonic));
+            };
+            let label = parse_label(label)?;
+            asm.emit(|asm| asm.bl(cond, label));
+            return Ok(());
+        }
+
+        if !MNEMONICS.contains(&mnemonic) {
+            let split = mnemonic.len().checked_sub(2).and_then(|at| {
+                let (base, suffix) = mnemonic.split_at_checked(at)?;
+                Some((MNEMONICS.contains(&base).then_some(base)?, cond(suffix)?))
+            });
+
+            if let Some((base, cond)) = split {
+                let mut result = Ok(());
+                asm.emit(|asm| {
+                    asm.when(cond, |mut asm| {
+                        result = Self::instr(&mut asm, base, operands);
+                        asm
+                    })
+                });
+                return result;
+            }
+        }
+
+        let op = match (mnemonic, operands) {
+            ("add" | "sub", [rd, rn, imm]) => {
+                let (rd, rn, imm) = (parse_reg(rd)?, parse_reg(rn)?, imm32(imm)?);
+
+                match mnemonic {
+                    "add" => Op::Addi(rd, rn, imm),
+                    _ => Op::Subi(rd, rn, imm),
+                }
+            }
+            ("add" | "sub", [rd, imm]) => {
+                let (rd, imm) = (parse_reg(rd)?, imm32(imm)?);
+
+                match mnemonic {
+                    "add" => Op::Addi(rd, rd, imm),
+                    _ => Op::Subi(rd, rd, imm),
+                }
+            }
+            ("adr", [rd, label]) => {
+                let (rd, label) = (parse_reg(rd)?, parse_label(label)?);
+                asm.emit(|asm| asm.adrl(rd, label));
+                return Ok(());
+            }
+            ("and", [rd, rn, rm]) => Op::Andr(parse_reg(rd)?, parse_reg(rn)?, parse_reg(rm)?),
+            ("blx", [rm]) if parse_reg::<Reg>(rm).is_ok() => Op::Blxr(parse_reg(rm)?),
+            ("blx", [label]) => {
+                let label = parse_label(label)?;
+                asm.emit(|asm| asm.blx(label));
+                return Ok(());
+            }
+            ("bx", [rm]) => Op::Bx(parse_reg(rm)?),
+            ("cmp", [rn, rm]) if parse_reg::<Reg>(rm).is_ok() => {
+                Op::Cmpr(parse_reg(rn)?, parse_reg(rm)?)
+            }
+            ("cmp", [rn, imm]) => Op::Cmpi(parse_reg(rn)?, imm32(imm)?),
+            ("ldm" | "ldmia" | "ldmfd" | "stmdb" | "stmfd" | "push" | "pop", _) => {
+                let (rn, wb, regs) = match operands {
+                    [regs] if mnemonic == "push" || mnemonic == "pop" => {
+                        (Reg::sp, true, parse_reg_list(regs)?)
+                    }
+                    [rn, regs] => match rn.strip_suffix('!') {
+                        Some(rn) => (parse_reg(rn)?, true, parse_reg_list(regs)?),
+                        None => (parse_reg(rn)?, false, parse_reg_list(regs)?),
+                    },
+                    _ => return Err(format!("invalid operands for `{}`", mnemonic)),
+                };
+
+                match mnemonic {
+                    "stmdb" | "stmfd" | "push" => Op::Stm(AddrMode::DecrBefore, rn, wb, regs),
+                    _ => Op::Ldm(AddrMode::IncrAfter, rn, wb, regs),
+                }
+            }
+            ("mov", [rd, rm]) => Op::Movr(parse_reg(rd)?, parse_reg(rm)?),
+            ("movt" | "movw", [rd, imm]) => {
+                let imm = u16::try_from(parse_int(imm)?)
+                    .map_err(|_| format!("`{}` doesn't fit 16 bits", imm))?;
+
+                match mnemonic {
+                    "movt" => Op::Movt(parse_reg(rd)?, imm.into()),
+                    _ => Op::Movw(parse_reg(rd)?, imm.into()),
+                }
+            }
+            ("mrs", [rd, psr]) if matches!(&*psr.to_ascii_lowercase(), "apsr" | "cpsr") => {
+                Op::Mrs(parse_reg(rd)?)
+            }
+            ("msr", [psr, rn]) if matches!(&*psr.to_ascii_lowercase(), "apsr_nzcvq" | "cpsr_f") => {
+                Op::Msr(parse_reg(rn)?)
+            }
+            ("ldr", [rt, label]) if !label.starts_with('[') => {
+                let (rt, label) = (parse_reg(rt)?, parse_label(label)?);
+                asm.emit(|asm| asm.ldrl(rt, label));
+                return Ok(());
+            }
+            ("ldr", [rt, mem, post @ ..]) if post.len() <= 1 => {
+                let (indexing, rn, imm) = parse_indexed(mem, post.first().copied())?;
+                let mode = match indexing {
+                    Indexing::Offset => AddrMode2::Offset,
+                    Indexing::Pre => AddrMode2::PreIndexed,
+                    Indexing::Post => AddrMode2::PostIndexed,
+                };
+                let imm =
+                    i32::try_from(imm).map_err(|_| format!("`{}` doesn't fit 32 bits", imm))?;
+
+                Op::Ldri(mode, parse_reg(rt)?, rn, imm)
+            }
+            ("svc", [imm]) => Op::Svc(imm32(imm)?),
+            ("vpop" | "vpush", [list]) => {
+                let regs: Vec<DReg> = parse_reg_list(list)?;
+                let consecutive = regs
+                    .windows(2)
+                    .all(|pair| pair[1] as u32 == pair[0] as u32 + 1);
+
+                match (regs.first(), u8::try_from(regs.len())) {
+                    (Some(first), Ok(count)) if consecutive => match mnemonic {
+                        "vpop" => Op::Vpop(*first, count),
+                        _ => Op::Vpush(*first, count),
+                    },
+                    _ => return Err(format!("invalid register list `{}`", list)),
+                }
+            }
+            _ if MNEMONICS.contains(&mnemonic) => {
+                return Err(format!("invalid operands for `{}`", mnemonic))
+            }
+            _ => return Err(format!("unknown instruction `{}`", mnemonic)),
+        };
+
+        asm.emit(|asm| asm.op(op));
+        Ok(())
+    }
+}
+
+fn imm32(operand: &str) -> Result<u32, String> {
+    u32::try_from(parse_int(operand)?).map_err(|_| format!("`{}` doesn't fit 32 bits", operand))
+}
+
+/// Parses the condition suffix of a mnemonic, e.g. `ne` for `bne` or `movne`.
+fn cond(name: &str) -> Option<Cond> {
+    Some(match name {
+        "eq" => Cond::Eq,
+        "ne" => Cond::Ne,
+        "hs" | "cs" => Cond::Hs,
+        "lo" | "cc" => Cond::Lo,
+        "mi" => Cond::Mi,
+        "pl" => Cond::Pl,
+        "vs" => Cond::Vs,
+        "vc" => Cond::Vc,
+        "hi" => Cond::Hi,
+        "ls" => Cond::Ls,
+        "ge" => Cond::Ge,
+        "lt" => Cond::Lt,
+        "gt" => Cond::Gt,
+        "le" => Cond::Le,
+        "al" | "" => Cond::Al,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arm::TinyAsm;
+
+    /// The expected encodings are the ones emitted by `llvm-mc -triple=armv7`.
+    #[test]
+    fn assembles_like_llvm() {
+        let code = TinyAsm::new()
+            .asm(
+                "
+                push {r4, r5, lr}
+                pop {r0-r3}
+                ldmia r1!, {r2, r3}
+                stmdb sp!, {r4-r6}
+                add r0, r1, #16
+                sub sp, sp, #128
+                and r0, r1, r2
+                cmp r0, #47
+                mov r0, r7
+                movw r7, #192
+                ldr r1, [r2, #4]
+                ldr r1, [r2], #-4
+                ldr r1, [sp, #-8]!
+                svc #0
+                1:
+                bl 1b
+                bleq 1b
+                blx 1b
+                blx r3
+                bx lr
+                bxne lr
+                movne r0, r1
+                addeq r0, r0, #0xff000000
+                cmp r0, r1
+                movt r0, #0x1234
+                vpush {d8-d15}
+                vpop {d8-d15}
+                vpush {d16, d17}
+                vpop {d0}
+                mrs r0, apsr
+                msr apsr_nzcvq, r1
+                ldrne r0, [r1]
+                popne {r4, pc}
+                ",
+            )
+            .build()
+            .unwrap();
+
+        let expected: &[[u8; 4]] = &[
+            [0x30, 0x40, 0x2d, 0xe9],
+            [0x0f, 0x00, 0xbd, 0xe8],
+            [0x0c, 0x00, 0xb1, 0xe8],
+            [0x70, 0x00, 0x2d, 0xe9],
+            [0x10, 0x00, 0x81, 0xe2],
+            [0x80, 0xd0, 0x4d, 0xe2],
+            [0x02, 0x00, 0x01, 0xe0],
+            [0x2f, 0x00, 0x50, 0xe3],
+            [0x07, 0x00, 0xa0, 0xe1],
+            [0xc0, 0x70, 0x00, 0xe3],
+            [0x04, 0x10, 0x92, 0xe5],
+            [0x04, 0x10, 0x12, 0xe4],
+            [0x08, 0x10, 0x3d, 0xe5],
+            [0x00, 0x00, 0x00, 0xef],
+            [0xfe, 0xff, 0xff, 0xeb],
+            [0xfd, 0xff, 0xff, 0x0b],
+            [0xfc, 0xff, 0xff, 0xfa],
+            [0x33, 0xff, 0x2f, 0xe1],
+            [0x1e, 0xff, 0x2f, 0xe1],
+            [0x1e, 0xff, 0x2f, 0x11],
+            [0x01, 0x00, 0xa0, 0x11],
+            [0xff, 0x04, 0x80, 0x02],
+            [0x01, 0x00, 0x50, 0xe1],
+            [0x34, 0x02, 0x41, 0xe3],
+            [0x10, 0x8b, 0x2d, 0xed],
+            [0x10, 0x8b, 0xbd, 0xec],
+            [0x04, 0x0b, 0x6d, 0xed],
+            [0x02, 0x0b, 0xbd, 0xec],
+            [0x00, 0x00, 0x0f, 0xe1],
+            [0x01, 0xf0, 0x28, 0xe1],
+            [0x00, 0x00, 0x91, 0x15],
+            [0x10, 0x80, 0xbd, 0x18],
+        ];
+
+        assert_eq!(code, expected.concat());
+    }
+
+    #[test]
+    fn assembles_like_the_builder() {
+        use crate::arm::{Cond, Reg::*};
+
+        let text = TinyAsm::new()
+            .asm(
+                "
+                loop:
+                    adr r0, path
+                    ldr r1, len
+                    cmp r1, #0
+                    bne loop
+                    b done
+                path: .asciz \"/tmp/lib.so\"
+                    .align 4
+                len: .long 0xffffffff
+                done:
+                ",
+            )
+            .build()
+            .unwrap();
+
+        let builder = TinyAsm::new()
+            .label("loop")
+            .adrl(r0, "path")
+            .ldrl(r1, "len")
+            .cmpi(r1, 0)
+            .b(Cond::Ne, "loop")
+            .b(Cond::Al, "done")
+            .label("path")
+            .asciiz("/tmp/lib.so")
+            .align::<4>()
+            .label("len")
+            .dword(0xffffffff)
+            .label("done")
+            .build()
+            .unwrap();
+
+        assert_eq!(text, builder);
+    }
+}
@@ -0,0 +1,63 @@
+use std::ops::BitOr;
+
+/// The condition codes a conditional branch can be taken under.
+#[derive(Clone, Copy)]
+pub enum Cond {
+    Eq = 0,
+    Ne = 1,
+    Hs = 2,
+    Lo = 3,
+    Mi = 4,
+    Pl = 5,
+    Vs = 6,
+    Vc = 7,
+    Hi = 8,
+    Ls = 9,
+    Ge = 10,
+    Lt = 11,
+    Gt = 12,
+    Le = 13,
+    Al = 14,
+}
+
+impl Cond {
+    /// Gets the condition encoded as the 4 bit field `val`.
+    ///
+    /// Returns [`None`] if it's `0b1111` (`NV`), which is never encoded.
+    pub(crate) fn from_val(val: u32) -> Option<Self> {
+        use Cond::*;
+
+        [Eq, Ne, Hs, Lo, Mi, Pl, Vs, Vc, Hi, Ls, Ge, Lt, Gt, Le, Al]
+            .into_iter()
+            .nth((val & 0xf) as usize)
+    }
+
+    /// Gets the name of the condition, e.g. `eq` for `B.EQ`.
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Cond::Eq => "eq",
+            Cond::Ne => "ne",
+            Cond::Hs => "hs",
+            Cond::Lo => "lo",
+            Cond::Mi => "mi",
+            Cond::Pl => "pl",
+            Cond::Vs => "vs",
+            Cond::Vc => "vc",
+            Cond::Hi => "hi",
+            Cond::Ls => "ls",
+            Cond::Ge => "ge",
+            Cond::Lt => "lt",
+            Cond::Gt => "gt",
+            Cond::Le => "le",
+            Cond::Al => "al",
+        }
+    }
+}
+
+impl BitOr<Cond> for u32 {
+    type Output = u32;
+
+    fn bitor(self, rhs: Cond) -> Self::Output {
+        self | rhs as u32
+    }
+}
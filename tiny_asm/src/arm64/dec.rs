@@ -0,0 +1,379 @@
+use crate::{
+    disasm::{imm, Labels},
+    Decode,
+};
+
+use super::{AddrMode2, Cond, Op, QReg, Reg, Shift, SysReg};
+
+impl Op {
+    /// Decodes `word` into the instruction it's the encoding of, e.g. `0xd2800100` into `Movi(x0, 8)`.
+    /// Label references are decoded into their relative variants, e.g. `Cbnzi` instead of `Cbnzl`.
+    ///
+    /// Returns [`None`] if `word` is not the encoding of an instruction, or it's not the one [`TinyAsm`](super::TinyAsm)
+    /// would emit: encoding the decoded instruction always gives `word` back.
+    pub fn decode(word: u32) -> Option<Self> {
+        // The 31st register is the stack pointer when it's a base or the operand of an immediate addition.
+        let reg = |shift: u32, sp: bool| Reg::from_val(word >> shift, sp);
+        let shifted = || (shift(word), (word >> 10 & 0x3f) as u8);
+
+        Some(match word {
+            _ if word & 0xff800000 == 0x91000000 => {
+                Op::Addi(reg(0, true), reg(5, true), arith_imm(word)?)
+            }
+            _ if word & 0xff800000 == 0xd1000000 => {
+                Op::Subi(reg(0, true), reg(5, true), arith_imm(word)?)
+            }
+            _ if word & 0x9f000000 == 0x10000000 => Op::Adri(
+                reg(0, false),
+                sext((word >> 5 & 0x7ffff) << 2 | word >> 29 & 3, 21),
+            ),
+            _ if word & 0xff200000 == 0x8a000000 => {
+                Op::Andsr(reg(0, false), reg(5, false), reg(16, false), shifted())
+            }
+            _ if word & 0xff200000 == 0xaa000000 => {
+                Op::Orrsr(reg(0, false), reg(5, false), reg(16, false), shifted())
+            }
+            _ if word & 0xfc000000 == 0x14000000 => Op::Bi(sext(word & 0x3ffffff, 26) * 4),
+            _ if word & 0xff000010 == 0x54000000 => {
+                Op::Bcondi(Cond::from_val(word)?, sext(word >> 5 & 0x7ffff, 19) * 4)
+            }
+            _ if word & 0xfc000000 == 0x94000000 => Op::Bli(sext(word & 0x3ffffff, 26) * 4),
+            _ if word & 0xfffffc1f == 0xd63f0000 => Op::Blr(reg(5, false)),
+            _ if word & 0xfffffc1f == 0xd61f0000 => Op::Br(reg(5, false)),
+            _ if word & 0xff000000 == 0xb5000000 => {
+                Op::Cbnzi(reg(0, false), sext(word >> 5 & 0x7ffff, 19) * 4)
+            }
+            _ if word & 0xff000000 == 0xb4000000 => {
+                Op::Cbzi(reg(0, false), sext(word >> 5 & 0x7ffff, 19) * 4)
+            }
+            _ if word & 0xff80001f == 0xf100001f => Op::Cmpi(reg(5, true), arith_imm(word)?),
+            _ if word & 0xffe0fc1f == 0xeb00001f => Op::Cmpr(reg(5, false), reg(16, false)),
+            _ if word & 0xfe400000 == 0xa8400000 => Op::Ldp(
+                pair_mode(word)?,
+                reg(0, false),
+                reg(10, false),
+                reg(5, true),
+                (sext(word >> 15 & 0x7f, 7) * 8) as i16,
+            ),
+            _ if word & 0xfe400000 == 0xa8000000 => Op::Stp(
+                pair_mode(word)?,
+                reg(0, false),
+                reg(10, false),
+                reg(5, true),
+                (sext(word >> 15 & 0x7f, 7) * 8) as i16,
+            ),
+            _ if word & 0xfe400000 == 0xac400000 => Op::Ldpq(
+                pair_mode(word)?,
+                QReg::from_val(word),
+                QReg::from_val(word >> 10),
+                reg(5, true),
+                (sext(word >> 15 & 0x7f, 7) * 16) as i16,
+            ),
+            _ if word & 0xfe400000 == 0xac000000 => Op::Stpq(
+                pair_mode(word)?,
+                QReg::from_val(word),
+                QReg::from_val(word >> 10),
+                reg(5, true),
+                (sext(word >> 15 & 0x7f, 7) * 16) as i16,
+            ),
+            _ if word & 0xffc00000 == 0xf9400000 => Op::Ldri(
+                AddrMode2::Offset,
+                reg(0, false),
+                reg(5, true),
+                (word >> 10 & 0xfff) as i32 * 8,
+            ),
+            _ if word & 0xffe00c00 == 0xf8400c00 => Op::Ldri(
+                AddrMode2::PreIndexed,
+                reg(0, false),
+                reg(5, true),
+                sext(word >> 12 & 0x1ff, 9),
+            ),
+            _ if word & 0xffe00c00 == 0xf8400400 => Op::Ldri(
+                AddrMode2::PostIndexed,
+                reg(0, false),
+                reg(5, true),
+                sext(word >> 12 & 0x1ff, 9),
+            ),
+            _ if word & 0xff000000 == 0x58000000 => {
+                Op::Ldrli(reg(0, false), sext(word >> 5 & 0x7ffff, 19) * 4)
+            }
+            _ if word & 0xffe00000 == 0xd2800000 => {
+                Op::Movi(reg(0, false), (word >> 5 & 0xffff) as i32)
+            }
+            _ if word & 0xffe00000 == 0x92800000 => {
+                Op::Movi(reg(0, false), !((word >> 5 & 0xffff) as i32))
+            }
+            // A MOVZ without shift is decoded as `Movi` instead.
+            _ if word & 0xff800000 == 0xd2800000 => Op::Movz(
+                reg(0, false),
+                (word >> 5 & 0xffff) as u16,
+                (word >> 21 & 3) as u8 * 16,
+            ),
+            _ if word & 0xff800000 == 0xf2800000 => Op::Movk(
+                reg(0, false),
+                (word >> 5 & 0xffff) as u16,
+                (word >> 21 & 3) as u8 * 16,
+            ),
+            _ if word & 0xfff00000 == 0xd5300000 => {
+                Op::Mrs(reg(0, false), SysReg::from_val(word >> 5)?)
+            }
+            _ if word & 0xfff00000 == 0xd5100000 => {
+                Op::Msr(SysReg::from_val(word >> 5)?, reg(0, false))
+            }
+            _ if word & 0xffc00000 == 0xf9000000 => Op::Stri(
+                AddrMode2::Offset,
+                reg(0, false),
+                reg(5, true),
+                (word >> 10 & 0xfff) as i32 * 8,
+            ),
+            _ if word & 0xffe00c00 == 0xf8000c00 => Op::Stri(
+                AddrMode2::PreIndexed,
+                reg(0, false),
+                reg(5, true),
+                sext(word >> 12 & 0x1ff, 9),
+            ),
+            _ if word & 0xffe00c00 == 0xf8000400 => Op::Stri(
+                AddrMode2::PostIndexed,
+                reg(0, false),
+                reg(5, true),
+                sext(word >> 12 & 0x1ff, 9),
+            ),
+            _ if word & 0xffe0001f == 0xd4000001 => Op::Svc((word >> 5 & 0xffff) as u16),
+            _ => return None,
+        })
+    }
+
+    /// Gets the offset the instruction, located at `offset`, refers to.
+    fn target(&self, offset: usize) -> Option<i64> {
+        match self {
+            Op::Adri(_, imm)
+            | Op::Bi(imm)
+            | Op::Bcondi(_, imm)
+            | Op::Bli(imm)
+            | Op::Cbnzi(_, imm)
+            | Op::Cbzi(_, imm)
+            | Op::Ldrli(_, imm) => Some(offset as i64 + i64::from(*imm)),
+            _ => None,
+        }
+    }
+
+    /// Formats the instruction, located at `offset`, in the text syntax.
+    ///
+    /// Returns [`None`] if it's a branch to an offset no label was put at, which can't be written.
+    fn text(&self, offset: usize, labels: &Labels) -> Option<String> {
+        let target = self.target(offset).unwrap_or_default();
+
+        Some(match self {
+            Op::Addi(xd, xn, imm) => format!("add {}, {}, #{}", xd, xn, fmt_imm(*imm)),
+            Op::Adri(xd, _) => format!("adr {}, {}", xd, labels.get(offset, target)?),
+            Op::Adrl(xd, label) => format!("adr {}, {}", xd, label),
+            Op::Andsr(xd, xn, xm, shift) => {
+                format!("and {}, {}, {}{}", xd, xn, xm, fmt_shift(shift))
+            }
+            Op::Bi(_) => format!("b {}", labels.get(offset, target)?),
+            Op::Bl(label) => format!("b {}", label),
+            Op::Bcondi(cond, _) => format!("b.{} {}", cond.name(), labels.get(offset, target)?),
+            Op::Bcondl(cond, label) => format!("b.{} {}", cond.name(), label),
+            Op::Bli(_) => format!("bl {}", labels.get(offset, target)?),
+            Op::Bll(label) => format!("bl {}", label),
+            Op::Blr(xn) => format!("blr {}", xn),
+            Op::Br(xn) => format!("br {}", xn),
+            Op::Cbnzi(xt, _) => format!("cbnz {}, {}", xt, labels.get(offset, target)?),
+            Op::Cbnzl(xt, label) => format!("cbnz {}, {}", xt, label),
+            Op::Cbzi(xt, _) => format!("cbz {}, {}", xt, labels.get(offset, target)?),
+            Op::Cbzl(xt, label) => format!("cbz {}, {}", xt, label),
+            Op::Cmpi(xn, imm) => format!("cmp {}, #{}", xn, fmt_imm(*imm)),
+            Op::Cmpr(xn, xm) => format!("cmp {}, {}", xn, xm),
+            Op::Ldp(mode, xt1, xt2, xn, imm) => {
+                format!("ldp {}, {}, {}", xt1, xt2, fmt_mem(mode, xn, *imm))
+            }
+            Op::Ldpq(mode, qt1, qt2, xn, imm) => {
+                format!("ldp {}, {}, {}", qt1, qt2, fmt_mem(mode, xn, *imm))
+            }
+            Op::Ldri(mode, xt, xn, imm) => format!("ldr {}, {}", xt, fmt_mem(mode, xn, *imm)),
+            Op::Ldrl(xt, label) => format!("ldr {}, {}", xt, label),
+            Op::Ldrli(xt, _) => format!("ldr {}, {}", xt, labels.get(offset, target)?),
+            Op::Movi(xd, imm) => format!("mov {}, #{}", xd, fmt_imm(*imm)),
+            Op::Movk(xd, imm, shift) => {
+                format!("movk {}, #{}{}", xd, fmt_imm(*imm), fmt_lsl(*shift))
+            }
+            Op::Movz(xd, imm, shift) => {
+                format!("movz {}, #{}{}", xd, fmt_imm(*imm), fmt_lsl(*shift))
+            }
+            Op::Mrs(xt, sys_reg) => format!("mrs {}, {}", xt, sys_reg.name()),
+            Op::Msr(sys_reg, xt) => format!("msr {}, {}", sys_reg.name(), xt),
+            Op::Orrsr(xd, Reg::xzr, xm, (Shift::Lsl, 0)) => format!("mov {}, {}", xd, xm),
+            Op::Orrsr(xd, xn, xm, shift) => {
+                format!("orr {}, {}, {}{}", xd, xn, xm, fmt_shift(shift))
+            }
+            Op::Stp(mode, xt1, xt2, xn, imm) => {
+                format!("stp {}, {}, {}", xt1, xt2, fmt_mem(mode, xn, *imm))
+            }
+            Op::Stpq(mode, qt1, qt2, xn, imm) => {
+                format!("stp {}, {}, {}", qt1, qt2, fmt_mem(mode, xn, *imm))
+            }
+            Op::Stri(mode, xt, xn, imm) => format!("str {}, {}", xt, fmt_mem(mode, xn, *imm)),
+            Op::Subi(xd, xn, imm) => format!("sub {}, {}, #{}", xd, xn, fmt_imm(*imm)),
+            Op::Svc(imm) => format!("svc #{}", fmt_imm(*imm)),
+            Op::Placeholder => ".long 0".into(),
+        })
+    }
+}
+
+impl Decode for Op {
+    fn decode(code: &[u8], offset: usize, labels: &Labels) -> Option<(usize, String)> {
+        if !offset.is_multiple_of(4) {
+            return None;
+        }
+
+        let word = u32::from_le_bytes(code.get(..4)?.try_into().ok()?);
+        let op = Op::decode(word)?;
+
+        match op.target(offset) {
+            Some(target) if !(0..=(offset + code.len()) as i64).contains(&target) => None,
+            _ => Some((4, op.text(offset, labels)?)),
+        }
+    }
+}
+
+/// Sign-extends the `bits` bits field `val`.
+fn sext(val: u32, bits: u32) -> i32 {
+    ((val << (32 - bits)) as i32) >> (32 - bits)
+}
+
+/// Decodes the `sh:imm12` field of an arithmetic immediate.
+///
+/// Returns [`None`] if it's a zero shifted left by 12, which is never encoded.
+fn arith_imm(word: u32) -> Option<u32> {
+    let imm = word >> 10 & 0xfff;
+
+    match word >> 22 & 1 {
+        0 => Some(imm),
+        _ if imm == 0 => None,
+        _ => Some(imm << 12),
+    }
+}
+
+fn shift(word: u32) -> Shift {
+    match word >> 22 & 3 {
+        0 => Shift::Lsl,
+        1 => Shift::Lsr,
+        2 => Shift::Asr,
+        _ => Shift::Ror,
+    }
+}
+
+/// Decodes the addressing mode of a `LDP` or `STP`.
+///
+/// Returns [`None`] if it's the non-temporal variant (`LDNP`, `STNP`), which is not encoded.
+fn pair_mode(word: u32) -> Option<AddrMode2> {
+    match word >> 23 & 3 {
+        1 => Some(AddrMode2::PostIndexed),
+        2 => Some(AddrMode2::Offset),
+        3 => Some(AddrMode2::PreIndexed),
+        _ => None,
+    }
+}
+
+fn fmt_shift((shift, amount): &(Shift, u8)) -> String {
+    let name = match shift {
+        Shift::Lsl if *amount == 0 => return String::new(),
+        Shift::Lsl => "lsl",
+        Shift::Lsr => "lsr",
+        Shift::Asr => "asr",
+        Shift::Ror => "ror",
+    };
+
+    format!(", {} #{}", name, amount)
+}
+
+fn fmt_lsl(shift: u8) -> String {
+    match shift {
+        0 => String::new(),
+        shift => format!(", lsl #{}", shift),
+    }
+}
+
+fn fmt_mem(mode: &AddrMode2, xn: &Reg, imm: impl Into<i64>) -> String {
+    match (mode, imm.into()) {
+        (AddrMode2::Offset, 0) => format!("[{}]", xn),
+        (AddrMode2::Offset, imm) => format!("[{}, #{}]", xn, fmt_imm(imm)),
+        (AddrMode2::PreIndexed, imm) => format!("[{}, #{}]!", xn, fmt_imm(imm)),
+        (AddrMode2::PostIndexed, imm) => format!("[{}], #{}", xn, fmt_imm(imm)),
+    }
+}
+
+fn fmt_imm(value: impl Into<i64>) -> String {
+    imm(value.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        arm64::{AddrMode2::*, Cond, Op, QReg::*, Reg::*, Shift, SysReg, TinyAsm},
+        disassemble,
+    };
+
+    #[test]
+    fn decodes_what_it_encodes() {
+        let (code, labels) = TinyAsm::new()
+            .stp(PreIndexed, x0, x1, sp, -16)
+            .label("loop")
+            .adr(x0, "path")
+            .ldrl(x1, "len")
+            .ldri(Offset, x2, sp, 8)
+            .ldri(PostIndexed, x2, x3, -8)
+            .stri(PreIndexed, x2, sp, -16)
+            .stri(Offset, xzr, x0, 0)
+            .addi(sp, sp, 16)
+            .subi(x0, x1, 0xfff)
+            .andsr(x0, x1, x2, Some((Shift::Lsr, 3)))
+            .orrsr(x0, x1, x2, None)
+            .movr(x28, x0)
+            .movi(x8, 222)
+            .movi(x0, -1)
+            .cbnz(x0, "loop")
+            .cbz(x1, "path")
+            .b("loop")
+            .bl("loop")
+            .bcond(Cond::Hs, "loop")
+            .addi(x0, x0, 0x10000)
+            .cmpi(x0, 0xfff)
+            .cmpr(sp, x1)
+            .movi64(x9, 0x1122_3344_5566_7788)
+            .movz(x0, 1, 32)
+            .mrs(x0, SysReg::nzcv)
+            .msr(SysReg::tpidr_el0, x1)
+            .stpq(PreIndexed, q0, q1, sp, -32)
+            .ldpq(Offset, q2, q31, x0, 1008)
+            .blr(x1)
+            .br(x28)
+            .svc(0)
+            .ldp(PostIndexed, x0, x1, sp, 16)
+            .label("path")
+            .asciiz("/tmp/lib.so")
+            .align::<8>()
+            .label("len")
+            .qword(u64::MAX)
+            .build_with_labels()
+            .unwrap();
+
+        let listing = disassemble::<Op>(&code, &labels);
+
+        assert!(listing.contains("cbnz x0, loop"), "{}", listing);
+        assert_eq!(
+            TinyAsm::new().asm(&listing).build(),
+            Ok(code),
+            "{}",
+            listing
+        );
+    }
+
+    #[test]
+    fn rejects_what_it_does_not_encode() {
+        // LDNP, MOVN with a shifted immediate, a 32 bit ORR, a zero shifted by 12 and B.NV.
+        for word in [0xa8400000, 0x92a00000, 0x2a0103e0, 0x91400000, 0x5400000f] {
+            assert!(Op::decode(word).is_none(), "{:#x}", word);
+        }
+    }
+}
@@ -1,23 +1,63 @@
 mod addr_mode_2;
+mod cond;
+mod dec;
 mod op;
+mod q_reg;
 mod reg;
 mod shift;
+mod syntax;
+mod sys_reg;
 
 pub use addr_mode_2::AddrMode2;
+pub use cond::Cond;
 pub use op::Op;
+pub use q_reg::QReg;
 pub use reg::Reg;
 pub use shift::Shift;
+pub use sys_reg::SysReg;
 
 use super::Label;
 
 /// https://developer.arm.com/documentation/ddi0596/2021-09/Base-Instructions
 impl TinyAsm {
+    /// Encoding of ADD (immediate): `ADD <Xd|SP>, <Xn|SP>, #<uimm12>{, LSL #12}`.
+    pub fn addi(self, xd: Reg, xn: Reg, imm: u32) -> Self {
+        self.op(Op::Addi(xd, xn, imm))
+    }
+
     /// Encoding of ADR: `ADR <Xd>, <label>`,
-    pub fn adr(mut self, xd: Reg, label: Label) -> Self {
+    pub fn adr(mut self, xd: Reg, label: impl Into<Label>) -> Self {
+        let label = self.refer(label);
         self.relocs.push((self.buf.len(), Op::Adrl(xd, label)));
         self.op(Op::Placeholder)
     }
 
+    /// Encoding of AND (Shifted Register): `AND <Xd>, <Xn>, <Xm>{, <shift> #<amount>}`.
+    pub fn andsr(self, xd: Reg, xn: Reg, xm: Reg, shift: Option<(Shift, u8)>) -> Self {
+        self.op(Op::Andsr(xd, xn, xm, shift.unwrap_or((Shift::Lsl, 0))))
+    }
+
+    /// Encoding of B: `B <label>`.
+    pub fn b(mut self, label: impl Into<Label>) -> Self {
+        let label = self.refer(label);
+        self.relocs.push((self.buf.len(), Op::Bl(label)));
+        self.op(Op::Placeholder)
+    }
+
+    /// Encoding of B.cond: `B.<cond> <label>`.
+    pub fn bcond(mut self, cond: Cond, label: impl Into<Label>) -> Self {
+        let label = self.refer(label);
+        self.relocs.push((self.buf.len(), Op::Bcondl(cond, label)));
+        self.op(Op::Placeholder)
+    }
+
+    /// Encoding of BL: `BL <label>`.
+    pub fn bl(mut self, label: impl Into<Label>) -> Self {
+        let label = self.refer(label);
+        self.relocs.push((self.buf.len(), Op::Bll(label)));
+        self.op(Op::Placeholder)
+    }
+
     /// Encoding of BLR: `BLR <Xn>`.
     pub fn blr(self, xn: Reg) -> Self {
         self.op(Op::Blr(xn))
@@ -28,18 +68,48 @@ impl TinyAsm {
         self.op(Op::Br(xn))
     }
 
+    /// Encoding of CBNZ: `CBNZ <Xt>, <label>`.
+    pub fn cbnz(mut self, xt: Reg, label: impl Into<Label>) -> Self {
+        let label = self.refer(label);
+        self.relocs.push((self.buf.len(), Op::Cbnzl(xt, label)));
+        self.op(Op::Placeholder)
+    }
+
+    /// Encoding of CBZ: `CBZ <Xt>, <label>`.
+    pub fn cbz(mut self, xt: Reg, label: impl Into<Label>) -> Self {
+        let label = self.refer(label);
+        self.relocs.push((self.buf.len(), Op::Cbzl(xt, label)));
+        self.op(Op::Placeholder)
+    }
+
+    /// Encoding of CMP (immediate): `CMP <Xn|SP>, #<uimm12>{, LSL #12}`.
+    pub fn cmpi(self, xn: Reg, imm: u32) -> Self {
+        self.op(Op::Cmpi(xn, imm))
+    }
+
+    /// Encoding of CMP (shifted register): `CMP <Xn>, <Xm>`.
+    pub fn cmpr(self, xn: Reg, xm: Reg) -> Self {
+        self.op(Op::Cmpr(xn, xm))
+    }
+
     /// Encoding of LDP: `LDP <Xt1>, <Xt2>, [<Xn|SP>], #<imm>`, `LDP <Xt1>, <Xt2>, [<Xn|SP>, #<imm>]!`, `LDP <Xt1>, <Xt2>, [<Xn|SP>{, #<imm>}]`.
     pub fn ldp(self, mode: AddrMode2, xt1: Reg, xt2: Reg, xn: Reg, imm: i16) -> Self {
         self.op(Op::Ldp(mode, xt1, xt2, xn, imm))
     }
 
+    /// Encoding of LDP (SIMD&FP): `LDP <Qt1>, <Qt2>, [<Xn|SP>], #<imm>`, `LDP <Qt1>, <Qt2>, [<Xn|SP>, #<imm>]!`, `LDP <Qt1>, <Qt2>, [<Xn|SP>{, #<imm>}]`.
+    pub fn ldpq(self, mode: AddrMode2, qt1: QReg, qt2: QReg, xn: Reg, imm: i16) -> Self {
+        self.op(Op::Ldpq(mode, qt1, qt2, xn, imm))
+    }
+
     /// Encoding of LDR (immediate): `LDR <Xt>, [<Xn|SP>], #<simm>`, `LDR <Xt>, [<Xn|SP>, #<simm>]!`, `LDR <Xt>, [<Xn|SP>{, #<pimm>}]`.
     pub fn ldri(self, mode: AddrMode2, xt: Reg, xn: Reg, imm: i32) -> Self {
         self.op(Op::Ldri(mode, xt, xn, imm))
     }
 
     /// Encoding of LDR (literal): `LDR <Xt>, <label>`.
-    pub fn ldrl(mut self, xt: Reg, label: Label) -> Self {
+    pub fn ldrl(mut self, xt: Reg, label: impl Into<Label>) -> Self {
+        let label = self.refer(label);
         self.relocs.push((self.buf.len(), Op::Ldrl(xt, label)));
         self.op(Op::Placeholder)
     }
@@ -54,6 +124,43 @@ impl TinyAsm {
         self.op(Op::Movi(xd, imm))
     }
 
+    /// Encoding of MOV (wide immediate) for a 64 bit immediate: `MOVZ <Xd>, #<imm16>, LSL #<shift>`, followed by
+    /// `MOVK <Xd>, #<imm16>, LSL #<shift>` for every other non-zero halfword.
+    pub fn movi64(mut self, xd: Reg, imm: u64) -> Self {
+        let halfword = |shift: u8| (imm >> shift) as u16;
+        let mut shifts = [0, 16, 32, 48]
+            .into_iter()
+            .filter(|shift| halfword(*shift) != 0);
+
+        let first = shifts.next().unwrap_or(0);
+        self = self.movz(xd, halfword(first), first);
+
+        for shift in shifts {
+            self = self.movk(xd, halfword(shift), shift);
+        }
+        self
+    }
+
+    /// Encoding of MOVK: `MOVK <Xd>, #<imm16>{, LSL #<shift>}`.
+    pub fn movk(self, xd: Reg, imm: u16, shift: u8) -> Self {
+        self.op(Op::Movk(xd, imm, shift))
+    }
+
+    /// Encoding of MOVZ: `MOVZ <Xd>, #<imm16>{, LSL #<shift>}`.
+    pub fn movz(self, xd: Reg, imm: u16, shift: u8) -> Self {
+        self.op(Op::Movz(xd, imm, shift))
+    }
+
+    /// Encoding of MRS: `MRS <Xt>, <systemreg>`.
+    pub fn mrs(self, xt: Reg, sys_reg: SysReg) -> Self {
+        self.op(Op::Mrs(xt, sys_reg))
+    }
+
+    /// Encoding of MSR (register): `MSR <systemreg>, <Xt>`.
+    pub fn msr(self, sys_reg: SysReg, xt: Reg) -> Self {
+        self.op(Op::Msr(sys_reg, xt))
+    }
+
     /// Encoding of ORR (Shifted Register): `ORR <Xd>, <Xn>, <Xm>{, <shift> #<amount>}`.
     pub fn orrsr(self, xd: Reg, xn: Reg, xm: Reg, shift: Option<(Shift, u8)>) -> Self {
         self.op(Op::Orrsr(xd, xn, xm, shift.unwrap_or((Shift::Lsl, 0))))
@@ -64,11 +171,21 @@ impl TinyAsm {
         self.op(Op::Stp(mode, xt1, xt2, xn, imm))
     }
 
+    /// Encoding of STP (SIMD&FP): `STP <Qt1>, <Qt2>, [<Xn|SP>], #<imm>`, `STP <Qt1>, <Qt2>, [<Xn|SP>, #<imm>]!`, `STP <Qt1>, <Qt2>, [<Xn|SP>{, #<imm>}]`.
+    pub fn stpq(self, mode: AddrMode2, qt1: QReg, qt2: QReg, xn: Reg, imm: i16) -> Self {
+        self.op(Op::Stpq(mode, qt1, qt2, xn, imm))
+    }
+
     /// Encoding of STR (immediate): `STR <Xt>, [<Xn|SP>], #<simm>`, `STR <Xt>, [<Xn|SP>, #<simm>]!`, `STR <Xt>, [<Xn|SP>{, #<pimm>}]`.
     pub fn stri(self, mode: AddrMode2, xt: Reg, xn: Reg, imm: i32) -> Self {
         self.op(Op::Stri(mode, xt, xn, imm))
     }
 
+    /// Encoding of SUB (immediate): `SUB <Xd|SP>, <Xn|SP>, #<uimm12>{, LSL #12}`.
+    pub fn subi(self, xd: Reg, xn: Reg, imm: u32) -> Self {
+        self.op(Op::Subi(xd, xn, imm))
+    }
+
     /// Encoding of SVC: `SVC #<imm16>`.
     pub fn svc(self, imm: u16) -> Self {
         self.op(Op::Svc(imm))
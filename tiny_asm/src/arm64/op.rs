@@ -1,119 +1,326 @@
 use std::collections::HashMap;
 
-use crate::{Encodable, Label};
+use crate::{
+    encodable::{signed, unsigned},
+    AsmError, Encodable, Label,
+};
 
-use super::{AddrMode2, Reg, Shift};
+use super::{AddrMode2, Cond, QReg, Reg, Shift, SysReg};
 
 pub enum Op {
+    Addi(Reg, Reg, u32),
     Adri(Reg, i32),
     Adrl(Reg, Label),
+    Andsr(Reg, Reg, Reg, (Shift, u8)),
+    Bi(i32),
+    Bl(Label),
+    Bcondi(Cond, i32),
+    Bcondl(Cond, Label),
+    Bli(i32),
+    Bll(Label),
     Blr(Reg),
     Br(Reg),
+    Cbnzi(Reg, i32),
+    Cbnzl(Reg, Label),
+    Cbzi(Reg, i32),
+    Cbzl(Reg, Label),
+    Cmpi(Reg, u32),
+    Cmpr(Reg, Reg),
     Ldp(AddrMode2, Reg, Reg, Reg, i16),
+    Ldpq(AddrMode2, QReg, QReg, Reg, i16),
     Ldri(AddrMode2, Reg, Reg, i32),
     Ldrl(Reg, Label),
     Ldrli(Reg, i32),
     Movi(Reg, i32),
+    Movk(Reg, u16, u8),
+    Movz(Reg, u16, u8),
+    Mrs(Reg, SysReg),
+    Msr(SysReg, Reg),
     Orrsr(Reg, Reg, Reg, (Shift, u8)),
     Stp(AddrMode2, Reg, Reg, Reg, i16),
+    Stpq(AddrMode2, QReg, QReg, Reg, i16),
     Stri(AddrMode2, Reg, Reg, i32),
+    Subi(Reg, Reg, u32),
     Svc(u16),
     Placeholder,
 }
 
-impl From<Op> for u32 {
-    fn from(op: Op) -> u32 {
-        match op {
+impl TryFrom<Op> for u32 {
+    type Error = AsmError;
+
+    fn try_from(op: Op) -> Result<u32, AsmError> {
+        Ok(match op {
+            Op::Addi(xd, xn, imm) => 0x91000000 | arith_imm(imm)? << 10 | xn << 5 | xd,
             Op::Adri(xd, imm) => {
-                if imm < 0 {
-                    Op::Adri(xd, (1 << 21) + imm).into()
-                } else {
-                    // TODO: this is @$^!@ but works
-                    (((((imm as u32) << 1) + 1) % 8) << 28) | ((imm as u32) >> 2) << 5 | xd
-                }
+                let imm = signed(imm.into(), 21, 1)?;
+                0x10000000 | (imm & 3) << 29 | (imm >> 2) << 5 | xd
             }
+            Op::Andsr(xd, xn, xm, (shift, amount)) => {
+                0x8a000000
+                    | shift << 22
+                    | xm << 16
+                    | unsigned(amount.into(), 6, 1)? << 10
+                    | xn << 5
+                    | xd
+            }
+            Op::Bi(imm) => 0x14000000 | signed(imm.into(), 26, 4)?,
+            Op::Bcondi(cond, imm) => 0x54000000 | signed(imm.into(), 19, 4)? << 5 | cond,
+            Op::Bli(imm) => 0x94000000 | signed(imm.into(), 26, 4)?,
             Op::Blr(xn) => 0xd63f0000 | xn << 5,
             Op::Br(xn) => 0xd61f0000 | xn << 5,
+            Op::Cbnzi(xt, imm) => 0xb5000000 | signed(imm.into(), 19, 4)? << 5 | xt,
+            Op::Cbzi(xt, imm) => 0xb4000000 | signed(imm.into(), 19, 4)? << 5 | xt,
+            // SUBS with the zero register as destination.
+            Op::Cmpi(xn, imm) => 0xf100001f | arith_imm(imm)? << 10 | xn << 5,
+            Op::Cmpr(xn, xm) => 0xeb00001f | xm << 16 | xn << 5,
             Op::Ldp(mode, xt1, xt2, xn, imm) => {
-                if imm < 0 {
-                    Op::Ldp(mode, xt1, xt2, xn, 1024 + imm).into()
-                } else {
-                    let mode = match mode {
-                        AddrMode2::Offset => 2,
-                        AddrMode2::PreIndexed => 3,
-                        AddrMode2::PostIndexed => 1,
-                    };
-                    0xa8400000 | mode << 23 | (imm as u32 >> 3) << 15 | xt2 << 10 | xn << 5 | xt1
-                }
+                0xa8400000
+                    | pair_mode(mode) << 23
+                    | signed(imm.into(), 7, 8)? << 15
+                    | xt2 << 10
+                    | xn << 5
+                    | xt1
             }
-            Op::Ldri(mode, xt, xn, imm) => {
-                if imm < 0 {
-                    Op::Ldri(mode, xt, xn, 512 + imm).into()
-                } else {
-                    match mode {
-                        AddrMode2::Offset => 0xf9400000 | (imm as u32) << 10 | xn << 5 | xt,
-                        AddrMode2::PreIndexed => 0xf8400c00 | (imm as u32) << 12 | xn << 5 | xt,
-                        AddrMode2::PostIndexed => 0xf8400400 | (imm as u32) << 12 | xn << 5 | xt,
-                    }
-                }
+            Op::Ldpq(mode, qt1, qt2, xn, imm) => {
+                0xac400000
+                    | pair_mode(mode) << 23
+                    | signed(imm.into(), 7, 16)? << 15
+                    | qt2 << 10
+                    | xn << 5
+                    | qt1
             }
-            Op::Ldrli(xt, imm) => {
-                if imm < 0 {
-                    Op::Ldrli(xt, (1 << 21) + imm).into()
-                } else {
-                    0x58000000 | (imm as u32) << 3 | xt
+            Op::Ldri(mode, xt, xn, imm) => match mode {
+                AddrMode2::Offset => 0xf9400000 | unsigned(imm.into(), 12, 8)? << 10 | xn << 5 | xt,
+                AddrMode2::PreIndexed => {
+                    0xf8400c00 | signed(imm.into(), 9, 1)? << 12 | xn << 5 | xt
                 }
-            }
+                AddrMode2::PostIndexed => {
+                    0xf8400400 | signed(imm.into(), 9, 1)? << 12 | xn << 5 | xt
+                }
+            },
+            Op::Ldrli(xt, imm) => 0x58000000 | signed(imm.into(), 19, 4)? << 5 | xt,
             Op::Movi(xd, imm) => {
                 if imm < 0 {
-                    0x40000020 ^ u32::from(Op::Movi(xd, -imm))
+                    // MOVN, which moves the bitwise inverse of its immediate.
+                    0x92800000 | unsigned((!imm).into(), 16, 1)? << 5 | xd
                 } else {
-                    0xd2800000 | (imm as u32) << 5 | xd
+                    0xd2800000 | unsigned(imm.into(), 16, 1)? << 5 | xd
                 }
             }
+            Op::Movk(xd, imm, shift) => {
+                0xf2800000 | wide_shift(shift)? << 21 | (imm as u32) << 5 | xd
+            }
+            Op::Movz(xd, imm, shift) => {
+                0xd2800000 | wide_shift(shift)? << 21 | (imm as u32) << 5 | xd
+            }
+            Op::Mrs(xt, sys_reg) => 0xd5200000 | sys_reg << 5 | xt,
+            Op::Msr(sys_reg, xt) => 0xd5000000 | sys_reg << 5 | xt,
             Op::Orrsr(xd, xn, xm, (shift, amount)) => {
-                0xaa000000 | shift << 22 | xm << 16 | (amount as u32) << 10 | xn << 5 | xd
+                0xaa000000
+                    | shift << 22
+                    | xm << 16
+                    | unsigned(amount.into(), 6, 1)? << 10
+                    | xn << 5
+                    | xd
             }
             Op::Stp(mode, xt1, xt2, xn, imm) => {
-                if imm < 0 {
-                    Op::Stp(mode, xt1, xt2, xn, 1024 + imm).into()
-                } else {
-                    let mode = match mode {
-                        AddrMode2::Offset => 2,
-                        AddrMode2::PreIndexed => 3,
-                        AddrMode2::PostIndexed => 1,
-                    };
-                    0xa8000000 | mode << 23 | (imm as u32 >> 3) << 15 | xt2 << 10 | xn << 5 | xt1
-                }
+                0xa8000000
+                    | pair_mode(mode) << 23
+                    | signed(imm.into(), 7, 8)? << 15
+                    | xt2 << 10
+                    | xn << 5
+                    | xt1
             }
-            Op::Stri(mode, xt, xn, imm) => {
-                if imm < 0 {
-                    Op::Stri(mode, xt, xn, 512 + imm).into()
-                } else {
-                    match mode {
-                        AddrMode2::Offset => 0xf9000000 | (imm as u32) << 10 | xn << 5 | xt,
-                        AddrMode2::PreIndexed => 0xf8000c00 | (imm as u32) << 12 | xn << 5 | xt,
-                        AddrMode2::PostIndexed => 0xf8000400 | (imm as u32) << 12 | xn << 5 | xt,
-                    }
-                }
+            Op::Stpq(mode, qt1, qt2, xn, imm) => {
+                0xac000000
+                    | pair_mode(mode) << 23
+                    | signed(imm.into(), 7, 16)? << 15
+                    | qt2 << 10
+                    | xn << 5
+                    | qt1
             }
+            Op::Stri(mode, xt, xn, imm) => match mode {
+                AddrMode2::Offset => 0xf9000000 | unsigned(imm.into(), 12, 8)? << 10 | xn << 5 | xt,
+                AddrMode2::PreIndexed => {
+                    0xf8000c00 | signed(imm.into(), 9, 1)? << 12 | xn << 5 | xt
+                }
+                AddrMode2::PostIndexed => {
+                    0xf8000400 | signed(imm.into(), 9, 1)? << 12 | xn << 5 | xt
+                }
+            },
+            Op::Subi(xd, xn, imm) => 0xd1000000 | arith_imm(imm)? << 10 | xn << 5 | xd,
             Op::Svc(imm) => 0xd4000001 | (imm as u32) << 5,
             _ => 0,
-        }
+        })
     }
 }
 
 impl Encodable<4> for Op {
-    fn enc(self, offset: usize, labels: &HashMap<Label, usize>) -> [u8; 4] {
-        u32::from(match self {
-            Op::Adrl(xd, label) => Op::Adri(xd, Self::res_lab(label, labels, offset)),
-            Op::Ldrl(xt, label) => Op::Ldrli(xt, Self::res_lab(label, labels, offset)),
+    fn enc(self, offset: usize, labels: &HashMap<Label, usize>) -> Result<[u8; 4], AsmError> {
+        u32::try_from(match self {
+            Op::Adrl(xd, label) => Op::Adri(xd, Self::res_lab(label, labels, offset)?),
+            Op::Bl(label) => Op::Bi(Self::res_lab(label, labels, offset)?),
+            Op::Bcondl(cond, label) => Op::Bcondi(cond, Self::res_lab(label, labels, offset)?),
+            Op::Bll(label) => Op::Bli(Self::res_lab(label, labels, offset)?),
+            Op::Cbnzl(xt, label) => Op::Cbnzi(xt, Self::res_lab(label, labels, offset)?),
+            Op::Cbzl(xt, label) => Op::Cbzi(xt, Self::res_lab(label, labels, offset)?),
+            Op::Ldrl(xt, label) => Op::Ldrli(xt, Self::res_lab(label, labels, offset)?),
             op => op,
         })
-        .to_le_bytes()
+        .map(u32::to_le_bytes)
     }
 
     fn calc_offset(instr_offset: i32, label_offset: i32) -> i32 {
         label_offset - instr_offset
     }
 }
+
+/// Encodes the `opc` field of the `LDP` and `STP` addressing modes.
+fn pair_mode(mode: AddrMode2) -> u32 {
+    match mode {
+        AddrMode2::Offset => 2,
+        AddrMode2::PreIndexed => 3,
+        AddrMode2::PostIndexed => 1,
+    }
+}
+
+/// Encodes the `sh:imm12` field of an arithmetic immediate, which is either a 12 bit one or a 12 bit one
+/// shifted left by 12.
+fn arith_imm(imm: u32) -> Result<u32, AsmError> {
+    match imm {
+        0..=0xfff => Ok(imm),
+        _ if imm.trailing_zeros() >= 12 && imm >> 12 <= 0xfff => Ok(1 << 12 | imm >> 12),
+        _ => Err(AsmError::ImmOutOfRange(imm.into())),
+    }
+}
+
+/// Encodes the `hw` field of a wide immediate move, whose `shift` is either 0, 16, 32 or 48.
+fn wide_shift(shift: u8) -> Result<u32, AsmError> {
+    match shift {
+        0 | 16 | 32 | 48 => Ok((shift / 16).into()),
+        _ => Err(AsmError::InvalidOperand(
+            "the shift must be either 0, 16, 32 or 48",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        arm64::{AddrMode2::*, Cond, QReg::*, Reg::*, SysReg, TinyAsm},
+        AsmError,
+    };
+
+    /// The expected encodings are the ones emitted by `llvm-mc -triple=aarch64`.
+    #[test]
+    fn encodes_like_llvm() {
+        let code = TinyAsm::new()
+            .label("1")
+            .b("1b")
+            .bl("1b")
+            .bcond(Cond::Ne, "1b")
+            .bcond(Cond::Hs, "1b")
+            .cbz(x3, "1b")
+            .addi(x0, x1, 4096)
+            .subi(sp, sp, 0x10000)
+            .cmpi(x0, 42)
+            .cmpi(sp, 4096)
+            .cmpr(x1, x2)
+            .movz(x0, 0x1234, 16)
+            .movk(x0, 0xbeef, 48)
+            .movk(x1, 7, 0)
+            .mrs(x0, SysReg::nzcv)
+            .msr(SysReg::nzcv, x1)
+            .mrs(x2, SysReg::fpcr)
+            .msr(SysReg::fpsr, x3)
+            .mrs(x4, SysReg::tpidr_el0)
+            .stpq(PreIndexed, q0, q1, sp, -32)
+            .ldpq(PostIndexed, q30, q31, sp, 32)
+            .stpq(Offset, q2, q3, x0, 64)
+            .build()
+            .unwrap();
+
+        let expected: &[u32] = &[
+            0x14000000, 0x97ffffff, 0x54ffffc1, 0x54ffffa2, 0xb4ffff83, 0x91400420, 0xd14043ff,
+            0xf100a81f, 0xf14007ff, 0xeb02003f, 0xd2a24680, 0xf2f7dde0, 0xf28000e1, 0xd53b4200,
+            0xd51b4201, 0xd53b4402, 0xd51b4423, 0xd53bd044, 0xadbf07e0, 0xacc17ffe, 0xad020c02,
+        ];
+
+        assert_eq!(
+            code,
+            expected
+                .iter()
+                .flat_map(|word| word.to_le_bytes())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn encodes_64_bit_immediates() {
+        let code = TinyAsm::new()
+            .movi64(x0, 0)
+            .movi64(x1, 0xdead_0000_beef_0000)
+            .build()
+            .unwrap();
+
+        let expected: &[u32] = &[0xd2800000, 0xd2b7dde1, 0xf2fbd5a1];
+
+        assert_eq!(
+            code,
+            expected
+                .iter()
+                .flat_map(|word| word.to_le_bytes())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn encodes_negative_movi() {
+        let code = TinyAsm::new().movi(x0, -1).movi(x1, -2).build().unwrap();
+
+        assert_eq!(code[..4], 0x92800000_u32.to_le_bytes());
+        assert_eq!(code[4..], 0x92800021_u32.to_le_bytes());
+    }
+
+    #[test]
+    fn rejects_out_of_range_immediates() {
+        let build = |asm: TinyAsm| asm.build();
+
+        assert_eq!(
+            build(TinyAsm::new().movi(x0, 0x10000)),
+            Err(AsmError::ImmOutOfRange(0x10000))
+        );
+        assert_eq!(
+            build(TinyAsm::new().ldp(PostIndexed, x0, x1, sp, 512)),
+            Err(AsmError::ImmOutOfRange(512))
+        );
+        assert_eq!(
+            build(TinyAsm::new().ldri(PreIndexed, x0, sp, -257)),
+            Err(AsmError::ImmOutOfRange(-257))
+        );
+        assert_eq!(
+            build(TinyAsm::new().stp(PreIndexed, x0, x1, sp, -12)),
+            Err(AsmError::Misaligned(-12))
+        );
+        assert_eq!(
+            build(TinyAsm::new().addi(x0, x0, 0x1001)),
+            Err(AsmError::ImmOutOfRange(0x1001))
+        );
+        assert!(matches!(
+            build(TinyAsm::new().movk(x0, 1, 8)),
+            Err(AsmError::InvalidOperand(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_unreachable_literal() {
+        let result = TinyAsm::new()
+            .ldrl(x0, "literal")
+            .bytes(&vec![0; 1 << 20])
+            .label("literal")
+            .qword(0)
+            .build();
+
+        assert_eq!(result, Err(AsmError::ImmOutOfRange((1 << 20) + 4)));
+    }
+}
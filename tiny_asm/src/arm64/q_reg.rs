@@ -0,0 +1,89 @@
+use std::{
+    fmt,
+    ops::{BitOr, Shl},
+    str::FromStr,
+};
+
+/// The 128 bit SIMD and floating-point registers.
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, PartialEq)]
+pub enum QReg {
+    q0 = 0,
+    q1 = 1,
+    q2 = 2,
+    q3 = 3,
+    q4 = 4,
+    q5 = 5,
+    q6 = 6,
+    q7 = 7,
+    q8 = 8,
+    q9 = 9,
+    q10 = 10,
+    q11 = 11,
+    q12 = 12,
+    q13 = 13,
+    q14 = 14,
+    q15 = 15,
+    q16 = 16,
+    q17 = 17,
+    q18 = 18,
+    q19 = 19,
+    q20 = 20,
+    q21 = 21,
+    q22 = 22,
+    q23 = 23,
+    q24 = 24,
+    q25 = 25,
+    q26 = 26,
+    q27 = 27,
+    q28 = 28,
+    q29 = 29,
+    q30 = 30,
+    q31 = 31,
+}
+
+const NUMBERED: [QReg; 32] = {
+    use QReg::*;
+    [
+        q0, q1, q2, q3, q4, q5, q6, q7, q8, q9, q10, q11, q12, q13, q14, q15, q16, q17, q18, q19,
+        q20, q21, q22, q23, q24, q25, q26, q27, q28, q29, q30, q31,
+    ]
+};
+
+impl QReg {
+    /// Gets the register encoded as the 5 bit field `val`.
+    pub(crate) fn from_val(val: u32) -> Self {
+        NUMBERED[(val & 0x1f) as usize]
+    }
+}
+
+impl BitOr<QReg> for u32 {
+    type Output = u32;
+
+    fn bitor(self, rhs: QReg) -> Self::Output {
+        self | rhs as u32
+    }
+}
+
+impl Shl<u32> for QReg {
+    type Output = u32;
+
+    fn shl(self, rhs: u32) -> Self::Output {
+        (self as u32) << rhs
+    }
+}
+
+impl FromStr for QReg {
+    type Err = ();
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let number: usize = name.strip_prefix('q').ok_or(())?.parse().map_err(|_| ())?;
+        NUMBERED.get(number).copied().ok_or(())
+    }
+}
+
+impl fmt::Display for QReg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "q{}", *self as u32)
+    }
+}
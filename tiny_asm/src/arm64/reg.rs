@@ -1,4 +1,8 @@
-use std::ops::{BitOr, Shl};
+use std::{
+    fmt,
+    ops::{BitOr, Shl},
+    str::FromStr,
+};
 
 #[allow(non_camel_case_types)]
 #[derive(Clone, Copy, PartialEq)]
@@ -38,7 +42,26 @@ pub enum Reg {
     xzr,
 }
 
+/// The registers by number, except the 31st one.
+const NUMBERED: [Reg; 31] = {
+    use Reg::*;
+    [
+        x0, x1, x2, x3, x4, x5, x6, x7, x8, x9, x10, x11, x12, x13, x14, x15, x16, x17, x18, x19,
+        x20, x21, x22, x23, x24, x25, x26, x27, x28, x29, x30,
+    ]
+};
+
 impl Reg {
+    /// Gets the register encoded as the 5 bit field `val`, where the 31st register is either `sp` or `xzr`
+    /// depending on the instruction.
+    pub(crate) fn from_val(val: u32, sp: bool) -> Self {
+        match NUMBERED.get((val & 0x1f) as usize) {
+            Some(reg) => *reg,
+            None if sp => Reg::sp,
+            None => Reg::xzr,
+        }
+    }
+
     const fn val(&self) -> u32 {
         match *self {
             Reg::sp | Reg::xzr => 31,
@@ -67,6 +90,34 @@ impl Shl<u32> for Reg {
     type Output = u32;
 
     fn shl(self, rhs: u32) -> Self::Output {
-        self.val() << rhs as u32
+        self.val() << rhs
+    }
+}
+
+impl FromStr for Reg {
+    type Err = ();
+
+    /// Parses a register name, either `x<n>`, `sp`, `xzr` or one of the `fp` and `lr` aliases.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "sp" => Ok(Reg::sp),
+            "xzr" => Ok(Reg::xzr),
+            "fp" => Ok(Reg::x29),
+            "lr" => Ok(Reg::x30),
+            _ => {
+                let number: usize = name.strip_prefix('x').ok_or(())?.parse().map_err(|_| ())?;
+                NUMBERED.get(number).copied().ok_or(())
+            }
+        }
+    }
+}
+
+impl fmt::Display for Reg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reg::sp => f.write_str("sp"),
+            Reg::xzr => f.write_str("xzr"),
+            reg => write!(f, "x{}", reg.val()),
+        }
     }
 }
@@ -1,9 +1,9 @@
 use std::ops::Shl;
 
 pub enum Shift {
-    Lsr = 0,
-    Asr = 1,
-    Lsl = 2,
+    Lsl = 0,
+    Lsr = 1,
+    Asr = 2,
     Ror = 3,
 }
 
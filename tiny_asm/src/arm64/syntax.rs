@@ -0,0 +1,305 @@
+use crate::{
+    text::{parse_indexed, parse_int, parse_label, parse_reg, Indexing},
+    Syntax, TinyAsm,
+};
+
+use super::{AddrMode2, Cond, Op, QReg, Shift};
+
+impl Syntax<4> for Op {
+    fn instr(asm: &mut TinyAsm<Self, 4>, mnemonic: &str, operands: &[&str]) -> Result<(), String> {
+        let op = match (mnemonic, operands) {
+            ("add" | "sub", [xd, xn, imm, shift @ ..]) if shift.len() <= 1 => {
+                let (xd, xn) = (parse_reg(xd)?, parse_reg(xn)?);
+                let imm = parse_arith_imm(imm, shift.first().copied())?;
+
+                match mnemonic {
+                    "add" => Op::Addi(xd, xn, imm),
+                    _ => Op::Subi(xd, xn, imm),
+                }
+            }
+            ("adr", [xd, label]) => {
+                let (xd, label) = (parse_reg(xd)?, parse_label(label)?);
+                asm.emit(|asm| asm.adr(xd, label));
+                return Ok(());
+            }
+            ("and" | "orr", [xd, xn, xm, shift @ ..]) if shift.len() <= 1 => {
+                let (xd, xn, xm) = (parse_reg(xd)?, parse_reg(xn)?, parse_reg(xm)?);
+                let shift = match shift.first() {
+                    Some(shift) => parse_shift(shift)?,
+                    None => (Shift::Lsl, 0),
+                };
+
+                match mnemonic {
+                    "and" => Op::Andsr(xd, xn, xm, shift),
+                    _ => Op::Orrsr(xd, xn, xm, shift),
+                }
+            }
+            ("b" | "bl", [label]) => {
+                let label = parse_label(label)?;
+                match mnemonic {
+                    "b" => asm.emit(|asm| asm.b(label)),
+                    _ => asm.emit(|asm| asm.bl(label)),
+                }
+                return Ok(());
+            }
+            (_, [label]) if mnemonic.starts_with("b.") => {
+                let cond = parse_cond(&mnemonic[2..])
+                    .ok_or_else(|| format!("unknown instruction `{}`", mnemonic))?;
+                let label = parse_label(label)?;
+                asm.emit(|asm| asm.bcond(cond, label));
+                return Ok(());
+            }
+            ("blr", [xn]) => Op::Blr(parse_reg(xn)?),
+            ("br", [xn]) => Op::Br(parse_reg(xn)?),
+            ("cbnz" | "cbz", [xt, label]) => {
+                let (xt, label) = (parse_reg(xt)?, parse_label(label)?);
+                match mnemonic {
+                    "cbnz" => asm.emit(|asm| asm.cbnz(xt, label)),
+                    _ => asm.emit(|asm| asm.cbz(xt, label)),
+                }
+                return Ok(());
+            }
+            ("cmp", [xn, imm, shift @ ..]) if imm.starts_with('#') && shift.len() <= 1 => Op::Cmpi(
+                parse_reg(xn)?,
+                parse_arith_imm(imm, shift.first().copied())?,
+            ),
+            ("cmp", [xn, xm]) => Op::Cmpr(parse_reg(xn)?, parse_reg(xm)?),
+            ("ldp" | "stp", [qt1, qt2, mem, post @ ..])
+                if post.len() <= 1 && qt1.trim().starts_with(['q', 'Q']) =>
+            {
+                let (qt1, qt2): (QReg, QReg) = (parse_reg(qt1)?, parse_reg(qt2)?);
+                let (indexing, xn, imm) = parse_indexed(mem, post.first().copied())?;
+                let imm =
+                    i16::try_from(imm).map_err(|_| format!("`{}` doesn't fit 16 bits", imm))?;
+
+                match mnemonic {
+                    "ldp" => Op::Ldpq(mode(indexing), qt1, qt2, xn, imm),
+                    _ => Op::Stpq(mode(indexing), qt1, qt2, xn, imm),
+                }
+            }
+            ("ldp" | "stp", [xt1, xt2, mem, post @ ..]) if post.len() <= 1 => {
+                let (xt1, xt2) = (parse_reg(xt1)?, parse_reg(xt2)?);
+                let (indexing, xn, imm) = parse_indexed(mem, post.first().copied())?;
+                let imm =
+                    i16::try_from(imm).map_err(|_| format!("`{}` doesn't fit 16 bits", imm))?;
+
+                match mnemonic {
+                    "ldp" => Op::Ldp(mode(indexing), xt1, xt2, xn, imm),
+                    _ => Op::Stp(mode(indexing), xt1, xt2, xn, imm),
+                }
+            }
+            ("ldr", [xt, label]) if !label.starts_with('[') => {
+                let (xt, label) = (parse_reg(xt)?, parse_label(label)?);
+                asm.emit(|asm| asm.ldrl(xt, label));
+                return Ok(());
+            }
+            ("ldr" | "str", [xt, mem, post @ ..]) if post.len() <= 1 => {
+                let xt = parse_reg(xt)?;
+                let (indexing, xn, imm) = parse_indexed(mem, post.first().copied())?;
+                let imm =
+                    i32::try_from(imm).map_err(|_| format!("`{}` doesn't fit 32 bits", imm))?;
+
+                match mnemonic {
+                    "ldr" => Op::Ldri(mode(indexing), xt, xn, imm),
+                    _ => Op::Stri(mode(indexing), xt, xn, imm),
+                }
+            }
+            ("mov", [xd, imm]) if imm.starts_with('#') => {
+                let imm = i32::try_from(parse_int(imm)?)
+                    .map_err(|_| format!("`{}` doesn't fit 32 bits", imm))?;
+                Op::Movi(parse_reg(xd)?, imm)
+            }
+            ("mov", [xd, xm]) => {
+                let (xd, xm) = (parse_reg(xd)?, parse_reg(xm)?);
+                asm.emit(|asm| asm.movr(xd, xm));
+                return Ok(());
+            }
+            ("movk" | "movz", [xd, imm, shift @ ..]) if shift.len() <= 1 => {
+                let xd = parse_reg(xd)?;
+                let imm = u16::try_from(parse_int(imm)?)
+                    .map_err(|_| format!("`{}` doesn't fit 16 bits", imm))?;
+                let shift = match shift.first() {
+                    Some(shift) => match parse_shift(shift)? {
+                        (Shift::Lsl, amount) => amount,
+                        _ => return Err(format!("invalid shift `{}`", shift)),
+                    },
+                    None => 0,
+                };
+
+                match mnemonic {
+                    "movk" => Op::Movk(xd, imm, shift),
+                    _ => Op::Movz(xd, imm, shift),
+                }
+            }
+            ("mrs", [xt, sys_reg]) => Op::Mrs(parse_reg(xt)?, parse_reg(sys_reg)?),
+            ("msr", [sys_reg, xt]) => Op::Msr(parse_reg(sys_reg)?, parse_reg(xt)?),
+            ("svc", [imm]) => {
+                let imm = u16::try_from(parse_int(imm)?)
+                    .map_err(|_| format!("`{}` doesn't fit 16 bits", imm))?;
+                Op::Svc(imm)
+            }
+            (
+                "add" | "sub" | "adr" | "and" | "orr" | "b" | "bl" | "blr" | "br" | "cbnz" | "cbz"
+                | "cmp" | "ldp" | "stp" | "ldr" | "str" | "mov" | "movk" | "movz" | "mrs" | "msr"
+                | "svc",
+                _,
+            ) => return Err(format!("invalid operands for `{}`", mnemonic)),
+            _ if mnemonic.starts_with("b.") => {
+                return Err(format!("invalid operands for `{}`", mnemonic))
+            }
+            _ => return Err(format!("unknown instruction `{}`", mnemonic)),
+        };
+
+        asm.emit(|asm| asm.op(op));
+        Ok(())
+    }
+}
+
+fn mode(indexing: Indexing) -> AddrMode2 {
+    match indexing {
+        Indexing::Offset => AddrMode2::Offset,
+        Indexing::Pre => AddrMode2::PreIndexed,
+        Indexing::Post => AddrMode2::PostIndexed,
+    }
+}
+
+/// Parses an arithmetic immediate, e.g. `#0x1000` or `#1` followed by the `lsl #12` shift.
+fn parse_arith_imm(imm: &str, shift: Option<&str>) -> Result<u32, String> {
+    let value =
+        u32::try_from(parse_int(imm)?).map_err(|_| format!("`{}` doesn't fit 32 bits", imm))?;
+
+    match shift.map(parse_shift).transpose()? {
+        None => Ok(value),
+        Some((Shift::Lsl, 12)) if value <= 0xfff => Ok(value << 12),
+        Some(_) => Err(format!("invalid shift `{}`", shift.unwrap_or_default())),
+    }
+}
+
+/// Parses the condition of a `B.<cond>` mnemonic, including the `cs` and `cc` aliases.
+fn parse_cond(name: &str) -> Option<Cond> {
+    Some(match name {
+        "eq" => Cond::Eq,
+        "ne" => Cond::Ne,
+        "hs" | "cs" => Cond::Hs,
+        "lo" | "cc" => Cond::Lo,
+        "mi" => Cond::Mi,
+        "pl" => Cond::Pl,
+        "vs" => Cond::Vs,
+        "vc" => Cond::Vc,
+        "hi" => Cond::Hi,
+        "ls" => Cond::Ls,
+        "ge" => Cond::Ge,
+        "lt" => Cond::Lt,
+        "gt" => Cond::Gt,
+        "le" => Cond::Le,
+        "al" => Cond::Al,
+        _ => return None,
+    })
+}
+
+/// Parses a shift, e.g. `lsl #3`.
+fn parse_shift(operand: &str) -> Result<(Shift, u8), String> {
+    let invalid = || format!("invalid shift `{}`", operand);
+
+    let (kind, amount) = operand
+        .trim()
+        .split_once(char::is_whitespace)
+        .ok_or_else(invalid)?;
+    let amount = u8::try_from(parse_int(amount)?).map_err(|_| invalid())?;
+
+    let kind = match kind.to_ascii_lowercase().as_str() {
+        "lsl" => Shift::Lsl,
+        "lsr" => Shift::Lsr,
+        "asr" => Shift::Asr,
+        "ror" => Shift::Ror,
+        _ => return Err(invalid()),
+    };
+
+    Ok((kind, amount))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arm64::TinyAsm;
+
+    /// The expected encodings are the ones emitted by `llvm-mc -triple=aarch64`.
+    #[test]
+    fn assembles_like_llvm() {
+        let code = TinyAsm::new()
+            .asm(
+                "
+                stp x0, x1, [sp, #-16]!
+                ldp x0, x1, [sp], #16
+                ldr x2, [x3, #8]
+                str x2, [sp, #-8]!
+                add x0, sp, #16
+                sub x1, x1, #1
+                and x0, x1, x2, lsl #3
+                orr x3, x4, x5
+                mov x0, x19
+                mov x8, #222
+                mov x1, #-1
+                blr x9
+                br x28
+                svc #0
+                ",
+            )
+            .build()
+            .unwrap();
+
+        let expected: &[[u8; 4]] = &[
+            [0xe0, 0x07, 0xbf, 0xa9],
+            [0xe0, 0x07, 0xc1, 0xa8],
+            [0x62, 0x04, 0x40, 0xf9],
+            [0xe2, 0x8f, 0x1f, 0xf8],
+            [0xe0, 0x43, 0x00, 0x91],
+            [0x21, 0x04, 0x00, 0xd1],
+            [0x20, 0x0c, 0x02, 0x8a],
+            [0x83, 0x00, 0x05, 0xaa],
+            [0xe0, 0x03, 0x13, 0xaa],
+            [0xc8, 0x1b, 0x80, 0xd2],
+            [0x01, 0x00, 0x80, 0x92],
+            [0x20, 0x01, 0x3f, 0xd6],
+            [0x80, 0x03, 0x1f, 0xd6],
+            [0x01, 0x00, 0x00, 0xd4],
+        ];
+
+        assert_eq!(code, expected.concat());
+    }
+
+    #[test]
+    fn assembles_like_the_builder() {
+        use crate::arm64::Reg::*;
+
+        let text = TinyAsm::new()
+            .asm(
+                "
+                1:  adr x0, path
+                    ldr x1, len
+                    cbnz x0, 1b
+                path:
+                    .asciz \"/proc/self/mem\"
+                    .align 8
+                len:
+                    .quad 14
+                ",
+            )
+            .build()
+            .unwrap();
+
+        let builder = TinyAsm::new()
+            .label("1")
+            .adr(x0, "path")
+            .ldrl(x1, "len")
+            .cbnz(x0, "1b")
+            .label("path")
+            .asciiz("/proc/self/mem")
+            .align::<8>()
+            .label("len")
+            .qword(14)
+            .build()
+            .unwrap();
+
+        assert_eq!(text, builder);
+    }
+}
@@ -0,0 +1,56 @@
+use std::{ops::Shl, str::FromStr};
+
+/// The system registers which can be read by `MRS` and written by `MSR` at EL0.
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, PartialEq)]
+pub enum SysReg {
+    /// The condition flags.
+    nzcv = 0xda10,
+    /// The floating-point control register.
+    fpcr = 0xda20,
+    /// The floating-point status register.
+    fpsr = 0xda21,
+    /// The thread pointer, e.g. the TLS base.
+    tpidr_el0 = 0xde82,
+}
+
+impl SysReg {
+    /// Gets the system register encoded as the 16 bit field `val` (`op0:op1:CRn:CRm:op2`).
+    pub(crate) fn from_val(val: u32) -> Option<Self> {
+        use SysReg::*;
+
+        [nzcv, fpcr, fpsr, tpidr_el0]
+            .into_iter()
+            .find(|reg| *reg as u32 == val & 0xffff)
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            SysReg::nzcv => "nzcv",
+            SysReg::fpcr => "fpcr",
+            SysReg::fpsr => "fpsr",
+            SysReg::tpidr_el0 => "tpidr_el0",
+        }
+    }
+}
+
+impl Shl<u32> for SysReg {
+    type Output = u32;
+
+    fn shl(self, rhs: u32) -> Self::Output {
+        (self as u32) << rhs
+    }
+}
+
+impl FromStr for SysReg {
+    type Err = ();
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        use SysReg::*;
+
+        [nzcv, fpcr, fpsr, tpidr_el0]
+            .into_iter()
+            .find(|reg| reg.name() == name)
+            .ok_or(())
+    }
+}
@@ -0,0 +1,174 @@
+use std::fmt::Write;
+
+use crate::Label;
+
+/// A backend which can decode the instructions it encodes, so that an assembled buffer can be printed back.
+pub trait Decode {
+    /// Decodes the instruction at the start of `code`, which is located at `offset` within the buffer, into the
+    /// text syntax accepted by [`crate::TinyAsm::asm`].
+    ///
+    /// Returns its length and its text, or [`None`] if it's not one of the instructions the backend encodes, or
+    /// if it refers to an offset which can only be written as a label, but no label was put there.
+    fn decode(code: &[u8], offset: usize, labels: &Labels) -> Option<(usize, String)>;
+}
+
+/// The offset of every label put into an assembled buffer, sorted by offset.
+pub type LabelOffsets = Vec<(usize, Label)>;
+
+/// The labels put into an assembled buffer, sorted by offset, which name the offsets its instructions refer to.
+pub struct Labels<'a>(&'a [(usize, Label)]);
+
+impl Labels<'_> {
+    /// Gets the name the instruction at `offset` refers to `target` with, e.g. `1f` for the next local label `1`.
+    ///
+    /// Returns [`None`] if no label was put at `target`, or if it's a local label which can't be referenced
+    /// from `offset`.
+    pub fn get(&self, offset: usize, target: i64) -> Option<String> {
+        let target = usize::try_from(target).ok()?;
+
+        self.0
+            .iter()
+            .filter(|(at, _)| *at == target)
+            .find_map(|(_, label)| match label.local() {
+                Some((number, None)) => {
+                    let (dir, range) = if target > offset {
+                        ('f', offset + 1..target)
+                    } else {
+                        ('b', target + 1..offset + 1)
+                    };
+
+                    // The reference would resolve to a nearer label sharing the same number.
+                    let shadowed = self.0.iter().any(|(at, other)| {
+                        range.contains(at) && other.local() == Some((number, None))
+                    });
+
+                    (!shadowed).then(|| format!("{}{}", number, dir))
+                }
+                _ => Some(label.name().to_string()),
+            })
+    }
+
+    /// Whether a label was put within `range`, e.g. within the bytes of an instruction which is actually data.
+    fn split(&self, range: std::ops::Range<usize>) -> bool {
+        self.0.iter().any(|(at, _)| range.contains(at))
+    }
+}
+
+/// Disassembles `code`, which has been assembled with the given `labels` (e.g. by
+/// [`crate::TinyAsm::build_with_labels`]), into the `.s` style: every label is put on its own line, every
+/// instruction is followed by a comment holding its offset and bytes.
+///
+/// The bytes which can't be decoded are printed as `.byte` directives, so that the listing can be assembled again;
+/// an instruction can't span across a label, since it's likely the start of some data or code.
+pub fn disassemble<T: Decode>(code: &[u8], labels: &[(usize, Label)]) -> String {
+    let labels = Labels(labels);
+    let mut listing = String::new();
+    let mut data = Vec::new();
+    let mut offset = 0;
+
+    while offset < code.len() {
+        let decoded = T::decode(&code[offset..], offset, &labels)
+            .filter(|(len, _)| !labels.split(offset + 1..offset + len));
+
+        let put = labels.split(offset..offset + 1);
+
+        if (decoded.is_some() || put || data.len() == 8) && !data.is_empty() {
+            flush(&mut listing, offset - data.len(), &mut data);
+        }
+
+        for (_, label) in labels.0.iter().filter(|(at, _)| *at == offset) {
+            let _ = writeln!(listing, "{}:", label);
+        }
+
+        match decoded {
+            Some((len, text)) => {
+                line(&mut listing, offset, &code[offset..offset + len], &text);
+                offset += len;
+            }
+            None => {
+                data.push(code[offset]);
+                offset += 1;
+            }
+        }
+    }
+
+    if !data.is_empty() {
+        flush(&mut listing, offset - data.len(), &mut data);
+    }
+
+    for (_, label) in labels.0.iter().filter(|(at, _)| *at >= code.len()) {
+        let _ = writeln!(listing, "{}:", label);
+    }
+
+    listing
+}
+
+/// Prints the undecodable `data` at `offset` as a `.byte` directive.
+fn flush(listing: &mut String, offset: usize, data: &mut Vec<u8>) {
+    let text = data
+        .iter()
+        .map(|byte| format!("{:#04x}", byte))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    line(listing, offset, data, &format!(".byte {}", text));
+    data.clear();
+}
+
+fn line(listing: &mut String, offset: usize, bytes: &[u8], text: &str) {
+    let bytes = bytes
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let _ = writeln!(listing, "    {:<40} // {:#06x}: {}", text, offset, bytes);
+}
+
+/// Formats an immediate the way it's usually read: decimal if it's small, hexadecimal otherwise.
+#[cfg(any(feature = "x86", feature = "arm", feature = "arm64"))]
+pub(crate) fn imm(imm: i64) -> String {
+    match imm {
+        -9..=9 => imm.to_string(),
+        _ if imm < 0 => format!("-{:#x}", imm.unsigned_abs()),
+        _ => format!("{:#x}", imm),
+    }
+}
+
+#[cfg(all(test, feature = "x86"))]
+mod tests {
+    use crate::{
+        disassemble,
+        x86::{Op, Reg::*, TinyAsm},
+    };
+
+    #[test]
+    fn prints_labels_instructions_and_data() {
+        let (code, labels) = TinyAsm::new()
+            .label("1")
+            .call("1f")
+            .label("1")
+            .pop(ebx)
+            .jmp("1b")
+            .label("data")
+            .bytes(&[0xff; 10])
+            .label("end")
+            .build_with_labels()
+            .unwrap();
+
+        assert_eq!(
+            disassemble::<Op>(&code, &labels),
+            "\
+1:
+    call 1f                                  // 0x0000: e8 00 00 00 00
+1:
+    pop ebx                                  // 0x0005: 5b
+    jmp 1b                                   // 0x0006: e9 fa ff ff ff
+data:
+    .byte 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff // 0x000b: ff ff ff ff ff ff ff ff
+    .byte 0xff, 0xff                         // 0x0013: ff ff
+end:
+"
+        );
+    }
+}
@@ -1,26 +1,74 @@
 use std::collections::HashMap;
 
-use crate::Label;
+use crate::{AsmError, Label};
 
 /// An instruction which can be encoded to `T` bytes.
 /// E.g. every `arm` and `arm64` intruction is encoded to 4 bytes or 32-bit unsigned integer.
-/// Unfortunately, `x86` and `x86-64` can't follow this beautiful pattern, so they will receive less love.
+/// Unfortunately, `x86` and `x86-64` can't follow this beautiful pattern: their instructions are pushed as they are
+/// encoded, and only the label references they contain (up to 4 bytes) are encodable.
 pub trait Encodable<const T: usize> {
-    /// Common function to grab the given label from the labels hash map, panicking on failure.
-    fn res_lab(lab: Label, labs: &HashMap<Label, usize>, instr_offset: usize) -> i32 {
-        let offset = labs
-            .get(lab)
-            .unwrap_or_else(|| panic!("Couldn't find label {}", lab));
-
-        Self::calc_offset(
-            instr_offset.try_into().unwrap(),
-            (*offset).try_into().unwrap(),
-        )
+    /// Common function to grab the given label from the labels hash map.
+    ///
+    /// Returns [`AsmError::UnknownLabel`] if the label was never put.
+    fn res_lab(
+        lab: Label,
+        labs: &HashMap<Label, usize>,
+        instr_offset: usize,
+    ) -> Result<i32, AsmError> {
+        let offset = labs.get(&lab).ok_or(AsmError::UnknownLabel(lab))?;
+
+        Ok(Self::calc_offset(
+            instr_offset
+                .try_into()
+                .map_err(|_| AsmError::ImmOutOfRange(instr_offset as i64))?,
+            (*offset)
+                .try_into()
+                .map_err(|_| AsmError::ImmOutOfRange(*offset as i64))?,
+        ))
+    }
+
+    /// How many of the `T` encoded bytes the instruction actually takes, e.g. `1` for a `rel8` operand.
+    fn size(&self) -> usize {
+        T
     }
 
     /// Relocation calculation implementation.
     fn calc_offset(instr_offset: i32, label_offset: i32) -> i32;
 
     /// Instructions encoding implementation.
-    fn enc(self, offset: usize, labels: &HashMap<Label, usize>) -> [u8; T];
+    ///
+    /// Returns [`AsmError`] if a label can't be resolved or an immediate can't be encoded.
+    fn enc(self, offset: usize, labels: &HashMap<Label, usize>) -> Result<[u8; T], AsmError>;
+}
+
+/// Checks whether `imm` fits a signed field of `bits` bits once divided by `scale`, returning the field value.
+#[cfg(any(feature = "arm", feature = "arm64"))]
+pub(crate) fn signed(imm: i64, bits: u32, scale: i64) -> Result<u32, AsmError> {
+    if imm % scale != 0 {
+        return Err(AsmError::Misaligned(imm));
+    }
+
+    let field = imm / scale;
+
+    if field < -(1 << (bits - 1)) || field >= 1 << (bits - 1) {
+        return Err(AsmError::ImmOutOfRange(imm));
+    }
+
+    Ok(field as u32 & ((1 << bits) - 1))
+}
+
+/// Checks whether `imm` fits an unsigned field of `bits` bits once divided by `scale`, returning the field value.
+#[cfg(any(feature = "arm", feature = "arm64"))]
+pub(crate) fn unsigned(imm: i64, bits: u32, scale: i64) -> Result<u32, AsmError> {
+    if imm % scale != 0 {
+        return Err(AsmError::Misaligned(imm));
+    }
+
+    let field = imm / scale;
+
+    if field < 0 || field >= 1 << bits {
+        return Err(AsmError::ImmOutOfRange(imm));
+    }
+
+    Ok(field as u32)
 }
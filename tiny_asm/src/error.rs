@@ -0,0 +1,23 @@
+use crate::Label;
+
+/// The errors may occurr while assembling.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AsmError {
+    /// It occurs when a label is referenced, but it's never put.
+    UnknownLabel(Label),
+    /// It occurs when a label is put more than once.
+    DuplicateLabel(Label),
+    /// It occurs when a local label is misused, e.g. `1f` is put or `1` is referenced.
+    InvalidLabel(Label),
+    /// It occurs when an immediate - or the offset of a label - doesn't fit the instruction encoding,
+    /// e.g. a branch target which is out of reach.
+    ImmOutOfRange(i64),
+    /// It occurs when an immediate - or the offset of a label - is not a multiple of the instruction scale,
+    /// e.g. a literal which is not word aligned.
+    Misaligned(i64),
+    /// It occurs when an operand can't be encoded, e.g. the stack pointer used as an index register.
+    InvalidOperand(&'static str),
+    /// It occurs when a line of text can't be assembled, e.g. an unknown mnemonic or a malformed operand.
+    /// It holds the line number and a description of the problem.
+    Syntax(usize, String),
+}
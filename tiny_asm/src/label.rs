@@ -1,2 +1,53 @@
-/// Labels aren't very dynamic right now.
-pub type Label = &'static str;
+use std::{fmt, sync::Arc};
+
+/// A label, which can be created from both static and dynamically generated names.
+///
+/// Besides plain names, two kinds of names are resolved by the assembler:
+/// - numeric local labels (`1`, `2`, ...), which can be put several times and are referenced as `1f` (the next one)
+///   or `1b` (the previous one);
+/// - scoped labels (`.name`), which are private to the innermost [`crate::TinyAsm::scope`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Label {
+    name: Arc<str>,
+    /// Disambiguates labels sharing the same name, e.g. the scope of a scoped label or the occurrence of a local label.
+    tag: usize,
+}
+
+impl Label {
+    /// Gets the name of the label.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn with(name: Arc<str>, tag: usize) -> Self {
+        Self { name, tag }
+    }
+
+    /// Splits a local label name into its number and its direction, e.g. `Some((1, Some('f')))` for `1f`.
+    pub(crate) fn local(&self) -> Option<(u32, Option<char>)> {
+        let (number, dir) = match self.name.strip_suffix(['f', 'b']) {
+            Some(number) => (number, self.name.chars().last()),
+            None => (&*self.name, None),
+        };
+
+        Some((number.parse().ok()?, dir))
+    }
+}
+
+impl From<&str> for Label {
+    fn from(name: &str) -> Self {
+        Self::with(name.into(), 0)
+    }
+}
+
+impl From<String> for Label {
+    fn from(name: String) -> Self {
+        Self::with(name.into(), 0)
+    }
+}
+
+impl fmt::Display for Label {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)
+    }
+}
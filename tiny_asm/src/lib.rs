@@ -1,7 +1,14 @@
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    mem,
+    sync::Arc,
+};
 
+mod disasm;
 mod encodable;
+mod error;
 mod label;
+mod text;
 
 #[cfg(feature = "arm")]
 pub mod arm;
@@ -12,8 +19,11 @@ pub mod x86;
 #[cfg(feature = "x86_64")]
 pub mod x86_64;
 
+pub use disasm::{disassemble, Decode, LabelOffsets, Labels};
 pub use encodable::Encodable;
+pub use error::AsmError;
 pub use label::Label;
+pub use text::Syntax;
 
 /// Holds the relevant basic stuff to perform intructions encoding and relocations.
 /// Every instruction is encoded immediately and pushed into the buffer. If it contains
@@ -21,11 +31,27 @@ pub use label::Label;
 /// are pushed instead; also the insert position and the original instruction are pushed
 /// into a vector. The final step (the call to [`TinyAsm::build`]) will iterate the
 /// relocation vector and replace the placeholder bytes with the re-encoded instruction.
+/// The first error is kept and returned by [`TinyAsm::build`], so that instructions can still be chained.
 /// Little-endian only.
 pub struct TinyAsm<T: Encodable<U>, const U: usize> {
     buf: Vec<u8>,
     relocs: Vec<(usize, T)>,
     labels: HashMap<Label, usize>,
+    /// The interned label names, so that labels sharing a name share its allocation too.
+    names: HashSet<Arc<str>>,
+    /// How many times each local label has been put so far.
+    locals: HashMap<u32, usize>,
+    /// The stack of the currently open scopes, innermost last.
+    scopes: Vec<usize>,
+    /// How many scopes have been opened so far.
+    scope_count: usize,
+    error: Option<AsmError>,
+}
+
+impl<T: Encodable<U>, const U: usize> Default for TinyAsm<T, U> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<T: Encodable<U>, const U: usize> TinyAsm<T, U> {
@@ -35,12 +61,17 @@ impl<T: Encodable<U>, const U: usize> TinyAsm<T, U> {
             buf: Default::default(),
             relocs: Default::default(),
             labels: Default::default(),
+            names: Default::default(),
+            locals: Default::default(),
+            scopes: Default::default(),
+            scope_count: 0,
+            error: None,
         }
     }
 
     /// Aligns the buffer to a `A` byte boundary.
     pub fn align<const A: usize>(mut self) -> Self {
-        while self.buf.len() % A != 0 {
+        while !self.buf.len().is_multiple_of(A) {
             self.buf.push(0);
         }
         self
@@ -78,26 +109,238 @@ impl<T: Encodable<U>, const U: usize> TinyAsm<T, U> {
     }
 
     /// Pushes the encoding of a instruction into the buffer.
+    /// If the instruction can't be encoded, placeholder bytes are pushed instead and the error is kept.
     pub fn op(mut self, op: T) -> Self {
-        self.buf.extend(op.enc(0, &self.labels));
+        let size = op.size();
+
+        match op.enc(self.buf.len(), &self.labels) {
+            Ok(bytes) => self.buf.extend(&bytes[..size]),
+            Err(err) => {
+                self.buf.extend(&[0; U][..size]);
+                self.error.get_or_insert(err);
+            }
+        }
         self
     }
 
+    /// Pushes placeholder bytes for an instruction which references a label: it's encoded by [`TinyAsm::build`].
+    #[cfg(feature = "x86")]
+    pub(crate) fn reloc(&mut self, op: T) {
+        let size = op.size();
+
+        self.relocs.push((self.buf.len(), op));
+        self.buf.extend(&[0; U][..size]);
+    }
+
+    /// Applies `f` to the assembler in place, e.g. to push an instruction while parsing text.
+    pub(crate) fn emit(&mut self, f: impl FnOnce(Self) -> Self) {
+        *self = f(mem::take(self));
+    }
+
+    /// Applies `f` to the assembler, e.g. to emit an instruction sequence shared by several payloads.
+    pub fn with(self, f: impl FnOnce(Self) -> Self) -> Self {
+        f(self)
+    }
+
+    /// Runs `f` within a new scope, so that the scoped labels (`.name`) it puts and references
+    /// are private to it - e.g. to emit the same block several times.
+    pub fn scope(mut self, f: impl FnOnce(Self) -> Self) -> Self {
+        self.scope_count += 1;
+        self.scopes.push(self.scope_count);
+
+        let mut asm = f(self);
+        asm.scopes.pop();
+        asm
+    }
+
     /// Puts a label at the current position (current buffer length).
-    pub fn label(mut self, label: Label) -> Self {
-        self.labels.insert(label, self.buf.len());
+    pub fn label(mut self, label: impl Into<Label>) -> Self {
+        let label = self.resolve(label.into(), true);
+
+        if self.labels.insert(label.clone(), self.buf.len()).is_some() {
+            self.error.get_or_insert(AsmError::DuplicateLabel(label));
+        }
         self
     }
 
+    /// Resolves a label referenced by the instruction which is being pushed.
+    #[cfg(any(feature = "x86", feature = "arm", feature = "arm64"))]
+    pub(crate) fn refer(&mut self, label: impl Into<Label>) -> Label {
+        self.resolve(label.into(), false)
+    }
+
+    /// Resolves which label `label` stands for at the current position, whether it's being `put` or referenced:
+    /// a local label gets its occurrence, a scoped label gets its scope.
+    fn resolve(&mut self, label: Label, put: bool) -> Label {
+        match label.local() {
+            Some((number, dir)) => {
+                let count = self.locals.get(&number).copied().unwrap_or(0);
+
+                let occurrence = match (dir, put) {
+                    (None, true) => {
+                        self.locals.insert(number, count + 1);
+                        Some(count)
+                    }
+                    (Some('f'), false) => Some(count),
+                    (Some('b'), false) if count > 0 => Some(count - 1),
+                    (Some('b'), false) => {
+                        self.error
+                            .get_or_insert(AsmError::UnknownLabel(label.clone()));
+                        None
+                    }
+                    _ => {
+                        self.error
+                            .get_or_insert(AsmError::InvalidLabel(label.clone()));
+                        None
+                    }
+                };
+
+                Label::with(
+                    self.intern(&number.to_string()),
+                    occurrence.unwrap_or(usize::MAX),
+                )
+            }
+            None => {
+                let scope = if label.name().starts_with('.') {
+                    self.scopes.last().copied().unwrap_or(0)
+                } else {
+                    0
+                };
+
+                Label::with(self.intern(label.name()), scope)
+            }
+        }
+    }
+
+    fn intern(&mut self, name: &str) -> Arc<str> {
+        match self.names.get(name) {
+            Some(name) => name.clone(),
+            None => {
+                let name: Arc<str> = name.into();
+                self.names.insert(name.clone());
+                name
+            }
+        }
+    }
+
     /// Performs the relocation of every pending instruction encoding, which
     /// couldn't be encoded early because it contained a reference to a label.
-    pub fn build(mut self) -> Vec<u8> {
+    ///
+    /// Returns the first [`AsmError`] that occurred, either now or while pushing instructions.
+    pub fn build(self) -> Result<Vec<u8>, AsmError> {
+        self.build_with_labels().map(|(buf, _)| buf)
+    }
+
+    /// Like [`TinyAsm::build`], but also returns the offset of every label put, sorted by offset, e.g. to
+    /// [`disassemble`] the buffer.
+    pub fn build_with_labels(mut self) -> Result<(Vec<u8>, LabelOffsets), AsmError> {
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+
         for (index, op) in self.relocs {
-            for (j, byte) in op.enc(index, &self.labels).iter().enumerate() {
-                self.buf[index + j] = *byte;
-            }
+            let size = op.size();
+            let bytes = op.enc(index, &self.labels)?;
+            self.buf[index..index + size].copy_from_slice(&bytes[..size]);
         }
 
-        self.buf
+        let mut labels: Vec<_> = self
+            .labels
+            .into_iter()
+            .map(|(label, offset)| (offset, label))
+            .collect();
+        labels.sort_by(|(a, a_label), (b, b_label)| (a, a_label.name()).cmp(&(b, b_label.name())));
+
+        Ok((self.buf, labels))
+    }
+}
+
+#[cfg(all(test, feature = "x86"))]
+mod tests {
+    use crate::{x86::TinyAsm, AsmError};
+
+    #[test]
+    fn rejects_unknown_label() {
+        let result = TinyAsm::new()
+            .instr_with_ref([0x81, 0xc3], "missing")
+            .build();
+
+        assert_eq!(result, Err(AsmError::UnknownLabel("missing".into())));
+    }
+
+    #[test]
+    fn rejects_duplicate_label() {
+        let result = TinyAsm::new()
+            .label("twice")
+            .instr([0x90])
+            .label("twice")
+            .build();
+
+        assert_eq!(result, Err(AsmError::DuplicateLabel("twice".into())));
+    }
+
+    #[test]
+    fn resolves_local_labels() {
+        let code = TinyAsm::new()
+            .label("1")
+            .instr_with_ref([0x68], "1b")
+            .instr_with_ref([0x68], "1f")
+            .label("1")
+            .instr_with_ref([0x68], "1b")
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            code,
+            [[0x68, 0, 0, 0, 0], [0x68, 10, 0, 0, 0], [0x68, 10, 0, 0, 0]].concat()
+        );
+    }
+
+    #[test]
+    fn rejects_misused_local_labels() {
+        let result = TinyAsm::new().instr_with_ref([0x68], "1b").build();
+        assert_eq!(result, Err(AsmError::UnknownLabel("1b".into())));
+
+        let result = TinyAsm::new()
+            .label("1")
+            .instr_with_ref([0x68], "1")
+            .build();
+        assert_eq!(result, Err(AsmError::InvalidLabel("1".into())));
+    }
+
+    #[test]
+    fn scopes_labels() {
+        let block = |asm: TinyAsm| asm.label(".here").instr_with_ref([0x68], ".here");
+
+        let code = TinyAsm::new()
+            .scope(block)
+            .scope(block)
+            .instr_with_ref([0x68], "end")
+            .label("end")
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            code,
+            [[0x68, 0, 0, 0, 0], [0x68, 5, 0, 0, 0], [0x68, 15, 0, 0, 0]].concat()
+        );
+    }
+
+    #[test]
+    fn accepts_generated_labels() {
+        let code = (0..3)
+            .fold(TinyAsm::new(), |asm, i| {
+                asm.instr_with_ref([0x68], format!("target{}", i))
+            })
+            .label("target0")
+            .label(String::from("target1"))
+            .instr([0x90])
+            .label(format!("target{}", 2))
+            .build()
+            .unwrap();
+
+        assert_eq!(code[1..5], 15_u32.to_le_bytes());
+        assert_eq!(code[6..10], 15_u32.to_le_bytes());
+        assert_eq!(code[11..15], 16_u32.to_le_bytes());
     }
 }
@@ -0,0 +1,379 @@
+use crate::{AsmError, Encodable, TinyAsm};
+
+/// The text syntax of a backend, e.g. how a mnemonic and its operands map to the instruction methods.
+pub trait Syntax<const U: usize>: Encodable<U> + Sized {
+    /// Pushes the instruction `mnemonic` (lowercase) with the given `operands`, which are trimmed
+    /// and split on the commas outside of brackets and braces.
+    ///
+    /// Returns a description of the problem if the instruction is unknown or an operand can't be parsed.
+    fn instr(asm: &mut TinyAsm<Self, U>, mnemonic: &str, operands: &[&str]) -> Result<(), String>;
+}
+
+impl<T: Syntax<U>, const U: usize> TinyAsm<T, U> {
+    /// Assembles `text`, written in the `.s` style: one statement per line, which is either an instruction or
+    /// one of the `.ascii`, `.asciz`, `.byte`, `.short`, `.long`, `.quad` and `.align` (in bytes) directives,
+    /// optionally preceded by labels (`name:`). Comments start with `//`.
+    ///
+    /// A line that can't be parsed is kept as [`AsmError::Syntax`], with its 1-based number.
+    pub fn asm(mut self, text: &str) -> Self {
+        for (index, line) in text.lines().enumerate() {
+            if let Err(message) = self.statement(strip_comment(line)) {
+                self.error
+                    .get_or_insert(AsmError::Syntax(index + 1, message));
+            }
+        }
+        self
+    }
+
+    fn statement(&mut self, mut stmt: &str) -> Result<(), String> {
+        while let Some((label, rest)) = stmt.split_once(':') {
+            let label = label.trim();
+
+            if label.is_empty() || !label.chars().all(is_label_char) {
+                break;
+            }
+            self.emit(|asm| asm.label(label));
+            stmt = rest;
+        }
+
+        let stmt = stmt.trim();
+
+        if stmt.is_empty() {
+            return Ok(());
+        }
+
+        let (mnemonic, operands) = stmt.split_once(char::is_whitespace).unwrap_or((stmt, ""));
+        let mnemonic = mnemonic.to_lowercase();
+        let operands = split_operands(operands);
+
+        match mnemonic.as_str() {
+            ".ascii" | ".asciz" | ".string" => {
+                let [str] = operands[..] else {
+                    return Err(format!("`{}` takes one string", mnemonic));
+                };
+                let mut bytes = parse_str(str)?;
+
+                if mnemonic != ".ascii" {
+                    bytes.push(0);
+                }
+                self.emit(|asm| asm.bytes(&bytes));
+            }
+            ".byte" => self.data::<1>(&operands)?,
+            ".short" => self.data::<2>(&operands)?,
+            ".long" => self.data::<4>(&operands)?,
+            ".quad" => self.data::<8>(&operands)?,
+            ".align" => {
+                let [align] = operands[..] else {
+                    return Err("`.align` takes one value".into());
+                };
+
+                match usize::try_from(parse_int(align)?) {
+                    Ok(align) if align > 0 => {
+                        while !self.buf.len().is_multiple_of(align) {
+                            self.buf.push(0);
+                        }
+                    }
+                    _ => return Err(format!("invalid alignment `{}`", align)),
+                }
+            }
+            _ if mnemonic.starts_with('.') => {
+                return Err(format!("unknown directive `{}`", mnemonic))
+            }
+            _ => T::instr(self, &mnemonic, &operands)?,
+        }
+
+        Ok(())
+    }
+
+    /// Pushes every value as a `N` bytes integer, which can be either signed or unsigned.
+    fn data<const N: usize>(&mut self, values: &[&str]) -> Result<(), String> {
+        for value in values {
+            let int = parse_int(value)?;
+            let bits = 8 * N as u32;
+
+            if bits < 64 && (int < -(1 << (bits - 1)) || int >= 1 << bits) {
+                return Err(format!("`{}` doesn't fit {} bytes", value, N));
+            }
+            self.buf.extend(&int.to_le_bytes()[..N]);
+        }
+        Ok(())
+    }
+}
+
+/// Assembles text at runtime with the `arch` backend (`x86`, `x86_64`, `arm` or `arm64`): every line is a
+/// string literal, so that the text is embedded at compile time. It returns the assembler, e.g. to put more
+/// instructions or to build it.
+///
+/// ```
+/// # #[cfg(feature = "x86_64")] {
+/// let code = tiny_asm::tiny_asm!(x86_64;
+///     "mov rax, 60",
+///     "xor rdi, rdi",
+///     "syscall",
+/// )
+/// .build()
+/// .unwrap();
+///
+/// assert_eq!(code, [0x48, 0xc7, 0xc0, 0x3c, 0, 0, 0, 0x48, 0x31, 0xff, 0x0f, 0x05]);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! tiny_asm {
+    ($arch:ident; $($line:literal),* $(,)?) => {
+        $crate::$arch::TinyAsm::new().asm(concat!($($line, "\n"),*))
+    };
+}
+
+/// Whether `char` can be part of a label name, e.g. `_start`, `.loop` or `1`.
+pub(crate) fn is_label_char(char: char) -> bool {
+    char.is_ascii_alphanumeric() || char == '_' || char == '.' || char == '$'
+}
+
+/// Parses a label name, which must not start with a digit unless it's a local label reference (`1f`, `1b`).
+#[cfg(any(feature = "x86", feature = "arm", feature = "arm64"))]
+pub(crate) fn parse_label(operand: &str) -> Result<&str, String> {
+    match operand.chars().next() {
+        Some(first) if operand.chars().all(is_label_char) => {
+            if !first.is_ascii_digit() || operand.ends_with(['f', 'b']) {
+                Ok(operand)
+            } else {
+                Err(format!("invalid label `{}`", operand))
+            }
+        }
+        _ => Err(format!("invalid label `{}`", operand)),
+    }
+}
+
+/// Parses an integer, optionally prefixed by `#`, either decimal, hexadecimal (`0x`) or binary (`0b`).
+pub(crate) fn parse_int(operand: &str) -> Result<i64, String> {
+    let digits = operand.trim().trim_start_matches('#').trim();
+    let (negative, digits) = match digits.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, digits.strip_prefix('+').unwrap_or(digits)),
+    };
+
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        u64::from_str_radix(hex, 16)
+    } else if let Some(bin) = digits.strip_prefix("0b") {
+        u64::from_str_radix(bin, 2)
+    } else {
+        digits.parse()
+    }
+    .map_err(|_| format!("invalid integer `{}`", operand))?;
+
+    match negative {
+        true if value <= 1 << 63 => Ok((value as i64).wrapping_neg()),
+        false => Ok(value as i64),
+        true => Err(format!("invalid integer `{}`", operand)),
+    }
+}
+
+/// Parses a double quoted string, which may contain the `\n`, `\r`, `\t`, `\0`, `\\`, `\"` and `\xNN` escapes.
+fn parse_str(operand: &str) -> Result<Vec<u8>, String> {
+    let invalid = || format!("invalid string `{}`", operand);
+
+    let inner = operand
+        .strip_prefix('"')
+        .and_then(|str| str.strip_suffix('"'))
+        .ok_or_else(invalid)?;
+
+    let mut bytes = Vec::new();
+    let mut chars = inner.chars();
+
+    while let Some(char) = chars.next() {
+        match char {
+            '\\' => bytes.push(match chars.next().ok_or_else(invalid)? {
+                'n' => b'\n',
+                'r' => b'\r',
+                't' => b'\t',
+                '0' => 0,
+                '\\' => b'\\',
+                '"' => b'"',
+                'x' => {
+                    let hex: String = chars.by_ref().take(2).collect();
+                    u8::from_str_radix(&hex, 16).map_err(|_| invalid())?
+                }
+                _ => return Err(invalid()),
+            }),
+            '"' => return Err(invalid()),
+            char => bytes.extend(char.encode_utf8(&mut [0; 4]).as_bytes()),
+        }
+    }
+
+    Ok(bytes)
+}
+
+/// Strips the `//` comment from `line`, unless it's within a string.
+fn strip_comment(line: &str) -> &str {
+    let mut in_str = false;
+    let mut escaped = false;
+
+    for (index, char) in line.char_indices() {
+        match char {
+            _ if escaped => escaped = false,
+            '\\' if in_str => escaped = true,
+            '"' => in_str = !in_str,
+            '/' if !in_str && line[index + 1..].starts_with('/') => return &line[..index],
+            _ => (),
+        }
+    }
+    line
+}
+
+/// Splits `operands` on the commas outside of strings, brackets and braces.
+fn split_operands(operands: &str) -> Vec<&str> {
+    let mut split = Vec::new();
+    let mut depth = 0;
+    let mut in_str = false;
+    let mut escaped = false;
+    let mut start = 0;
+
+    for (index, char) in operands.char_indices() {
+        match char {
+            _ if escaped => escaped = false,
+            '\\' if in_str => escaped = true,
+            '"' => in_str = !in_str,
+            '[' | '{' if !in_str => depth += 1,
+            ']' | '}' if !in_str => depth -= 1,
+            ',' if !in_str && depth == 0 => {
+                split.push(operands[start..index].trim());
+                start = index + 1;
+            }
+            _ => (),
+        }
+    }
+
+    let last = operands[start..].trim();
+
+    if !last.is_empty() || !split.is_empty() {
+        split.push(last);
+    }
+    split
+}
+
+/// The indexing of an `arm` or `arm64` memory operand.
+#[cfg(any(feature = "arm", feature = "arm64"))]
+pub(crate) enum Indexing {
+    /// `[<Rn>{, #<imm>}]`.
+    Offset,
+    /// `[<Rn>, #<imm>]!`.
+    Pre,
+    /// `[<Rn>], #<imm>`.
+    Post,
+}
+
+/// Parses an `arm` or `arm64` memory operand, followed by the immediate of the post-indexed form.
+#[cfg(any(feature = "arm", feature = "arm64"))]
+pub(crate) fn parse_indexed<R: std::str::FromStr>(
+    mem: &str,
+    post: Option<&str>,
+) -> Result<(Indexing, R, i64), String> {
+    let invalid = || format!("invalid memory operand `{}`", mem);
+
+    let (inner, pre) = match mem.strip_suffix('!') {
+        Some(mem) => (mem.trim_end(), true),
+        None => (mem, false),
+    };
+
+    let inner = inner
+        .strip_prefix('[')
+        .and_then(|inner| inner.strip_suffix(']'))
+        .ok_or_else(invalid)?;
+
+    let (base, imm) = match inner.split_once(',') {
+        Some((base, imm)) => (base, parse_int(imm)?),
+        None => (inner, 0),
+    };
+
+    let base = parse_reg(base)?;
+
+    match (pre, post, inner.contains(',')) {
+        (false, None, _) => Ok((Indexing::Offset, base, imm)),
+        (true, None, true) => Ok((Indexing::Pre, base, imm)),
+        (false, Some(post), false) => Ok((Indexing::Post, base, parse_int(post)?)),
+        _ => Err(invalid()),
+    }
+}
+
+/// Parses a register list, e.g. `{r0, r1, r4-r7}` or `{d8-d15}`.
+#[cfg(feature = "arm")]
+pub(crate) fn parse_reg_list<R: std::str::FromStr>(operand: &str) -> Result<Vec<R>, String> {
+    let invalid = || format!("invalid register list `{}`", operand);
+
+    let inner = operand
+        .strip_prefix('{')
+        .and_then(|inner| inner.strip_suffix('}'))
+        .ok_or_else(invalid)?;
+
+    let mut regs = Vec::new();
+
+    for item in inner.split(',') {
+        match item.split_once('-') {
+            Some((first, last)) => {
+                let (first, last) = (first.trim(), last.trim());
+                let prefix = &first[..first
+                    .find(|char: char| char.is_ascii_digit())
+                    .ok_or_else(invalid)?];
+                let number = |reg: &str| {
+                    reg.strip_prefix(prefix)
+                        .and_then(|number| number.parse::<usize>().ok())
+                        .ok_or_else(invalid)
+                };
+
+                for number in number(first)?..=number(last)? {
+                    regs.push(parse_reg(&format!("{}{}", prefix, number))?);
+                }
+            }
+            None => regs.push(parse_reg(item)?),
+        }
+    }
+
+    Ok(regs)
+}
+
+/// Parses a register name, case insensitively.
+#[cfg(any(feature = "arm", feature = "arm64"))]
+pub(crate) fn parse_reg<R: std::str::FromStr>(operand: &str) -> Result<R, String> {
+    operand
+        .trim()
+        .to_ascii_lowercase()
+        .parse()
+        .map_err(|_| format!("invalid register `{}`", operand.trim()))
+}
+
+#[cfg(all(test, feature = "x86"))]
+mod tests {
+    use crate::{x86::TinyAsm, AsmError};
+
+    #[test]
+    fn assembles_directives_and_labels() {
+        let code = TinyAsm::new()
+            .asm(
+                r#"
+                start: jmp short end // skip the data
+                msg: .ascii "a//\"\x01"
+                .byte 1, -1
+                .short 0x1234
+                .align 4
+                end: nop
+                "#,
+            )
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            code,
+            [0xeb, 0x0a, b'a', b'/', b'/', b'"', 1, 1, 0xff, 0x34, 0x12, 0, 0x90]
+        );
+    }
+
+    #[test]
+    fn reports_the_first_invalid_line() {
+        let error = TinyAsm::new()
+            .asm("nop\nmov eax\n.bogus\n")
+            .build()
+            .unwrap_err();
+
+        assert!(matches!(error, AsmError::Syntax(2, _)));
+    }
+}
@@ -0,0 +1,20 @@
+/// The condition codes a conditional jump can be taken under.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cond {
+    O = 0,
+    No = 1,
+    B = 2,
+    Ae = 3,
+    E = 4,
+    Ne = 5,
+    Be = 6,
+    A = 7,
+    S = 8,
+    Ns = 9,
+    P = 10,
+    Np = 11,
+    L = 12,
+    Ge = 13,
+    Le = 14,
+    G = 15,
+}
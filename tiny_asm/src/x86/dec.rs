@@ -0,0 +1,480 @@
+use std::fmt::Display;
+
+use crate::{
+    disasm::{imm, Labels},
+    Decode,
+};
+
+use super::{Family, Op};
+
+impl Decode for Op {
+    fn decode(code: &[u8], offset: usize, labels: &Labels) -> Option<(usize, String)> {
+        decode::<Self>(code, offset, labels)
+    }
+}
+
+#[cfg(feature = "x86_64")]
+impl Decode for crate::x86_64::Op {
+    fn decode(code: &[u8], offset: usize, labels: &Labels) -> Option<(usize, String)> {
+        decode::<Self>(code, offset, labels)
+    }
+}
+
+/// The mnemonics of the `0x80`-`0x83` group, by their `/digit`.
+const ALU: [Option<&str>; 8] = [
+    Some("add"),
+    Some("or"),
+    None,
+    None,
+    Some("and"),
+    Some("sub"),
+    Some("xor"),
+    Some("cmp"),
+];
+
+/// The conditions of the `J<cc>` mnemonics, by their number.
+const CONDS: [&str; 16] = [
+    "o", "no", "b", "ae", "e", "ne", "be", "a", "s", "ns", "p", "np", "l", "ge", "le", "g",
+];
+
+/// The bytes of the instruction being decoded, which is located at `offset`.
+struct Cursor<'a> {
+    code: &'a [u8],
+    offset: usize,
+    len: usize,
+}
+
+impl Cursor<'_> {
+    /// Gets the offset `rel` bytes after the end of the bytes decoded so far.
+    ///
+    /// Returns [`None`] if it's out of the buffer, e.g. the bytes are actually data.
+    fn target(&self, rel: impl Into<i64>) -> Option<i64> {
+        let target = (self.offset + self.len) as i64 + rel.into();
+        (0..=(self.offset + self.code.len()) as i64)
+            .contains(&target)
+            .then_some(target)
+    }
+
+    fn take<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let bytes = self.code.get(self.len..self.len + N)?.try_into().ok()?;
+        self.len += N;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take().map(u8::from_le_bytes)
+    }
+
+    fn i8(&mut self) -> Option<i8> {
+        self.take().map(i8::from_le_bytes)
+    }
+
+    fn i32(&mut self) -> Option<i32> {
+        self.take().map(i32::from_le_bytes)
+    }
+
+    fn i64(&mut self) -> Option<i64> {
+        self.take().map(i64::from_le_bytes)
+    }
+}
+
+/// The `r/m` operand of a `ModRM` encoded instruction.
+enum Rm<R> {
+    Reg(R),
+    Mem(Mem<R>),
+}
+
+/// A decoded memory operand.
+struct Mem<R> {
+    base: Base<R>,
+    index: Option<(R, u8)>,
+    disp: Disp,
+}
+
+enum Base<R> {
+    None,
+    Reg(R),
+    Rip,
+}
+
+enum Disp {
+    None,
+    I8(i8),
+    I32(i32),
+}
+
+/// Decodes one of the instructions the `O` mode encodes into the Intel syntax.
+fn decode<O: Family>(code: &[u8], offset: usize, labels: &Labels) -> Option<(usize, String)> {
+    let reg = |number: u8| O::Reg::try_from(number).ok();
+
+    let mut cur = Cursor {
+        code,
+        offset,
+        len: 0,
+    };
+    let mut opcode = cur.u8()?;
+    let mut rex = 0;
+
+    if O::LONG && opcode & 0xf0 == 0x40 {
+        rex = opcode;
+        opcode = cur.u8()?;
+
+        // An empty `REX` prefix is never pushed.
+        if rex == 0x40 {
+            return None;
+        }
+    }
+
+    // Whether the operand size is the native one, e.g. `REX.W` is set in 64 bit mode.
+    let native = rex & 8 != 0 || !O::LONG;
+    // Whether only `REX.B` may be set.
+    let short = rex & !1 == 0 || rex == 0x41;
+
+    let text = match opcode {
+        0x01 | 0x09 | 0x21 | 0x29 | 0x31 | 0x39 | 0x85 | 0x89 if native => {
+            let (src, dst) = modrm::<O>(&mut cur, rex)?;
+            let mnemonic = match opcode {
+                0x01 => "add",
+                0x09 => "or",
+                0x21 => "and",
+                0x29 => "sub",
+                0x31 => "xor",
+                0x39 => "cmp",
+                0x85 => "test",
+                _ => "mov",
+            };
+
+            match dst {
+                Rm::Reg(dst) => format!("{} {}, {}", mnemonic, dst, reg(src)?),
+                Rm::Mem(mem) if opcode == 0x89 => {
+                    let mem = fmt_mem(&mem, &cur, labels)?;
+                    format!("mov {}, {}", mem, reg(src)?)
+                }
+                Rm::Mem(_) => return None,
+            }
+        }
+        0x8b | 0x8d if native => match modrm::<O>(&mut cur, rex)? {
+            (dst, Rm::Mem(mem)) => {
+                let mnemonic = if opcode == 0x8b { "mov" } else { "lea" };
+                let mem = fmt_mem(&mem, &cur, labels)?;
+                format!("{} {}, {}", mnemonic, reg(dst)?, mem)
+            }
+            _ => return None,
+        },
+        0x81 | 0x83 if native => match modrm::<O>(&mut cur, rex)? {
+            (digit, Rm::Reg(dst)) => {
+                let mnemonic = ALU.get(usize::from(digit)).copied().flatten()?;
+
+                if opcode == 0x83 {
+                    format!("{} {}, {}", mnemonic, dst, imm(cur.i8()?.into()))
+                } else {
+                    let value = cur.i32()?;
+                    // The shorter forms are always preferred, unless the immediate is the offset of a label.
+                    let long = i8::try_from(value).is_err() && Into::<u8>::into(dst) != 0;
+                    let label = matches!(mnemonic, "add" | "sub")
+                        .then(|| labels.get(offset, value.into()))
+                        .flatten()
+                        // An immediate which happens to be the offset of a local label is likely just a number.
+                        .filter(|label| !long || !label.starts_with(|c: char| c.is_ascii_digit()));
+
+                    match label {
+                        Some(label) => format!("{} {}, {}", mnemonic, dst, label),
+                        None if !long => return None,
+                        None => format!("{} {}, {}", mnemonic, dst, imm(value.into())),
+                    }
+                }
+            }
+            _ => return None,
+        },
+        0x05 | 0x0d | 0x25 | 0x2d | 0x35 | 0x3d if native && rex & !8 == 0 => {
+            let mnemonic = ALU[usize::from(opcode >> 3)]?;
+
+            match cur.i32()? {
+                // The shorter form is always preferred.
+                value if i8::try_from(value).is_ok() => return None,
+                value => format!("{} {}, {}", mnemonic, reg(0)?, imm(value.into())),
+            }
+        }
+        0xc7 if native => match modrm::<O>(&mut cur, rex)? {
+            (0, Rm::Reg(dst)) if O::LONG => format!("mov {}, {}", dst, imm(cur.i32()?.into())),
+            (0, Rm::Mem(mem)) => {
+                let value = cur.i32()?;
+                let mem = fmt_mem(&mem, &cur, labels)?;
+                format!("mov {}, {}", mem, imm(value.into()))
+            }
+            _ => return None,
+        },
+        0xb8..=0xbf if O::LONG && native && rex & !9 == 0 => {
+            let dst = reg(opcode & 7 | (rex & 1) << 3)?;
+
+            match cur.i64()? {
+                // The shorter form is always preferred.
+                value if i32::try_from(value).is_ok() => return None,
+                value => format!("mov {}, {}", dst, imm(value)),
+            }
+        }
+        0xb8..=0xbf if !O::LONG => {
+            let dst = reg(opcode & 7)?;
+            format!("mov {}, {}", dst, imm(cur.i32()? as u32 as i64))
+        }
+        0x50..=0x57 if short => format!("push {}", reg(opcode & 7 | (rex & 1) << 3)?),
+        0x58..=0x5f if short => format!("pop {}", reg(opcode & 7 | (rex & 1) << 3)?),
+        0x6a if rex == 0 => format!("push {}", imm(cur.i8()?.into())),
+        0x68 if rex == 0 => match cur.i32()? {
+            // The shorter form is always preferred.
+            value if i8::try_from(value).is_ok() => return None,
+            value => format!("push {}", imm(value.into())),
+        },
+        0xe8 | 0xe9 if rex == 0 => {
+            let rel = cur.i32()?;
+            let target = cur.target(rel)?;
+            let mnemonic = if opcode == 0xe8 { "call" } else { "jmp" };
+
+            format!("{} {}", mnemonic, labels.get(offset, target)?)
+        }
+        0xeb | 0x70..=0x7f if rex == 0 => {
+            let rel = cur.i8()?;
+            let target = cur.target(rel)?;
+            let mnemonic = match opcode {
+                0xeb => "jmp".to_string(),
+                _ => format!("j{}", CONDS[usize::from(opcode & 0xf)]),
+            };
+
+            format!("{} short {}", mnemonic, labels.get(offset, target)?)
+        }
+        0x0f if rex == 0 => match cur.u8()? {
+            0x05 => "syscall".into(),
+            opcode @ 0x80..=0x8f => {
+                let rel = cur.i32()?;
+                let target = cur.target(rel)?;
+
+                format!(
+                    "j{} {}",
+                    CONDS[usize::from(opcode & 0xf)],
+                    labels.get(offset, target)?
+                )
+            }
+            _ => return None,
+        },
+        0xff if rex & 8 == 0 => {
+            let (digit, rm) = modrm::<O>(&mut cur, rex)?;
+            let mnemonic = match digit {
+                2 => "call",
+                4 => "jmp",
+                _ => return None,
+            };
+
+            match rm {
+                Rm::Reg(reg) => format!("{} {}", mnemonic, reg),
+                Rm::Mem(mem) => {
+                    let mem = fmt_mem(&mem, &cur, labels)?;
+                    format!("{} {}", mnemonic, mem)
+                }
+            }
+        }
+        0xcd if rex == 0 => format!("int {}", imm(cur.u8()?.into())),
+        0xc3 if rex == 0 => "ret".into(),
+        0x90 if rex == 0 => "nop".into(),
+        _ => return None,
+    };
+
+    Some((cur.len, text))
+}
+
+/// Decodes `<ModRM> [SIB] [disp]`, returning the `reg` field (either a register or an opcode extension)
+/// and the `r/m` operand.
+fn modrm<O: Family>(cur: &mut Cursor, rex: u8) -> Option<(u8, Rm<O::Reg>)> {
+    let reg = |number: u8| O::Reg::try_from(number).ok();
+
+    let modrm = cur.u8()?;
+    let (mode, field, rm) = (modrm >> 6, (modrm >> 3 & 7) | (rex & 4) << 1, modrm & 7);
+
+    if mode == 3 {
+        return Some((field, Rm::Reg(reg(rm | (rex & 1) << 3)?)));
+    }
+
+    let (base, index) = match rm {
+        4 => {
+            let sib = cur.u8()?;
+            let index = match (sib >> 3 & 7) | (rex & 2) << 2 {
+                4 => None,
+                index => Some((reg(index)?, 1 << (sib >> 6))),
+            };
+
+            match sib & 7 {
+                5 if mode == 0 => (Base::None, index),
+                base => (Base::Reg(reg(base | (rex & 1) << 3)?), index),
+            }
+        }
+        5 if mode == 0 && O::LONG => (Base::Rip, None),
+        5 if mode == 0 => (Base::None, None),
+        base => (Base::Reg(reg(base | (rex & 1) << 3)?), None),
+    };
+
+    let disp = match (mode, &base) {
+        (0, Base::Reg(_)) => Disp::None,
+        (0, _) | (2, _) => Disp::I32(cur.i32()?),
+        _ => Disp::I8(cur.i8()?),
+    };
+
+    Some((field, Rm::Mem(Mem { base, index, disp })))
+}
+
+/// Formats a memory operand of the instruction, once it has been decoded.
+///
+/// Returns [`None`] if it's RIP-relative, and it refers out of the buffer.
+fn fmt_mem<R: Display>(mem: &Mem<R>, cur: &Cursor, labels: &Labels) -> Option<String> {
+    let offset = cur.offset;
+    let mut terms = Vec::new();
+
+    match &mem.base {
+        Base::None => (),
+        Base::Reg(base) => terms.push(base.to_string()),
+        Base::Rip => terms.push("rip".to_string()),
+    }
+
+    if let Some((index, scale)) = &mem.index {
+        terms.push(format!("{}*{}", index, scale));
+    }
+
+    let disp = match (&mem.base, &mem.disp) {
+        (_, Disp::None) => None,
+        (_, Disp::I8(disp)) => Some(imm((*disp).into())),
+        (Base::Rip, Disp::I32(disp)) => Some(
+            labels
+                .get(offset, cur.target(*disp)?)
+                .unwrap_or_else(|| imm((*disp).into())),
+        ),
+        // The shorter displacement is always preferred, unless it's the offset of a label.
+        (Base::Reg(_), Disp::I32(disp)) if i8::try_from(*disp).is_ok() => Some(
+            labels
+                .get(offset, (*disp).into())
+                .unwrap_or_else(|| imm((*disp).into())),
+        ),
+        (_, Disp::I32(disp)) => Some(imm((*disp).into())),
+    };
+
+    let mut text = terms.join(" + ");
+
+    match disp {
+        Some(disp) if text.is_empty() => text = disp,
+        Some(disp) => match disp.strip_prefix('-') {
+            Some(disp) => text = format!("{} - {}", text, disp),
+            None => text = format!("{} + {}", text, disp),
+        },
+        None => (),
+    }
+
+    Some(format!("[{}]", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        disassemble,
+        x86::{Cond, Mem, Op, Reg::*, TinyAsm},
+    };
+
+    #[test]
+    fn decodes_what_it_encodes() {
+        let (code, labels) = TinyAsm::new()
+            .label("start")
+            .call("1f")
+            .label("1")
+            .pop(ebx)
+            .subl(ebx, "1b")
+            .addl(eax, "data")
+            .lea(ecx, Mem::base(ebx).label("data"))
+            .lea(esi, Mem::base(eax).index(edi, 2).disp(-4))
+            .movrm(edx, Mem::base(esp).disp(0x100))
+            .movmr(Mem::abs(0x1000), eax)
+            .movmi(Mem::base(ebp).disp(8), -1)
+            .movi(eax, 0xffffffff)
+            .addi(eax, 1000)
+            .andi(ebx, -0x1000)
+            .cmpi(eax, 47)
+            .xorr(eax, eax)
+            .testr(eax, eax)
+            .push(ebp)
+            .pushi(1)
+            .pushi(-200)
+            .callr(eax)
+            .jmpm(Mem::base(eax))
+            .jcc(Cond::Ne, "start")
+            .jccs(Cond::E, "start")
+            .jmps("data")
+            .int(0x80)
+            .nop()
+            .ret()
+            .label("data")
+            .asciiz("/tmp")
+            .build_with_labels()
+            .unwrap();
+
+        let listing = disassemble::<Op>(&code, &labels);
+
+        assert!(listing.contains("sub ebx, 1b"), "{}", listing);
+        assert_eq!(
+            TinyAsm::new().asm(&listing).build(),
+            Ok(code),
+            "{}",
+            listing
+        );
+    }
+
+    #[cfg(feature = "x86_64")]
+    #[test]
+    fn decodes_what_it_encodes_in_long_mode() {
+        use crate::x86_64::{Mem, Op, Reg::*, TinyAsm};
+
+        let (code, labels) = TinyAsm::new()
+            .label("start")
+            .movr(rdi, r15)
+            .movi(rax, 2)
+            .movi(r9, -1)
+            .movi(r10, 0x1122334455667788)
+            .lea(rsi, Mem::rip("path"))
+            .movrm(rdx, Mem::rip("len"))
+            .movmr(Mem::base(r12).index(r13, 8), r8)
+            .subi(rsp, 0x1000)
+            .push(r15)
+            .pop(rbx)
+            .callm(Mem::base(rsp).disp(8))
+            .jmpr(r11)
+            .syscall()
+            .jmp("start")
+            .label("path")
+            .asciiz("/tmp")
+            .label("len")
+            .bytes(&[4, 0, 0, 0, 0, 0, 0, 0])
+            .build_with_labels()
+            .unwrap();
+
+        let listing = disassemble::<Op>(&code, &labels);
+
+        assert!(listing.contains("[rip + len]"), "{}", listing);
+        assert_eq!(
+            TinyAsm::new().asm(&listing).build(),
+            Ok(code),
+            "{}",
+            listing
+        );
+    }
+
+    #[test]
+    fn rejects_what_it_does_not_encode() {
+        // A long `PUSH` of a short immediate, a long `ADD` of a short one and `SUB <r/m>, <reg>` into memory.
+        for code in [
+            &[0x68, 0x01, 0x00, 0x00, 0x00][..],
+            &[0x81, 0xc1, 0x01, 0x00, 0x00, 0x00],
+            &[0x29, 0x08],
+        ] {
+            assert_eq!(
+                disassemble::<Op>(code, &[]).matches(".byte").count(),
+                1,
+                "{:x?}",
+                code
+            );
+        }
+    }
+}
@@ -0,0 +1,562 @@
+use crate::{AsmError, Label, TinyAsm};
+
+use super::{
+    mem::{Base, Disp},
+    Cond, Family, Memory, Op,
+};
+
+/// The `r/m` operand of a `ModRM` encoded instruction.
+enum Rm<R> {
+    Reg(R),
+    Mem(Memory<R>),
+}
+
+/// The immediate operand which follows the `ModRM` operands.
+enum Imm {
+    None,
+    I8(i8),
+    I32(i32),
+    Label(Label),
+}
+
+impl Imm {
+    #[cfg(feature = "x86_64")]
+    fn size(&self) -> u8 {
+        match self {
+            Imm::None => 0,
+            Imm::I8(_) => 1,
+            Imm::I32(_) | Imm::Label(_) => 4,
+        }
+    }
+}
+
+/// The displacement of a memory operand.
+enum Field {
+    Disp8(i8),
+    Disp32(i32),
+    /// The offset of a label within the buffer.
+    Ref(Label),
+    /// The offset of a label relative to the end of the instruction.
+    #[cfg(feature = "x86_64")]
+    Rel(Label),
+}
+
+/// The operations of the `0x80`-`0x83` group, e.g. the `/digit` of their immediate form.
+#[derive(Clone, Copy)]
+enum Alu {
+    Add = 0,
+    Or = 1,
+    And = 4,
+    Sub = 5,
+    Xor = 6,
+    Cmp = 7,
+}
+
+/// https://www.intel.com/content/www/us/en/developer/articles/technical/intel-sdm.html
+///
+/// Every operation works on the native operand size: 32 bit for `x86`, 64 bit for `x86-64`.
+/// Branches to labels are always encoded with a `rel32` operand, unless the short (`rel8`) variant is used.
+impl<O: Family> TinyAsm<O, 4> {
+    /// Encoding of ADD: `ADD <r/m>, <reg>`.
+    pub fn addr(self, dst: O::Reg, src: O::Reg) -> Self {
+        self.modrm(&[0x01], O::LONG, src.into(), Rm::Reg(dst), Imm::None)
+    }
+
+    /// Encoding of ADD: `ADD <r/m>, <imm>`.
+    pub fn addi(self, dst: O::Reg, imm: i32) -> Self {
+        self.alui(Alu::Add, dst, imm)
+    }
+
+    /// Encoding of ADD: `ADD <r/m>, <imm32>`, where `<imm32>` is the offset of `label` within the buffer.
+    pub fn addl(mut self, dst: O::Reg, label: impl Into<Label>) -> Self {
+        let label = self.refer(label);
+        self.modrm(
+            &[0x81],
+            O::LONG,
+            Alu::Add as u8,
+            Rm::Reg(dst),
+            Imm::Label(label),
+        )
+    }
+
+    /// Encoding of AND: `AND <r/m>, <reg>`.
+    pub fn andr(self, dst: O::Reg, src: O::Reg) -> Self {
+        self.modrm(&[0x21], O::LONG, src.into(), Rm::Reg(dst), Imm::None)
+    }
+
+    /// Encoding of AND: `AND <r/m>, <imm>`.
+    pub fn andi(self, dst: O::Reg, imm: i32) -> Self {
+        self.alui(Alu::And, dst, imm)
+    }
+
+    /// Encoding of CALL: `CALL <rel32>`.
+    pub fn call(self, label: impl Into<Label>) -> Self {
+        self.rel32(&[0xe8], label)
+    }
+
+    /// Encoding of CALL: `CALL <r/m>`, with a register operand.
+    pub fn callr(self, reg: O::Reg) -> Self {
+        self.modrm(&[0xff], false, 2, Rm::Reg(reg), Imm::None)
+    }
+
+    /// Encoding of CALL: `CALL <r/m>`, with a memory operand.
+    pub fn callm(self, mem: Memory<O::Reg>) -> Self {
+        self.modrm(&[0xff], false, 2, Rm::Mem(mem), Imm::None)
+    }
+
+    /// Encoding of CMP: `CMP <r/m>, <reg>`.
+    pub fn cmpr(self, dst: O::Reg, src: O::Reg) -> Self {
+        self.modrm(&[0x39], O::LONG, src.into(), Rm::Reg(dst), Imm::None)
+    }
+
+    /// Encoding of CMP: `CMP <r/m>, <imm>`.
+    pub fn cmpi(self, dst: O::Reg, imm: i32) -> Self {
+        self.alui(Alu::Cmp, dst, imm)
+    }
+
+    /// Encoding of INT: `INT <imm8>`.
+    pub fn int(mut self, imm: u8) -> Self {
+        self.buf.extend([0xcd, imm]);
+        self
+    }
+
+    /// Encoding of Jcc: `J<cc> <rel32>`.
+    pub fn jcc(self, cond: Cond, label: impl Into<Label>) -> Self {
+        self.rel32(&[0x0f, 0x80 | cond as u8], label)
+    }
+
+    /// Encoding of Jcc: `J<cc> <rel8>`.
+    pub fn jccs(self, cond: Cond, label: impl Into<Label>) -> Self {
+        self.rel8(0x70 | cond as u8, label)
+    }
+
+    /// Encoding of JMP: `JMP <rel32>`.
+    pub fn jmp(self, label: impl Into<Label>) -> Self {
+        self.rel32(&[0xe9], label)
+    }
+
+    /// Encoding of JMP: `JMP <rel8>`.
+    pub fn jmps(self, label: impl Into<Label>) -> Self {
+        self.rel8(0xeb, label)
+    }
+
+    /// Encoding of JMP: `JMP <r/m>`, with a register operand.
+    pub fn jmpr(self, reg: O::Reg) -> Self {
+        self.modrm(&[0xff], false, 4, Rm::Reg(reg), Imm::None)
+    }
+
+    /// Encoding of JMP: `JMP <r/m>`, with a memory operand.
+    pub fn jmpm(self, mem: Memory<O::Reg>) -> Self {
+        self.modrm(&[0xff], false, 4, Rm::Mem(mem), Imm::None)
+    }
+
+    /// Encoding of LEA: `LEA <reg>, <m>`.
+    pub fn lea(self, dst: O::Reg, mem: Memory<O::Reg>) -> Self {
+        self.modrm(&[0x8d], O::LONG, dst.into(), Rm::Mem(mem), Imm::None)
+    }
+
+    /// Encoding of MOV: `MOV <r/m>, <reg>`.
+    pub fn movr(self, dst: O::Reg, src: O::Reg) -> Self {
+        self.modrm(&[0x89], O::LONG, src.into(), Rm::Reg(dst), Imm::None)
+    }
+
+    /// Encoding of MOV: `MOV <reg>, <imm32>` on `x86`, which accepts both signed and unsigned values.
+    /// On `x86-64`, `MOV <r/m>, <imm32>` (sign-extended) if `imm` fits, otherwise `MOV <reg>, <imm64>`.
+    pub fn movi(mut self, dst: O::Reg, imm: i64) -> Self {
+        let reg: u8 = dst.into();
+
+        if O::LONG {
+            match i32::try_from(imm) {
+                Ok(imm) => self.modrm(&[0xc7], true, 0, Rm::Reg(dst), Imm::I32(imm)),
+                Err(_) => {
+                    self.buf.extend([0x48 | reg >> 3, 0xb8 | reg & 7]);
+                    self.buf.extend(imm.to_le_bytes());
+                    self
+                }
+            }
+        } else if imm < i32::MIN.into() || imm > u32::MAX.into() {
+            self.error.get_or_insert(AsmError::ImmOutOfRange(imm));
+            self
+        } else {
+            self.buf.push(0xb8 | reg);
+            self.buf.extend((imm as u32).to_le_bytes());
+            self
+        }
+    }
+
+    /// Encoding of MOV: `MOV <reg>, <r/m>`, e.g. a load.
+    pub fn movrm(self, dst: O::Reg, mem: Memory<O::Reg>) -> Self {
+        self.modrm(&[0x8b], O::LONG, dst.into(), Rm::Mem(mem), Imm::None)
+    }
+
+    /// Encoding of MOV: `MOV <r/m>, <reg>`, e.g. a store.
+    pub fn movmr(self, mem: Memory<O::Reg>, src: O::Reg) -> Self {
+        self.modrm(&[0x89], O::LONG, src.into(), Rm::Mem(mem), Imm::None)
+    }
+
+    /// Encoding of MOV: `MOV <r/m>, <imm32>` (sign-extended on `x86-64`), e.g. a store of an immediate.
+    pub fn movmi(self, mem: Memory<O::Reg>, imm: i32) -> Self {
+        self.modrm(&[0xc7], O::LONG, 0, Rm::Mem(mem), Imm::I32(imm))
+    }
+
+    /// Encoding of NOP: `NOP`.
+    pub fn nop(mut self) -> Self {
+        self.buf.push(0x90);
+        self
+    }
+
+    /// Encoding of OR: `OR <r/m>, <reg>`.
+    pub fn orr(self, dst: O::Reg, src: O::Reg) -> Self {
+        self.modrm(&[0x09], O::LONG, src.into(), Rm::Reg(dst), Imm::None)
+    }
+
+    /// Encoding of OR: `OR <r/m>, <imm>`.
+    pub fn ori(self, dst: O::Reg, imm: i32) -> Self {
+        self.alui(Alu::Or, dst, imm)
+    }
+
+    /// Encoding of POP: `POP <reg>`.
+    pub fn pop(self, reg: O::Reg) -> Self {
+        self.short(0x58, reg)
+    }
+
+    /// Encoding of PUSH: `PUSH <reg>`.
+    pub fn push(self, reg: O::Reg) -> Self {
+        self.short(0x50, reg)
+    }
+
+    /// Encoding of PUSH: `PUSH <imm8>` if `imm` fits, otherwise `PUSH <imm32>` (sign-extended on `x86-64`).
+    pub fn pushi(mut self, imm: i32) -> Self {
+        match i8::try_from(imm) {
+            Ok(imm) => self.buf.extend([0x6a, imm as u8]),
+            Err(_) => {
+                self.buf.push(0x68);
+                self.buf.extend(imm.to_le_bytes());
+            }
+        }
+        self
+    }
+
+    /// Encoding of RET: `RET`.
+    pub fn ret(mut self) -> Self {
+        self.buf.push(0xc3);
+        self
+    }
+
+    /// Encoding of SUB: `SUB <r/m>, <reg>`.
+    pub fn subr(self, dst: O::Reg, src: O::Reg) -> Self {
+        self.modrm(&[0x29], O::LONG, src.into(), Rm::Reg(dst), Imm::None)
+    }
+
+    /// Encoding of SUB: `SUB <r/m>, <imm>`.
+    pub fn subi(self, dst: O::Reg, imm: i32) -> Self {
+        self.alui(Alu::Sub, dst, imm)
+    }
+
+    /// Encoding of SUB: `SUB <r/m>, <imm32>`, where `<imm32>` is the offset of `label` within the buffer.
+    pub fn subl(mut self, dst: O::Reg, label: impl Into<Label>) -> Self {
+        let label = self.refer(label);
+        self.modrm(
+            &[0x81],
+            O::LONG,
+            Alu::Sub as u8,
+            Rm::Reg(dst),
+            Imm::Label(label),
+        )
+    }
+
+    /// Encoding of SYSCALL: `SYSCALL`.
+    pub fn syscall(mut self) -> Self {
+        self.buf.extend([0x0f, 0x05]);
+        self
+    }
+
+    /// Encoding of TEST: `TEST <r/m>, <reg>`.
+    pub fn testr(self, dst: O::Reg, src: O::Reg) -> Self {
+        self.modrm(&[0x85], O::LONG, src.into(), Rm::Reg(dst), Imm::None)
+    }
+
+    /// Encoding of XOR: `XOR <r/m>, <reg>`.
+    pub fn xorr(self, dst: O::Reg, src: O::Reg) -> Self {
+        self.modrm(&[0x31], O::LONG, src.into(), Rm::Reg(dst), Imm::None)
+    }
+
+    /// Encoding of XOR: `XOR <r/m>, <imm>`.
+    pub fn xori(self, dst: O::Reg, imm: i32) -> Self {
+        self.alui(Alu::Xor, dst, imm)
+    }
+
+    /// Encodes an operation of the `0x80`-`0x83` group with the shortest immediate form, e.g. `<imm8>`
+    /// if `imm` fits, then the accumulator form.
+    fn alui(mut self, alu: Alu, dst: O::Reg, imm: i32) -> Self {
+        let digit = alu as u8;
+
+        match i8::try_from(imm) {
+            Ok(imm) => self.modrm(&[0x83], O::LONG, digit, Rm::Reg(dst), Imm::I8(imm)),
+            Err(_) if Into::<u8>::into(dst) == 0 => {
+                if O::LONG {
+                    self.buf.push(0x48);
+                }
+                self.buf.push(digit << 3 | 0x05);
+                self.buf.extend(imm.to_le_bytes());
+                self
+            }
+            Err(_) => self.modrm(&[0x81], O::LONG, digit, Rm::Reg(dst), Imm::I32(imm)),
+        }
+    }
+
+    /// Encodes an instruction whose register is added to the opcode, e.g. `PUSH <reg>`.
+    fn short(mut self, opcode: u8, reg: O::Reg) -> Self {
+        let reg: u8 = reg.into();
+
+        if reg >> 3 != 0 {
+            self.buf.push(0x41);
+        }
+        self.buf.push(opcode | reg & 7);
+        self
+    }
+
+    /// Encodes `opcode` followed by a `rel32` branch target.
+    fn rel32(mut self, opcode: &[u8], label: impl Into<Label>) -> Self {
+        let label = self.refer(label);
+        self.buf.extend(opcode);
+        self.reloc(Op::Rel(label, 0).into());
+        self
+    }
+
+    /// Encodes `opcode` followed by a `rel8` branch target.
+    fn rel8(mut self, opcode: u8, label: impl Into<Label>) -> Self {
+        let label = self.refer(label);
+        self.buf.push(opcode);
+        self.reloc(Op::Rel8(label).into());
+        self
+    }
+
+    /// Encodes `[REX] <opcode> <ModRM> [SIB] [disp] [imm]`, where `reg` is either a register or an opcode extension.
+    /// The `REX` prefix is only pushed in 64 bit mode, when `w` is set or an extended register is used.
+    fn modrm(mut self, opcode: &[u8], w: bool, reg: u8, rm: Rm<O::Reg>, imm: Imm) -> Self {
+        let mut rex = u8::from(w) << 3 | (reg >> 3) << 2;
+        let reg = (reg & 7) << 3;
+
+        let (modrm, sib, disp) = match rm {
+            Rm::Reg(rm) => {
+                let rm: u8 = rm.into();
+                rex |= rm >> 3;
+
+                (0xc0 | reg | rm & 7, None, None)
+            }
+            Rm::Mem(mem) => {
+                let index = match mem.index {
+                    Some((index, scale)) => {
+                        let index: u8 = index.into();
+                        let scale = match scale {
+                            1 => 0,
+                            2 => 1,
+                            4 => 2,
+                            8 => 3,
+                            _ => return self.invalid("the scale must be either 1, 2, 4 or 8"),
+                        };
+
+                        if index == 4 {
+                            return self.invalid("the stack pointer can't be used as index");
+                        }
+                        rex |= (index >> 3) << 1;
+
+                        Some(scale << 6 | (index & 7) << 3)
+                    }
+                    None => None,
+                };
+
+                let disp = match mem.disp {
+                    Disp::Imm(disp) => Field::Disp32(disp),
+                    Disp::Label(label) => Field::Ref(self.refer(label)),
+                };
+
+                match mem.base {
+                    #[cfg(feature = "x86_64")]
+                    Base::Rip => match (index, disp) {
+                        (None, Field::Ref(label)) => (0x05 | reg, None, Some(Field::Rel(label))),
+                        _ => return self.invalid("RIP-relative operands only take a label"),
+                    },
+                    Base::None => match index {
+                        Some(index) => (0x04 | reg, Some(index | 0x05), Some(disp)),
+                        None if O::LONG => (0x04 | reg, Some(0x25), Some(disp)),
+                        None => (0x05 | reg, None, Some(disp)),
+                    },
+                    Base::Reg(base) => {
+                        let base: u8 = base.into();
+                        rex |= base >> 3;
+
+                        let (mode, disp) = match disp {
+                            Field::Disp32(0) if base & 7 != 5 => (0x00, None),
+                            Field::Disp32(disp) if i8::try_from(disp).is_ok() => {
+                                (0x40, Some(Field::Disp8(disp as i8)))
+                            }
+                            disp => (0x80, Some(disp)),
+                        };
+
+                        match index {
+                            Some(index) => (mode | 0x04 | reg, Some(index | base & 7), disp),
+                            None if base & 7 == 4 => (mode | 0x04 | reg, Some(0x24), disp),
+                            None => (mode | reg | base & 7, None, disp),
+                        }
+                    }
+                }
+            }
+        };
+
+        if O::LONG && rex != 0 {
+            self.buf.push(0x40 | rex);
+        }
+        self.buf.extend(opcode);
+        self.buf.push(modrm);
+        self.buf.extend(sib);
+
+        match disp {
+            Some(Field::Disp8(disp)) => self.buf.push(disp as u8),
+            Some(Field::Disp32(disp)) => self.buf.extend(disp.to_le_bytes()),
+            Some(Field::Ref(label)) => self.reloc(Op::Ref(label).into()),
+            #[cfg(feature = "x86_64")]
+            Some(Field::Rel(label)) => self.reloc(Op::Rel(label, imm.size()).into()),
+            None => (),
+        }
+
+        match imm {
+            Imm::None => (),
+            Imm::I8(imm) => self.buf.push(imm as u8),
+            Imm::I32(imm) => self.buf.extend(imm.to_le_bytes()),
+            Imm::Label(label) => self.reloc(Op::Ref(label).into()),
+        }
+        self
+    }
+
+    fn invalid(mut self, reason: &'static str) -> Self {
+        self.error.get_or_insert(AsmError::InvalidOperand(reason));
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        x86::{Cond, Mem, Reg::*, TinyAsm},
+        AsmError,
+    };
+
+    /// The expected encodings are the ones emitted by `llvm-mc -triple=i386`.
+    #[test]
+    fn encodes_like_llvm() {
+        let code = TinyAsm::new()
+            .movi(eax, 5)
+            .movi(ecx, -1)
+            .movi(edx, 0xf7f1b579)
+            .movr(ebx, edi)
+            .movrm(eax, Mem::base(ebp))
+            .movmr(Mem::base(esp), ecx)
+            .movrm(edx, Mem::abs(0x1000))
+            .movmi(Mem::base(ebx), -1)
+            .lea(esp, Mem::base(esp).disp(-128))
+            .lea(ebx, Mem::base(esp).disp(-129))
+            .lea(esi, Mem::base(eax).index(edi, 2).disp(-4))
+            .lea(ecx, Mem::base(ebx).disp(0x100))
+            .push(ebp)
+            .pop(ebx)
+            .pushi(1)
+            .pushi(-200)
+            .callr(eax)
+            .jmpr(esp)
+            .addi(ecx, 128)
+            .addi(eax, 1000)
+            .andi(ebx, -4096)
+            .subr(ecx, ebx)
+            .cmpi(eax, 47)
+            .testr(eax, eax)
+            .int(0x80)
+            .ret()
+            .build()
+            .unwrap();
+
+        let expected: &[&[u8]] = &[
+            &[0xb8, 0x05, 0x00, 0x00, 0x00],
+            &[0xb9, 0xff, 0xff, 0xff, 0xff],
+            &[0xba, 0x79, 0xb5, 0xf1, 0xf7],
+            &[0x89, 0xfb],
+            &[0x8b, 0x45, 0x00],
+            &[0x89, 0x0c, 0x24],
+            &[0x8b, 0x15, 0x00, 0x10, 0x00, 0x00],
+            &[0xc7, 0x03, 0xff, 0xff, 0xff, 0xff],
+            &[0x8d, 0x64, 0x24, 0x80],
+            &[0x8d, 0x9c, 0x24, 0x7f, 0xff, 0xff, 0xff],
+            &[0x8d, 0x74, 0x78, 0xfc],
+            &[0x8d, 0x8b, 0x00, 0x01, 0x00, 0x00],
+            &[0x55],
+            &[0x5b],
+            &[0x6a, 0x01],
+            &[0x68, 0x38, 0xff, 0xff, 0xff],
+            &[0xff, 0xd0],
+            &[0xff, 0xe4],
+            &[0x81, 0xc1, 0x80, 0x00, 0x00, 0x00],
+            &[0x05, 0xe8, 0x03, 0x00, 0x00],
+            &[0x81, 0xe3, 0x00, 0xf0, 0xff, 0xff],
+            &[0x29, 0xd9],
+            &[0x83, 0xf8, 0x2f],
+            &[0x85, 0xc0],
+            &[0xcd, 0x80],
+            &[0xc3],
+        ];
+
+        assert_eq!(code, expected.concat());
+    }
+
+    #[test]
+    fn encodes_label_references() {
+        let code = TinyAsm::new()
+            .label("start")
+            .call("1f")
+            .label("1")
+            .pop(ebx)
+            .subl(ebx, "1b")
+            .lea(ecx, Mem::base(ebx).label("data"))
+            .jcc(Cond::Ne, "start")
+            .jccs(Cond::E, "start")
+            .jmps("data")
+            .label("data")
+            .build()
+            .unwrap();
+
+        let expected: &[&[u8]] = &[
+            &[0xe8, 0x00, 0x00, 0x00, 0x00],
+            &[0x5b],
+            &[0x81, 0xeb, 0x05, 0x00, 0x00, 0x00],
+            &[0x8d, 0x8b, 0x1c, 0x00, 0x00, 0x00],
+            &[0x0f, 0x85, 0xe8, 0xff, 0xff, 0xff],
+            &[0x74, 0xe6],
+            &[0xeb, 0x00],
+        ];
+
+        assert_eq!(code, expected.concat());
+    }
+
+    #[test]
+    fn rejects_invalid_operands() {
+        let result = TinyAsm::new()
+            .lea(eax, Mem::base(eax).index(esp, 1))
+            .build();
+        assert!(matches!(result, Err(AsmError::InvalidOperand(_))));
+
+        let result = TinyAsm::new()
+            .lea(eax, Mem::base(eax).index(ecx, 3))
+            .build();
+        assert!(matches!(result, Err(AsmError::InvalidOperand(_))));
+
+        let result = TinyAsm::new().movi(eax, 1 << 32).build();
+        assert_eq!(result, Err(AsmError::ImmOutOfRange(1 << 32)));
+
+        let result = TinyAsm::new()
+            .jmps("far")
+            .bytes(&[0x90; 128])
+            .label("far")
+            .build();
+        assert_eq!(result, Err(AsmError::ImmOutOfRange(128)));
+    }
+}
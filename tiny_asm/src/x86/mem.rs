@@ -0,0 +1,74 @@
+use crate::Label;
+
+/// A memory operand, e.g. `[base + index * scale + disp]`, generic over the registers of the mode.
+#[derive(Clone, Debug)]
+pub struct Memory<R> {
+    pub(crate) base: Base<R>,
+    pub(crate) index: Option<(R, u8)>,
+    pub(crate) disp: Disp,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) enum Base<R> {
+    None,
+    Reg(R),
+    #[cfg(feature = "x86_64")]
+    Rip,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) enum Disp {
+    Imm(i32),
+    Label(Label),
+}
+
+impl<R> Memory<R> {
+    /// `[base]`.
+    pub fn base(base: R) -> Self {
+        Self {
+            base: Base::Reg(base),
+            index: None,
+            disp: Disp::Imm(0),
+        }
+    }
+
+    /// `[disp]`, e.g. an absolute address.
+    pub fn abs(disp: i32) -> Self {
+        Self {
+            base: Base::None,
+            index: None,
+            disp: Disp::Imm(disp),
+        }
+    }
+
+    /// Adds `index * scale`, where `scale` is either 1, 2, 4 or 8.
+    pub fn index(mut self, index: R, scale: u8) -> Self {
+        self.index = Some((index, scale));
+        self
+    }
+
+    /// Sets the displacement to `disp`.
+    pub fn disp(mut self, disp: i32) -> Self {
+        self.disp = Disp::Imm(disp);
+        self
+    }
+
+    /// Sets the displacement to the offset of `label` within the buffer, e.g. `[base + label]` where `base`
+    /// holds the address of the buffer.
+    pub fn label(mut self, label: impl Into<Label>) -> Self {
+        self.disp = Disp::Label(label.into());
+        self
+    }
+}
+
+#[cfg(feature = "x86_64")]
+impl Memory<crate::x86_64::Reg> {
+    /// `[rip + label]`, e.g. the address of `label` relative to the end of the instruction.
+    pub fn rip(label: impl Into<Label>) -> Self {
+        Self {
+            base: Base::Rip,
+            index: None,
+            disp: Disp::Label(label.into()),
+        }
+    }
+}
@@ -1,8 +1,38 @@
+mod cond;
+mod dec;
+mod enc;
+mod mem;
 mod op;
+mod reg;
+mod syntax;
 
+pub use cond::Cond;
+pub use mem::Memory;
 pub use op::Op;
+pub use reg::Reg;
 
-use crate::Label;
+use std::{fmt::Display, str::FromStr};
+
+use crate::{Encodable, Label};
+
+/// A memory operand addressed by `x86` registers.
+pub type Mem = Memory<Reg>;
+
+/// A mode of the `x86` family: every mode shares the same encoder, so the typed instruction
+/// methods are available to both [`TinyAsm`] and `x86_64::TinyAsm`.
+pub trait Family: Encodable<4> + From<Op> {
+    /// The general purpose registers, which convert to and from their number.
+    type Reg: Copy + Into<u8> + TryFrom<u8> + FromStr + Display;
+
+    /// Whether the operand size is 64 bit, e.g. the `REX` prefix is available.
+    const LONG: bool;
+}
+
+impl Family for Op {
+    type Reg = Reg;
+
+    const LONG: bool = false;
+}
 
 impl TinyAsm {
     pub fn instr<const T: usize>(mut self, bytes: [u8; T]) -> Self {
@@ -10,10 +40,27 @@ impl TinyAsm {
         self
     }
 
-    pub fn instr_with_ref<const T: usize>(mut self, bytes: [u8; T], label: Label) -> Self {
+    pub fn instr_with_ref<const T: usize>(
+        mut self,
+        bytes: [u8; T],
+        label: impl Into<Label>,
+    ) -> Self {
+        let label = self.refer(label);
+        self.buf.extend(bytes);
+        self.reloc(Op::Ref(label));
+        self
+    }
+
+    /// Pushes `bytes` followed by the 32-bit offset of `label` relative to the end of the instruction, e.g. a `rel32` operand.
+    pub fn instr_with_rel<const T: usize>(
+        mut self,
+        bytes: [u8; T],
+        label: impl Into<Label>,
+    ) -> Self {
+        let label = self.refer(label);
         self.buf.extend(bytes);
-        self.relocs.push((self.buf.len(), Op::Ref(label)));
-        self.op(Op::Placeholder)
+        self.reloc(Op::Rel(label, 0));
+        self
     }
 }
 
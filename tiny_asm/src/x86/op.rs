@@ -1,19 +1,41 @@
 use std::collections::HashMap;
 
-use crate::{Encodable, Label};
+use crate::{AsmError, Encodable, Label};
 
+/// The label references of the `x86` family, which are the only parts of an instruction encoded late.
 pub enum Op {
-    Placeholder,
+    /// The offset of a label within the buffer, e.g. an `imm32` or `disp32` operand.
     Ref(Label),
+    /// The 32-bit offset of a label relative to the end of the instruction, which ends the given amount of bytes
+    /// after the operand, e.g. a `rel32` or RIP-relative operand.
+    Rel(Label, u8),
+    /// The 8-bit offset of a label relative to the end of the instruction, e.g. a `rel8` operand.
+    Rel8(Label),
 }
 
 impl Encodable<4> for Op {
-    fn enc(self, instr_offset: usize, labels: &HashMap<Label, usize>) -> [u8; 4] {
+    fn size(&self) -> usize {
         match self {
-            Op::Placeholder => 0,
-            Op::Ref(label) => Self::res_lab(label, labels, instr_offset),
+            Op::Rel8(_) => 1,
+            _ => 4,
         }
-        .to_le_bytes()
+    }
+
+    fn enc(self, instr_offset: usize, labels: &HashMap<Label, usize>) -> Result<[u8; 4], AsmError> {
+        let end = |size: usize| (instr_offset + size) as i32;
+
+        Ok(match self {
+            Op::Ref(label) => Self::res_lab(label, labels, instr_offset)?,
+            Op::Rel(label, trailing) => {
+                Self::res_lab(label, labels, instr_offset)? - end(4 + usize::from(trailing))
+            }
+            Op::Rel8(label) => {
+                let rel = Self::res_lab(label, labels, instr_offset)? - end(1);
+
+                i8::try_from(rel).map_err(|_| AsmError::ImmOutOfRange(rel.into()))? as u8 as i32
+            }
+        }
+        .to_le_bytes())
     }
 
     fn calc_offset(_: i32, label_offset: i32) -> i32 {
@@ -0,0 +1,60 @@
+use std::{fmt, str::FromStr};
+
+/// The general purpose registers.
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reg {
+    eax = 0,
+    ecx = 1,
+    edx = 2,
+    ebx = 3,
+    esp = 4,
+    ebp = 5,
+    esi = 6,
+    edi = 7,
+}
+
+impl From<Reg> for u8 {
+    fn from(reg: Reg) -> Self {
+        reg as u8
+    }
+}
+
+impl TryFrom<u8> for Reg {
+    type Error = ();
+
+    fn try_from(number: u8) -> Result<Self, Self::Error> {
+        use Reg::*;
+
+        [eax, ecx, edx, ebx, esp, ebp, esi, edi]
+            .get(usize::from(number))
+            .copied()
+            .ok_or(())
+    }
+}
+
+impl FromStr for Reg {
+    type Err = ();
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        use Reg::*;
+
+        Ok(match name {
+            "eax" => eax,
+            "ecx" => ecx,
+            "edx" => edx,
+            "ebx" => ebx,
+            "esp" => esp,
+            "ebp" => ebp,
+            "esi" => esi,
+            "edi" => edi,
+            _ => return Err(()),
+        })
+    }
+}
+
+impl fmt::Display for Reg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
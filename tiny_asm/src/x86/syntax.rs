@@ -0,0 +1,264 @@
+use crate::{
+    text::{parse_int, parse_label},
+    Syntax, TinyAsm,
+};
+
+use super::{
+    mem::{Base, Disp},
+    Cond, Family, Memory, Op,
+};
+
+/// An operand of the Intel syntax.
+enum Operand<R> {
+    Reg(R),
+    Imm(i64),
+    Mem(Memory<R>),
+    Label(String),
+}
+
+impl Syntax<4> for Op {
+    fn instr(asm: &mut TinyAsm<Self, 4>, mnemonic: &str, operands: &[&str]) -> Result<(), String> {
+        instr(asm, mnemonic, operands)
+    }
+}
+
+#[cfg(feature = "x86_64")]
+impl Syntax<4> for crate::x86_64::Op {
+    fn instr(asm: &mut TinyAsm<Self, 4>, mnemonic: &str, operands: &[&str]) -> Result<(), String> {
+        instr(asm, mnemonic, operands)
+    }
+}
+
+/// The mnemonics of the Intel syntax, except the `J<cc>` ones.
+const MNEMONICS: [&str; 17] = [
+    "add", "and", "call", "cmp", "int", "jmp", "lea", "mov", "nop", "or", "pop", "push", "ret",
+    "sub", "syscall", "test", "xor",
+];
+
+/// Pushes an instruction written in the Intel syntax, e.g. `mov rdx, qword ptr [rip + len]`.
+fn instr<O: Family>(
+    asm: &mut TinyAsm<O, 4>,
+    mnemonic: &str,
+    operands: &[&str],
+) -> Result<(), String> {
+    use Operand::*;
+
+    if let (Some(cond), [operand]) = (mnemonic.strip_prefix('j').and_then(cond), operands) {
+        match operand.strip_prefix("short ") {
+            Some(label) => {
+                let label = parse_label(label.trim())?;
+                asm.emit(|asm| asm.jccs(cond, label));
+            }
+            None => {
+                let label = parse_label(operand)?;
+                asm.emit(|asm| asm.jcc(cond, label));
+            }
+        }
+        return Ok(());
+    }
+
+    if let ("jmp", [operand]) = (mnemonic, operands) {
+        if let Some(label) = operand.strip_prefix("short ") {
+            let label = parse_label(label.trim())?;
+            asm.emit(|asm| asm.jmps(label));
+            return Ok(());
+        }
+    }
+
+    let operands = operands
+        .iter()
+        .map(|operand| parse_operand::<O>(operand))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    match (mnemonic, &operands[..]) {
+        ("mov", [Reg(dst), Reg(src)]) => asm.emit(|asm| asm.movr(*dst, *src)),
+        ("mov", [Reg(dst), Imm(imm)]) => asm.emit(|asm| asm.movi(*dst, *imm)),
+        ("mov", [Reg(dst), Mem(mem)]) => asm.emit(|asm| asm.movrm(*dst, mem.clone())),
+        ("mov", [Mem(mem), Reg(src)]) => asm.emit(|asm| asm.movmr(mem.clone(), *src)),
+        ("mov", [Mem(mem), Imm(imm)]) => {
+            let imm = imm32(*imm)?;
+            asm.emit(|asm| asm.movmi(mem.clone(), imm))
+        }
+        ("lea", [Reg(dst), Mem(mem)]) => asm.emit(|asm| asm.lea(*dst, mem.clone())),
+        ("add", [Reg(dst), Reg(src)]) => asm.emit(|asm| asm.addr(*dst, *src)),
+        ("or", [Reg(dst), Reg(src)]) => asm.emit(|asm| asm.orr(*dst, *src)),
+        ("and", [Reg(dst), Reg(src)]) => asm.emit(|asm| asm.andr(*dst, *src)),
+        ("sub", [Reg(dst), Reg(src)]) => asm.emit(|asm| asm.subr(*dst, *src)),
+        ("xor", [Reg(dst), Reg(src)]) => asm.emit(|asm| asm.xorr(*dst, *src)),
+        ("cmp", [Reg(dst), Reg(src)]) => asm.emit(|asm| asm.cmpr(*dst, *src)),
+        ("test", [Reg(dst), Reg(src)]) => asm.emit(|asm| asm.testr(*dst, *src)),
+        ("add" | "or" | "and" | "sub" | "xor" | "cmp", [Reg(dst), Imm(imm)]) => {
+            let (dst, imm) = (*dst, imm32(*imm)?);
+
+            asm.emit(|asm| match mnemonic {
+                "add" => asm.addi(dst, imm),
+                "or" => asm.ori(dst, imm),
+                "and" => asm.andi(dst, imm),
+                "sub" => asm.subi(dst, imm),
+                "xor" => asm.xori(dst, imm),
+                _ => asm.cmpi(dst, imm),
+            })
+        }
+        ("add", [Reg(dst), Label(label)]) => asm.emit(|asm| asm.addl(*dst, label.as_str())),
+        ("sub", [Reg(dst), Label(label)]) => asm.emit(|asm| asm.subl(*dst, label.as_str())),
+        ("push", [Reg(reg)]) => asm.emit(|asm| asm.push(*reg)),
+        ("push", [Imm(imm)]) => {
+            let imm = imm32(*imm)?;
+            asm.emit(|asm| asm.pushi(imm))
+        }
+        ("pop", [Reg(reg)]) => asm.emit(|asm| asm.pop(*reg)),
+        ("call", [Label(label)]) => asm.emit(|asm| asm.call(label.as_str())),
+        ("call", [Reg(reg)]) => asm.emit(|asm| asm.callr(*reg)),
+        ("call", [Mem(mem)]) => asm.emit(|asm| asm.callm(mem.clone())),
+        ("jmp", [Label(label)]) => asm.emit(|asm| asm.jmp(label.as_str())),
+        ("jmp", [Reg(reg)]) => asm.emit(|asm| asm.jmpr(*reg)),
+        ("jmp", [Mem(mem)]) => asm.emit(|asm| asm.jmpm(mem.clone())),
+        ("int", [Imm(imm)]) => {
+            let imm = u8::try_from(*imm).map_err(|_| format!("`{}` doesn't fit 8 bits", imm))?;
+            asm.emit(|asm| asm.int(imm))
+        }
+        ("syscall", []) => asm.emit(|asm| asm.syscall()),
+        ("ret", []) => asm.emit(|asm| asm.ret()),
+        ("nop", []) => asm.emit(|asm| asm.nop()),
+        _ if MNEMONICS.contains(&mnemonic) => {
+            return Err(format!("invalid operands for `{}`", mnemonic))
+        }
+        _ => return Err(format!("unknown instruction `{}`", mnemonic)),
+    }
+
+    Ok(())
+}
+
+fn imm32(imm: i64) -> Result<i32, String> {
+    i32::try_from(imm).map_err(|_| format!("`{}` doesn't fit 32 bits", imm))
+}
+
+/// Parses the condition of a `J<cc>` mnemonic, including its aliases, e.g. `z` for `e`.
+fn cond(name: &str) -> Option<Cond> {
+    Some(match name {
+        "o" => Cond::O,
+        "no" => Cond::No,
+        "b" | "c" | "nae" => Cond::B,
+        "ae" | "nb" | "nc" => Cond::Ae,
+        "e" | "z" => Cond::E,
+        "ne" | "nz" => Cond::Ne,
+        "be" | "na" => Cond::Be,
+        "a" | "nbe" => Cond::A,
+        "s" => Cond::S,
+        "ns" => Cond::Ns,
+        "p" | "pe" => Cond::P,
+        "np" | "po" => Cond::Np,
+        "l" | "nge" => Cond::L,
+        "ge" | "nl" => Cond::Ge,
+        "le" | "ng" => Cond::Le,
+        "g" | "nle" => Cond::G,
+        _ => return None,
+    })
+}
+
+fn parse_operand<O: Family>(operand: &str) -> Result<Operand<O::Reg>, String> {
+    let lower = operand.to_ascii_lowercase();
+
+    let sized = ["byte", "word", "dword", "qword"].iter().find_map(|size| {
+        let rest = lower.strip_prefix(size)?.trim_start().strip_prefix("ptr")?;
+        Some((*size, operand[operand.len() - rest.len()..].trim_start()))
+    });
+
+    if let Some((size, mem)) = sized {
+        if size != if O::LONG { "qword" } else { "dword" } {
+            return Err(format!("unsupported operand size `{}`", size));
+        }
+        return parse_mem::<O>(mem).map(Operand::Mem);
+    }
+
+    if operand.starts_with('[') {
+        parse_mem::<O>(operand).map(Operand::Mem)
+    } else if let Ok(reg) = lower.parse() {
+        Ok(Operand::Reg(reg))
+    } else if let Ok(imm) = parse_int(operand) {
+        Ok(Operand::Imm(imm))
+    } else {
+        parse_label(operand).map(|label| Operand::Label(label.into()))
+    }
+}
+
+/// Parses a memory operand, e.g. `[base + index * scale + disp]`, `[base + label]` or `[rip + label]`.
+fn parse_mem<O: Family>(operand: &str) -> Result<Memory<O::Reg>, String> {
+    let invalid = || format!("invalid memory operand `{}`", operand);
+
+    let inner = operand
+        .strip_prefix('[')
+        .and_then(|operand| operand.strip_suffix(']'))
+        .ok_or_else(invalid)?;
+
+    let mut base = Base::None;
+    let mut index = None;
+    let mut disp = 0_i64;
+    let mut label = None;
+
+    let mut rest = inner.trim();
+    let mut negative = false;
+
+    if let Some(stripped) = rest.strip_prefix('-') {
+        (rest, negative) = (stripped.trim(), true);
+    }
+
+    while !rest.is_empty() {
+        let end = rest.find(['+', '-']).unwrap_or(rest.len());
+        let term = rest[..end].trim();
+        let lower = term.to_ascii_lowercase();
+
+        if let Some((left, right)) = term.split_once('*') {
+            let (left, right) = (left.trim(), right.trim());
+            let (reg, scale) = match (
+                left.to_ascii_lowercase().parse(),
+                right.to_ascii_lowercase().parse(),
+            ) {
+                (Ok(reg), _) => (reg, right),
+                (_, Ok(reg)) => (reg, left),
+                _ => return Err(invalid()),
+            };
+            let scale = u8::try_from(parse_int(scale)?).map_err(|_| invalid())?;
+
+            if negative || index.replace((reg, scale)).is_some() {
+                return Err(invalid());
+            }
+        } else if let Ok(reg) = lower.parse() {
+            match (negative, &base, &index) {
+                (false, Base::None, _) => base = Base::Reg(reg),
+                (false, _, None) => index = Some((reg, 1)),
+                _ => return Err(invalid()),
+            }
+        } else if lower == "rip" {
+            if !O::LONG || negative || !matches!(base, Base::None) {
+                return Err(invalid());
+            }
+            #[cfg(feature = "x86_64")]
+            {
+                base = Base::Rip;
+            }
+        } else if let Ok(imm) = parse_int(term) {
+            disp += if negative { -imm } else { imm };
+        } else if !negative && label.is_none() {
+            label = Some(parse_label(term)?.to_string());
+        } else {
+            return Err(invalid());
+        }
+
+        negative = rest[end..].starts_with('-');
+        rest = rest.get(end + 1..).unwrap_or("").trim();
+    }
+
+    let disp = match (label, disp) {
+        (Some(label), 0) => Disp::Label(label.into()),
+        (Some(_), _) => return Err(invalid()),
+        (None, disp) => Disp::Imm(i32::try_from(disp).map_err(|_| invalid())?),
+    };
+
+    #[cfg(feature = "x86_64")]
+    if matches!(base, Base::Rip) && (index.is_some() || matches!(disp, Disp::Imm(_))) {
+        return Err(invalid());
+    }
+
+    Ok(Memory { base, index, disp })
+}
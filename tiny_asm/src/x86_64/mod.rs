@@ -1,8 +1,24 @@
 mod op;
+mod reg;
 
 pub use op::Op;
+pub use reg::Reg;
 
-use crate::Label;
+pub use crate::x86::Cond;
+
+use crate::{
+    x86::{self, Family, Memory},
+    Label,
+};
+
+/// A memory operand addressed by `x86-64` registers.
+pub type Mem = Memory<Reg>;
+
+impl Family for Op {
+    type Reg = Reg;
+
+    const LONG: bool = true;
+}
 
 impl TinyAsm {
     pub fn instr<const T: usize>(mut self, bytes: [u8; T]) -> Self {
@@ -10,11 +26,131 @@ impl TinyAsm {
         self
     }
 
-    pub fn instr_with_ref<const T: usize>(mut self, bytes: [u8; T], label: Label) -> Self {
+    pub fn instr_with_ref<const T: usize>(
+        mut self,
+        bytes: [u8; T],
+        label: impl Into<Label>,
+    ) -> Self {
+        let label = self.refer(label);
         self.buf.extend(bytes);
-        self.relocs.push((self.buf.len(), Op::Refl(label)));
-        self.op(Op::Placeholder)
+        self.reloc(x86::Op::Rel(label, 0).into());
+        self
     }
 }
 
 pub type TinyAsm = super::TinyAsm<Op, 4>;
+
+#[cfg(test)]
+mod tests {
+    use super::{Cond, Mem, Reg::*, TinyAsm};
+
+    /// The expected encodings are the ones emitted by `llvm-mc -triple=x86_64`.
+    #[test]
+    fn encodes_like_llvm() {
+        let code = TinyAsm::new()
+            .movr(rdi, r15)
+            .movi(rax, 2)
+            .movi(rax, -1)
+            .movi(r10, 0x123456789)
+            .movmr(Mem::base(rsp).disp(8), rax)
+            .movrm(rax, Mem::base(r12))
+            .movrm(rax, Mem::base(r13))
+            .movmi(Mem::base(rsp).disp(8), 5)
+            .lea(rdi, Mem::base(rsp).disp(-128))
+            .lea(r10, Mem::base(rsp).disp(-129))
+            .lea(rax, Mem::base(rbx).index(rcx, 4).disp(16))
+            .lea(rax, Mem::base(r8).index(r9, 8))
+            .push(rax)
+            .push(r15)
+            .pop(r12)
+            .pushi(1)
+            .pushi(0x1000)
+            .callr(rax)
+            .callr(r11)
+            .jmpr(rsp)
+            .jmpr(r15)
+            .addi(rsi, 128)
+            .addi(rax, 1000)
+            .addr(rsi, rdi)
+            .subr(rsi, rdi)
+            .andi(rsp, -16)
+            .andi(rdi, -4096)
+            .cmpi(rax, 47)
+            .cmpi(r9, 1000)
+            .testr(rax, rax)
+            .xorr(r8, r8)
+            .orr(rcx, rdx)
+            .syscall()
+            .int(0x80)
+            .ret()
+            .nop()
+            .build()
+            .unwrap();
+
+        let expected: &[&[u8]] = &[
+            &[0x4c, 0x89, 0xff],
+            &[0x48, 0xc7, 0xc0, 0x02, 0x00, 0x00, 0x00],
+            &[0x48, 0xc7, 0xc0, 0xff, 0xff, 0xff, 0xff],
+            &[0x49, 0xba, 0x89, 0x67, 0x45, 0x23, 0x01, 0x00, 0x00, 0x00],
+            &[0x48, 0x89, 0x44, 0x24, 0x08],
+            &[0x49, 0x8b, 0x04, 0x24],
+            &[0x49, 0x8b, 0x45, 0x00],
+            &[0x48, 0xc7, 0x44, 0x24, 0x08, 0x05, 0x00, 0x00, 0x00],
+            &[0x48, 0x8d, 0x7c, 0x24, 0x80],
+            &[0x4c, 0x8d, 0x94, 0x24, 0x7f, 0xff, 0xff, 0xff],
+            &[0x48, 0x8d, 0x44, 0x8b, 0x10],
+            &[0x4b, 0x8d, 0x04, 0xc8],
+            &[0x50],
+            &[0x41, 0x57],
+            &[0x41, 0x5c],
+            &[0x6a, 0x01],
+            &[0x68, 0x00, 0x10, 0x00, 0x00],
+            &[0xff, 0xd0],
+            &[0x41, 0xff, 0xd3],
+            &[0xff, 0xe4],
+            &[0x41, 0xff, 0xe7],
+            &[0x48, 0x81, 0xc6, 0x80, 0x00, 0x00, 0x00],
+            &[0x48, 0x05, 0xe8, 0x03, 0x00, 0x00],
+            &[0x48, 0x01, 0xfe],
+            &[0x48, 0x29, 0xfe],
+            &[0x48, 0x83, 0xe4, 0xf0],
+            &[0x48, 0x81, 0xe7, 0x00, 0xf0, 0xff, 0xff],
+            &[0x48, 0x83, 0xf8, 0x2f],
+            &[0x49, 0x81, 0xf9, 0xe8, 0x03, 0x00, 0x00],
+            &[0x48, 0x85, 0xc0],
+            &[0x4d, 0x31, 0xc0],
+            &[0x48, 0x09, 0xd1],
+            &[0x0f, 0x05],
+            &[0xcd, 0x80],
+            &[0xc3],
+            &[0x90],
+        ];
+
+        assert_eq!(code, expected.concat());
+    }
+
+    #[test]
+    fn encodes_rip_relative_operands() {
+        let code = TinyAsm::new()
+            .movrm(rdx, Mem::rip("data"))
+            .callm(Mem::rip("data"))
+            .movmi(Mem::rip("data"), -1)
+            .jcc(Cond::Ne, "data")
+            .jmpm(Mem::rip("data"))
+            .label("data")
+            .build()
+            .unwrap();
+
+        let expected: &[&[u8]] = &[
+            &[0x48, 0x8b, 0x15, 0x1d, 0x00, 0x00, 0x00],
+            &[0xff, 0x15, 0x17, 0x00, 0x00, 0x00],
+            &[
+                0x48, 0xc7, 0x05, 0x0c, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff,
+            ],
+            &[0x0f, 0x85, 0x06, 0x00, 0x00, 0x00],
+            &[0xff, 0x25, 0x00, 0x00, 0x00, 0x00],
+        ];
+
+        assert_eq!(code, expected.concat());
+    }
+}
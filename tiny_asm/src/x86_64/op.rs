@@ -1,22 +1,26 @@
-use std::{collections::HashMap, mem::size_of};
+use std::collections::HashMap;
 
-use crate::{Encodable, Label};
+use crate::{x86, AsmError, Encodable, Label};
 
-pub enum Op {
-    Placeholder,
-    Refl(Label),
+/// The label references of `x86-64`, which are encoded the same way as the `x86` ones.
+pub struct Op(pub(crate) x86::Op);
+
+impl From<x86::Op> for Op {
+    fn from(op: x86::Op) -> Self {
+        Self(op)
+    }
 }
 
 impl Encodable<4> for Op {
-    fn enc(self, instr_offset: usize, labels: &HashMap<Label, usize>) -> [u8; 4] {
-        match self {
-            Op::Refl(label) => Self::res_lab(label, labels, instr_offset),
-            Op::Placeholder => 0,
-        }
-        .to_le_bytes()
+    fn size(&self) -> usize {
+        self.0.size()
+    }
+
+    fn enc(self, instr_offset: usize, labels: &HashMap<Label, usize>) -> Result<[u8; 4], AsmError> {
+        self.0.enc(instr_offset, labels)
     }
 
     fn calc_offset(instr_offset: i32, label_offset: i32) -> i32 {
-        label_offset - instr_offset - size_of::<i32>() as i32
+        x86::Op::calc_offset(instr_offset, label_offset)
     }
 }
@@ -0,0 +1,78 @@
+use std::{fmt, str::FromStr};
+
+/// The general purpose registers.
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reg {
+    rax = 0,
+    rcx = 1,
+    rdx = 2,
+    rbx = 3,
+    rsp = 4,
+    rbp = 5,
+    rsi = 6,
+    rdi = 7,
+    r8 = 8,
+    r9 = 9,
+    r10 = 10,
+    r11 = 11,
+    r12 = 12,
+    r13 = 13,
+    r14 = 14,
+    r15 = 15,
+}
+
+impl From<Reg> for u8 {
+    fn from(reg: Reg) -> Self {
+        reg as u8
+    }
+}
+
+impl TryFrom<u8> for Reg {
+    type Error = ();
+
+    fn try_from(number: u8) -> Result<Self, Self::Error> {
+        use Reg::*;
+
+        [
+            rax, rcx, rdx, rbx, rsp, rbp, rsi, rdi, r8, r9, r10, r11, r12, r13, r14, r15,
+        ]
+        .get(usize::from(number))
+        .copied()
+        .ok_or(())
+    }
+}
+
+impl FromStr for Reg {
+    type Err = ();
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        use Reg::*;
+
+        Ok(match name {
+            "rax" => rax,
+            "rcx" => rcx,
+            "rdx" => rdx,
+            "rbx" => rbx,
+            "rsp" => rsp,
+            "rbp" => rbp,
+            "rsi" => rsi,
+            "rdi" => rdi,
+            "r8" => r8,
+            "r9" => r9,
+            "r10" => r10,
+            "r11" => r11,
+            "r12" => r12,
+            "r13" => r13,
+            "r14" => r14,
+            "r15" => r15,
+            _ => return Err(()),
+        })
+    }
+}
+
+impl fmt::Display for Reg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}